@@ -0,0 +1,77 @@
+//! the workload, program and timing harness shared by the benchmarks
+
+#![allow(dead_code)] // each benchmark uses a different part
+
+use rustvm::hardware::vm::VM;
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+pub struct Workload {
+    pub name: &'static str,
+    pub setup: fn() -> VM,
+    /// instructions per run, programs that halt on their own stop earlier
+    pub budget: u64,
+    pub check: fn(&VM),
+}
+
+// x3000:            LD R2, OUTER
+// x3001 OUTERLOOP:  LD R1, INNER
+// x3002 INNERLOOP:  ADD R0, R0, #1
+// x3003:            ADD R1, R1, #-1
+// x3004:            BRp INNERLOOP
+// x3005:            ADD R2, R2, #-1
+// x3006:            BRp OUTERLOOP
+// x3007:            HALT
+// x3008 OUTER:      .FILL #100
+// x3009 INNER:      .FILL #10000
+const ARITHMETIC_LOOP: [u16; 10] = [
+    0x2407, 0x2207, 0x1021, 0x127F, 0x03FD, 0x14BF, 0x03FA, 0xF025, 100, 10000,
+];
+
+pub fn load(vm: &mut VM, origin: u16, words: &[u16]) {
+    for (offset, word) in words.iter().enumerate() {
+        vm.write_memory(origin + offset as u16, *word);
+    }
+}
+
+pub fn program(words: &[u16]) -> VM {
+    let mut vm = VM::new();
+    load(&mut vm, 0x3000, words);
+    vm.set_output(Box::new(io::sink()));
+    vm
+}
+
+pub fn arithmetic() -> VM {
+    program(&ARITHMETIC_LOOP)
+}
+
+pub fn check_halted(vm: &VM) {
+    assert!(vm.is_halted(), "workload did not halt");
+}
+
+fn run_once(workload: &Workload, decode_cache: bool) -> (u64, Duration) {
+    let mut vm = (workload.setup)();
+    vm.set_decode_cache(decode_cache);
+
+    let start = Instant::now();
+    let executed = vm.run_for(workload.budget).expect("workload failed");
+    let elapsed = start.elapsed();
+    (workload.check)(&vm);
+    (black_box(executed), elapsed)
+}
+
+/// instructions per second over `RUNS` runs after a warm up run
+pub fn measure(workload: &Workload, decode_cache: bool) -> f64 {
+    run_once(workload, decode_cache);
+    let mut instructions = 0;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..RUNS {
+        let (count, time) = run_once(workload, decode_cache);
+        instructions += count;
+        elapsed += time;
+    }
+    instructions as f64 / elapsed.as_secs_f64()
+}
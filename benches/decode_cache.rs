@@ -0,0 +1,15 @@
+//! the decode cache against decoding every fetch on a tight loop, the speedup the cache
+//! was added for. `benches/interpreter.rs` compares both over more workloads
+
+mod common;
+
+use common::{Workload, arithmetic, check_halted, measure};
+
+fn main() {
+    let workload = Workload { name: "arithmetic loop", setup: arithmetic, budget: u64::MAX, check: check_halted };
+    let decoding = measure(&workload, false);
+    let cached = measure(&workload, true);
+    println!("decode every fetch: {:>8.2} M instructions/s", decoding / 1e6);
+    println!("decode cache:       {:>8.2} M instructions/s", cached / 1e6);
+    println!("speedup:            {:>8.2}x", cached / decoding);
+}
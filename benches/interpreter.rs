@@ -0,0 +1,109 @@
+//! instructions per second for representative workloads, once with every fetch decoded
+//! and once through the decode cache. run with `cargo bench --bench interpreter [filter]`
+
+mod common;
+
+use common::{Workload, arithmetic, check_halted, load, measure, program};
+use rustvm::hardware::vm::VM;
+use std::io;
+
+// bubble sort of N words at x4000
+// x3000:          LD R6, N
+// x3001 OUTER:    ADD R6, R6, #-1
+// x3002:          BRnz DONE
+// x3003:          LD R1, DATA
+// x3004:          ADD R2, R6, #0
+// x3005 INNER:    LDR R3, R1, #0
+// x3006:          LDR R4, R1, #1
+// x3007:          NOT R5, R4
+// x3008:          ADD R5, R5, #1
+// x3009:          ADD R5, R3, R5
+// x300A:          BRnz NOSWAP
+// x300B:          STR R4, R1, #0
+// x300C:          STR R3, R1, #1
+// x300D NOSWAP:   ADD R1, R1, #1
+// x300E:          ADD R2, R2, #-1
+// x300F:          BRp INNER
+// x3010:          BRnzp OUTER
+// x3011 DONE:     HALT
+// x3012 N:        .FILL #400
+// x3013 DATA:     .FILL x4000
+const BUBBLE_SORT: [u16; 20] = [
+    0x2C11, 0x1DBF, 0x0C0E, 0x220F, 0x15A0, 0x6640, 0x6841, 0x9B3F, 0x1B61, 0x1AC5, 0x0C02, 0x7840,
+    0x7641, 0x1261, 0x14BF, 0x03F5, 0x0FF0, 0xF025, 400, 0x4000,
+];
+const SORT_DATA: u16 = 0x4000;
+const SORT_LEN: u16 = 400;
+
+// x3000:       LD R1, COUNT
+// x3001:       LD R0, CHAR
+// x3002 LOOP:  OUT
+// x3003:       ADD R1, R1, #-1
+// x3004:       BRp LOOP
+// x3005:       HALT
+// x3006 COUNT: .FILL #30000
+// x3007 CHAR:  .FILL 'x'
+const OUTPUT_LOOP: [u16; 8] = [0x2205, 0x2005, 0xF021, 0x127F, 0x03FD, 0xF025, 30000, 0x0078];
+
+fn sorting() -> VM {
+    let mut vm = program(&BUBBLE_SORT);
+    for i in 0..SORT_LEN {
+        vm.write_memory(SORT_DATA + i, SORT_LEN - i);
+    }
+    vm
+}
+
+fn output() -> VM {
+    program(&OUTPUT_LOOP)
+}
+
+fn game_2048() -> VM {
+    let image = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/2048.obj")).expect("failed to read 2048.obj");
+    let words: Vec<u16> = image
+        .chunks_exact(2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .collect();
+    let mut vm = VM::new();
+    load(&mut vm, words[0], &words[1..]);
+    vm.set_output(Box::new(io::sink()));
+    vm.set_input("wasd".repeat(5000));
+    vm
+}
+
+fn check_sorted(vm: &VM) {
+    check_halted(vm);
+    for i in 0..SORT_LEN {
+        assert_eq!(vm.read_memory(SORT_DATA + i), i + 1, "data not sorted");
+    }
+}
+
+fn check_running(vm: &VM) {
+    assert!(!vm.is_halted(), "game ended before the instruction budget was used");
+}
+
+fn main() {
+    let workloads = [
+        Workload { name: "arithmetic loop", setup: arithmetic, budget: u64::MAX, check: check_halted },
+        Workload { name: "bubble sort", setup: sorting, budget: u64::MAX, check: check_sorted },
+        Workload { name: "trap output", setup: output, budget: u64::MAX, check: check_halted },
+        Workload { name: "2048 scripted", setup: game_2048, budget: 5_000_000, check: check_running },
+    ];
+    // `cargo bench` passes --bench, anything else filters workloads by name
+    let filter: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+
+    println!("{:<16} {:>14} {:>14} {:>8}", "workload", "decode M/s", "cached M/s", "speedup");
+    for workload in &workloads {
+        if !filter.is_empty() && !filter.iter().any(|f| workload.name.contains(f.as_str())) {
+            continue;
+        }
+        let decoding = measure(workload, false);
+        let cached = measure(workload, true);
+        println!(
+            "{:<16} {:>14.2} {:>14.2} {:>7.2}x",
+            workload.name,
+            decoding / 1e6,
+            cached / 1e6,
+            cached / decoding
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! control-flow graph of a program image, found by decoding from the entry point along
+//! every BR, JMP and JSR instead of running it
+
+use crate::hardware::decode::{Instruction, decode};
+use crate::hardware::disasm::{disassemble, trap_name};
+use crate::hardware::registers::Register;
+use crate::loader::Image;
+use crate::symbols::SymbolTable;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Write};
+
+/// how control leaves a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Exit {
+    /// runs into the next block, which something else branches to
+    Fallthrough,
+    /// BR, to its target and unless it is BRnzp to the next word
+    Branch,
+    /// JSR or JSRR, on to the next word once the subroutine returns
+    Call,
+    /// RET
+    Return,
+    /// JMP through a register other than R7, where to is not known
+    Jump,
+    Halt,
+    /// RTI, a reserved opcode or the end of the image
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    Fallthrough,
+    Branch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Word {
+    pub address: u16,
+    pub word: u16,
+    pub disassembly: String,
+}
+
+/// straight line code, only the first word is entered from elsewhere and only the last
+/// one goes anywhere but the next word
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<Word>,
+    pub exit: Exit,
+    pub successors: Vec<Edge>,
+    /// for an exit by call, the subroutine unless it is a JSRR
+    pub call: Option<u16>,
+}
+
+impl Block {
+    pub fn end(&self) -> u16 {
+        self.instructions.last().map_or(self.start, |word| word.address)
+    }
+}
+
+/// a call from one subroutine to another, `target` is none for JSRR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Call {
+    pub site: u16,
+    pub target: Option<u16>,
+}
+
+/// the blocks reachable from an entry point without following calls. the program entry
+/// is the first one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Subroutine {
+    pub entry: u16,
+    /// label of the entry, if the symbols have one
+    pub name: Option<String>,
+    /// start addresses
+    pub blocks: Vec<u16>,
+    pub calls: Vec<Call>,
+    /// vectors of the TRAPs it executes
+    pub traps: Vec<u8>,
+}
+
+impl Subroutine {
+    /// the label, or the address as `x3010`
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("x{:04X}", self.entry))
+    }
+}
+
+/// inclusive range of image words that are never executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DataRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cfg {
+    pub entry: u16,
+    /// by start address
+    pub blocks: Vec<Block>,
+    pub data: Vec<DataRange>,
+    pub subroutines: Vec<Subroutine>,
+}
+
+/// what an instruction does to the flow of control
+struct Flow {
+    exit: Option<Exit>,
+    branch: Option<u16>,
+    /// JSR target, none inside for JSRR
+    call: Option<Option<u16>>,
+    falls_through: bool,
+}
+
+fn flow(addr: u16, word: u16) -> Flow {
+    let next = addr.wrapping_add(1);
+    let plain = Flow {
+        exit: None,
+        branch: None,
+        call: None,
+        falls_through: true,
+    };
+    let ends = |exit, falls_through| Flow {
+        exit: Some(exit),
+        falls_through,
+        ..plain
+    };
+    match decode(word) {
+        // BR without condition bits never branches
+        Instruction::Br { nzp: 0, .. } => plain,
+        Instruction::Br { nzp, offset } => Flow {
+            branch: Some(next.wrapping_add(offset)),
+            ..ends(Exit::Branch, nzp != 0b111)
+        },
+        Instruction::Jmp { base: Register::R7 } => ends(Exit::Return, false),
+        Instruction::Jmp { .. } => ends(Exit::Jump, false),
+        Instruction::Jsr { offset } => Flow {
+            call: Some(Some(next.wrapping_add(offset))),
+            ..ends(Exit::Call, true)
+        },
+        Instruction::Jsrr { .. } => Flow {
+            call: Some(None),
+            ..ends(Exit::Call, true)
+        },
+        Instruction::Trap { vector: 0x25 } => ends(Exit::Halt, false),
+        Instruction::Rti | Instruction::Res => ends(Exit::Stop, false),
+        _ => plain,
+    }
+}
+
+impl Cfg {
+    /// decodes `image` from `entry` and from every JSR target found on the way, words
+    /// that are never reached are data. `symbols` name the subroutines
+    pub fn build(image: &Image, entry: u16, symbols: &SymbolTable) -> Cfg {
+        let words: BTreeMap<u16, u16> = image.iter().collect();
+        let mut code = BTreeSet::new();
+        let mut leaders = BTreeSet::from([entry]);
+        let mut entries = vec![entry];
+        let mut work = vec![entry];
+        while let Some(addr) = work.pop() {
+            let Some(&word) = words.get(&addr) else {
+                continue;
+            };
+            if !code.insert(addr) {
+                continue;
+            }
+            let flow = flow(addr, word);
+            let next = addr.wrapping_add(1);
+            if let Some(target) = flow.branch {
+                leaders.insert(target);
+                work.push(target);
+            }
+            if let Some(Some(target)) = flow.call {
+                leaders.insert(target);
+                if !entries.contains(&target) {
+                    entries.push(target);
+                }
+                work.push(target);
+            }
+            if flow.falls_through {
+                if flow.exit.is_some() {
+                    leaders.insert(next);
+                }
+                work.push(next);
+            }
+        }
+
+        let mut blocks = Vec::new();
+        for &start in leaders.iter().filter(|addr| code.contains(addr)) {
+            let mut instructions = Vec::new();
+            let mut addr = start;
+            let block = loop {
+                let word = words[&addr];
+                instructions.push(Word {
+                    address: addr,
+                    word,
+                    disassembly: disassemble(addr, word),
+                });
+                let flow = flow(addr, word);
+                let next = addr.wrapping_add(1);
+                let last = flow.exit.is_some() || !code.contains(&next) || leaders.contains(&next);
+                if !last {
+                    addr = next;
+                    continue;
+                }
+                let mut successors = Vec::new();
+                if let Some(target) = flow.branch {
+                    successors.push(Edge {
+                        target,
+                        kind: EdgeKind::Branch,
+                    });
+                }
+                if flow.falls_through && words.contains_key(&next) {
+                    successors.push(Edge {
+                        target: next,
+                        kind: EdgeKind::Fallthrough,
+                    });
+                }
+                let exit = match flow.exit {
+                    Some(exit) => exit,
+                    None if code.contains(&next) => Exit::Fallthrough,
+                    None => Exit::Stop,
+                };
+                break Block {
+                    start,
+                    instructions,
+                    exit,
+                    successors,
+                    call: flow.call.flatten(),
+                };
+            };
+            blocks.push(block);
+        }
+
+        // the program entry stays first
+        entries[1..].sort();
+
+        let mut data = Vec::new();
+        for (addr, _) in image.iter().filter(|(addr, _)| !code.contains(addr)) {
+            match data.last_mut() {
+                Some(DataRange { end, .. }) if end.wrapping_add(1) == addr => *end = addr,
+                _ => data.push(DataRange { start: addr, end: addr }),
+            }
+        }
+
+        let mut cfg = Cfg {
+            entry,
+            blocks,
+            data,
+            subroutines: Vec::new(),
+        };
+        cfg.subroutines = entries
+            .iter()
+            .map(|&entry| cfg.subroutine(entry, symbols.name(entry).map(str::to_string)))
+            .collect();
+        cfg
+    }
+
+    fn subroutine(&self, entry: u16, name: Option<String>) -> Subroutine {
+        let mut seen = BTreeSet::new();
+        let mut queue = VecDeque::from([entry]);
+        let (mut calls, mut traps) = (Vec::new(), BTreeSet::new());
+        while let Some(start) = queue.pop_front() {
+            let Some(block) = self.block(start) else {
+                continue;
+            };
+            if !seen.insert(start) {
+                continue;
+            }
+            for word in &block.instructions {
+                if let Instruction::Trap { vector } = decode(word.word) {
+                    traps.insert(vector);
+                }
+            }
+            if block.exit == Exit::Call {
+                calls.push(Call {
+                    site: block.end(),
+                    target: block.call,
+                });
+            }
+            queue.extend(block.successors.iter().map(|edge| edge.target));
+        }
+        calls.sort_by_key(|call| call.site);
+        Subroutine {
+            entry,
+            name,
+            blocks: seen.into_iter().collect(),
+            calls,
+            traps: traps.into_iter().collect(),
+        }
+    }
+
+    /// the block starting at `start`
+    pub fn block(&self, start: u16) -> Option<&Block> {
+        let index = self.blocks.binary_search_by_key(&start, |block| block.start).ok()?;
+        Some(&self.blocks[index])
+    }
+
+    /// whether the word at `addr` is reached as an instruction
+    pub fn is_code(&self, addr: u16) -> bool {
+        let index = self.blocks.partition_point(|block| block.start <= addr);
+        index > 0 && addr <= self.blocks[index - 1].end()
+    }
+
+    pub fn write_json<W: Write>(&self, mut output: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut output, self)?;
+        writeln!(output)
+    }
+
+    /// graphviz graph with one cluster per subroutine, branches as solid edges, falling
+    /// through as dashed ones and calls dotted
+    pub fn write_dot<W: Write>(&self, mut output: W, symbols: &SymbolTable) -> io::Result<()> {
+        writeln!(output, "digraph cfg {{")?;
+        writeln!(output, "    node [shape=box, fontname=\"monospace\"];")?;
+        let mut placed = BTreeSet::new();
+        for subroutine in &self.subroutines {
+            writeln!(output, "    subgraph \"cluster_x{:04X}\" {{", subroutine.entry)?;
+            writeln!(output, "        label=\"{}\";", escape(&subroutine.label()))?;
+            for &start in subroutine.blocks.iter().filter(|&&start| placed.insert(start)) {
+                let block = self.block(start).expect("subroutines are made of blocks");
+                let mut label = String::new();
+                for word in &block.instructions {
+                    if let Some(name) = symbols.name(word.address) {
+                        label.push_str(&format!("{}:\\l", escape(name)));
+                    }
+                    label.push_str(&format!("x{:04X}  {}\\l", word.address, escape(&word.disassembly)));
+                }
+                writeln!(output, "        \"x{:04X}\" [label=\"{}\"];", start, label)?;
+            }
+            writeln!(output, "    }}")?;
+        }
+        for block in &self.blocks {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Branch => "solid",
+                    EdgeKind::Fallthrough => "dashed",
+                };
+                writeln!(output, "    \"x{:04X}\" -> \"x{:04X}\" [style={}];", block.start, edge.target, style)?;
+            }
+            if let Some(target) = block.call {
+                writeln!(output, "    \"x{:04X}\" -> \"x{:04X}\" [style=dotted];", block.start, target)?;
+            }
+        }
+        writeln!(output, "}}")
+    }
+
+    /// graphviz graph of which subroutine calls which, TRAPs as ellipses and JSRR calls
+    /// to an unknown node
+    pub fn write_call_graph_dot<W: Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "digraph calls {{")?;
+        writeln!(output, "    node [shape=box];")?;
+        for subroutine in &self.subroutines {
+            writeln!(output, "    \"x{:04X}\" [label=\"{}\"];", subroutine.entry, escape(&subroutine.label()))?;
+        }
+        let traps: BTreeSet<u8> = self.subroutines.iter().flat_map(|s| s.traps.iter().copied()).collect();
+        for vector in traps {
+            let name = trap_name(vector).map_or_else(|| format!("TRAP x{:02X}", vector), str::to_string);
+            writeln!(output, "    \"trap_x{:02X}\" [label=\"{}\", shape=ellipse];", vector, name)?;
+        }
+        if self.subroutines.iter().any(|s| s.calls.iter().any(|call| call.target.is_none())) {
+            writeln!(output, "    \"unknown\" [label=\"JSRR\", shape=diamond];")?;
+        }
+        for subroutine in &self.subroutines {
+            let targets: BTreeSet<String> = subroutine
+                .calls
+                .iter()
+                .map(|call| call.target.map_or_else(|| "unknown".to_string(), |target| format!("x{:04X}", target)))
+                .chain(subroutine.traps.iter().map(|vector| format!("trap_x{:02X}", vector)))
+                .collect();
+            for target in targets {
+                writeln!(output, "    \"x{:04X}\" -> \"{}\";", subroutine.entry, target)?;
+            }
+        }
+        writeln!(output, "}}")
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,161 @@
+use super::cfg::*;
+use crate::assembler::{Program, assemble};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+MAIN    LEA R0, MSG
+        JSR PRINT
+        AND R1, R1, #0
+LOOP    ADD R1, R1, #1
+        BRn LOOP
+        HALT
+MSG     .STRINGZ \"hi\"
+PRINT   ST R7, SAVE
+        PUTS
+        JSR NEWLINE
+        LD R7, SAVE
+        RET
+SAVE    .BLKW 1
+NEWLINE LD R0, NL
+        OUT
+        RET
+NL      .FILL x0A
+        .END
+";
+
+fn build(source: &str) -> (Program, Cfg) {
+    let program = assemble(source).unwrap();
+    let cfg = Cfg::build(&program.image, 0x3000, &program.symbols);
+    (program, cfg)
+}
+
+#[test]
+fn blocks_end_at_branches_calls_and_targets() {
+    let (_, cfg) = build(PROGRAM);
+    let blocks: Vec<(u16, u16, Exit)> = cfg.blocks.iter().map(|block| (block.start, block.end(), block.exit)).collect();
+    assert_eq!(
+        blocks,
+        vec![
+            (0x3000, 0x3001, Exit::Call),
+            (0x3002, 0x3002, Exit::Fallthrough),
+            (0x3003, 0x3004, Exit::Branch),
+            (0x3005, 0x3005, Exit::Halt),
+            (0x3009, 0x300B, Exit::Call),
+            (0x300C, 0x300D, Exit::Return),
+            (0x300F, 0x3011, Exit::Return),
+        ]
+    );
+    let branch = cfg.block(0x3003).unwrap();
+    assert_eq!(
+        branch.successors,
+        vec![
+            Edge {
+                target: 0x3003,
+                kind: EdgeKind::Branch
+            },
+            Edge {
+                target: 0x3005,
+                kind: EdgeKind::Fallthrough
+            },
+        ]
+    );
+    assert_eq!(cfg.block(0x3000).unwrap().call, Some(0x3009));
+    assert_eq!(cfg.block(0x3004), None);
+}
+
+#[test]
+fn words_never_reached_are_data() {
+    let (_, cfg) = build(PROGRAM);
+    let data: Vec<(u16, u16)> = cfg.data.iter().map(|range| (range.start, range.end)).collect();
+    assert_eq!(data, vec![(0x3006, 0x3008), (0x300E, 0x300E), (0x3012, 0x3012)]);
+    assert!(cfg.is_code(0x3004));
+    assert!(!cfg.is_code(0x3007));
+    assert!(!cfg.is_code(0x2FFF));
+}
+
+#[test]
+fn subroutines_and_the_call_graph() {
+    let (_, cfg) = build(PROGRAM);
+    let subroutines: Vec<(String, Vec<u16>, Vec<u8>)> =
+        cfg.subroutines.iter().map(|s| (s.label(), s.blocks.clone(), s.traps.clone())).collect();
+    assert_eq!(
+        subroutines,
+        vec![
+            ("MAIN".to_string(), vec![0x3000, 0x3002, 0x3003, 0x3005], vec![0x25]),
+            ("PRINT".to_string(), vec![0x3009, 0x300C], vec![0x22]),
+            ("NEWLINE".to_string(), vec![0x300F], vec![0x21]),
+        ]
+    );
+    let calls: Vec<&[Call]> = cfg.subroutines.iter().map(|s| s.calls.as_slice()).collect();
+    assert_eq!(
+        calls,
+        vec![
+            &[Call {
+                site: 0x3001,
+                target: Some(0x3009)
+            }][..],
+            &[Call {
+                site: 0x300B,
+                target: Some(0x300F)
+            }][..],
+            &[][..],
+        ]
+    );
+
+    let mut dot = Vec::new();
+    cfg.write_call_graph_dot(&mut dot).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "\
+digraph calls {
+    node [shape=box];
+    \"x3000\" [label=\"MAIN\"];
+    \"x3009\" [label=\"PRINT\"];
+    \"x300F\" [label=\"NEWLINE\"];
+    \"trap_x21\" [label=\"OUT\", shape=ellipse];
+    \"trap_x22\" [label=\"PUTS\", shape=ellipse];
+    \"trap_x25\" [label=\"HALT\", shape=ellipse];
+    \"x3000\" -> \"trap_x25\";
+    \"x3000\" -> \"x3009\";
+    \"x3009\" -> \"trap_x22\";
+    \"x3009\" -> \"x300F\";
+    \"x300F\" -> \"trap_x21\";
+}
+"
+    );
+}
+
+#[test]
+fn dot_and_json_exports() {
+    let (program, cfg) = build(PROGRAM);
+    let mut dot = Vec::new();
+    cfg.write_dot(&mut dot, &program.symbols).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("    subgraph \"cluster_x3009\" {\n        label=\"PRINT\";\n"));
+    assert!(dot.contains("        \"x3003\" [label=\"LOOP:\\lx3003  ADD R1, R1, #1\\lx3004  BRn x3003\\l\"];\n"));
+    assert!(dot.contains("    \"x3003\" -> \"x3003\" [style=solid];\n"));
+    assert!(dot.contains("    \"x3003\" -> \"x3005\" [style=dashed];\n"));
+    assert!(dot.contains("    \"x3000\" -> \"x3009\" [style=dotted];\n"));
+
+    let mut json = Vec::new();
+    cfg.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["entry"], 0x3000);
+    assert_eq!(json["blocks"][2]["exit"], "branch");
+    assert_eq!(json["blocks"][2]["instructions"][1]["disassembly"], "BRn x3003");
+    assert_eq!(json["data"][0], serde_json::json!({"start": 0x3006, "end": 0x3008}));
+    assert_eq!(json["subroutines"][1]["calls"][0], serde_json::json!({"site": 0x300B, "target": 0x300F}));
+}
+
+#[test]
+fn indirect_jumps_and_calls_end_the_known_flow() {
+    let (_, cfg) = build(".ORIG x3000\nLEA R1, SUB\nJSRR R1\nJMP R1\nSUB RET\n.END\n");
+    assert_eq!(cfg.block(0x3000).unwrap().exit, Exit::Call);
+    assert_eq!(cfg.block(0x3000).unwrap().call, None);
+    assert_eq!(cfg.block(0x3002).unwrap().exit, Exit::Jump);
+    assert!(cfg.block(0x3002).unwrap().successors.is_empty());
+    // nothing reaches SUB but an indirect call
+    assert!(!cfg.is_code(0x3003));
+    assert_eq!(cfg.subroutines[0].calls, vec![Call { site: 0x3001, target: None }]);
+}
@@ -0,0 +1,305 @@
+//! dataflow over the control-flow graph: registers read before anything wrote them,
+//! subroutines that lose their return address and stores into the program's own code
+
+use super::cfg::{Block, Cfg, Exit};
+use crate::hardware::decode::{Instruction, Operand, decode};
+use crate::hardware::disasm::trap_name;
+use crate::hardware::registers::Register;
+use crate::loader::Image;
+use crate::symbols::SymbolTable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// general purpose registers as bits, R0 the lowest
+type Registers = u8;
+
+const ALL: Registers = 0xFF;
+const R7: Registers = bit(Register::R7);
+
+const HALT: u8 = 0x25;
+
+const fn bit(register: Register) -> Registers {
+    1 << register as u8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// a register read on a path where nothing wrote it
+    Uninitialized,
+    /// a call or TRAP in a subroutine before it saved R7
+    UnsavedR7,
+    /// ST, STI or STR into a word that runs as an instruction
+    StoreIntoCode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub address: u16,
+    pub check: Check,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    /// `x3004: message`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{:04X}: {}", self.address, self.message)
+    }
+}
+
+/// every check over the code `cfg` found in `image`, by address. `symbols` name
+/// subroutines and targets in the messages
+pub fn analyze(cfg: &Cfg, image: &Image, symbols: &SymbolTable) -> Vec<Warning> {
+    let mut warnings = uninitialized(cfg);
+    warnings.extend(unsaved_r7(cfg, symbols));
+    warnings.extend(stores_into_code(cfg, image, symbols));
+    warnings.sort_by_key(|warning| (warning.address, warning.check));
+    warnings
+}
+
+/// registers an instruction reads and writes. clearing with `AND R, R, #0` reads nothing
+/// and TRAP writes R7 as it does on LC-3 hardware, even though the built-in service
+/// routines here leave it alone
+fn effect(word: u16) -> (Registers, Registers) {
+    match decode(word) {
+        Instruction::And {
+            dr,
+            operand: Operand::Immediate(0),
+            ..
+        } => (0, bit(dr)),
+        Instruction::Add { dr, sr1, operand } | Instruction::And { dr, sr1, operand } => match operand {
+            Operand::Register(sr2) => (bit(sr1) | bit(sr2), bit(dr)),
+            Operand::Immediate(_) => (bit(sr1), bit(dr)),
+        },
+        Instruction::Not { dr, sr } => (bit(sr), bit(dr)),
+        Instruction::Jmp { base } => (bit(base), 0),
+        Instruction::Jsr { .. } => (0, R7),
+        Instruction::Jsrr { base } => (bit(base), R7),
+        Instruction::Ld { dr, .. } | Instruction::Ldi { dr, .. } | Instruction::Lea { dr, .. } => (0, bit(dr)),
+        Instruction::Ldr { dr, base, .. } => (bit(base), bit(dr)),
+        Instruction::St { sr, .. } | Instruction::Sti { sr, .. } => (bit(sr), 0),
+        Instruction::Str { sr, base, .. } => (bit(sr) | bit(base), 0),
+        // GETC and IN
+        Instruction::Trap { vector: 0x20 | 0x23 } => (0, bit(Register::R0) | R7),
+        // OUT, PUTS and PUTSP
+        Instruction::Trap { vector: 0x21 | 0x22 | 0x24 } => (bit(Register::R0), R7),
+        Instruction::Trap { .. } => (0, R7),
+        Instruction::Br { .. } | Instruction::Rti | Instruction::Res => (0, 0),
+    }
+}
+
+/// registers written on every path through `block`, given the ones written on every path
+/// to it
+fn transfer(block: &Block, written: Registers) -> Registers {
+    block.instructions.iter().fold(written, |written, word| written | effect(word.word).1)
+}
+
+/// registers written on every path into each block reached from `starts`. a call goes on
+/// to the next word with what the subroutine always writes, `summaries` by entry, and
+/// with everything for a JSRR since it could write anything. with `into_calls` the state
+/// at a call also flows into the subroutine
+fn solve(
+    cfg: &Cfg,
+    starts: &[(u16, Registers)],
+    summaries: &BTreeMap<u16, Registers>,
+    into_calls: bool,
+) -> BTreeMap<u16, Registers> {
+    let mut states: BTreeMap<u16, Registers> = BTreeMap::new();
+    let mut work = Vec::new();
+    let meet = |states: &mut BTreeMap<u16, Registers>, work: &mut Vec<u16>, start: u16, written: Registers| {
+        let met = states.get(&start).map_or(written, |&old| old & written);
+        if states.insert(start, met) != Some(met) {
+            work.push(start);
+        }
+    };
+    for &(start, written) in starts {
+        meet(&mut states, &mut work, start, written);
+    }
+    while let Some(start) = work.pop() {
+        let Some(block) = cfg.block(start) else {
+            continue;
+        };
+        let mut written = transfer(block, states[&start]);
+        if block.exit == Exit::Call {
+            if into_calls && let Some(target) = block.call {
+                meet(&mut states, &mut work, target, written);
+            }
+            written |= block.call.map_or(ALL, |target| summaries.get(&target).copied().unwrap_or(ALL));
+        }
+        for edge in &block.successors {
+            meet(&mut states, &mut work, edge.target, written);
+        }
+    }
+    states
+}
+
+/// registers each subroutine writes on every path from its entry to a RET, all of them for
+/// one that never returns
+fn summaries(cfg: &Cfg) -> BTreeMap<u16, Registers> {
+    let mut summaries: BTreeMap<u16, Registers> = cfg.subroutines.iter().map(|s| (s.entry, ALL)).collect();
+    loop {
+        let mut changed = false;
+        for subroutine in &cfg.subroutines {
+            let states = solve(cfg, &[(subroutine.entry, 0)], &summaries, false);
+            let summary = states
+                .iter()
+                .filter_map(|(&start, &written)| Some((cfg.block(start)?, written)))
+                .filter(|(block, _)| block.exit == Exit::Return)
+                .fold(ALL, |summary, (block, written)| summary & transfer(block, written));
+            changed |= summaries.insert(subroutine.entry, summary) != Some(summary);
+        }
+        if !changed {
+            return summaries;
+        }
+    }
+}
+
+/// reads of registers that some path from the program entry never wrote, whether the VM
+/// happened to clear them or not. a subroutine gets what is written at every call to it
+fn uninitialized(cfg: &Cfg) -> Vec<Warning> {
+    let states = solve(cfg, &[(cfg.entry, 0)], &summaries(cfg), true);
+    let mut warnings = Vec::new();
+    for (&start, &written) in &states {
+        let Some(block) = cfg.block(start) else {
+            continue;
+        };
+        let mut written = written;
+        for word in &block.instructions {
+            let (reads, writes) = effect(word.word);
+            for register in &Register::ALL[..8] {
+                if reads & !written & bit(*register) != 0 {
+                    warnings.push(Warning {
+                        address: word.address,
+                        check: Check::Uninitialized,
+                        message: format!("reads {:?}, which is not written on every path to here", register),
+                    });
+                }
+            }
+            // once per block is enough
+            written |= reads | writes;
+        }
+    }
+    warnings
+}
+
+/// `x3010`, or `x3010 (LOOP)` when it has a label
+fn describe(addr: u16, symbols: &SymbolTable) -> String {
+    match symbols.name(addr) {
+        Some(name) => format!("x{:04X} ({})", addr, name),
+        None => format!("x{:04X}", addr),
+    }
+}
+
+/// whether an instruction keeps the return address, storing R7 or copying it with
+/// `ADD R, R7, #0`
+fn saves_r7(word: u16) -> bool {
+    matches!(
+        decode(word),
+        Instruction::St { sr: Register::R7, .. }
+            | Instruction::Sti { sr: Register::R7, .. }
+            | Instruction::Str { sr: Register::R7, .. }
+            | Instruction::Add {
+                sr1: Register::R7,
+                operand: Operand::Immediate(0),
+                ..
+            }
+    )
+}
+
+/// JSR, JSRR and TRAPs other than HALT in a subroutine, reached on some path from its
+/// entry that did not save R7 first. the program entry is not a subroutine
+pub fn unsaved_r7(cfg: &Cfg, symbols: &SymbolTable) -> Vec<Warning> {
+    let mut warnings: Vec<Warning> = Vec::new();
+    for subroutine in cfg.subroutines.iter().filter(|s| s.entry != cfg.entry) {
+        // saved on every path into each block
+        let mut saved: BTreeMap<u16, bool> = BTreeMap::from([(subroutine.entry, false)]);
+        let mut work = vec![subroutine.entry];
+        while let Some(start) = work.pop() {
+            let Some(block) = cfg.block(start) else {
+                continue;
+            };
+            let mut is_saved = saved[&start];
+            for word in &block.instructions {
+                let call = match decode(word.word) {
+                    Instruction::Jsr { offset } => Some(format!(
+                        "calls {}",
+                        describe(word.address.wrapping_add(1).wrapping_add(offset), symbols)
+                    )),
+                    Instruction::Jsrr { base } => Some(format!("calls through {:?}", base)),
+                    Instruction::Trap { vector } if vector != HALT => Some(format!(
+                        "runs {}",
+                        trap_name(vector).map_or_else(|| format!("TRAP x{:02X}", vector), str::to_string)
+                    )),
+                    _ => None,
+                };
+                if let Some(call) = call
+                    && !is_saved
+                    && !warnings.iter().any(|warning| warning.address == word.address)
+                {
+                    warnings.push(Warning {
+                        address: word.address,
+                        check: Check::UnsavedR7,
+                        message: format!(
+                            "subroutine {} {} before saving R7, its RET will come back here",
+                            subroutine.label(),
+                            call
+                        ),
+                    });
+                }
+                is_saved |= saves_r7(word.word);
+            }
+            for edge in &block.successors {
+                let met = saved.get(&edge.target).map_or(is_saved, |&old| old && is_saved);
+                if saved.insert(edge.target, met) != Some(met) {
+                    work.push(edge.target);
+                }
+            }
+        }
+    }
+    warnings
+}
+
+/// ST and STI to addresses that run as code, and STR through a register that LEA pointed
+/// at code earlier in the same block. STI goes by the pointer's value in the image
+fn stores_into_code(cfg: &Cfg, image: &Image, symbols: &SymbolTable) -> Vec<Warning> {
+    let word_at = |addr: u16| image.iter().find(|&(at, _)| at == addr).map(|(_, word)| word);
+    let mut warnings = Vec::new();
+    for block in &cfg.blocks {
+        // registers holding a known address
+        let mut known: [Option<u16>; 8] = [None; 8];
+        for word in &block.instructions {
+            let next = word.address.wrapping_add(1);
+            let target = match decode(word.word) {
+                Instruction::St { offset, .. } => Some(next.wrapping_add(offset)),
+                Instruction::Sti { offset, .. } => word_at(next.wrapping_add(offset)),
+                Instruction::Str { base, offset, .. } => known[base.index()].map(|addr| addr.wrapping_add(offset)),
+                _ => None,
+            };
+            if let Some(target) = target.filter(|&target| cfg.is_code(target)) {
+                warnings.push(Warning {
+                    address: word.address,
+                    check: Check::StoreIntoCode,
+                    message: format!("stores into {}, which runs as code", describe(target, symbols)),
+                });
+            }
+            match decode(word.word) {
+                Instruction::Lea { dr, offset } => known[dr.index()] = Some(next.wrapping_add(offset)),
+                Instruction::Add {
+                    dr,
+                    sr1,
+                    operand: Operand::Immediate(imm),
+                } => known[dr.index()] = known[sr1.index()].map(|addr| addr.wrapping_add(imm)),
+                _ => {
+                    let (_, writes) = effect(word.word);
+                    for (register, value) in known.iter_mut().enumerate() {
+                        if writes & (1 << register) != 0 {
+                            *value = None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    warnings
+}
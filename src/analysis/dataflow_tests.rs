@@ -0,0 +1,120 @@
+use super::cfg::Cfg;
+use super::dataflow::*;
+use crate::assembler::assemble;
+
+/// (address, message) of the warnings from one check
+fn warnings(source: &str, check: Check) -> Vec<(u16, String)> {
+    let program = assemble(source).unwrap();
+    let cfg = Cfg::build(&program.image, 0x3000, &program.symbols);
+    analyze(&cfg, &program.image, &program.symbols)
+        .into_iter()
+        .filter(|warning| warning.check == check)
+        .map(|warning| (warning.address, warning.message))
+        .collect()
+}
+
+#[test]
+fn reads_before_writes_on_some_path() {
+    let source = "
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R1, R2, #1
+        BRz SKIP
+        LD R3, ONE
+SKIP    ADD R4, R3, R0
+        JSR GET
+        ADD R5, R0, R6
+        HALT
+ONE     .FILL 1
+GET     GETC
+        RET
+        .END
+";
+    assert_eq!(
+        warnings(source, Check::Uninitialized),
+        vec![
+            (0x3001, "reads R2, which is not written on every path to here".to_string()),
+            (0x3004, "reads R3, which is not written on every path to here".to_string()),
+            (0x3006, "reads R6, which is not written on every path to here".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn subroutines_get_what_every_caller_wrote() {
+    let late = "
+        .ORIG x3000
+        JSR DOUBLE
+        LD R1, N
+        JSR DOUBLE
+        HALT
+N       .FILL 3
+DOUBLE  ADD R1, R1, R1
+        RET
+        .END
+";
+    assert_eq!(
+        warnings(late, Check::Uninitialized),
+        vec![(0x3005, "reads R1, which is not written on every path to here".to_string())]
+    );
+    let early = late.replacen("JSR DOUBLE\n        LD R1, N", "LD R1, N\n        JSR DOUBLE", 1);
+    assert_eq!(warnings(&early, Check::Uninitialized), vec![]);
+}
+
+#[test]
+fn calls_and_traps_before_r7_is_saved() {
+    let source = "
+        .ORIG x3000
+        JSR PRINT
+        HALT
+PRINT   BRz SAVED
+        PUTS
+SAVED   ST R7, SAVE
+        JSR NEWLINE
+        LD R7, SAVE
+        RET
+SAVE    .BLKW 1
+NEWLINE LD R0, NL
+        OUT
+        RET
+NL      .FILL x0A
+        .END
+";
+    assert_eq!(
+        warnings(source, Check::UnsavedR7),
+        vec![
+            (0x3003, "subroutine PRINT runs PUTS before saving R7, its RET will come back here".to_string()),
+            (0x300A, "subroutine NEWLINE runs OUT before saving R7, its RET will come back here".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn stores_into_the_code_region() {
+    let source = "
+        .ORIG x3000
+        LEA R1, PATCH
+        LD R0, NOP
+        STR R0, R1, #0
+        ST R0, PATCH
+        STI R0, PTR
+        ST R0, DATA
+PATCH   AND R2, R2, #0
+        HALT
+NOP     .FILL x0000
+PTR     .FILL PATCH
+DATA    .BLKW 1
+        .END
+";
+    let message = "stores into x3006 (PATCH), which runs as code".to_string();
+    assert_eq!(
+        warnings(source, Check::StoreIntoCode),
+        vec![(0x3002, message.clone()), (0x3003, message.clone()), (0x3004, message.clone())]
+    );
+
+    let program = assemble(source).unwrap();
+    let cfg = Cfg::build(&program.image, 0x3000, &program.symbols);
+    let all = analyze(&cfg, &program.image, &program.symbols);
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0].to_string(), format!("x3002: {}", message));
+}
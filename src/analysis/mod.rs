@@ -0,0 +1,9 @@
+//! what can be said about a program without running it
+
+pub mod cfg;
+pub mod dataflow;
+
+#[cfg(test)]
+mod cfg_tests;
+#[cfg(test)]
+mod dataflow_tests;
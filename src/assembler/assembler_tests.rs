@@ -0,0 +1,534 @@
+use super::*;
+use crate::linker::{RelocationKind, Target};
+use crate::source_map::SourceMap;
+
+fn words(source: &str) -> Vec<u16> {
+    assemble(source).unwrap().image.words
+}
+
+fn error_line(source: &str) -> (usize, String) {
+    match assemble(source) {
+        Err(Error::Assemble { line, message, .. }) => (line, message),
+        other => panic!("expected an assembly error, got {:?}", other.map(|p| p.image)),
+    }
+}
+
+#[test]
+fn encodes_every_instruction_form() {
+    let source = "
+        .ORIG x3000
+        ADD R1, R2, R3
+        ADD R1, R2, #-1
+        AND R0, R0, #0
+        NOT R4, R5
+        BRnz LOOP
+LOOP    BR LOOP
+        JMP R2
+        RET
+        JSR LOOP
+        JSRR R3
+        LD R1, DATA
+        LDI R2, DATA
+        LDR R3, R4, #-2
+        LEA R5, DATA
+        ST R6, DATA
+        STI R7, DATA
+        STR R0, R6, #31
+        TRAP x25
+        RTI
+DATA    .FILL xBEEF
+        .END
+    ";
+    assert_eq!(
+        words(source),
+        vec![
+            0x1283, 0x12BF, 0x5020, 0x997F, 0x0C00, 0x0FFF, 0xC080, 0xC1C0, 0x4FFC, 0x40C0, 0x2208, 0xA407,
+            0x673E, 0xEA05, 0x3C04, 0xBE03, 0x719F, 0xF025, 0x8000, 0xBEEF,
+        ]
+    );
+}
+
+#[test]
+fn directives_and_trap_aliases() {
+    let program = assemble(
+        r#"
+        .orig x4000
+MSG     .STRINGZ "hi\n"
+BUF     .BLKW 2
+        .BLKW 1 #7
+        getc
+        OUT
+        PUTS
+        IN
+        PUTSP
+        HALT
+CHAR    .FILL 'a'
+PTR     .FILL MSG
+    "#,
+    )
+    .unwrap();
+    assert_eq!(program.image.origin, 0x4000);
+    assert_eq!(
+        program.image.words,
+        vec![b'h' as u16, b'i' as u16, 10, 0, 0, 0, 7, 0xF020, 0xF021, 0xF022, 0xF023, 0xF024, 0xF025, 97, 0x4000]
+    );
+    assert_eq!(program.symbols.address("BUF"), Some(0x4004));
+    assert_eq!(program.symbols.address("PTR"), Some(0x400E));
+}
+
+#[test]
+fn label_on_its_own_line_names_the_next_word() {
+    let program = assemble(".ORIG x3000\nSTART\n; comment\n  HALT\n.END").unwrap();
+    assert_eq!(program.symbols.address("START"), Some(0x3000));
+}
+
+#[test]
+fn errors_report_the_line() {
+    assert_eq!(error_line(".ORIG x3000\nADD R1, R2\n").0, 2);
+    assert_eq!(error_line(".ORIG x3000\nBR NOWHERE\n"), (2, "undefined label NOWHERE".to_string()));
+    assert_eq!(error_line(".ORIG x3000\nADD R1, R1, #16\n").0, 2);
+    assert_eq!(error_line(".ORIG x3000\nA HALT\nA HALT\n"), (3, "label A already defined on line 2".to_string()));
+    assert_eq!(error_line("HALT\n").1, "code before .ORIG");
+    assert_eq!(error_line(".ORIG x3000\nFOO R1\n").1, "unknown instruction R1");
+}
+
+#[test]
+fn offsets_out_of_range_are_rejected() {
+    let far = ".ORIG x3000\nBR FAR\n.BLKW 300\nFAR HALT\n";
+    let (line, message) = error_line(far);
+    assert_eq!(line, 2);
+    assert!(message.contains("9 bit"), "{}", message);
+}
+
+#[test]
+fn constants_and_expressions() {
+    let source = "
+SIZE    .EQU 4
+MASK    .EQU (1 << 4) - 1
+        .ORIG x3000
+        ADD R1, R1, SIZE - 5
+        AND R2, R2, MASK
+        LEA R0, TABLE + 2
+        LD R3, TABLE+SIZE-1
+        .FILL TABLE + SIZE * 2
+        .BLKW SIZE / 2
+TABLE   .BLKW SIZE
+        .FILL -1
+        .FILL 2 + 3 * 4
+        .FILL (2 + 3) * 4
+        .FILL ~0 & xFF
+        .FILL 1 << 15 | 1
+        .FILL -(3)
+        .FILL 7 % 4
+        .END
+    ";
+    assert_eq!(
+        words(source),
+        vec![
+            0x127F, 0x54AF, 0xE006, 0x2606, 0x300F, 0, 0, 0, 0, 0, 0, 0xFFFF, 14, 20, 255, 0x8001, 0xFFFD, 3
+        ]
+    );
+    assert_eq!(error_line(".ORIG x3000\n.FILL 1 / (2 - 2)\n").1, "division by zero in (#1 / (#2 - #2))");
+    assert_eq!(error_line("A .EQU 1\nA .EQU 2\n"), (2, "label A already defined on line 1".to_string()));
+}
+
+const STACK_MACROS: &str = r"
+        .MACRO PUSH reg
+        ADD R6, R6, #-1
+        STR \reg, R6, #0
+        .ENDM
+        .MACRO POP reg
+        LDR \reg, R6, #0
+        ADD R6, R6, #1
+        .ENDM
+";
+
+#[test]
+fn macros_expand_arguments_and_unique_labels() {
+    let source = format!(
+        r"{}
+        .MACRO WAIT count
+        LD R0, N\@
+L\@     ADD R0, R0, #-1
+        BRp L\@
+        BR E\@
+N\@     .FILL \count
+E\@
+        .ENDM
+
+        .ORIG x3000
+START   PUSH R1
+        pop R2
+        WAIT #3
+        WAIT 5
+        HALT
+        .END
+",
+        STACK_MACROS
+    );
+    let program = assemble(&source).unwrap();
+    assert_eq!(program.image.words[..4], [0x1DBF, 0x7380, 0x6580, 0x1DA1]);
+    assert_eq!(program.image.words[8], 3);
+    assert_eq!(program.image.words[13], 5);
+    assert_eq!(program.symbols.address("START"), Some(0x3000));
+    assert_eq!(program.symbols.address("L3"), Some(0x3005));
+    assert_eq!(program.symbols.address("L4"), Some(0x300A));
+    assert_eq!(program.symbols.address("E4"), Some(0x300E));
+
+    // the listing knows where expanded statements came from
+    let store = &program.lines[1];
+    assert_eq!((store.addr, store.text.trim()), (0x3001, r"STR R1, R6, #0"));
+    assert_eq!(store.location.line, 4);
+    assert_eq!(store.location.expansion[0].name, "PUSH");
+    assert_eq!(store.location.expansion[0].line, 21);
+    let mut listing = Vec::new();
+    program.write_listing(&mut listing).unwrap();
+    let listing = String::from_utf8(listing).unwrap();
+    assert!(
+        listing.contains("x3001  x7380  0111001110000000  line 4   STR R1, R6, #0  ; in PUSH expanded on line 21\n"),
+        "{}",
+        listing
+    );
+}
+
+#[test]
+fn macro_errors_point_at_the_body_and_the_invocation() {
+    let source = format!("{}\n.ORIG x3000\nPUSH R9\n", STACK_MACROS);
+    assert_eq!(
+        assemble(&source).unwrap_err().to_string(),
+        "line 4: expected a register, found R9, in PUSH expanded on line 12"
+    );
+    assert_eq!(error_line(&format!("{}\n.ORIG x3000\nPUSH R1, R2\n", STACK_MACROS)).1, "PUSH expects 1 argument, found 2");
+    let recursive = ".MACRO AGAIN\nAGAIN\n.ENDM\n.ORIG x3000\nAGAIN\n";
+    assert!(error_line(recursive).1.starts_with("macro expansion nested too deeply, is AGAIN recursive?"));
+    assert_eq!(error_line(".MACRO OPEN\nHALT\n"), (1, ".MACRO OPEN without .ENDM".to_string()));
+}
+
+#[test]
+fn conditional_assembly() {
+    let source = "
+DEBUG   .EQU 1
+        .ORIG x3000
+        .IF DEBUG
+        .IFDEF VERBOSE
+        .FILL 1
+        .ELSE
+        .FILL 2
+        .ENDIF
+        .ELSE
+        .FILL 3
+        .ENDIF
+        .IFNDEF DEBUG
+        not even valid ###
+        .ENDIF
+        .IF DEBUG - 1
+        .FILL 4
+        .ENDIF
+        .END
+    ";
+    assert_eq!(words(source), vec![2]);
+    assert_eq!(error_line(".ORIG x3000\n.ENDIF\n"), (2, ".ENDIF without .IF".to_string()));
+    assert_eq!(error_line(".ORIG x3000\n.IF 1\nHALT\n"), (2, ".IF without .ENDIF".to_string()));
+    assert_eq!(error_line(".ORIG x3000\n.IF UNKNOWN\n.ENDIF\n").1, "undefined label UNKNOWN");
+    assert_eq!(
+        error_line(".ORIG x3000\n.IF 1\nDONE .ENDIF\nHALT\n"),
+        (3, "a label cannot name .ENDIF, put it on a line of its own".to_string())
+    );
+    assert_eq!(error_line(".ORIG x3000\nSKIP .IF 0\n.ENDIF\n").1, "a label cannot name .IF, put it on a line of its own");
+}
+
+fn temp_dir() -> std::path::PathBuf {
+    let unique = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("rustvm-asm-{}", unique));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    dir
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let dir = temp_dir();
+    std::fs::write(dir.join("lib/stack.asm"), ".INCLUDE \"macros.asm\"\nSTACK_TOP .EQU xFE00\n").unwrap();
+    std::fs::write(dir.join("lib/macros.asm"), STACK_MACROS).unwrap();
+    std::fs::write(
+        dir.join("main.asm"),
+        ".INCLUDE \"lib/stack.asm\"\n.ORIG x3000\nLD R6, STACK\nPUSH R1\nHALT\nSTACK .FILL STACK_TOP\n.END\n",
+    )
+    .unwrap();
+
+    let program = assemble_file(&dir.join("main.asm")).unwrap();
+    assert_eq!(program.image.words, vec![0x2C03, 0x1DBF, 0x7380, 0xF025, 0xFE00]);
+    assert_eq!(program.lines[1].location.file, Some(dir.join("lib/macros.asm")));
+
+    std::fs::write(dir.join("lib/macros.asm"), ".INCLUDE \"stack.asm\"\n").unwrap();
+    let error = assemble_file(&dir.join("main.asm")).unwrap_err().to_string();
+    assert_eq!(error, format!("{}:1: recursive .INCLUDE of stack.asm", dir.join("lib/macros.asm").display()));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn listing_and_source_map_point_at_the_source() {
+    let source = "
+        .ORIG x3000
+MAIN    LEA R0, MSG
+        PUTS
+        HALT
+MSG     .STRINGZ \"hi\"
+        .BLKW 0
+        .END
+    ";
+    let program = assemble(source).unwrap();
+    let mut listing = Vec::new();
+    program.write_listing(&mut listing).unwrap();
+    assert_eq!(
+        String::from_utf8(listing).unwrap(),
+        "\
+x3000  xE002  1110000000000010  line 3  MAIN    LEA R0, MSG
+x3001  xF022  1111000000100010  line 4  PUTS
+x3002  xF025  1111000000100101  line 5  HALT
+x3003  x0068  0000000001101000  line 6  MSG     .STRINGZ \"hi\"
+x3004  x0069  0000000001101001
+x3005  x0000  0000000000000000
+x3006                           line 7  .BLKW 0
+"
+    );
+
+    let map = program.source_map();
+    assert_eq!(map.lookup(0x3001).map(|entry| entry.line), Some(4));
+    let string = map.lookup(0x3005).unwrap();
+    assert_eq!((string.address, string.line, string.data), (0x3003, 6, true));
+    assert_eq!(map.lookup(0x3006), None);
+    assert_eq!(map.lookup(0x2FFF), None);
+
+    let mut json = Vec::new();
+    map.write_json(&mut json).unwrap();
+    assert_eq!(SourceMap::read(json.as_slice()).unwrap(), map);
+}
+
+#[test]
+fn source_map_names_included_files() {
+    let dir = temp_dir();
+    std::fs::write(dir.join("lib/stack.asm"), STACK_MACROS).unwrap();
+    std::fs::write(dir.join("main.asm"), ".INCLUDE \"lib/stack.asm\"\n.ORIG x3000\nPUSH R1\nHALT\n.END\n").unwrap();
+
+    let map = assemble_file(&dir.join("main.asm")).unwrap().source_map();
+    let push = map.lookup(0x3001).unwrap();
+    assert_eq!(push.to_string(), format!("{}:4", dir.join("lib/stack.asm").display()));
+    assert_eq!(map.address("stack.asm", 4), Some(0x3001));
+    assert_eq!(map.address("main.asm", 4), Some(0x3002));
+    assert_eq!(map.address("main.asm", 3), None);
+    assert_eq!(map.address("lib/stack.asm", 4), Some(0x3001));
+    assert_eq!(map.address("ack.asm", 4), None);
+    assert_eq!(map.address("ain.asm", 4), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn objects_leave_outside_references_to_the_linker() {
+    let source = "
+        .EXTERN PRINT
+        .GLOBAL MAIN
+        .SECTION text
+MAIN    JSR PRINT
+        LEA R0, MSG
+        BR MAIN
+        .FILL MAIN + 2
+        .SECTION data
+MSG     .FILL PRINT - 1
+";
+    let object = assemble_object_in(source, Path::new("")).unwrap();
+    let text = &object.sections[0];
+    assert_eq!((text.name.as_str(), text.origin), ("text", None));
+    assert_eq!(text.words, vec![0x4800, 0xE000, 0x0FFD, 0]);
+    let relocations: Vec<_> = text
+        .relocations
+        .iter()
+        .map(|relocation| (relocation.offset, relocation.kind, relocation.target.clone(), relocation.addend))
+        .collect();
+    assert_eq!(
+        relocations,
+        vec![
+            (0, RelocationKind::Pc11, Target::Symbol("PRINT".to_string()), 0),
+            (1, RelocationKind::Pc9, Target::Section(1), 0),
+            (3, RelocationKind::Word, Target::Section(0), 2),
+        ]
+    );
+    assert_eq!(object.sections[1].relocations[0].addend, -1);
+    assert!(object.symbols.iter().any(|symbol| symbol.name == "MAIN" && symbol.global));
+    assert!(object.symbols.iter().any(|symbol| symbol.name == "MSG" && !symbol.global));
+
+    let error = |source: &str| assemble_object_in(source, Path::new("")).unwrap_err().to_string();
+    assert_eq!(
+        error(".EXTERN A, B\n.SECTION text\n.FILL A - B\n"),
+        "line 3: (A - B) refers to more than one label the linker places"
+    );
+    assert_eq!(
+        error(".EXTERN A\n.SECTION text\n.FILL A * 2\n"),
+        "line 3: (A * #2) cannot be relocated, use a label plus or minus a constant"
+    );
+    assert_eq!(
+        error(".GLOBAL NOWHERE\n.SECTION text\nHALT\n"),
+        "line 1: NOWHERE is declared .GLOBAL but is not a label of this file"
+    );
+    assert_eq!(
+        error(".SECTION text\n.SECTION text\n"),
+        "line 2: section text already started on line 1"
+    );
+}
+
+#[test]
+fn programs_cannot_refer_to_other_modules() {
+    assert_eq!(
+        error_line(".EXTERN PRINT\n.ORIG x3000\nJSR PRINT\n"),
+        (3, "PRINT is .EXTERN, the program has to be assembled as an object and linked".to_string())
+    );
+    assert_eq!(
+        error_line(".SECTION text\nHALT\n"),
+        (1, ".SECTION code has to be assembled as an object and linked".to_string())
+    );
+}
+
+#[test]
+fn definitions_are_found_in_files_that_do_not_assemble() {
+    let dir = temp_dir();
+    std::fs::write(dir.join("lib/stack.asm"), STACK_MACROS).unwrap();
+    let source = "
+        .INCLUDE \"lib/stack.asm\"
+SIZE    .EQU 4
+        .ORIG x3000
+LOOP    PUSH R1
+        BRz NOWHERE
+        ADD R1, R1, R9
+DONE    HALT
+";
+    let found: Vec<(String, DefinitionKind, Option<std::path::PathBuf>, usize)> = definitions(source, None, &dir)
+        .into_iter()
+        .map(|definition| (definition.name, definition.kind, definition.file, definition.line))
+        .collect();
+    let stack = Some(dir.join("lib/stack.asm"));
+    assert_eq!(
+        found,
+        vec![
+            ("PUSH".to_string(), DefinitionKind::Macro, stack.clone(), 2),
+            ("POP".to_string(), DefinitionKind::Macro, stack, 6),
+            ("SIZE".to_string(), DefinitionKind::Constant, None, 3),
+            ("LOOP".to_string(), DefinitionKind::Label, None, 5),
+            ("DONE".to_string(), DefinitionKind::Label, None, 8),
+        ]
+    );
+    assert!(mnemonics().contains(&"BRNZ"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn format_lines_up_columns() {
+    let source = "\
+; header stays put
+  .orig x3000
+main lea r0,MSG   ; comment after code
+  puts
+ brnz main
+VERYLONGLABEL add r1,r1,#1
+   ; indented comment
+MSG .stringz \"a; b,c\"
+        PUSH R1
+\t.end
+";
+    let dir = temp_dir();
+    std::fs::write(dir.join("lib/stack.asm"), STACK_MACROS).unwrap();
+    let source = format!(".INCLUDE \"lib/stack.asm\"\n{}", source);
+    let formatted = format(&source, &dir);
+    assert_eq!(
+        formatted,
+        "        .INCLUDE \"lib/stack.asm\"
+; header stays put
+        .ORIG   x3000
+main    LEA     r0, MSG                 ; comment after code
+        PUTS
+        BRnz    main
+VERYLONGLABEL ADD r1, r1, #1
+        ; indented comment
+MSG     .STRINGZ \"a; b,c\"
+        PUSH    R1
+        .END
+"
+    );
+    assert_eq!(format(&formatted, &dir), formatted);
+    assert_eq!(assemble_in(&formatted, &dir).unwrap().image.words, assemble_in(&source, &dir).unwrap().image.words);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn warnings(source: &str) -> Vec<(usize, String)> {
+    lint_in(source, Path::new(""))
+        .unwrap()
+        .into_iter()
+        .map(|warning| (warning.location.line, warning.message))
+        .collect()
+}
+
+#[test]
+fn lint_flags_likely_mistakes() {
+    let source = "
+        .ORIG x3000
+        JSR SUB
+        BRnzp DONE
+        ADD R1, R1, #1
+DONE    HALT
+        ADD R2, R2, #1
+SUB     JSR INNER
+        RET
+INNER   BR INNER
+        ADD R0, R0, #1
+        .FILL 5
+        .BLKW 200
+        LD R0, FAR
+        RET
+        .BLKW 245
+FAR     .FILL 0
+";
+    assert_eq!(
+        warnings(source),
+        vec![
+            (5, "unreachable, nothing branches here and line 4 never falls through".to_string()),
+            (7, "unreachable, nothing branches here and line 6 never falls through".to_string()),
+            (8, "subroutine SUB calls x3007 (INNER) before saving R7, its RET will come back here".to_string()),
+            (10, "BR without condition bits, write BRnzp to branch always".to_string()),
+            (11, "unreachable, nothing branches here and line 10 never falls through".to_string()),
+            (12, "data in the instruction stream, execution runs into it after line 11".to_string()),
+            (14, "PC offset 246 is within 16 words of the PCoffset9 limit (-256 to 255)".to_string()),
+            (17, "missing .END".to_string()),
+        ]
+    );
+
+    let clean = "
+        .ORIG x3000
+        JSR SUB
+        HALT
+SUB     ST R7, SAVE
+        JSR INNER
+        LD R7, SAVE
+        RET
+INNER   RET
+SAVE    .BLKW 1
+        .END
+";
+    assert_eq!(warnings(clean), vec![]);
+
+    // agrees with `rustvm analyze`: copying R7 saves it, a TRAP needs it saved
+    let copies = clean.replace("ST R7, SAVE", "ADD R5, R7, #0").replace("LD R7, SAVE", "ADD R7, R5, #0");
+    assert_eq!(warnings(&copies), vec![]);
+    let traps = clean.replace("ST R7, SAVE", "OUT");
+    assert_eq!(
+        warnings(&traps),
+        vec![
+            (5, "subroutine SUB runs OUT before saving R7, its RET will come back here".to_string()),
+            (6, "subroutine SUB calls x3006 (INNER) before saving R7, its RET will come back here".to_string()),
+        ]
+    );
+}
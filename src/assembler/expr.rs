@@ -0,0 +1,179 @@
+use super::lexer::Token;
+use std::fmt;
+
+/// an operand value: numbers and symbols combined with `+ - * / % << >> & | ^ ~`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i32),
+    /// a label, a `.EQU` constant or a register name
+    Symbol(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Str(String),
+    Expr(Expr),
+}
+
+/// binding strength of the binary operators, like in C
+fn precedence(op: &str) -> Option<u8> {
+    match op {
+        "|" => Some(1),
+        "^" => Some(2),
+        "&" => Some(3),
+        "<<" | ">>" => Some(4),
+        "+" | "-" => Some(5),
+        "*" | "/" | "%" => Some(6),
+        _ => None,
+    }
+}
+
+/// operands are separated by commas, or only by whitespace like `.BLKW 2 #7`, in which
+/// case an operator after an operand continues it: `.FILL 1 -2` is a single operand
+pub fn parse_operands(tokens: &[Token]) -> Result<Vec<Operand>, String> {
+    let mut parser = Parser { tokens, next: 0 };
+    let mut operands = Vec::new();
+    while let Some(token) = parser.peek() {
+        if *token == Token::Comma {
+            if operands.is_empty() {
+                return Err("expected an operand before ','".to_string());
+            }
+            parser.next += 1;
+            if parser.peek().is_none() {
+                return Err("expected an operand after ','".to_string());
+            }
+        }
+        let operand = match parser.peek() {
+            Some(Token::Str(text)) => {
+                let text = text.clone();
+                parser.next += 1;
+                Operand::Str(text)
+            }
+            _ => Operand::Expr(parser.expr(0)?),
+        };
+        operands.push(operand);
+    }
+    Ok(operands)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        self.next += 1;
+        self.tokens.get(self.next - 1)
+    }
+
+    /// operators binding tighter than `min` and their operands
+    fn expr(&mut self, min: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(&Token::Op(op)) = self.peek()
+            && let Some(strength) = precedence(op)
+            && strength > min
+        {
+            self.next += 1;
+            let right = self.expr(strength)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Number(value)) => Ok(Expr::Number(*value)),
+            Some(Token::Ident(name)) => Ok(Expr::Symbol(name.clone())),
+            Some(&Token::Op(op @ ("-" | "+" | "~"))) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+            Some(Token::Op("(")) => {
+                let inner = self.expr(0)?;
+                match self.advance() {
+                    Some(Token::Op(")")) => Ok(inner),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("expected a value, found {}", token)),
+            None => Err("expected a value at the end of the line".to_string()),
+        }
+    }
+}
+
+impl Expr {
+    /// the symbol when the expression is nothing else
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Expr::Symbol(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// whether a symbol `matches` accepts appears anywhere in the expression
+    pub fn mentions(&self, matches: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Symbol(name) => matches(name),
+            Expr::Unary(_, inner) => inner.mentions(matches),
+            Expr::Binary(_, left, right) => left.mentions(matches) || right.mentions(matches),
+        }
+    }
+
+    /// value with symbols resolved by `lookup`, arithmetic wraps like on a 32 bit machine
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<i32>) -> Result<i32, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name) => lookup(name).ok_or_else(|| format!("undefined label {}", name)),
+            Expr::Unary(op, inner) => {
+                let value = inner.eval(lookup)?;
+                Ok(match *op {
+                    "-" => value.wrapping_neg(),
+                    "~" => !value,
+                    _ => value,
+                })
+            }
+            Expr::Binary(op, left, right) => {
+                let (a, b) = (left.eval(lookup)?, right.eval(lookup)?);
+                let shift = || u32::try_from(b).ok().filter(|&b| b < 32).ok_or(format!("cannot shift by {}", b));
+                Ok(match *op {
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    "/" | "%" if b == 0 => return Err(format!("division by zero in {}", self)),
+                    "/" => a.wrapping_div(b),
+                    "%" => a.wrapping_rem(b),
+                    "<<" => a << shift()?,
+                    ">>" => a >> shift()?,
+                    "&" => a & b,
+                    "^" => a ^ b,
+                    _ => a | b,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "#{}", value),
+            Expr::Symbol(name) => write!(f, "{}", name),
+            Expr::Unary(op, inner) => write!(f, "{}{}", op, inner),
+            Expr::Binary(op, left, right) => write!(f, "({} {} {})", left, op, right),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Str(text) => write!(f, "\"{}\"", text),
+            Operand::Expr(expr) => write!(f, "{}", expr),
+        }
+    }
+}
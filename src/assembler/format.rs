@@ -0,0 +1,98 @@
+//! `rustvm fmt`: labels in the first column, operations and operands in columns of their
+//! own and comments lined up after the code
+
+use super::preprocess::split_outside_quotes;
+use super::{DefinitionKind, branch_condition, definitions, is_operation};
+use std::collections::HashSet;
+use std::path::Path;
+
+const OPERATION_COLUMN: usize = 8;
+const OPERAND_COLUMN: usize = 16;
+/// for comments after code, a comment on a line of its own stays at the start of the
+/// line or goes to the operation column
+const COMMENT_COLUMN: usize = 40;
+
+/// `source` laid out canonically. operations are upper case with the flags of BR in
+/// lower case, operands are separated by `, `. macros defined in included files are looked
+/// up relative to `dir`, so their invocations are not taken for labels
+pub fn format(source: &str, dir: &Path) -> String {
+    let macros: HashSet<String> = definitions(source, None, dir)
+        .into_iter()
+        .filter(|definition| definition.kind == DefinitionKind::Macro)
+        .map(|definition| definition.name.to_ascii_uppercase())
+        .collect();
+    let mut formatted = String::new();
+    for line in source.lines() {
+        formatted.push_str(&format_line(line, &macros));
+        formatted.push('\n');
+    }
+    formatted
+}
+
+fn format_line(line: &str, macros: &HashSet<String>) -> String {
+    let code = split_outside_quotes(line, ';').swap_remove(0);
+    let comment = line[code.len()..].trim_end();
+    let (first, rest) = split_word(code.trim());
+
+    let (label, operation, operands) = if first.is_empty() || is_operation(first) || macros.contains(&first.to_ascii_uppercase()) {
+        ("", first, rest)
+    } else {
+        let (operation, operands) = split_word(rest);
+        (first, operation, operands)
+    };
+
+    let mut text = label.to_string();
+    if !operation.is_empty() {
+        pad(&mut text, OPERATION_COLUMN);
+        text.push_str(&canonical_operation(operation));
+        if !operands.is_empty() {
+            pad(&mut text, OPERAND_COLUMN);
+            if split_outside_quotes(operands, ',').len() > 1 {
+                let operands: Vec<String> =
+                    split_outside_quotes(operands, ',').iter().map(|operand| operand.trim().to_string()).collect();
+                text.push_str(&operands.join(", "));
+            } else {
+                text.push_str(operands);
+            }
+        }
+    }
+    if !comment.is_empty() {
+        if !text.is_empty() {
+            pad(&mut text, COMMENT_COLUMN);
+        } else if !line.starts_with(';') {
+            pad(&mut text, OPERATION_COLUMN);
+        }
+        text.push_str(comment);
+    }
+    text
+}
+
+/// the first word of `text` and the rest, trimmed
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// spaces up to `column`, or a single one when the text already reaches it
+fn pad(text: &mut String, column: usize) {
+    let width = text.chars().count();
+    if width < column {
+        text.extend(std::iter::repeat_n(' ', column - width));
+    } else {
+        text.push(' ');
+    }
+}
+
+fn canonical_operation(operation: &str) -> String {
+    let upper = operation.to_ascii_uppercase();
+    if branch_condition(&upper).is_some() {
+        format!("BR{}", upper[2..].to_ascii_lowercase())
+    } else if is_operation(operation) {
+        upper
+    } else {
+        // a macro, as it was written
+        operation.to_string()
+    }
+}
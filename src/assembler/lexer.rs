@@ -0,0 +1,159 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// labels, opcodes, directives and register names
+    Ident(String),
+    Number(i32),
+    Str(String),
+    Comma,
+    /// expression operators and parentheses
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 13] = ["<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")"];
+
+/// tokens of a single source line, comments are dropped
+pub fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if let Some(op) = OPERATORS.iter().find(|op| line[start..].starts_with(*op)) {
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push(Token::Op(op));
+            continue;
+        }
+        match c {
+            ';' => break,
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Str(string_literal(&mut chars)?));
+            }
+            '\'' => {
+                chars.next();
+                let c = match chars.next() {
+                    Some((_, '\\')) => escape(chars.next().map(|(_, c)| c))?,
+                    Some((_, c)) => c,
+                    None => return Err("unterminated character literal".to_string()),
+                };
+                if !matches!(chars.next(), Some((_, '\''))) {
+                    return Err("unterminated character literal".to_string());
+                }
+                tokens.push(Token::Number(c as i32));
+            }
+            _ => {
+                let mut end = line.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    // the sign of `#-3` or `x-1F` belongs to the number
+                    let sign = c == '-' && matches!(&line[start..i], "#" | "x" | "X" | "b" | "B" | "0x" | "0X");
+                    let operator = !sign && "+-*/%&|^~()<>".contains(c);
+                    if c.is_whitespace() || c == ',' || c == ';' || c == '"' || c == '\'' || operator {
+                        end = i;
+                        break;
+                    }
+                    chars.next();
+                }
+                if end == start {
+                    return Err(format!("unexpected character '{}'", c));
+                }
+                tokens.push(word_token(&line[start..end])?);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn string_literal(chars: &mut impl Iterator<Item = (usize, char)>) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some((_, '"')) => return Ok(value),
+            Some((_, '\\')) => value.push(escape(chars.next().map(|(_, c)| c))?),
+            Some((_, c)) => value.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn escape(c: Option<char>) -> Result<char, String> {
+    match c {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('0') => Ok('\0'),
+        Some('e') => Ok('\x1b'),
+        Some(c @ ('\\' | '"' | '\'')) => Ok(c),
+        Some(c) => Err(format!("unknown escape '\\{}'", c)),
+        None => Err("unterminated escape".to_string()),
+    }
+}
+
+/// numbers are `#12`, `#-3`, `12`, `x3000`, `0x3000` and `b101`, anything else is an identifier
+fn word_token(word: &str) -> Result<Token, String> {
+    if let Some(number) = parse_number(word) {
+        return number.map(Token::Number);
+    }
+    Ok(Token::Ident(word.to_string()))
+}
+
+/// none when `word` does not look like a number at all
+pub fn parse_number(word: &str) -> Option<Result<i32, String>> {
+    let invalid = || format!("invalid number '{}'", word);
+    let (digits, radix) = if let Some(rest) = word.strip_prefix('#') {
+        (rest, 10)
+    } else if let Some(rest) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        (rest, 16)
+    } else if let Some(rest) = word.strip_prefix(['x', 'X']) {
+        // x followed by anything but hex digits is a label like `xloop`
+        let hex = rest.strip_prefix('-').unwrap_or(rest);
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        (rest, 16)
+    } else if let Some(rest) = word.strip_prefix(['b', 'B']) {
+        let bits = rest.strip_prefix('-').unwrap_or(rest);
+        if bits.is_empty() || !bits.chars().all(|c| c == '0' || c == '1') {
+            return None;
+        }
+        (rest, 2)
+    } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        (word, 10)
+    } else {
+        return None;
+    };
+
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits),
+    };
+    let value = i32::from_str_radix(digits, radix).map_err(|_| invalid());
+    Some(value.map(|v| if negative { -v } else { v }))
+}
+
+/// `word` as an unsigned 16 bit value, for addresses and values given on the command line
+/// or to the debugger
+pub fn parse_word(word: &str) -> Option<u16> {
+    u16::try_from(parse_number(word)?.ok()?).ok()
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Number(value) => write!(f, "#{}", value),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::Comma => write!(f, "','"),
+            Token::Op(op) => write!(f, "'{}'", op),
+        }
+    }
+}
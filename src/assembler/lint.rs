@@ -0,0 +1,164 @@
+//! `rustvm lint`: mistakes the assembler accepts but that rarely do what was meant
+
+use super::lexer::{Token, tokenize};
+use super::{Location, Program, SourceLine, assemble_file, assemble_in};
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dataflow::unsaved_r7;
+use crate::error::Result;
+use crate::hardware::decode::{Instruction, decode};
+use std::fmt;
+use std::path::Path;
+
+/// PC offsets this close to the end of their range break as soon as a few more words go
+/// in between
+const NEAR_LIMIT: i32 = 16;
+
+/// a statement that assembles but looks wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    /// `prog.asm:12: message`, with the macro invocations that led to the line like errors
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.location.error(self.message.as_str()))
+    }
+}
+
+pub fn lint_file(path: &Path) -> Result<Vec<Warning>> {
+    let source = std::fs::read_to_string(path)?;
+    Ok(lint(&assemble_file(path)?, &source, Some(path)))
+}
+
+/// warnings for source that is not in a file, `.INCLUDE` paths are relative to `dir`
+pub fn lint_in(source: &str, dir: &Path) -> Result<Vec<Warning>> {
+    Ok(lint(&assemble_in(source, dir)?, source, None))
+}
+
+/// code nothing jumps to, data execution runs into, PC offsets about to go out of range,
+/// BR without condition bits, subroutines that call or TRAP before saving R7 and a
+/// missing `.END`
+fn lint(program: &Program, source: &str, file: Option<&Path>) -> Vec<Warning> {
+    let statements: Vec<(&SourceLine, u16)> = program
+        .lines
+        .iter()
+        .filter(|line| line.size > 0)
+        .filter_map(|line| Some((line, *program.image.words.get(line.addr.wrapping_sub(program.image.origin) as usize)?)))
+        .collect();
+    let mut warnings = Vec::new();
+    let mut warn = |line: &SourceLine, message: String| {
+        warnings.push(Warning {
+            location: line.location.clone(),
+            message,
+        })
+    };
+
+    let mut previous: Option<(&SourceLine, u16)> = None;
+    for &(line, word) in &statements {
+        let falls_through = previous.is_none_or(|(previous, word)| !previous.data && !ends_flow(word));
+        if line.data && falls_through {
+            warn(
+                line,
+                match previous {
+                    Some((previous, _)) => format!(
+                        "data in the instruction stream, execution runs into it after line {}",
+                        previous.location.line
+                    ),
+                    None => "data in the instruction stream, execution starts here".to_string(),
+                },
+            );
+        }
+        if !line.data
+            && let Some((previous, previous_word)) = previous
+            && !previous.data
+            && ends_flow(previous_word)
+            && program.symbols.name(line.addr).is_none()
+        {
+            warn(
+                line,
+                format!("unreachable, nothing branches here and line {} never falls through", previous.location.line),
+            );
+        }
+        if !line.data {
+            if let Some((bits, offset)) = pc_offset(word) {
+                let limit = 1 << (bits - 1);
+                if offset < -limit + NEAR_LIMIT || offset >= limit - NEAR_LIMIT {
+                    warn(
+                        line,
+                        format!(
+                            "PC offset {} is within {} words of the PCoffset{} limit ({} to {})",
+                            offset,
+                            NEAR_LIMIT,
+                            bits,
+                            -limit,
+                            limit - 1
+                        ),
+                    );
+                }
+            }
+            if operation(&line.text).as_deref() == Some("BR") {
+                warn(line, "BR without condition bits, write BRnzp to branch always".to_string());
+            }
+        }
+        previous = Some((line, word));
+    }
+
+    // the same check `rustvm analyze` runs, over the control-flow graph from the origin
+    let cfg = Cfg::build(&program.image, program.image.origin, &program.symbols);
+    for warning in unsaved_r7(&cfg, &program.symbols) {
+        if let Some(&(line, _)) = statements.iter().find(|(line, _)| line.addr == warning.address) {
+            warn(line, warning.message);
+        }
+    }
+    warnings.sort_by(|a, b| (&a.location.file, a.location.line).cmp(&(&b.location.file, b.location.line)));
+    if !has_end(source) {
+        warnings.push(Warning {
+            location: Location {
+                file: file.map(Path::to_path_buf),
+                line: source.lines().count(),
+                expansion: Vec::new(),
+            },
+            message: "missing .END".to_string(),
+        });
+    }
+    warnings
+}
+
+/// HALT, BRnzp, JMP, RET and RTI never go on to the next word
+fn ends_flow(word: u16) -> bool {
+    match decode(word) {
+        Instruction::Br { nzp, .. } => nzp == 0b111,
+        Instruction::Jmp { .. } | Instruction::Rti => true,
+        Instruction::Trap { vector } => vector == 0x25,
+        _ => false,
+    }
+}
+
+/// field width and offset of a pc relative instruction
+fn pc_offset(word: u16) -> Option<(u32, i32)> {
+    match decode(word) {
+        Instruction::Jsr { offset } => Some((11, offset as i16 as i32)),
+        Instruction::Br { offset, .. }
+        | Instruction::Ld { offset, .. }
+        | Instruction::Ldi { offset, .. }
+        | Instruction::Lea { offset, .. }
+        | Instruction::St { offset, .. }
+        | Instruction::Sti { offset, .. } => Some((9, offset as i16 as i32)),
+        _ => None,
+    }
+}
+
+/// upper case operation of a statement, after its label
+fn operation(text: &str) -> Option<String> {
+    let tokens = tokenize(text).ok()?;
+    tokens.iter().take(2).find_map(|token| match token {
+        Token::Ident(name) if super::is_operation(name) => Some(name.to_ascii_uppercase()),
+        _ => None,
+    })
+}
+
+fn has_end(source: &str) -> bool {
+    source.lines().any(|line| operation(line).as_deref() == Some(".END"))
+}
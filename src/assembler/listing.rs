@@ -0,0 +1,56 @@
+use super::{Program, SourceLine};
+use crate::source_map::{SourceEntry, SourceMap};
+use std::io::{self, Write};
+
+impl SourceLine {
+    pub(super) fn entry(&self) -> SourceEntry {
+        SourceEntry {
+            address: self.addr,
+            words: self.size,
+            file: self.location.file.as_ref().map(|file| file.display().to_string()),
+            line: self.location.line,
+            text: self.text.trim().to_string(),
+            data: self.data,
+        }
+    }
+}
+
+impl Program {
+    /// file and line of every statement that produced words
+    pub fn source_map(&self) -> SourceMap {
+        let mut map = SourceMap::new();
+        for line in self.lines.iter().filter(|line| line.size > 0) {
+            map.insert(line.entry());
+        }
+        map
+    }
+
+    /// one line per word: address, word in hex and binary, and for the first word of
+    /// every statement where it was written and its text. statements from a macro end
+    /// with the invocations that expanded them
+    pub fn write_listing<W: Write>(&self, mut output: W) -> io::Result<()> {
+        let entries: Vec<SourceEntry> = self.lines.iter().map(SourceLine::entry).collect();
+        let places: Vec<String> = entries.iter().map(SourceEntry::to_string).collect();
+        let width = places.iter().map(String::len).max().unwrap_or(0);
+        let mut words = self.image.words.iter();
+        for ((entry, place), line) in entries.iter().zip(&places).zip(&self.lines) {
+            let text = if line.location.expansion.is_empty() {
+                entry.text.clone()
+            } else {
+                format!("{}  ; {}", entry.text, line.location.expansion_trace())
+            };
+            if entry.words == 0 {
+                writeln!(output, "x{:04X}  {:25}{:width$}  {}", entry.address, "", place, text)?;
+            }
+            for (offset, &word) in words.by_ref().take(entry.words).enumerate() {
+                let addr = entry.address.wrapping_add(offset as u16);
+                if offset == 0 {
+                    writeln!(output, "x{:04X}  x{:04X}  {:016b}  {:width$}  {}", addr, word, word, place, text)?;
+                } else {
+                    writeln!(output, "x{:04X}  x{:04X}  {:016b}", addr, word, word)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,914 @@
+use crate::error::{Error, Result};
+use crate::hardware::isa::Isa;
+use crate::linker::{Object, ObjectSymbol, Relocation, RelocationKind, Section, Target};
+use crate::loader::Image;
+use crate::symbols::SymbolTable;
+use expr::{Expr, Operand, parse_operands};
+use lexer::{Token, tokenize};
+use preprocess::{Macro, Reader, macro_arguments};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub use format::format;
+pub use lexer::{parse_number, parse_word};
+pub use lint::{Warning, lint_file, lint_in};
+pub use outline::{Definition, DefinitionKind, definitions, mnemonics};
+pub use preprocess::{Location, MacroCall};
+
+mod expr;
+mod format;
+mod lexer;
+mod lint;
+mod listing;
+mod outline;
+mod preprocess;
+
+#[cfg(test)]
+mod assembler_tests;
+
+/// output of a successful assembly
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub image: Image,
+    pub symbols: SymbolTable,
+    /// every statement in address order, with macro expansions and included files inlined
+    pub lines: Vec<SourceLine>,
+}
+
+/// a statement of the program and where it came from
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub addr: u16,
+    /// number of words it assembled to
+    pub size: usize,
+    /// after macro arguments were substituted
+    pub text: String,
+    pub location: Location,
+    /// `.FILL`, `.BLKW` or `.STRINGZ`
+    pub data: bool,
+}
+
+/// a line with an operation, after labels were split off
+struct Statement {
+    location: Location,
+    text: String,
+    section: usize,
+    /// relative to the start of the section for a `.SECTION`
+    addr: u16,
+    size: u32,
+    operation: String,
+    operands: Vec<Operand>,
+}
+
+pub fn assemble_file(path: &Path) -> Result<Program> {
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    finish(first_pass(Reader::new(&source, Some(path), dir))?)
+}
+
+/// assembles a module to be linked with others, see `crate::linker`
+pub fn assemble_object_file(path: &Path) -> Result<Object> {
+    let source = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    finish_object(first_pass(Reader::new(&source, Some(path), dir))?, Some(path))
+}
+
+/// an object from source that is not in a file, `.INCLUDE` paths are relative to `dir`
+pub fn assemble_object_in(source: &str, dir: &Path) -> Result<Object> {
+    finish_object(first_pass(Reader::new(source, None, dir))?, None)
+}
+
+/// assembles source that is not in a file, `.INCLUDE` paths are relative to `dir`
+pub fn assemble_in(source: &str, dir: &Path) -> Result<Program> {
+    finish(first_pass(Reader::new(source, None, dir))?)
+}
+
+/// two pass assembler for the lc3as dialect: one `.ORIG` block, labels, `.FILL`, `.BLKW`,
+/// `.STRINGZ`, the trap aliases and `#`, `x` and `b` number literals. on top of that
+/// `.INCLUDE "file"`, `.MACRO`/`.ENDM`, `NAME .EQU value`, `.IF`/`.IFDEF`/`.IFNDEF` with
+/// `.ELSE` and `.ENDIF`, and expressions wherever a value goes. objects can also have
+/// `.SECTION name` blocks the linker places and `.GLOBAL` and `.EXTERN` labels
+pub fn assemble(source: &str) -> Result<Program> {
+    assemble_in(source, Path::new(""))
+}
+
+/// encodes every statement into the section it is in, references the linker has to
+/// resolve become relocations when `relocatable`, errors otherwise
+fn encode(pass: &FirstPass, relocatable: bool) -> Result<(Vec<Section>, Vec<SourceLine>)> {
+    let mut sections: Vec<Section> = pass
+        .sections
+        .iter()
+        .map(|section| Section {
+            name: section.name.clone(),
+            origin: section.origin,
+            words: Vec::new(),
+            relocations: Vec::new(),
+            lines: Vec::new(),
+        })
+        .collect();
+    let mut lines = Vec::new();
+    for statement in &pass.statements {
+        let encoder = Encoder {
+            statement,
+            symbols: &pass.symbols,
+            sections: &pass.sections,
+            relocatable,
+            relocation: RefCell::new(None),
+        };
+        let section = &mut sections[statement.section];
+        let start = section.words.len();
+        encoder.encode(&mut section.words)?;
+        if let Some((kind, target, addend)) = encoder.relocation.take() {
+            for offset in start..section.words.len() {
+                section.relocations.push(Relocation {
+                    offset: offset as u16,
+                    kind,
+                    target: target.clone(),
+                    addend,
+                    file: statement.location.file.as_ref().map(|file| file.display().to_string()),
+                    line: statement.location.line,
+                });
+            }
+        }
+        let line = SourceLine {
+            addr: statement.addr,
+            size: statement.size as usize,
+            text: statement.text.clone(),
+            location: statement.location.clone(),
+            data: statement.operation.starts_with('.'),
+        };
+        if line.size > 0 {
+            let mut entry = line.entry();
+            entry.address = line.addr.wrapping_sub(section.origin.unwrap_or(0));
+            section.lines.push(entry);
+        }
+        lines.push(line);
+    }
+    Ok((sections, lines))
+}
+
+/// an absolute program: a single `.ORIG` block that refers to nothing outside of it
+fn finish(pass: FirstPass) -> Result<Program> {
+    if let Some(section) = pass.sections.iter().find(|section| section.origin.is_none()) {
+        return Err(section
+            .location
+            .error(".SECTION code has to be assembled as an object and linked"));
+    }
+    let (mut sections, lines) = encode(&pass, false)?;
+    let Some(Section {
+        origin: Some(origin),
+        words,
+        ..
+    }) = sections.pop()
+    else {
+        return Err(Error::Assemble {
+            file: None,
+            line: 0,
+            message: "missing .ORIG".to_string(),
+        });
+    };
+
+    let mut symbols = SymbolTable::new();
+    for (name, label) in &pass.symbols.labels {
+        symbols.insert(name, label.addr);
+    }
+    Ok(Program {
+        image: Image { origin, words, isa: Isa::Lc3 },
+        symbols,
+        lines,
+    })
+}
+
+fn finish_object(pass: FirstPass, source: Option<&Path>) -> Result<Object> {
+    let mut globals = HashMap::new();
+    for (name, location) in &pass.globals {
+        if !pass.symbols.labels.contains_key(name) {
+            return Err(location.error(format!("{} is declared .GLOBAL but is not a label of this file", name)));
+        }
+        globals.insert(name.as_str(), location);
+    }
+    let (sections, _) = encode(&pass, true)?;
+
+    let mut symbols: Vec<ObjectSymbol> = pass
+        .symbols
+        .labels
+        .iter()
+        .map(|(name, label)| ObjectSymbol {
+            name: name.clone(),
+            section: label.section,
+            offset: label.addr.wrapping_sub(sections[label.section].origin.unwrap_or(0)),
+            global: globals.contains_key(name.as_str()),
+        })
+        .collect();
+    symbols.sort_by(|a, b| (a.section, a.offset, &a.name).cmp(&(b.section, b.offset, &b.name)));
+    let mut imports: Vec<String> = pass.symbols.externs.keys().cloned().collect();
+    imports.sort();
+    Ok(Object {
+        source: source.map(|path| path.display().to_string()),
+        sections,
+        symbols,
+        imports,
+    })
+}
+
+struct Label {
+    /// relative to the start of the section for a `.SECTION`
+    addr: u16,
+    section: usize,
+    location: Location,
+}
+
+/// labels, `.EQU` constants and `.EXTERN` names with where they were defined
+#[derive(Default)]
+struct Symbols {
+    labels: HashMap<String, Label>,
+    constants: HashMap<String, (i32, Location)>,
+    externs: HashMap<String, Location>,
+}
+
+impl Symbols {
+    fn value(&self, name: &str) -> Option<i32> {
+        self.constants
+            .get(name)
+            .map(|(value, _)| *value)
+            .or_else(|| self.labels.get(name).map(|label| label.addr as i32))
+    }
+
+    fn defined(&self, name: &str) -> Option<&Location> {
+        self.constants
+            .get(name)
+            .map(|(_, location)| location)
+            .or_else(|| self.labels.get(name).map(|label| &label.location))
+            .or_else(|| self.externs.get(name))
+    }
+
+    fn eval(&self, expr: &Expr) -> std::result::Result<i32, String> {
+        expr.eval(&|name| self.value(name))
+    }
+}
+
+/// an `.IF` block being assembled
+struct Conditional {
+    location: Location,
+    /// whether the enclosing block is assembled
+    outer: bool,
+    /// whether a branch was already assembled
+    taken: bool,
+    active: bool,
+    seen_else: bool,
+}
+
+/// a `.ORIG` block or a `.SECTION`
+struct SectionStart {
+    name: String,
+    /// none for a `.SECTION`, which the linker places
+    origin: Option<u16>,
+    location: Location,
+}
+
+/// state of the pass that assigns an address to every statement and label
+struct FirstPass {
+    reader: Reader,
+    /// the last one is the one being assembled
+    sections: Vec<SectionStart>,
+    /// address in the current section
+    addr: u32,
+    statements: Vec<Statement>,
+    symbols: Symbols,
+    /// by upper case name
+    macros: HashMap<String, Macro>,
+    conditionals: Vec<Conditional>,
+    /// names declared `.GLOBAL`
+    globals: Vec<(String, Location)>,
+}
+
+fn first_pass(reader: Reader) -> Result<FirstPass> {
+    let mut pass = FirstPass {
+        reader,
+        sections: Vec::new(),
+        addr: 0,
+        statements: Vec::new(),
+        symbols: Symbols::default(),
+        macros: HashMap::new(),
+        conditionals: Vec::new(),
+        globals: Vec::new(),
+    };
+    while let Some((text, location)) = pass.reader.next_line() {
+        if !pass.line(&text, &location)? {
+            break;
+        }
+    }
+
+    if let Some(open) = pass.conditionals.last() {
+        return Err(open.location.error(".IF without .ENDIF"));
+    }
+    if pass.addr > u16::MAX as u32 + 1 {
+        let location = pass.statements.last().map(|s| s.location.clone());
+        return Err(location
+            .unwrap_or(Location {
+                file: None,
+                line: 0,
+                expansion: Vec::new(),
+            })
+            .error("program runs past the end of memory"));
+    }
+    Ok(pass)
+}
+
+impl FirstPass {
+    fn active(&self) -> bool {
+        self.conditionals.last().is_none_or(|conditional| conditional.active)
+    }
+
+    fn is_operation(&self, word: &str) -> bool {
+        is_operation(word) || self.macros.contains_key(&word.to_ascii_uppercase())
+    }
+
+    /// handles one line, false once `.END` is reached
+    fn line(&mut self, text: &str, location: &Location) -> Result<bool> {
+        let error = |message: String| location.error(message);
+        let tokens = match tokenize(text) {
+            Ok(tokens) => tokens,
+            Err(message) if self.active() => return Err(error(message)),
+            // skipped lines do not have to be valid
+            Err(_) => return Ok(true),
+        };
+
+        let mut rest = tokens.as_slice();
+        let mut label = None;
+        if let [Token::Ident(name), after @ ..] = rest
+            && !self.is_operation(name)
+        {
+            label = Some(name.clone());
+            rest = after;
+        }
+        let operation = match rest.split_first() {
+            Some((Token::Ident(operation), after)) => {
+                rest = after;
+                Some(operation.to_ascii_uppercase())
+            }
+            Some((token, _)) if self.active() => return Err(error(format!("expected an instruction, found {}", token))),
+            Some(_) => return Ok(true),
+            None => None,
+        };
+
+        // conditionals nest even inside blocks that are skipped
+        if let (Some(operation), Some(_)) = (&operation, &label)
+            && matches!(operation.as_str(), ".IF" | ".IFDEF" | ".IFNDEF" | ".ELSE" | ".ENDIF")
+        {
+            return Err(error(format!("a label cannot name {}, put it on a line of its own", operation)));
+        }
+        if let Some(operation) = &operation
+            && self.conditional(operation, rest, location)?
+        {
+            return Ok(true);
+        }
+        if !self.active() {
+            return Ok(true);
+        }
+        let operands = parse_operands(rest).map_err(error)?;
+
+        match operation.as_deref() {
+            Some(".ORIG") => {
+                if self.sections.iter().any(|section| section.origin.is_some()) {
+                    return Err(error("only one .ORIG block is supported".to_string()));
+                }
+                if label.is_some() {
+                    return Err(error("a label cannot name .ORIG".to_string()));
+                }
+                let value = match operands.as_slice() {
+                    [Operand::Expr(expr)] => self.symbols.eval(expr).map_err(error)?,
+                    _ => return Err(error(".ORIG expects an address".to_string())),
+                };
+                let start = u16::try_from(value).map_err(|_| error(format!("invalid .ORIG address {}", value)))?;
+                self.start_section(".ORIG".to_string(), Some(start), location)?;
+                return Ok(true);
+            }
+            Some(".SECTION") => {
+                if label.is_some() {
+                    return Err(error("a label cannot name .SECTION, the name goes after it".to_string()));
+                }
+                let name = match operands.as_slice() {
+                    [Operand::Str(name)] => name.clone(),
+                    [Operand::Expr(Expr::Symbol(name))] => name.clone(),
+                    _ => return Err(error(".SECTION expects a name".to_string())),
+                };
+                if let Some(first) = self.sections.iter().find(|section| section.name == name) {
+                    return Err(error(format!("section {} already started on {}", name, first.location.place())));
+                }
+                self.start_section(name, None, location)?;
+                return Ok(true);
+            }
+            Some(operation @ (".GLOBAL" | ".EXTERN")) => {
+                if label.is_some() {
+                    return Err(error(format!("a label cannot name {}, the names go after it", operation)));
+                }
+                if operands.is_empty() {
+                    return Err(error(format!("{} expects at least one name", operation)));
+                }
+                for operand in operands {
+                    let Operand::Expr(Expr::Symbol(name)) = operand else {
+                        return Err(error(format!("expected a name, found {}", operand)));
+                    };
+                    if operation == ".GLOBAL" {
+                        self.globals.push((name, location.clone()));
+                    } else {
+                        self.check_unique(&name, location)?;
+                        self.symbols.externs.insert(name, location.clone());
+                    }
+                }
+                return Ok(true);
+            }
+            Some(".END") => return Ok(false),
+            Some(".EQU") => {
+                let Some(name) = label else {
+                    return Err(error(".EQU needs a name in front of it".to_string()));
+                };
+                let value = match operands.as_slice() {
+                    [Operand::Expr(expr)] => self.symbols.eval(expr).map_err(error)?,
+                    _ => return Err(error(".EQU expects a value".to_string())),
+                };
+                self.check_unique(&name, location)?;
+                self.symbols.constants.insert(name, (value, location.clone()));
+                return Ok(true);
+            }
+            Some(".INCLUDE") => {
+                let [Operand::Str(path)] = operands.as_slice() else {
+                    return Err(error(".INCLUDE expects a file name in quotes".to_string()));
+                };
+                if label.is_some() {
+                    return Err(error("a label cannot name .INCLUDE".to_string()));
+                }
+                self.reader.include(path, location)?;
+                return Ok(true);
+            }
+            Some(".MACRO") => {
+                self.define_macro(label, operands, location)?;
+                return Ok(true);
+            }
+            Some(".ENDM") => return Err(error(".ENDM without .MACRO".to_string())),
+            _ => {}
+        }
+
+        if self.sections.is_empty() && (label.is_some() || operation.is_some()) {
+            return Err(error("code before .ORIG".to_string()));
+        }
+        if self.addr > u16::MAX as u32 + 1 {
+            return Err(error("program runs past the end of memory".to_string()));
+        }
+        if let Some(name) = label {
+            self.check_unique(&name, location)?;
+            let label = Label {
+                addr: self.addr as u16,
+                section: self.sections.len() - 1,
+                location: location.clone(),
+            };
+            self.symbols.labels.insert(name, label);
+        }
+        let Some(operation) = operation else {
+            return Ok(true);
+        };
+
+        if let Some(definition) = self.macros.get(&operation) {
+            let skip = if label_present(&tokens, rest) { 2 } else { 1 };
+            self.reader.expand(definition, macro_arguments(text, skip), location)?;
+            return Ok(true);
+        }
+
+        let size = statement_size(&operation, &operands, &self.symbols).map_err(error)?;
+        self.statements.push(Statement {
+            location: location.clone(),
+            text: text.to_string(),
+            section: self.sections.len() - 1,
+            addr: self.addr as u16,
+            size,
+            operation,
+            operands,
+        });
+        self.addr += size;
+        Ok(true)
+    }
+
+    /// continues at `origin`, or at 0 of a section the linker places
+    fn start_section(&mut self, name: String, origin: Option<u16>, location: &Location) -> Result<()> {
+        if self.addr > u16::MAX as u32 + 1 {
+            return Err(location.error("the previous section runs past the end of memory"));
+        }
+        self.sections.push(SectionStart {
+            name,
+            origin,
+            location: location.clone(),
+        });
+        self.addr = origin.unwrap_or(0) as u32;
+        Ok(())
+    }
+
+    fn check_unique(&self, name: &str, location: &Location) -> Result<()> {
+        match self.symbols.defined(name) {
+            Some(first) => Err(location.error(format!("label {} already defined on {}", name, first.place()))),
+            None => Ok(()),
+        }
+    }
+
+    /// `.IF`, `.IFDEF`, `.IFNDEF`, `.ELSE` and `.ENDIF`, false for any other operation
+    fn conditional(&mut self, operation: &str, operands: &[Token], location: &Location) -> Result<bool> {
+        let error = |message: String| location.error(message);
+        match operation {
+            ".IF" | ".IFDEF" | ".IFNDEF" => {
+                let outer = self.active();
+                // conditions inside a skipped block are not even evaluated
+                let condition = outer
+                    && match (operation, operands) {
+                        (".IF", _) => match parse_operands(operands).map_err(error)?.as_slice() {
+                            [Operand::Expr(expr)] => self.symbols.eval(expr).map_err(error)? != 0,
+                            _ => return Err(error(".IF expects a value".to_string())),
+                        },
+                        (_, [Token::Ident(name)]) => {
+                            let defined = self.symbols.defined(name).is_some()
+                                || self.macros.contains_key(&name.to_ascii_uppercase());
+                            defined == (operation == ".IFDEF")
+                        }
+                        _ => return Err(error(format!("{} expects a name", operation))),
+                    };
+                self.conditionals.push(Conditional {
+                    location: location.clone(),
+                    outer,
+                    taken: condition,
+                    active: condition,
+                    seen_else: false,
+                });
+            }
+            ".ELSE" => {
+                let Some(conditional) = self.conditionals.last_mut() else {
+                    return Err(error(".ELSE without .IF".to_string()));
+                };
+                if conditional.seen_else {
+                    return Err(error(format!(".IF on {} already has an .ELSE", conditional.location.place())));
+                }
+                conditional.seen_else = true;
+                conditional.active = conditional.outer && !conditional.taken;
+            }
+            ".ENDIF" => {
+                if self.conditionals.pop().is_none() {
+                    return Err(error(".ENDIF without .IF".to_string()));
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// `.MACRO NAME param, ...` and its body up to `.ENDM`
+    fn define_macro(&mut self, label: Option<String>, operands: Vec<Operand>, location: &Location) -> Result<()> {
+        let error = |message: String| location.error(message);
+        if label.is_some() {
+            return Err(error("a label cannot name .MACRO, the name goes after it".to_string()));
+        }
+        let mut names = operands.iter().map(|operand| match operand {
+            Operand::Expr(Expr::Symbol(name)) => Ok(name.clone()),
+            operand => Err(error(format!("expected a name, found {}", operand))),
+        });
+        let name = names.next().ok_or_else(|| error(".MACRO expects a name".to_string()))??;
+        let params = names.collect::<Result<Vec<String>>>()?;
+        if is_operation(&name) {
+            return Err(error(format!("{} is an instruction and cannot be a macro", name)));
+        }
+        let key = name.to_ascii_uppercase();
+        if let Some(first) = self.macros.get(&key) {
+            return Err(error(format!("macro {} already defined on {}", name, first.defined.place())));
+        }
+        let definition = self.reader.define_macro(name, params, location)?;
+        self.macros.insert(key, definition);
+        Ok(())
+    }
+}
+
+/// whether `tokens` started with a label before `rest`, the operation and its operands
+fn label_present(tokens: &[Token], rest: &[Token]) -> bool {
+    tokens.len() == rest.len() + 2
+}
+
+/// number of words `operation` takes up
+fn statement_size(operation: &str, operands: &[Operand], symbols: &Symbols) -> std::result::Result<u32, String> {
+    match operation {
+        ".BLKW" => match operands.first() {
+            Some(Operand::Expr(expr)) => match symbols.eval(expr)? {
+                count @ 0.. => Ok(count as u32),
+                count => Err(format!(".BLKW cannot reserve {} words", count)),
+            },
+            _ => Err(".BLKW expects a word count".to_string()),
+        },
+        ".STRINGZ" => match operands {
+            [Operand::Str(text)] => Ok(text.chars().count() as u32 + 1),
+            _ => Err(".STRINGZ expects a string".to_string()),
+        },
+        _ if is_operation(operation) => Ok(1),
+        _ => Err(format!("unknown instruction {}", operation)),
+    }
+}
+
+const OPERATIONS: [&str; 39] = [
+    "ADD", "AND", "NOT", "JMP", "RET", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI",
+    "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT", ".ORIG", ".END", ".FILL", ".BLKW", ".STRINGZ", ".EQU", ".INCLUDE",
+    ".MACRO", ".ENDM", ".IF", ".IFDEF", ".IFNDEF", ".ELSE", ".ENDIF", ".SECTION", ".GLOBAL", ".EXTERN",
+];
+
+fn is_operation(word: &str) -> bool {
+    let word = word.to_ascii_uppercase();
+    OPERATIONS.contains(&word.as_str()) || branch_condition(&word).is_some()
+}
+
+/// nzp bits of a BR mnemonic, plain BR is BRnzp
+fn branch_condition(operation: &str) -> Option<u16> {
+    let flags = operation.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some(0b111);
+    }
+    let mut nzp = 0;
+    let mut rest = flags;
+    for (letter, bit) in [('N', 0b100), ('Z', 0b010), ('P', 0b001)] {
+        if let Some(after) = rest.strip_prefix(letter) {
+            nzp |= bit;
+            rest = after;
+        }
+    }
+    rest.is_empty().then_some(nzp)
+}
+
+fn trap_vector(operation: &str) -> Option<u16> {
+    match operation {
+        "GETC" => Some(0x20),
+        "OUT" => Some(0x21),
+        "PUTS" => Some(0x22),
+        "IN" => Some(0x23),
+        "PUTSP" => Some(0x24),
+        "HALT" => Some(0x25),
+        _ => None,
+    }
+}
+
+struct Encoder<'a> {
+    statement: &'a Statement,
+    symbols: &'a Symbols,
+    sections: &'a [SectionStart],
+    /// whether references can be left to the linker
+    relocatable: bool,
+    /// set when a field was left for the linker to fill in
+    relocation: RefCell<Option<(RelocationKind, Target, i32)>>,
+}
+
+impl Encoder<'_> {
+    fn error(&self, message: String) -> Error {
+        self.statement.location.error(message)
+    }
+
+    fn encode(&self, words: &mut Vec<u16>) -> Result<()> {
+        let operation = self.statement.operation.as_str();
+        let operands = &self.statement.operands;
+        match operation {
+            ".FILL" => {
+                self.expect_operands(1)?;
+                words.push(self.value(0)?);
+            }
+            ".BLKW" => {
+                let count = self.number(0)? as usize;
+                let fill = if operands.len() > 1 { self.value(1)? } else { 0 };
+                words.extend(std::iter::repeat_n(fill, count));
+            }
+            ".STRINGZ" => {
+                let Some(Operand::Str(text)) = operands.first() else {
+                    return Err(self.error(".STRINGZ expects a string".to_string()));
+                };
+                words.extend(text.chars().map(|c| c as u16));
+                words.push(0);
+            }
+            _ => words.push(self.instruction()?),
+        }
+        Ok(())
+    }
+
+    fn instruction(&self) -> Result<u16> {
+        let operation = self.statement.operation.as_str();
+        if let Some(nzp) = branch_condition(operation) {
+            self.expect_operands(1)?;
+            return Ok(nzp << 9 | self.pc_offset(0, 9)?);
+        }
+        if let Some(vector) = trap_vector(operation) {
+            self.expect_operands(0)?;
+            return Ok(0xF000 | vector);
+        }
+
+        let word = match operation {
+            "ADD" | "AND" => {
+                self.expect_operands(3)?;
+                let opcode = if operation == "ADD" { 0x1000 } else { 0x5000 };
+                let second = match &self.statement.operands[2] {
+                    Operand::Expr(expr) if expr.symbol().is_some_and(|name| register_number(name).is_some()) => {
+                        self.register(2)?
+                    }
+                    _ => 0x20 | self.immediate(2, 5)?,
+                };
+                opcode | self.register(0)? << 9 | self.register(1)? << 6 | second
+            }
+            "NOT" => {
+                self.expect_operands(2)?;
+                0x903F | self.register(0)? << 9 | self.register(1)? << 6
+            }
+            "JMP" => {
+                self.expect_operands(1)?;
+                0xC000 | self.register(0)? << 6
+            }
+            "RET" => {
+                self.expect_operands(0)?;
+                0xC1C0
+            }
+            "JSR" => {
+                self.expect_operands(1)?;
+                0x4800 | self.pc_offset(0, 11)?
+            }
+            "JSRR" => {
+                self.expect_operands(1)?;
+                0x4000 | self.register(0)? << 6
+            }
+            "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+                self.expect_operands(2)?;
+                let opcode = match operation {
+                    "LD" => 0x2000,
+                    "LDI" => 0xA000,
+                    "LEA" => 0xE000,
+                    "ST" => 0x3000,
+                    _ => 0xB000,
+                };
+                opcode | self.register(0)? << 9 | self.pc_offset(1, 9)?
+            }
+            "LDR" | "STR" => {
+                self.expect_operands(3)?;
+                let opcode = if operation == "LDR" { 0x6000 } else { 0x7000 };
+                opcode | self.register(0)? << 9 | self.register(1)? << 6 | self.immediate(2, 6)?
+            }
+            "TRAP" => {
+                self.expect_operands(1)?;
+                let vector = self.number(0)?;
+                if !(0..=0xFF).contains(&vector) {
+                    return Err(self.error(format!("trap vector {} is out of range", vector)));
+                }
+                0xF000 | vector as u16
+            }
+            "RTI" => {
+                self.expect_operands(0)?;
+                0x8000
+            }
+            _ => return Err(self.error(format!("unknown instruction {}", operation))),
+        };
+        Ok(word)
+    }
+
+    fn expect_operands(&self, count: usize) -> Result<()> {
+        let found = self.statement.operands.len();
+        if found != count {
+            return Err(self.error(format!(
+                "{} expects {} operand{}, found {}",
+                self.statement.operation,
+                count,
+                if count == 1 { "" } else { "s" },
+                found
+            )));
+        }
+        Ok(())
+    }
+
+    fn operand(&self, index: usize) -> Result<&Operand> {
+        self.statement
+            .operands
+            .get(index)
+            .ok_or_else(|| self.error(format!("{} is missing an operand", self.statement.operation)))
+    }
+
+    fn expr(&self, index: usize) -> Result<&Expr> {
+        match self.operand(index)? {
+            Operand::Expr(expr) => Ok(expr),
+            operand => Err(self.error(format!("expected a value, found {}", operand))),
+        }
+    }
+
+    fn register(&self, index: usize) -> Result<u16> {
+        let operand = self.operand(index)?;
+        match operand {
+            Operand::Expr(expr) => expr.symbol().and_then(register_number),
+            Operand::Str(_) => None,
+        }
+        .ok_or_else(|| self.error(format!("expected a register, found {}", operand)))
+    }
+
+    fn number(&self, index: usize) -> Result<i32> {
+        self.symbols.eval(self.expr(index)?).map_err(|message| self.error(message))
+    }
+
+    /// a full word, negative values in two's complement
+    fn value(&self, index: usize) -> Result<u16> {
+        if self.relocate(index, RelocationKind::Word)? {
+            return Ok(0);
+        }
+        match self.number(index)? {
+            value @ -0x8000..=0xFFFF => Ok(value as u16),
+            value => Err(self.error(format!("{} does not fit in a word", value))),
+        }
+    }
+
+    /// signed immediate of `bits` bits, masked to the field
+    fn immediate(&self, index: usize, bits: u32) -> Result<u16> {
+        let value = self.number(index)?;
+        self.fit(value, bits)
+    }
+
+    /// an expression with a label in it is an address made relative to the incremented pc,
+    /// anything else a literal offset
+    fn pc_offset(&self, index: usize, bits: u32) -> Result<u16> {
+        let kind = if bits == 11 { RelocationKind::Pc11 } else { RelocationKind::Pc9 };
+        if self.relocate(index, kind)? {
+            return Ok(0);
+        }
+        let expr = self.expr(index)?;
+        let value = self.number(index)?;
+        let offset = if expr.mentions(&|name| self.symbols.labels.contains_key(name)) {
+            value - (self.statement.addr as i32 + 1)
+        } else {
+            value
+        };
+        self.fit(offset, bits)
+    }
+
+    /// leaves the operand to the linker when it refers to an `.EXTERN`, for a pc offset to
+    /// a label in another section and for a whole word to a label in a section the linker
+    /// places. only `NAME`, `NAME + constant` and `NAME - constant` can be relocated
+    fn relocate(&self, index: usize, kind: RelocationKind) -> Result<bool> {
+        let expr = self.expr(index)?;
+        let linked = |name: &str| match self.symbols.labels.get(name) {
+            Some(label) if kind == RelocationKind::Word => self.sections[label.section].origin.is_none(),
+            Some(label) => label.section != self.statement.section,
+            None => self.symbols.externs.contains_key(name),
+        };
+        if !expr.mentions(&linked) {
+            return Ok(false);
+        }
+
+        let (name, constant) = match expr {
+            Expr::Symbol(name) => (name.as_str(), None),
+            Expr::Binary(op @ ("+" | "-"), left, right) => match (left.symbol(), right.symbol()) {
+                (Some(name), _) if linked(name) => (name, Some((*op, right.as_ref()))),
+                (_, Some(name)) if *op == "+" && linked(name) => (name, Some((*op, left.as_ref()))),
+                _ => return Err(self.error(format!("{} cannot be relocated, use a label plus or minus a constant", expr))),
+            },
+            _ => return Err(self.error(format!("{} cannot be relocated, use a label plus or minus a constant", expr))),
+        };
+        let mut addend = match constant {
+            Some((_, constant)) if constant.mentions(&linked) => {
+                return Err(self.error(format!("{} refers to more than one label the linker places", expr)));
+            }
+            Some((op, constant)) => {
+                let value = self.symbols.eval(constant).map_err(|message| self.error(message))?;
+                if op == "-" { value.wrapping_neg() } else { value }
+            }
+            None => 0,
+        };
+        let target = match self.symbols.labels.get(name) {
+            Some(label) => {
+                let section = &self.sections[label.section];
+                addend += label.addr.wrapping_sub(section.origin.unwrap_or(0)) as i32;
+                Target::Section(label.section)
+            }
+            None if self.relocatable => Target::Symbol(name.to_string()),
+            None => {
+                return Err(self.error(format!(
+                    "{} is .EXTERN, the program has to be assembled as an object and linked",
+                    name
+                )));
+            }
+        };
+        *self.relocation.borrow_mut() = Some((kind, target, addend));
+        Ok(true)
+    }
+
+    fn fit(&self, value: i32, bits: u32) -> Result<u16> {
+        let limit = 1 << (bits - 1);
+        if !(-limit..limit).contains(&value) {
+            return Err(self.error(format!(
+                "{} does not fit in a {} bit field ({} to {})",
+                value,
+                bits,
+                -limit,
+                limit - 1
+            )));
+        }
+        Ok(value as u16 & ((1 << bits) - 1))
+    }
+}
+
+fn register_number(name: &str) -> Option<u16> {
+    let digit = name.strip_prefix(['R', 'r'])?;
+    match digit.parse() {
+        Ok(number) if number < 8 && digit.len() == 1 => Some(number),
+        _ => None,
+    }
+}
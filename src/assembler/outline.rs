@@ -0,0 +1,125 @@
+//! the names a source file defines, found line by line without assembling it so editors
+//! still get them while the file has errors
+
+use super::lexer::{Token, tokenize};
+use super::{OPERATIONS, is_operation};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Label,
+    /// `NAME .EQU value`
+    Constant,
+    Macro,
+}
+
+/// a name and the line that defines it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: DefinitionKind,
+    /// none for the source itself when it was not read from a file
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    /// the defining line, trimmed
+    pub text: String,
+}
+
+/// every instruction, trap alias and directive the assembler knows, with all the BR variants
+pub fn mnemonics() -> Vec<&'static str> {
+    let mut all = OPERATIONS.to_vec();
+    all.extend(["BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP", "BRNZP"]);
+    all
+}
+
+/// labels, constants and macros of `source` and of the files it includes, in the order
+/// they are written. lines that do not parse are skipped
+pub fn definitions(source: &str, file: Option<&Path>, dir: &Path) -> Vec<Definition> {
+    let mut scan = Scan {
+        definitions: Vec::new(),
+        macros: HashSet::new(),
+        included: file.and_then(|file| fs::canonicalize(file).ok()).into_iter().collect(),
+    };
+    scan.source(source, file, dir);
+    scan.definitions
+}
+
+struct Scan {
+    definitions: Vec<Definition>,
+    /// by upper case name, so macro invocations are not taken for labels
+    macros: HashSet<String>,
+    /// canonical paths already scanned, include cycles are an error for the assembler
+    included: Vec<PathBuf>,
+}
+
+impl Scan {
+    fn source(&mut self, source: &str, file: Option<&Path>, dir: &Path) {
+        let mut in_macro = false;
+        for (index, text) in source.lines().enumerate() {
+            let Ok(tokens) = tokenize(text) else {
+                continue;
+            };
+            let mut rest = tokens.as_slice();
+            let mut label = None;
+            if let [Token::Ident(name), after @ ..] = rest
+                && !is_operation(name)
+                && !self.macros.contains(&name.to_ascii_uppercase())
+            {
+                label = Some(name.clone());
+                rest = after;
+            }
+            let operation = match rest.first() {
+                Some(Token::Ident(operation)) => operation.to_ascii_uppercase(),
+                _ => String::new(),
+            };
+            if in_macro {
+                // labels in a body are templates, they get defined when it is expanded
+                in_macro = operation != ".ENDM";
+                continue;
+            }
+
+            let mut define = |name: &str, kind| {
+                self.definitions.push(Definition {
+                    name: name.to_string(),
+                    kind,
+                    file: file.map(Path::to_path_buf),
+                    line: index + 1,
+                    text: text.trim().to_string(),
+                })
+            };
+            match (operation.as_str(), label) {
+                (".MACRO", _) => {
+                    if let Some(Token::Ident(name)) = rest.get(1) {
+                        define(name, DefinitionKind::Macro);
+                        self.macros.insert(name.to_ascii_uppercase());
+                    }
+                    in_macro = true;
+                }
+                (".EQU", Some(name)) => define(&name, DefinitionKind::Constant),
+                (".INCLUDE", _) => {
+                    if let Some(Token::Str(path)) = rest.get(1) {
+                        self.include(&dir.join(path));
+                    }
+                }
+                (_, Some(name)) => define(&name, DefinitionKind::Label),
+                _ => {}
+            }
+        }
+    }
+
+    fn include(&mut self, file: &Path) {
+        let Ok(canonical) = fs::canonicalize(file) else {
+            return;
+        };
+        if self.included.contains(&canonical) {
+            return;
+        }
+        self.included.push(canonical);
+        if let Ok(text) = fs::read_to_string(file) {
+            let dir = file.parent().map_or_else(PathBuf::new, Path::to_path_buf);
+            self.source(&text, Some(file), &dir);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! where the assembler's lines come from: the main source, `.INCLUDE`d files and macro
+//! expansions, each line keeping track of its origin
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// macros expanding macros deeper than this are taken to be recursive
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// an invocation of a macro
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroCall {
+    pub name: String,
+    pub file: Option<PathBuf>,
+    pub line: usize,
+}
+
+/// file and line a source line was written on, for a line produced by a macro the line
+/// of its body, `expansion` holds the invocations that produced it, innermost first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// none for source that was not read from a file
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub expansion: Vec<MacroCall>,
+}
+
+/// `line 3`, or `lib.asm line 3` for lines from a file
+fn place(file: &Option<PathBuf>, line: usize) -> String {
+    match file {
+        Some(file) => format!("{} line {}", file.display(), line),
+        None => format!("line {}", line),
+    }
+}
+
+impl Location {
+    pub fn place(&self) -> String {
+        place(&self.file, self.line)
+    }
+
+    /// `in PUSH expanded on line 12, in ...` for the macro invocations that produced this
+    /// line, innermost first, empty for a line written out in the source
+    pub fn expansion_trace(&self) -> String {
+        let calls: Vec<String> = self
+            .expansion
+            .iter()
+            .map(|call| format!("in {} expanded on {}", call.name, place(&call.file, call.line)))
+            .collect();
+        calls.join(", ")
+    }
+
+    /// an assembly error at this line, naming the macro invocations that led to it
+    pub fn error(&self, message: impl Into<String>) -> Error {
+        let mut message = message.into();
+        if !self.expansion.is_empty() {
+            message.push_str(&format!(", {}", self.expansion_trace()));
+        }
+        Error::Assemble {
+            file: self.file.as_ref().map(|file| file.display().to_string()),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// a `.MACRO` definition, `\name` in the body stands for the argument of parameter
+/// `name` and `\@` for a number unique to every expansion, for labels
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    params: Vec<String>,
+    /// (line number, text)
+    body: Vec<(usize, String)>,
+    file: Option<PathBuf>,
+    dir: PathBuf,
+    pub defined: Location,
+}
+
+impl Macro {
+    fn expand(&self, args: &[String], unique: usize) -> Vec<(usize, String)> {
+        self.body
+            .iter()
+            .map(|(line, text)| (*line, self.substitute(text, args, unique)))
+            .collect()
+    }
+
+    fn substitute(&self, text: &str, args: &[String], unique: usize) -> String {
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('\\') {
+            expanded.push_str(&rest[..i]);
+            let after = &rest[i + 1..];
+            if let Some(tail) = after.strip_prefix('@') {
+                expanded.push_str(&unique.to_string());
+                rest = tail;
+                continue;
+            }
+            let len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            match self.params.iter().position(|param| param.eq_ignore_ascii_case(&after[..len])) {
+                Some(index) if len > 0 => {
+                    expanded.push_str(&args[index]);
+                    rest = &after[len..];
+                }
+                // escapes in strings and anything else are left alone
+                _ => {
+                    expanded.push('\\');
+                    rest = after;
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+struct Frame {
+    lines: Vec<(usize, String)>,
+    next: usize,
+    file: Option<PathBuf>,
+    /// `.INCLUDE` paths are relative to this
+    dir: PathBuf,
+    expansion: Vec<MacroCall>,
+    /// canonical path of an included file, to catch include cycles
+    included: Option<PathBuf>,
+}
+
+/// the lines to assemble, includes and expansions are read before the rest of the
+/// file they appear in
+pub struct Reader {
+    frames: Vec<Frame>,
+    expansions: usize,
+}
+
+fn numbered(text: &str) -> Vec<(usize, String)> {
+    text.lines().enumerate().map(|(i, line)| (i + 1, line.to_string())).collect()
+}
+
+impl Reader {
+    pub fn new(source: &str, file: Option<&Path>, dir: &Path) -> Self {
+        Reader {
+            frames: vec![Frame {
+                lines: numbered(source),
+                next: 0,
+                file: file.map(Path::to_path_buf),
+                dir: dir.to_path_buf(),
+                expansion: Vec::new(),
+                included: file.and_then(|file| fs::canonicalize(file).ok()),
+            }],
+            expansions: 0,
+        }
+    }
+
+    pub fn next_line(&mut self) -> Option<(String, Location)> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            let Some((line, text)) = frame.lines.get(frame.next) else {
+                self.frames.pop();
+                continue;
+            };
+            frame.next += 1;
+            let location = Location {
+                file: frame.file.clone(),
+                line: *line,
+                expansion: frame.expansion.clone(),
+            };
+            return Some((text.clone(), location));
+        }
+    }
+
+    /// continues with the lines of `path`, relative to the file `from` is in
+    pub fn include(&mut self, path: &str, from: &Location) -> Result<()> {
+        let frame = self.frames.last().expect("an include comes from a line");
+        let file = frame.dir.join(path);
+        let cannot = |e: std::io::Error| from.error(format!("cannot include {}: {}", path, e));
+        let canonical = fs::canonicalize(&file).map_err(cannot)?;
+        if self.frames.iter().any(|frame| frame.included.as_ref() == Some(&canonical)) {
+            return Err(from.error(format!("recursive .INCLUDE of {}", path)));
+        }
+        let text = fs::read_to_string(&file).map_err(cannot)?;
+        self.frames.push(Frame {
+            lines: numbered(&text),
+            next: 0,
+            dir: file.parent().map_or_else(PathBuf::new, Path::to_path_buf),
+            file: Some(file),
+            expansion: from.expansion.clone(),
+            included: Some(canonical),
+        });
+        Ok(())
+    }
+
+    /// reads the body of a macro up to its `.ENDM`
+    pub fn define_macro(&mut self, name: String, params: Vec<String>, defined: &Location) -> Result<Macro> {
+        let frame = self.frames.last_mut().expect("a definition comes from a line");
+        let mut body = Vec::new();
+        loop {
+            let Some((line, text)) = frame.lines.get(frame.next) else {
+                return Err(defined.error(format!(".MACRO {} without .ENDM", name)));
+            };
+            frame.next += 1;
+            let directive = text.split(';').next().unwrap_or("").split_whitespace().next();
+            match directive.map(str::to_ascii_uppercase).as_deref() {
+                Some(".ENDM") => break,
+                Some(".MACRO") => {
+                    return Err(Location {
+                        line: *line,
+                        ..defined.clone()
+                    }
+                    .error("macros cannot be defined inside a macro"));
+                }
+                _ => body.push((*line, text.clone())),
+            }
+        }
+        Ok(Macro {
+            name,
+            params,
+            body,
+            file: frame.file.clone(),
+            dir: frame.dir.clone(),
+            defined: defined.clone(),
+        })
+    }
+
+    /// continues with the body of `definition` for `args`
+    pub fn expand(&mut self, definition: &Macro, args: Vec<String>, from: &Location) -> Result<()> {
+        if args.len() != definition.params.len() {
+            return Err(from.error(format!(
+                "{} expects {} argument{}, found {}",
+                definition.name,
+                definition.params.len(),
+                if definition.params.len() == 1 { "" } else { "s" },
+                args.len()
+            )));
+        }
+        if from.expansion.len() >= MAX_EXPANSION_DEPTH {
+            return Err(from.error(format!("macro expansion nested too deeply, is {} recursive?", definition.name)));
+        }
+        self.expansions += 1;
+        let mut expansion = vec![MacroCall {
+            name: definition.name.clone(),
+            file: from.file.clone(),
+            line: from.line,
+        }];
+        expansion.extend(from.expansion.iter().cloned());
+        self.frames.push(Frame {
+            lines: definition.expand(&args, self.expansions),
+            next: 0,
+            file: definition.file.clone(),
+            dir: definition.dir.clone(),
+            expansion,
+            included: None,
+        });
+        Ok(())
+    }
+}
+
+/// raw argument text of a macro invocation, `skip` words into `line`: split at commas
+/// when there are any, otherwise at whitespace
+pub fn macro_arguments(line: &str, skip: usize) -> Vec<String> {
+    let code = split_outside_quotes(line, ';').into_iter().next().unwrap_or_default();
+    let mut rest = code.trim_start();
+    for _ in 0..skip {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    let rest = rest.trim_end();
+    if rest.is_empty() {
+        Vec::new()
+    } else if rest.contains(',') {
+        split_outside_quotes(rest, ',').iter().map(|arg| arg.trim().to_string()).collect()
+    } else {
+        rest.split_whitespace().map(str::to_string).collect()
+    }
+}
+
+/// `text` cut at every `separator` that is not inside a string or character literal
+pub fn split_outside_quotes(text: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(open), c) if c == open => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) if c == separator => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().expect("never empty").push(c);
+    }
+    parts
+}
@@ -0,0 +1,15 @@
+//! language server for lc3 assembly, speaks the language server protocol on stdin and
+//! stdout. exits with 1 when the editor leaves without asking for a shutdown first
+use std::io;
+use std::process;
+
+fn main() {
+    match rustvm::lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("rustvm-lsp: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::analysis::cfg::Cfg;
+use crate::assembler::assemble;
+use crate::hardware::isa::Isa;
+use crate::hardware::vm::VM;
+use crate::loader::Image;
+use crate::symbols::SymbolTable;
+
+// x3000 MAIN: AND R0, R0, #0
+// x3001 LOOP: ADD R0, R0, #1
+// x3002:      ADD R1, R0, #-3
+// x3003:      BRn LOOP
+// x3004:      BRz DONE
+// x3005:      ADD R2, R2, #1
+// x3006 DONE: HALT
+const PROGRAM: [u16; 7] = [0x5020, 0x1021, 0x123D, 0x09FD, 0x0401, 0x14A1, 0xF025];
+
+fn covered_vm() -> VM {
+    let mut vm = VM::new();
+    for (offset, word) in PROGRAM.iter().enumerate() {
+        vm.write_memory(0x3000 + offset as u16, *word);
+    }
+    vm.enable_coverage();
+    vm.execute().unwrap();
+    vm
+}
+
+#[test]
+fn records_hits_and_branch_directions() {
+    let vm = covered_vm();
+    let coverage = vm.coverage().unwrap();
+
+    assert_eq!(coverage.hits(0x3001), 3);
+    assert_eq!(coverage.hits(0x3005), 0);
+    let loop_branch = coverage.branch(0x3003).unwrap();
+    assert_eq!((loop_branch.taken, loop_branch.not_taken), (2, 1));
+    let done_branch = coverage.branch(0x3004).unwrap();
+    assert_eq!((done_branch.taken, done_branch.not_taken), (1, 0));
+}
+
+#[test]
+fn summary_counts_words_and_branch_directions() {
+    let vm = covered_vm();
+    let summary = vm.coverage().unwrap().summary(0x3000..=0x3006, |addr| vm.read_memory(addr));
+
+    assert_eq!((summary.instructions_hit, summary.instructions), (6, 7));
+    assert_eq!((summary.branch_directions_hit, summary.branch_directions), (3, 4));
+}
+
+#[test]
+fn lcov_output_has_lines_branches_and_functions() {
+    let vm = covered_vm();
+    let symbols = SymbolTable::parse("MAIN x3000\nLOOP x3001\nDONE x3006\n".as_bytes()).unwrap();
+    let image = Image { origin: 0x3000, words: PROGRAM.to_vec(), isa: Isa::Lc3 };
+    let cfg = Cfg::build(&image, 0x3000, &symbols);
+    let mut output = Vec::new();
+    vm.coverage()
+        .unwrap()
+        .write_lcov(&mut output, "count.obj", 0x3000..=0x3006, Some(&cfg), |addr| vm.read_memory(addr))
+        .unwrap();
+    let lcov = String::from_utf8(output).unwrap();
+
+    for expected in [
+        "SF:count.obj",
+        "FN:1,MAIN",
+        "FNDA:1,MAIN",
+        "FNF:1",
+        "FNH:1",
+        "BRDA:4,0,0,2",
+        "BRDA:4,0,1,1",
+        "BRDA:5,0,1,0",
+        "BRF:4",
+        "BRH:3",
+        "DA:6,0",
+        "LF:7",
+        "LH:6",
+        "end_of_record",
+    ] {
+        assert!(lcov.lines().any(|line| line == expected), "missing {expected} in:\n{lcov}");
+    }
+    // branch targets inside MAIN are not functions
+    assert!(!lcov.contains("LOOP") && !lcov.contains("DONE"), "{lcov}");
+}
+
+#[test]
+fn mapped_lcov_reports_source_lines_and_adds_up_expansions() {
+    let source = "
+        .MACRO BUMP
+        ADD R0, R0, #1
+        .ENDM
+        .ORIG x3000
+MAIN    BUMP
+LOOP    BUMP
+        ADD R1, R0, #-4
+        BRn LOOP
+        HALT
+DATA    .FILL 7
+        .END
+    ";
+    let program = assemble(source).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.enable_coverage();
+    vm.execute().unwrap();
+
+    let cfg = Cfg::build(&program.image, 0x3000, &program.symbols);
+    let mut output = Vec::new();
+    vm.coverage()
+        .unwrap()
+        .write_lcov_mapped(&mut output, "bump.asm", &program.source_map(), Some(&cfg), |addr| vm.read_memory(addr))
+        .unwrap();
+    let lcov = String::from_utf8(output).unwrap();
+
+    for expected in [
+        "SF:bump.asm",
+        "FN:3,MAIN",
+        "FNDA:1,MAIN",
+        "FNF:1",
+        "DA:3,4",
+        "DA:8,3",
+        "BRDA:9,0,0,2",
+        "BRDA:9,0,1,1",
+        "DA:10,1",
+        "LF:4",
+        "LH:4",
+        "end_of_record",
+    ] {
+        assert!(lcov.lines().any(|line| line == expected), "missing {expected} in:\n{lcov}");
+    }
+    // the .FILL is data, not an unexecuted line
+    assert!(!lcov.contains("DA:11"), "{lcov}");
+    assert!(!lcov.contains("DATA") && !lcov.contains("LOOP"), "{lcov}");
+}
+
+#[test]
+fn lcov_functions_are_the_subroutines_the_cfg_finds() {
+    let source = "        .ORIG x3000
+MAIN    JSR TWICE
+        JSR TWICE
+        HALT
+TWICE   ADD R0, R0, #1
+        ADD R0, R0, #1
+        RET
+COUNT   .FILL 2
+        .END
+";
+    let program = assemble(source).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.enable_coverage();
+    vm.execute().unwrap();
+    let cfg = Cfg::build(&program.image, 0x3000, &program.symbols);
+
+    let mut mapped = Vec::new();
+    vm.coverage()
+        .unwrap()
+        .write_lcov_mapped(&mut mapped, "twice.asm", &program.source_map(), Some(&cfg), |addr| vm.read_memory(addr))
+        .unwrap();
+    let mut unmapped = Vec::new();
+    vm.coverage()
+        .unwrap()
+        .write_lcov(&mut unmapped, "twice.obj", 0x3000..=0x3006, Some(&cfg), |addr| vm.read_memory(addr))
+        .unwrap();
+
+    for (lcov, main, twice) in [(mapped, "FN:2,MAIN", "FN:5,TWICE"), (unmapped, "FN:1,MAIN", "FN:4,TWICE")] {
+        let lcov = String::from_utf8(lcov).unwrap();
+        for expected in [main, twice, "FNDA:1,MAIN", "FNDA:2,TWICE", "FNF:2", "FNH:2"] {
+            assert!(lcov.lines().any(|line| line == expected), "missing {expected} in:\n{lcov}");
+        }
+        assert!(!lcov.contains("COUNT"), "{lcov}");
+    }
+}
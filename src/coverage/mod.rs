@@ -0,0 +1,256 @@
+use crate::analysis::cfg::Cfg;
+use crate::hardware::memory::MEMORY_SIZE;
+use crate::hardware::processor::OpCode;
+use crate::source_map::{SourceEntry, SourceMap};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+#[cfg(test)]
+mod coverage_tests;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// which addresses were executed and which way each conditional branch went
+pub struct Coverage {
+    hits: Vec<u64>,
+    branches: BTreeMap<u16, BranchCounts>,
+}
+
+/// entries of the subroutines in `cfg` that have a label, the functions of a tracefile
+fn functions(cfg: Option<&Cfg>) -> BTreeMap<u16, &str> {
+    cfg.map(|cfg| {
+        cfg.subroutines
+            .iter()
+            .filter_map(|subroutine| Some((subroutine.entry, subroutine.name.as_deref()?)))
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// a conditional branch is a BR with some but not all of n, z and p set
+fn is_conditional_branch(instr: u16) -> bool {
+    let nzp = (instr >> 9) & 0x7;
+    matches!(OpCode::get_op_code(&instr), Some(OpCode::BR)) && nzp != 0 && nzp != 0x7
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            hits: vec![0; MEMORY_SIZE],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    /// accounts for `instr` executed at `pc`, `cond` is the condition register before it ran
+    pub fn record(&mut self, pc: u16, instr: u16, cond: u16) {
+        self.hits[pc as usize] += 1;
+        if is_conditional_branch(instr) {
+            let counts = self.branches.entry(pc).or_default();
+            if (instr >> 9) & cond != 0 {
+                counts.taken += 1;
+            } else {
+                counts.not_taken += 1;
+            }
+        }
+    }
+
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits[addr as usize]
+    }
+
+    pub fn branch(&self, addr: u16) -> Option<BranchCounts> {
+        self.branches.get(&addr).copied()
+    }
+
+    /// summary over `range`, `read` supplies memory contents to find branches that never ran
+    pub fn summary(&self, range: RangeInclusive<u16>, read: impl Fn(u16) -> u16) -> CoverageSummary {
+        let mut summary = CoverageSummary::default();
+        for addr in range {
+            summary.instructions += 1;
+            if self.hits(addr) > 0 {
+                summary.instructions_hit += 1;
+            }
+            if is_conditional_branch(read(addr)) {
+                let counts = self.branch(addr).unwrap_or_default();
+                summary.branch_directions += 2;
+                summary.branch_directions_hit += (counts.taken > 0) as usize + (counts.not_taken > 0) as usize;
+            }
+        }
+        summary
+    }
+
+    /// lcov tracefile for the words in `range`. without a source map there is no way to tell
+    /// code from data, so every word is a line and line n is the n-th word of the range.
+    /// labelled subroutines of `cfg` become functions. see `write_lcov_mapped` for assembled programs
+    pub fn write_lcov<W: Write>(
+        &self,
+        mut output: W,
+        source: &str,
+        range: RangeInclusive<u16>,
+        cfg: Option<&Cfg>,
+        read: impl Fn(u16) -> u16,
+    ) -> io::Result<()> {
+        let start = *range.start();
+        let line = |addr: u16| (addr - start) as usize + 1;
+
+        writeln!(output, "TN:")?;
+        writeln!(output, "SF:{}", source)?;
+
+        let functions: Vec<(u16, &str)> =
+            functions(cfg).into_iter().filter(|(addr, _)| range.contains(addr)).collect();
+        for &(addr, name) in &functions {
+            writeln!(output, "FN:{},{}", line(addr), name)?;
+        }
+        for &(addr, name) in &functions {
+            writeln!(output, "FNDA:{},{}", self.hits(addr), name)?;
+        }
+        writeln!(output, "FNF:{}", functions.len())?;
+        writeln!(output, "FNH:{}", functions.iter().filter(|(addr, _)| self.hits(*addr) > 0).count())?;
+
+        let mut branches_found = 0;
+        let mut branches_hit = 0;
+        for addr in range.clone() {
+            if !is_conditional_branch(read(addr)) {
+                continue;
+            }
+            let counts = self.branch(addr).unwrap_or_default();
+            for (index, count) in [counts.taken, counts.not_taken].into_iter().enumerate() {
+                branches_found += 1;
+                if count > 0 {
+                    branches_hit += 1;
+                }
+                if self.hits(addr) == 0 {
+                    writeln!(output, "BRDA:{},0,{},-", line(addr), index)?;
+                } else {
+                    writeln!(output, "BRDA:{},0,{},{}", line(addr), index, count)?;
+                }
+            }
+        }
+        writeln!(output, "BRF:{}", branches_found)?;
+        writeln!(output, "BRH:{}", branches_hit)?;
+
+        let mut lines_hit = 0;
+        for addr in range.clone() {
+            if self.hits(addr) > 0 {
+                lines_hit += 1;
+            }
+            writeln!(output, "DA:{},{}", line(addr), self.hits(addr))?;
+        }
+        writeln!(output, "LF:{}", range.count())?;
+        writeln!(output, "LH:{}", lines_hit)?;
+        writeln!(output, "end_of_record")
+    }
+
+    /// lcov tracefile with the real source lines from `map`, a record per source file.
+    /// data words are left out, the hits of a line are those of the instructions assembled
+    /// from it added up, so a macro body counts every expansion. entries without a file are
+    /// reported as `source`, labelled subroutines of `cfg` become functions
+    pub fn write_lcov_mapped<W: Write>(
+        &self,
+        mut output: W,
+        source: &str,
+        map: &SourceMap,
+        cfg: Option<&Cfg>,
+        read: impl Fn(u16) -> u16,
+    ) -> io::Result<()> {
+        let names = functions(cfg);
+        let mut files: BTreeMap<&str, BTreeMap<usize, Vec<&SourceEntry>>> = BTreeMap::new();
+        for entry in map.iter().filter(|entry| !entry.data) {
+            let file = entry.file.as_deref().unwrap_or(source);
+            files.entry(file).or_default().entry(entry.line).or_default().push(entry);
+        }
+
+        for (file, lines) in &files {
+            writeln!(output, "TN:")?;
+            writeln!(output, "SF:{}", file)?;
+
+            let functions: Vec<(usize, u16, &str)> = lines
+                .iter()
+                .flat_map(|(&line, entries)| entries.iter().map(move |entry| (line, entry.address)))
+                .filter_map(|(line, addr)| Some((line, addr, *names.get(&addr)?)))
+                .collect();
+            for &(line, _, name) in &functions {
+                writeln!(output, "FN:{},{}", line, name)?;
+            }
+            for &(_, addr, name) in &functions {
+                writeln!(output, "FNDA:{},{}", self.hits(addr), name)?;
+            }
+            writeln!(output, "FNF:{}", functions.len())?;
+            writeln!(output, "FNH:{}", functions.iter().filter(|(_, addr, _)| self.hits(*addr) > 0).count())?;
+
+            let mut branches_found = 0;
+            let mut branches_hit = 0;
+            for (line, entries) in lines {
+                // one block per expansion of the line
+                for (block, entry) in entries.iter().enumerate() {
+                    if !is_conditional_branch(read(entry.address)) {
+                        continue;
+                    }
+                    let counts = self.branch(entry.address).unwrap_or_default();
+                    for (index, count) in [counts.taken, counts.not_taken].into_iter().enumerate() {
+                        branches_found += 1;
+                        if count > 0 {
+                            branches_hit += 1;
+                        }
+                        if self.hits(entry.address) == 0 {
+                            writeln!(output, "BRDA:{},{},{},-", line, block, index)?;
+                        } else {
+                            writeln!(output, "BRDA:{},{},{},{}", line, block, index, count)?;
+                        }
+                    }
+                }
+            }
+            writeln!(output, "BRF:{}", branches_found)?;
+            writeln!(output, "BRH:{}", branches_hit)?;
+
+            let mut lines_hit = 0;
+            for (line, entries) in lines {
+                let hits: u64 = entries.iter().map(|entry| self.hits(entry.address)).sum();
+                if hits > 0 {
+                    lines_hit += 1;
+                }
+                writeln!(output, "DA:{},{}", line, hits)?;
+            }
+            writeln!(output, "LF:{}", lines.len())?;
+            writeln!(output, "LH:{}", lines_hit)?;
+            writeln!(output, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CoverageSummary {
+    pub instructions: usize,
+    pub instructions_hit: usize,
+    /// two per conditional branch: taken and not taken
+    pub branch_directions: usize,
+    pub branch_directions_hit: usize,
+}
+
+impl CoverageSummary {
+    pub fn write_text<W: Write>(&self, mut output: W) -> io::Result<()> {
+        let percent = |hit: usize, total: usize| hit as f64 * 100.0 / total.max(1) as f64;
+        writeln!(
+            output,
+            "coverage: {}/{} words executed ({:.1}%), {}/{} branch directions taken ({:.1}%)",
+            self.instructions_hit,
+            self.instructions,
+            percent(self.instructions_hit, self.instructions),
+            self.branch_directions_hit,
+            self.branch_directions,
+            percent(self.branch_directions_hit, self.branch_directions)
+        )
+    }
+}
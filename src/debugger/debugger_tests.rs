@@ -0,0 +1,150 @@
+use crate::hardware::registers::Register;
+use super::Debugger;
+use crate::hardware::vm::VM;
+use crate::hardware::isa::Isa;
+
+fn debugger_with_program(words: &[u16]) -> Debugger {
+    let mut vm = VM::new();
+    for (offset, word) in words.iter().enumerate() {
+        vm.write_memory(0x3000 + offset as u16, *word);
+    }
+    vm.enable_history(64);
+    Debugger::new(vm)
+}
+
+fn run_commands(debugger: &mut Debugger, script: &str) -> String {
+    let mut output = Vec::new();
+    debugger.run(script.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+// x3000: AND R0, R0, #0
+// x3001: ADD R0, R0, #1
+// x3002: ADD R1, R0, #-3
+// x3003: BRn #-3        -- back to x3001
+// x3004: HALT
+const COUNT_TO_THREE: [u16; 5] = [0x5020, 0x1021, 0x123D, 0x09FD, 0xF025];
+
+#[test]
+fn continue_stops_at_breakpoint_and_reverse_continue_returns_to_it() {
+    let mut debugger = debugger_with_program(&COUNT_TO_THREE);
+    let output = run_commands(&mut debugger, "b x3002\nc\nc\n");
+    assert!(output.contains("pc x3002"), "{output}");
+    assert_eq!(debugger.vm().register(Register::R0), 2);
+
+    run_commands(&mut debugger, "rc\n");
+    assert_eq!(debugger.vm().pc(), 0x3002);
+    assert_eq!(debugger.vm().register(Register::R0), 1);
+}
+
+#[test]
+fn step_back_past_start_of_history_is_reported() {
+    let mut debugger = debugger_with_program(&COUNT_TO_THREE);
+    let output = run_commands(&mut debugger, "s 2\nsb 5\n");
+
+    assert!(output.contains("reached the start of the recorded history"), "{output}");
+    assert_eq!(debugger.vm().pc(), 0x3000);
+}
+
+#[test]
+fn who_reports_last_writer() {
+    // x3000: ST R0, #1
+    // x3001: HALT
+    let mut debugger = debugger_with_program(&[0x3001, 0xF025, 0xFFFF]);
+    let output = run_commands(&mut debugger, "c\nwho x3002\nwho 0x3001\n");
+
+    assert!(output.contains("x3002 last written by x3000"), "{output}");
+    assert!(output.contains("no recorded write to x3001"), "{output}");
+}
+
+#[test]
+fn invalid_arguments_do_not_end_the_session() {
+    let mut debugger = debugger_with_program(&COUNT_TO_THREE);
+    let output = run_commands(&mut debugger, "b nowhere\ns\n");
+
+    assert!(output.contains("invalid address 'nowhere'"), "{output}");
+    assert_eq!(debugger.vm().pc(), 0x3001);
+}
+
+#[test]
+fn addresses_are_written_like_assembler_numbers() {
+    let mut debugger = debugger_with_program(&COUNT_TO_THREE);
+    let output = run_commands(&mut debugger, "b #12290\nb 0x3004\nx #12288\nx #-1\n");
+
+    assert!(output.contains("breakpoint at x3002"), "{output}");
+    assert!(output.contains("breakpoint at x3004"), "{output}");
+    assert!(output.contains("x3000: x5020"), "{output}");
+    assert!(output.contains("invalid address '#-1'"), "{output}");
+}
+
+#[test]
+fn continue_stops_at_watchpoint() {
+    // x3000: ADD R0, R0, #5
+    // x3001: ST R0, #1
+    // x3002: HALT
+    let mut debugger = debugger_with_program(&[0x1025, 0x3001, 0xF025, 0x0000]);
+    let output = run_commands(&mut debugger, "watch x3003 changed\nwatches\nc\n");
+
+    assert!(output.contains("watchpoint 0: write x3003 if changed"), "{output}");
+    assert!(output.contains("watchpoint 0: x3003 written x0000 -> x0005 at x3001"), "{output}");
+    assert_eq!(debugger.vm().pc(), 0x3002);
+}
+
+#[test]
+fn source_lines_are_shown_and_accepted_as_breakpoints() {
+    let path = std::env::temp_dir().join(format!("rustvm-debugger-{}.asm", std::process::id()));
+    std::fs::write(
+        &path,
+        ".ORIG x3000\nAND R0, R0, #0\nLOOP ADD R0, R0, #1\nADD R1, R0, #-3\nBRn LOOP\nHALT\n.END\n",
+    )
+    .unwrap();
+    let program = crate::assembler::assemble_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.set_source_map(program.source_map());
+    let mut debugger = Debugger::new(vm);
+
+    let file = path.file_name().unwrap().to_str().unwrap();
+    let script = format!("b {file}:4\nc\nx x3004\nb {file}:1\n");
+    let output = run_commands(&mut debugger, &script);
+    let place = |line| format!("{}:{}", path.display(), line);
+    assert!(output.contains("breakpoint at x3002"), "{output}");
+    assert!(output.contains(&format!("pc x3002: x123D  {}  ADD R1, R0, #-3", place(4))), "{output}");
+    assert!(output.contains(&format!("x3004: xF025  {}  HALT", place(6))), "{output}");
+    assert!(output.contains(&format!("no code at {file}:1")), "{output}");
+}
+
+#[test]
+fn guest_input_is_queued_by_the_input_command() {
+    // x3000: GETC
+    // x3001: OUT
+    // x3002: HALT
+    let mut debugger = debugger_with_program(&[0xF020, 0xF021, 0xF025]);
+    debugger.vm_mut().set_output(Box::new(std::io::sink()));
+    debugger.vm_mut().set_input(Vec::new());
+    let output = run_commands(&mut debugger, "c\ns\n");
+    assert!(output.contains("pc x3000: waiting for input"), "{output}");
+    assert_eq!(debugger.vm().instruction_count(), 0);
+
+    let output = run_commands(&mut debugger, "input  k m\ns\n");
+    assert!(output.contains("4 bytes of input queued"), "{output}");
+    assert_eq!(debugger.vm().register(Register::R0), u16::from(b'k'));
+    assert_eq!(debugger.vm().pending_input(), 3);
+}
+
+#[test]
+fn examining_more_lc3b_words_than_memory_holds_wraps_around() {
+    let mut vm = VM::new();
+    vm.set_isa(Isa::Lc3b);
+    let mut debugger = Debugger::new(vm);
+    let output = run_commands(&mut debugger, "x xFFFE 40000\n");
+    let lines: Vec<&str> = output
+        .lines()
+        .map(|line| line.trim_start_matches("(lc3) "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    assert_eq!(lines.len(), 40000);
+    assert_eq!(&lines[..2], ["xFFFE: x0000", "x0000: x0000"]);
+    assert_eq!(lines[32768], "xFFFE: x0000");
+}
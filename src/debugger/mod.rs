@@ -0,0 +1,353 @@
+use crate::assembler::parse_word;
+use crate::error::Result;
+use crate::hardware::decode::{Instruction, decode};
+use crate::hardware::registers::Register;
+use crate::hardware::vm::VM;
+use crate::hardware::watch::{WatchCondition, WatchKind, Watchpoint};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+#[cfg(test)]
+mod debugger_tests;
+
+const HELP: &str = "\
+commands:
+  s, step [n]              execute n instructions (default 1)
+  c, continue              run until a breakpoint or HALT
+  sb, step-back [n]        undo n instructions (default 1)
+  rc, reverse-continue     undo instructions until a breakpoint or the start of the history
+  b, break <addr>          set a breakpoint, with a source map addr can also be file:line
+  d, delete <addr>         remove a breakpoint
+  watch <range> [cond]     stop when a word in range is written, range is addr or addr-addr
+  rwatch <range> [cond]    stop when a word in range is read
+  awatch <range> [cond]    stop when a word in range is read or written
+                           cond is one of old=<value>, new=<value> or changed
+  unwatch <id>             remove a watchpoint
+  watches                  list watchpoints
+  who <addr>               show the last recorded instruction that wrote to addr
+  r, regs                  show registers
+  x <addr> [n]             show n memory words starting at addr (default 1)
+  input <text>             queue text and a newline as keyboard input for the guest
+  q, quit                  leave the debugger
+";
+
+/// interactive command line debugger on top of the vm
+pub struct Debugger {
+    vm: VM,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new(vm: VM) -> Self {
+        Self {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// reads commands from `input` until `quit` or end of input
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "(lc3) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            match self.command(line.trim(), &mut output) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => writeln!(output, "{}", e)?,
+                Err(e) => return Err(e),
+            }
+            write!(output, "(lc3) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// executes a single command, returns false when the debugger should exit
+    pub fn command<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        let mut parts = line.split_whitespace();
+        let Some(command) = parts.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = parts.collect();
+
+        match command {
+            "s" | "step" => {
+                let count = parse_count(args.first())?;
+                for _ in 0..count {
+                    if self.vm.is_halted() || self.waiting_for_input() {
+                        break;
+                    }
+                    if let Err(e) = self.vm.step() {
+                        writeln!(output, "error: {}", e)?;
+                        break;
+                    }
+                    if !self.vm.watch_hits().is_empty() {
+                        break;
+                    }
+                }
+                self.report_watch_hits(output)?;
+                self.report_position(output)?;
+            }
+            "c" | "continue" => {
+                if let Err(e) = self.continue_forward() {
+                    writeln!(output, "error: {}", e)?;
+                }
+                self.report_watch_hits(output)?;
+                self.report_position(output)?;
+            }
+            "sb" | "step-back" => {
+                let count = parse_count(args.first())?;
+                let mut undone = 0;
+                while undone < count && self.vm.step_back() {
+                    undone += 1;
+                }
+                if undone < count {
+                    writeln!(output, "reached the start of the recorded history")?;
+                }
+                self.report_position(output)?;
+            }
+            "rc" | "reverse-continue" => {
+                if !self.reverse_continue() {
+                    writeln!(output, "reached the start of the recorded history")?;
+                }
+                self.report_position(output)?;
+            }
+            "b" | "break" => {
+                let addr = self.parse_location(args.first())?;
+                self.breakpoints.insert(addr);
+                writeln!(output, "breakpoint at x{:04X}", addr)?;
+            }
+            "d" | "delete" => {
+                let addr = self.parse_location(args.first())?;
+                if self.breakpoints.remove(&addr) {
+                    writeln!(output, "deleted breakpoint at x{:04X}", addr)?;
+                } else {
+                    writeln!(output, "no breakpoint at x{:04X}", addr)?;
+                }
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match command {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                let watchpoint = parse_watchpoint(kind, &args)?;
+                let description = watchpoint.to_string();
+                let id = self.vm.add_watchpoint(watchpoint);
+                writeln!(output, "watchpoint {}: {}", id, description)?;
+            }
+            "unwatch" => {
+                let id = parse_count(args.first())?;
+                if self.vm.remove_watchpoint(id) {
+                    writeln!(output, "deleted watchpoint {}", id)?;
+                } else {
+                    writeln!(output, "no watchpoint {}", id)?;
+                }
+            }
+            "watches" => {
+                for (id, watchpoint) in self.vm.watchpoints() {
+                    writeln!(output, "watchpoint {}: {}", id, watchpoint)?;
+                }
+            }
+            "who" => {
+                let addr = parse_address(args.first())?;
+                match self.vm.last_writer(addr) {
+                    Some(record) => writeln!(
+                        output,
+                        "x{:04X} last written by x{:04X} ({:#06x}) at instruction {}{}",
+                        addr,
+                        record.pc,
+                        record.instruction,
+                        record.count,
+                        self.source_suffix(record.pc)
+                    )?,
+                    None => writeln!(output, "no recorded write to x{:04X}", addr)?,
+                }
+            }
+            "r" | "regs" => self.print_registers(output)?,
+            "x" => {
+                let addr = parse_address(args.first())?;
+                let count = parse_count(args.get(1))?;
+                for offset in 0..count {
+                    let addr = addr.wrapping_add((offset as u16).wrapping_mul(self.vm.isa().word_size()));
+                    writeln!(
+                        output,
+                        "x{:04X}: x{:04X}{}",
+                        addr,
+                        self.vm.read_memory(addr),
+                        self.source_suffix(addr)
+                    )?;
+                }
+            }
+            // more input would replace the replay and the run would no longer be the recorded one
+            "input" if self.vm.is_replaying_input() => {
+                writeln!(output, "input comes from the replayed recording")?
+            }
+            "input" => {
+                // the rest of the line as typed, inner spaces included
+                let text = line.split_once(char::is_whitespace).map_or("", |(_, text)| text.trim_start());
+                self.vm.push_input(format!("{}\n", text).as_bytes());
+                writeln!(output, "{} bytes of input queued", self.vm.pending_input())?;
+            }
+            "h" | "help" => write!(output, "{}", HELP)?,
+            "q" | "quit" => return Ok(false),
+            _ => writeln!(output, "unknown command '{}', try 'help'", command)?,
+        }
+        Ok(true)
+    }
+
+    /// steps at least once, then until the pc reaches a breakpoint, a watchpoint
+    /// triggers, the machine halts, the guest waits for input or an instruction fails
+    pub fn continue_forward(&mut self) -> Result<()> {
+        while !self.vm.is_halted() && !self.waiting_for_input() {
+            self.vm.step()?;
+            if self.breakpoints.contains(&self.vm.pc()) || !self.vm.watch_hits().is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// steps back at least once, then until the pc reaches a breakpoint.
+    /// returns false when the history ran out first
+    pub fn reverse_continue(&mut self) -> bool {
+        while self.vm.step_back() {
+            if self.breakpoints.contains(&self.vm.pc()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// the next instruction is GETC or IN and no input is queued for it. the guest never
+    /// reads stdin under the debugger, that is where the commands come from
+    pub fn waiting_for_input(&self) -> bool {
+        !self.vm.is_halted()
+            && self.vm.pending_input() == 0
+            && matches!(decode(self.vm.read_memory(self.vm.pc())), Instruction::Trap { vector: 0x20 | 0x23 })
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// returns false if there was none at `addr`
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    fn report_watch_hits<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for hit in self.vm.watch_hits() {
+            writeln!(output, "{}", hit)?;
+        }
+        Ok(())
+    }
+
+    fn report_position<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        if self.vm.is_halted() {
+            writeln!(output, "halted after {} instructions", self.vm.instruction_count())
+        } else if self.waiting_for_input() {
+            writeln!(output, "pc x{:04X}: waiting for input, queue some with 'input <text>'", self.vm.pc())
+        } else {
+            let pc = self.vm.pc();
+            writeln!(output, "pc x{:04X}: x{:04X}{}", pc, self.vm.read_memory(pc), self.source_suffix(pc))
+        }
+    }
+
+    /// `  prog.asm:12  ADD R0, R0, #1` for an address the source map knows
+    fn source_suffix(&self, addr: u16) -> String {
+        match self.vm.source_line(addr) {
+            Some(entry) => format!("  {}  {}", entry, entry.text),
+            None => String::new(),
+        }
+    }
+
+    /// an address, or `file:line` looked up in the source map
+    fn parse_location(&self, arg: Option<&&str>) -> io::Result<u16> {
+        let Some((file, line)) = arg.and_then(|arg| arg.rsplit_once(':')) else {
+            return parse_address(arg);
+        };
+        let line = line
+            .parse()
+            .map_err(|_| invalid_input(format!("invalid line number '{}'", line)))?;
+        let map = self
+            .vm
+            .source_map()
+            .ok_or_else(|| invalid_input("no source map loaded".to_string()))?;
+        map.address(file, line)
+            .ok_or_else(|| invalid_input(format!("no code at {}:{}", file, line)))
+    }
+
+    fn print_registers<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for register in &Register::ALL[..8] {
+            write!(output, "{} x{:04X}  ", register, self.vm.register(*register))?;
+            if *register == Register::R3 {
+                writeln!(output)?;
+            }
+        }
+        writeln!(output)?;
+        let cond = self.vm.condition();
+        writeln!(output, "PC x{:04X}  COND x{:04X} ({})", self.vm.pc(), cond.bits(), cond)
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_count(arg: Option<&&str>) -> io::Result<usize> {
+    match arg {
+        None => Ok(1),
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| invalid_input(format!("invalid count '{}'", arg))),
+    }
+}
+
+fn parse_address(arg: Option<&&str>) -> io::Result<u16> {
+    let arg = arg.ok_or_else(|| invalid_input("missing address".to_string()))?;
+    parse_word(arg).ok_or_else(|| invalid_input(format!("invalid address '{}'", arg)))
+}
+
+/// `<addr>[-<addr>] [old=<value> | new=<value> | changed]`
+fn parse_watchpoint(kind: WatchKind, args: &[&str]) -> io::Result<Watchpoint> {
+    let range = args.first().ok_or_else(|| invalid_input("missing address".to_string()))?;
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_address(Some(&start))?, parse_address(Some(&end))?),
+        None => {
+            let addr = parse_address(Some(range))?;
+            (addr, addr)
+        }
+    };
+    if end < start {
+        return Err(invalid_input(format!("empty range '{}'", range)));
+    }
+
+    let condition = match &args[1..] {
+        [] => WatchCondition::Always,
+        ["changed"] => WatchCondition::Changed,
+        [condition] => {
+            let value = |v: &str| parse_word(v).ok_or_else(|| invalid_input(format!("invalid value '{}'", v)));
+            match condition.split_once('=') {
+                Some(("old", v)) => WatchCondition::OldEquals(value(v)?),
+                Some(("new", v)) => WatchCondition::NewEquals(value(v)?),
+                _ => return Err(invalid_input(format!("invalid condition '{}'", condition))),
+            }
+        }
+        _ => return Err(invalid_input("expected at most one condition".to_string())),
+    };
+    Ok(Watchpoint::new(start..=end, kind).with_condition(condition))
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// everything that can go wrong while loading or running a program
+#[derive(Debug)]
+pub enum Error {
+    /// register index outside r0-r7, pc and cond
+    InvalidRegister(u16),
+    UnknownTrap { vector: u8, pc: u16 },
+    /// an encoding the ISA does not allow, only raised in strict mode
+    IllegalInstruction { instruction: u16, pc: u16, reason: &'static str },
+    /// the guest asked for input but stdin is closed or the scripted input ran out
+    EndOfInput,
+    /// a replayed byte was consumed at a different instruction than it was recorded at
+    ReplayDiverged { byte: u8, recorded: u64, consumed: u64 },
+    /// an object file without even the origin word
+    EmptyImage,
+    ImageTooLarge { origin: u16, len: usize },
+    /// `file` is none for source that was not read from a file
+    Assemble { file: Option<String>, line: usize, message: String },
+    /// objects that cannot be linked, or an object file that does not parse
+    Link(String),
+    /// a test spec that does not parse or refers to something that does not exist
+    InvalidSpec(String),
+    /// a microcode file that does not parse
+    Microcode { line: usize, message: String },
+    /// the cycle-level model reached a state it cannot go on from
+    MicrocodeStuck { state: u8, pc: u16, reason: &'static str },
+    Io(io::Error),
+}
+
+impl Error {
+    /// address of the instruction a guest fault happened at
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            Error::UnknownTrap { pc, .. }
+            | Error::IllegalInstruction { pc, .. }
+            | Error::MicrocodeStuck { pc, .. } => Some(*pc),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidRegister(index) => write!(f, "invalid register index {}", index),
+            Error::UnknownTrap { vector, pc } => write!(f, "unknown TRAP vector x{:02X} at x{:04X}", vector, pc),
+            Error::IllegalInstruction { instruction, pc, reason } => {
+                write!(f, "illegal instruction x{:04X} at x{:04X}: {}", instruction, pc, reason)
+            }
+            Error::EndOfInput => write!(f, "the program asked for input but there is none left"),
+            Error::ReplayDiverged { byte, recorded, consumed } => write!(
+                f,
+                "input replay diverged: byte {} was recorded at instruction {} but consumed at {}",
+                byte, recorded, consumed
+            ),
+            Error::EmptyImage => write!(f, "object file is empty, expected an origin word"),
+            Error::ImageTooLarge { origin, len } => {
+                write!(f, "{} words starting at x{:04X} do not fit in memory", len, origin)
+            }
+            Error::Assemble { file: Some(file), line, message } => write!(f, "{}:{}: {}", file, line, message),
+            Error::Assemble { file: None, line, message } => write!(f, "line {}: {}", line, message),
+            Error::Link(message) => write!(f, "{}", message),
+            Error::InvalidSpec(message) => write!(f, "invalid test spec: {}", message),
+            Error::Microcode { line, message } => write!(f, "line {}: {}", line, message),
+            Error::MicrocodeStuck { state, pc, reason } => {
+                write!(f, "microcode stuck in state {} at x{:04X}: {}", state, pc, reason)
+            }
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
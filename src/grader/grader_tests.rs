@@ -0,0 +1,101 @@
+use super::*;
+
+const RUBRIC: &str = r#"
+max_instructions = 1000
+
+[[case]]
+name = "doubles 3"
+points = 2
+registers = { R1 = 3 }
+expect = { registers = { R0 = 6 } }
+
+[[case]]
+name = "prints the result"
+points = 3
+registers = { R1 = 2 }
+expect = { output = "result\n4\n" }
+
+[[case]]
+name = "terminates"
+registers = { R1 = 1 }
+timeout_ms = 1000
+"#;
+
+fn submission(source: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rustvm-grader-{}-{}.asm",
+        std::process::id(),
+        source.len()
+    ));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn points_are_awarded_per_passing_case() {
+    let student = submission(
+        r#"
+        .ORIG x3000
+        ADD R0, R1, R1
+        ST R0, SAVE
+        LEA R0, MSG
+        PUTS
+        LD R0, SAVE
+        LD R2, ZERO
+        ADD R0, R0, R2
+        OUT
+        HALT
+SAVE    .FILL 0
+ZERO    .FILL x30
+MSG     .STRINGZ "result\n"
+        .END
+"#,
+    );
+    let grade = grade(&Spec::parse(RUBRIC).unwrap(), &student, "alice");
+    // R0 ends up as the printed digit and the newline after it is missing
+    assert_eq!((grade.score, grade.max_score), (1, 6));
+    assert_eq!(grade.cases[1].output_diff.as_deref(), Some(" result\n 4\n-\n"));
+    assert_eq!(grade.cases[2].points, 1);
+}
+
+#[test]
+fn infinite_loops_time_out_without_stopping_the_grading() {
+    let student = submission(".ORIG x3000\nLOOP BR LOOP\n.END\n");
+    let grade = grade(&Spec::parse(RUBRIC).unwrap(), &student, "bob");
+    assert_eq!(grade.score, 0);
+    assert!(grade.cases.iter().all(|case| case.result.outcome == Outcome::Timeout));
+    assert_eq!(grade.cases[0].result.instructions, 1000);
+}
+
+#[test]
+fn panics_become_crashed_cases() {
+    let result = isolated("boom", || panic!("register file on fire"));
+    assert_eq!(result.outcome, Outcome::Crash);
+    assert_eq!(result.failures, vec!["the emulator crashed: register file on fire".to_string()]);
+}
+
+#[test]
+fn reports_are_json_and_junit() {
+    let student = submission(".ORIG x3000\nTRAP x99\n.END\n");
+    let grade = grade(&Spec::parse(RUBRIC).unwrap(), &student, "carol <c>");
+
+    let mut json = Vec::new();
+    grade.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["max_score"], 6);
+    assert_eq!(json["cases"][0]["outcome"], "error");
+    let error = format!("unknown TRAP vector x99 at x3000 ({}:2)", student.display());
+    assert_eq!(json["cases"][0]["error"], error.as_str());
+
+    let mut junit = Vec::new();
+    grade.write_junit(&mut junit).unwrap();
+    let junit = String::from_utf8(junit).unwrap();
+    assert!(junit.contains(r#"<testsuite name="carol &lt;c&gt;" tests="3" failures="3" errors="0">"#));
+    assert!(junit.contains(&format!("<failure message=\"expected outcome Halt, got Error: {}\">", error)));
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    assert_eq!(diff_lines("a\nb\nc", "a\nx\nc"), " a\n+x\n-b\n c\n");
+    assert_eq!(diff_lines("same", "same"), " same\n");
+}
@@ -0,0 +1,223 @@
+use crate::runner::{Case, CaseResult, Outcome, Spec};
+use serde::Serialize;
+use std::any::Any;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+#[cfg(test)]
+mod grader_tests;
+
+/// points a case is worth when the rubric does not say
+pub const DEFAULT_POINTS: u32 = 1;
+
+/// result of grading one submission against a rubric
+#[derive(Debug, Clone, Serialize)]
+pub struct Grade {
+    pub student: String,
+    pub submission: String,
+    pub score: u32,
+    pub max_score: u32,
+    pub cases: Vec<GradedCase>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GradedCase {
+    pub name: String,
+    pub points: u32,
+    pub max_points: u32,
+    #[serde(flatten)]
+    pub result: CaseResult,
+    /// expected against actual output, only for cases with an output mismatch
+    pub output_diff: Option<String>,
+}
+
+/// a rubric is a test spec whose cases carry `points` and run the submission
+/// unless they name their own program
+pub fn grade(rubric: &Spec, submission: &Path, student: &str) -> Grade {
+    let mut spec = rubric.clone();
+    // relative to the current directory, not to the rubric
+    let path = std::path::absolute(submission).unwrap_or_else(|_| submission.to_path_buf());
+    spec.program = Some(path.to_string_lossy().into_owned());
+
+    let cases: Vec<GradedCase> = rubric.cases.iter().map(|case| grade_case(&spec, case)).collect();
+    Grade {
+        student: student.to_string(),
+        submission: submission.display().to_string(),
+        score: cases.iter().map(|case| case.points).sum(),
+        max_score: cases.iter().map(|case| case.max_points).sum(),
+        cases,
+    }
+}
+
+/// runs one case in a fresh vm
+fn grade_case(spec: &Spec, case: &Case) -> GradedCase {
+    let result = isolated(&case.name, || spec.run_case(case));
+
+    let max_points = case.points.unwrap_or(DEFAULT_POINTS);
+    let output_diff = case
+        .expect
+        .output
+        .as_ref()
+        .filter(|expected| **expected != result.output && result.outcome != Outcome::Crash)
+        .map(|expected| diff_lines(expected, &result.output));
+    GradedCase {
+        name: case.name.clone(),
+        points: if result.passed() { max_points } else { 0 },
+        max_points,
+        result,
+        output_diff,
+    }
+}
+
+/// a panic inside `run` fails only this case
+fn isolated(name: &str, run: impl FnOnce() -> CaseResult) -> CaseResult {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+        let message = panic_message(&payload);
+        CaseResult {
+            name: name.to_string(),
+            outcome: Outcome::Crash,
+            instructions: 0,
+            output: String::new(),
+            error: Some(message.clone()),
+            failures: vec![format!("the emulator crashed: {}", message)],
+        }
+    })
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// line diff in unified style: ` ` common, `-` only expected, `+` only actual
+pub fn diff_lines(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.split('\n').collect();
+    let new: Vec<&str> = actual.split('\n').collect();
+
+    // lengths of the longest common subsequences of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        } else {
+            diff.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        }
+    }
+    diff
+}
+
+impl Grade {
+    pub fn write_json<W: Write>(&self, output: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(output, self).map_err(io::Error::other)
+    }
+
+    /// one test suite per student, crashes are junit errors and everything else failures
+    pub fn write_junit<W: Write>(&self, mut output: W) -> io::Result<()> {
+        let failures = self
+            .cases
+            .iter()
+            .filter(|case| !case.result.passed() && case.result.outcome != Outcome::Crash)
+            .count();
+        let errors = self.cases.iter().filter(|case| case.result.outcome == Outcome::Crash).count();
+
+        writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(output, "<testsuites>")?;
+        writeln!(
+            output,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}">"#,
+            xml_escape(&self.student),
+            self.cases.len(),
+            failures,
+            errors
+        )?;
+        writeln!(
+            output,
+            r#"    <properties><property name="score" value="{}/{}"/></properties>"#,
+            self.score, self.max_score
+        )?;
+        for case in &self.cases {
+            write!(
+                output,
+                r#"    <testcase name="{}" classname="{}""#,
+                xml_escape(&case.name),
+                xml_escape(&self.student)
+            )?;
+            if case.result.passed() {
+                writeln!(output, "/>")?;
+                continue;
+            }
+            writeln!(output, ">")?;
+            let tag = if case.result.outcome == Outcome::Crash { "error" } else { "failure" };
+            let mut details = case.result.failures.join("\n");
+            if let Some(diff) = &case.output_diff {
+                details.push_str("\n\n");
+                details.push_str(diff);
+            }
+            writeln!(
+                output,
+                r#"      <{} message="{}">{}</{}>"#,
+                tag,
+                xml_escape(&case.result.failures[0]),
+                xml_escape(&details),
+                tag
+            )?;
+            writeln!(output, "    </testcase>")?;
+        }
+        writeln!(output, "  </testsuite>")?;
+        writeln!(output, "</testsuites>")
+    }
+
+    pub fn write_text<W: Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}: {}/{}", self.student, self.score, self.max_score)?;
+        for case in &self.cases {
+            writeln!(output, "  {:>3}/{:<3} {}", case.points, case.max_points, case.name)?;
+            for failure in &case.result.failures {
+                writeln!(output, "          {}", failure)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in xml 1.0 at all, guest output can contain anything
+            c if (c as u32) < 0x20 && !matches!(c, '\n' | '\r' | '\t') => {
+                escaped.push_str(&format!("\\x{:02X}", c as u32));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,53 @@
+use super::registers::Register;
+use std::fmt;
+
+/// return address handed to a called subroutine, the word just below user space.
+/// the call is over once the pc reaches it
+pub const RETURN_SENTINEL: u16 = 0x2FFF;
+
+/// registers a subroutine has to restore by the usual LC-3 convention: R0 carries the
+/// result and R7 the return address
+pub const CALLEE_SAVED: [Register; 6] = [
+    Register::R1,
+    Register::R2,
+    Register::R3,
+    Register::R4,
+    Register::R5,
+    Register::R6,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    /// control came back to the sentinel
+    Returned,
+    /// the subroutine halted the machine instead of returning
+    Halted,
+    /// the instruction budget ran out first
+    Timeout,
+}
+
+/// a register the subroutine did not restore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clobber {
+    pub register: Register,
+    pub before: u16,
+    pub after: u16,
+}
+
+impl fmt::Display for Clobber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} not preserved: x{:04X} before the call, x{:04X} after",
+            self.register, self.before, self.after
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallResult {
+    pub outcome: CallOutcome,
+    pub instructions: u64,
+    /// only checked when the subroutine returned
+    pub clobbered: Vec<Clobber>,
+}
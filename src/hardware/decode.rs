@@ -0,0 +1,104 @@
+use super::processor::OpCode;
+use super::registers::Register;
+use crate::utils::sign_extend;
+
+/// second source of ADD and AND
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    /// already sign extended
+    Immediate(u16),
+}
+
+/// an instruction with its fields extracted and offsets sign extended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Add { dr: Register, sr1: Register, operand: Operand },
+    And { dr: Register, sr1: Register, operand: Operand },
+    Not { dr: Register, sr: Register },
+    Br { nzp: u16, offset: u16 },
+    Jmp { base: Register },
+    Jsr { offset: u16 },
+    Jsrr { base: Register },
+    Ld { dr: Register, offset: u16 },
+    Ldi { dr: Register, offset: u16 },
+    Ldr { dr: Register, base: Register, offset: u16 },
+    Lea { dr: Register, offset: u16 },
+    St { sr: Register, offset: u16 },
+    Sti { sr: Register, offset: u16 },
+    Str { sr: Register, base: Register, offset: u16 },
+    Trap { vector: u8 },
+    Rti,
+    Res,
+}
+
+pub fn decode(instr: u16) -> Instruction {
+    let dr = Register::general(instr >> 9);
+    let sr1 = Register::general(instr >> 6);
+    let pcoffset9 = || sign_extend(instr & 0x1FF, 9);
+    let offset6 = || sign_extend(instr & 0x3F, 6);
+    let operand = || match (instr >> 5) & 0x1 {
+        0 => Operand::Register(Register::general(instr)),
+        _ => Operand::Immediate(sign_extend(instr & 0x1F, 5)),
+    };
+
+    match OpCode::get_op_code(&instr).expect("every 4 bit value is an opcode") {
+        OpCode::ADD => Instruction::Add { dr, sr1, operand: operand() },
+        OpCode::AND => Instruction::And { dr, sr1, operand: operand() },
+        OpCode::NOT => Instruction::Not { dr, sr: sr1 },
+        OpCode::BR => Instruction::Br { nzp: (instr >> 9) & 0x7, offset: pcoffset9() },
+        OpCode::JMP => Instruction::Jmp { base: sr1 },
+        OpCode::JSR => match (instr >> 11) & 0x1 {
+            0 => Instruction::Jsrr { base: sr1 },
+            _ => Instruction::Jsr { offset: sign_extend(instr & 0x7FF, 11) },
+        },
+        OpCode::LD => Instruction::Ld { dr, offset: pcoffset9() },
+        OpCode::LDI => Instruction::Ldi { dr, offset: pcoffset9() },
+        OpCode::LDR => Instruction::Ldr { dr, base: sr1, offset: offset6() },
+        OpCode::LEA => Instruction::Lea { dr, offset: pcoffset9() },
+        OpCode::ST => Instruction::St { sr: dr, offset: pcoffset9() },
+        OpCode::STI => Instruction::Sti { sr: dr, offset: pcoffset9() },
+        OpCode::STR => Instruction::Str { sr: dr, base: sr1, offset: offset6() },
+        OpCode::TRAP => Instruction::Trap { vector: (instr & 0xFF) as u8 },
+        OpCode::RTI => Instruction::Rti,
+        OpCode::RES => Instruction::Res,
+    }
+}
+
+/// why `instr` breaks the ISA, if it does: reserved opcodes, RTI without a supervisor
+/// mode and fields the encoding fixes to zeros or ones
+pub fn spec_violation(instr: u16) -> Option<&'static str> {
+    let must_be = |mask: u16, value: u16| instr & mask == value;
+    let well_formed = match OpCode::get_op_code(&instr).expect("every 4 bit value is an opcode") {
+        OpCode::RES => return Some("reserved opcode"),
+        OpCode::RTI => return Some("RTI in user mode"),
+        OpCode::ADD | OpCode::AND => instr & 0x20 != 0 || must_be(0x18, 0),
+        OpCode::NOT => must_be(0x3F, 0x3F),
+        OpCode::JMP => must_be(0xE3F, 0),
+        OpCode::JSR => instr & 0x800 != 0 || must_be(0x63F, 0),
+        OpCode::TRAP => must_be(0xF00, 0),
+        _ => true,
+    };
+    if well_formed { None } else { Some("reserved bits set") }
+}
+
+/// decoded instructions per address, an entry is dropped whenever its word is written
+pub(super) struct DecodeCache {
+    entries: Box<[Option<Instruction>]>,
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; super::memory::MEMORY_SIZE].into_boxed_slice(),
+        }
+    }
+
+    pub fn get_or_decode(&mut self, addr: u16, word: u16) -> Instruction {
+        *self.entries[addr as usize].get_or_insert_with(|| decode(word))
+    }
+
+    pub fn invalidate(&mut self, addr: u16) {
+        self.entries[addr as usize] = None;
+    }
+}
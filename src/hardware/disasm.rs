@@ -0,0 +1,68 @@
+use super::processor::OpCode;
+use crate::utils::sign_extend;
+
+pub fn trap_name(vector: u8) -> Option<&'static str> {
+    match vector {
+        0x20 => Some("GETC"),
+        0x21 => Some("OUT"),
+        0x22 => Some("PUTS"),
+        0x23 => Some("IN"),
+        0x24 => Some("PUTSP"),
+        0x25 => Some("HALT"),
+        _ => None,
+    }
+}
+
+/// renders `instr` fetched from `addr` as assembly, pc relative operands become absolute addresses
+pub fn disassemble(addr: u16, instr: u16) -> String {
+    let op = OpCode::get_op_code(&instr).expect("every 4 bit value is an opcode");
+    let dr = (instr >> 9) & 0x7;
+    let sr1 = (instr >> 6) & 0x7;
+    let pc = addr.wrapping_add(1);
+    let pc_target = |bits: u8| pc.wrapping_add(sign_extend(instr & ((1 << bits) - 1), bits));
+    let second_operand = || {
+        if (instr >> 5) & 0x1 == 1 {
+            format!("#{}", sign_extend(instr & 0x1F, 5) as i16)
+        } else {
+            format!("R{}", instr & 0x7)
+        }
+    };
+
+    match op {
+        OpCode::BR => {
+            let nzp = (instr >> 9) & 0x7;
+            if nzp == 0 {
+                return format!(".FILL x{:04X}", instr);
+            }
+            let flags: String = [(4, 'n'), (2, 'z'), (1, 'p')]
+                .iter()
+                .filter(|(bit, _)| nzp & bit != 0)
+                .map(|(_, flag)| flag)
+                .collect();
+            format!("BR{} x{:04X}", flags, pc_target(9))
+        }
+        OpCode::ADD => format!("ADD R{}, R{}, {}", dr, sr1, second_operand()),
+        OpCode::AND => format!("AND R{}, R{}, {}", dr, sr1, second_operand()),
+        OpCode::NOT => format!("NOT R{}, R{}", dr, sr1),
+        OpCode::LD => format!("LD R{}, x{:04X}", dr, pc_target(9)),
+        OpCode::LDI => format!("LDI R{}, x{:04X}", dr, pc_target(9)),
+        OpCode::LEA => format!("LEA R{}, x{:04X}", dr, pc_target(9)),
+        OpCode::ST => format!("ST R{}, x{:04X}", dr, pc_target(9)),
+        OpCode::STI => format!("STI R{}, x{:04X}", dr, pc_target(9)),
+        OpCode::LDR => format!("LDR R{}, R{}, #{}", dr, sr1, sign_extend(instr & 0x3F, 6) as i16),
+        OpCode::STR => format!("STR R{}, R{}, #{}", dr, sr1, sign_extend(instr & 0x3F, 6) as i16),
+        OpCode::JMP if sr1 == 7 => "RET".to_string(),
+        OpCode::JMP => format!("JMP R{}", sr1),
+        OpCode::JSR if (instr >> 11) & 0x1 == 1 => format!("JSR x{:04X}", pc_target(11)),
+        OpCode::JSR => format!("JSRR R{}", sr1),
+        OpCode::TRAP => {
+            let vector = (instr & 0xFF) as u8;
+            match trap_name(vector) {
+                Some(name) => name.to_string(),
+                None => format!("TRAP x{:02X}", vector),
+            }
+        }
+        OpCode::RTI => "RTI".to_string(),
+        OpCode::RES => format!(".FILL x{:04X}", instr),
+    }
+}
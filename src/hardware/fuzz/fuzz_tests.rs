@@ -0,0 +1,104 @@
+use super::*;
+
+fn single(instruction: u16, registers: [u16; 8]) -> FuzzCase {
+    FuzzCase {
+        registers,
+        pc: 0x3000,
+        cond: 0b010,
+        program: vec![instruction],
+        memory_seed: 0,
+        memory: Vec::new(),
+        steps: 1,
+    }
+}
+
+#[test]
+fn models_agree_on_ordinary_instructions() {
+    // ADD R1, R2, #-1; LDR R3, R1, #2; STR R3, R2, #0; BRn #-4
+    let case = FuzzCase {
+        program: vec![0x12BF, 0x6642, 0x7680, 0x09FC],
+        memory_seed: 7,
+        steps: 12,
+        ..single(0, [0, 0, 0x4000, 0, 0, 0, 0, 0])
+    };
+    assert!(check(&case).is_none());
+}
+
+#[test]
+fn reference_model_follows_the_isa() {
+    let mut memory = vec![0u16; 0x10000];
+    // JSRR R7 jumps to the old R7
+    memory[0x3000] = 0x41C0;
+    let mut reference = Reference {
+        r: [0, 0, 0, 0, 0, 0, 0, 0x4000],
+        pc: 0x3000,
+        n: false,
+        z: true,
+        p: false,
+    };
+    reference.step(&mut memory[..]);
+    assert_eq!((reference.pc, reference.r[7]), (0x4000, 0x3001));
+
+    // LDI R0, #-1 reads its own word as the address
+    memory[0x4000] = 0xA1FF;
+    memory[0xA1FF] = 0x8001;
+    reference.step(&mut memory[..]);
+    assert_eq!(reference.r[0], 0x8001);
+    assert_eq!(reference.cond(), 0b100);
+}
+
+#[test]
+fn jsrr_r7_agrees_with_the_reference() {
+    assert!(check(&single(0x41C0, [0, 0, 0, 0, 0, 0, 0, 0x1234])).is_none());
+}
+
+#[test]
+fn fuzzing_finds_no_divergence() {
+    for seed in 0..8 {
+        if let Some(divergence) = Fuzzer::new(seed).run(2_000) {
+            panic!("seed {}: {}", seed, divergence);
+        }
+    }
+}
+
+#[test]
+fn divergence_report_shows_the_reproducer_and_the_difference() {
+    // what JSRR R7 used to do
+    let reproducer = single(0x41C0, [0, 0, 0, 0, 0, 0, 0, 0x1234]);
+    let expected = State {
+        registers: [0, 0, 0, 0, 0, 0, 0, 0x3001],
+        pc: 0x1234,
+        cond: 0b010,
+        writes: Vec::new(),
+    };
+    let divergence = Divergence {
+        case: reproducer.clone(),
+        step: 0,
+        reproducer,
+        actual: State {
+            pc: 0x3001,
+            ..expected.clone()
+        },
+        expected,
+    };
+    let report = divergence.to_string();
+    assert!(report.starts_with("divergence at instruction 1 of the run"), "{}", report);
+    assert!(report.contains("R7 x1234  COND z\nx3000  x41C0  JSRR R7\n"), "{}", report);
+    assert!(report.contains("other memory zeroed\n"), "{}", report);
+    assert!(report.ends_with("PC: reference x1234, processor x3001\n"), "{}", report);
+}
+
+#[test]
+fn programs_placed_near_the_top_of_memory_wrap_around() {
+    // ADD R0, R0, #1 four times from xFFFE, the run wraps to x0000
+    let case = FuzzCase {
+        pc: 0xFFFE,
+        program: vec![0x1021; 4],
+        steps: 4,
+        ..single(0x1021, [0; 8])
+    };
+    assert!(check(&case).is_none());
+    let report = case.to_string();
+    assert!(report.contains("xFFFF  x1021  ADD R0, R0, #1\nx0000  x1021"), "{}", report);
+    assert!(report.contains("x0001  x1021"), "{}", report);
+}
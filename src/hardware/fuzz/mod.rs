@@ -0,0 +1,367 @@
+//! differential fuzzing: random programs and machine states run through the processor and
+//! through an independent reference model, the first disagreement is shrunk to a reproducer
+
+use super::disasm::disassemble;
+use super::memory::Bus;
+use super::processor::Processor;
+use super::registers::{Condition, Register};
+use std::collections::HashMap;
+use std::fmt;
+
+mod reference;
+
+pub use reference::Reference;
+
+#[cfg(test)]
+mod fuzz_tests;
+
+/// splitmix64, small and good enough for picking instructions
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.0)
+    }
+
+    /// uniform in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn word(&mut self) -> u16 {
+        self.next_u64() as u16
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 64K words derived from a seed, so a case does not have to spell out all of memory,
+/// seed 0 is zeroed memory
+struct SeededMemory {
+    seed: u64,
+    words: HashMap<u16, u16>,
+    /// (address, value) of every write since the last `take_writes`
+    writes: Vec<(u16, u16)>,
+}
+
+impl SeededMemory {
+    fn new(case: &FuzzCase) -> Self {
+        let mut words: HashMap<u16, u16> = case.memory.iter().copied().collect();
+        for (offset, &word) in case.program.iter().enumerate() {
+            words.insert(case.pc.wrapping_add(offset as u16), word);
+        }
+        SeededMemory {
+            seed: case.memory_seed,
+            words,
+            writes: Vec::new(),
+        }
+    }
+
+    fn take_writes(&mut self) -> Vec<(u16, u16)> {
+        std::mem::take(&mut self.writes)
+    }
+
+    /// every word that may differ from the seeded contents except the one at `pc`, in address order
+    fn changed(&self, pc: u16) -> Vec<(u16, u16)> {
+        let mut changed: Vec<(u16, u16)> = self
+            .words
+            .iter()
+            .filter(|&(&addr, _)| addr != pc)
+            .map(|(&addr, &word)| (addr, word))
+            .collect();
+        changed.sort_unstable();
+        changed
+    }
+}
+
+impl Bus for SeededMemory {
+    fn read(&mut self, addr: u16) -> u16 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u16 {
+        match self.words.get(&addr) {
+            Some(&word) => word,
+            None if self.seed == 0 => 0,
+            None => mix(self.seed ^ addr as u64) as u16,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        self.words.insert(addr, value);
+        self.writes.push((addr, value));
+    }
+}
+
+/// initial state of one fuzzing run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzCase {
+    pub registers: [u16; 8],
+    pub pc: u16,
+    /// nzp bits
+    pub cond: u16,
+    /// placed at `pc`
+    pub program: Vec<u16>,
+    pub memory_seed: u64,
+    /// (address, word) overriding the seeded memory
+    pub memory: Vec<(u16, u16)>,
+    /// instructions to run at most, a run also stops at an instruction the model leaves out
+    pub steps: usize,
+}
+
+/// registers after an instruction and the words it wrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub registers: [u16; 8],
+    pub pc: u16,
+    pub cond: u16,
+    pub writes: Vec<(u16, u16)>,
+}
+
+/// first instruction after which the processor and the reference model disagree
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub case: FuzzCase,
+    /// index of the instruction in the run
+    pub step: usize,
+    /// the machine right before that instruction, as a case running only it
+    pub reproducer: FuzzCase,
+    pub expected: State,
+    pub actual: State,
+}
+
+/// runs `case` on both models, comparing them after every instruction
+pub fn check(case: &FuzzCase) -> Option<Divergence> {
+    let mut reference = Reference {
+        r: case.registers,
+        pc: case.pc,
+        n: false,
+        z: false,
+        p: false,
+    };
+    reference.set_cond(case.cond);
+    let mut expected_memory = SeededMemory::new(case);
+
+    let mut processor = Processor::new();
+    for (i, &value) in case.registers.iter().enumerate() {
+        processor.registers[Register::general(i as u16)] = value;
+    }
+    processor.registers.set_condition(Condition::from_bits(case.cond));
+    let mut actual_memory = SeededMemory::new(case);
+
+    for step in 0..case.steps {
+        let pc = reference.pc;
+        let instruction = expected_memory.read(pc);
+        if !Reference::models(instruction) {
+            return None;
+        }
+        let reproducer = FuzzCase {
+            registers: reference.r,
+            pc,
+            cond: reference.cond(),
+            program: vec![instruction],
+            memory_seed: case.memory_seed,
+            memory: expected_memory.changed(pc),
+            steps: 1,
+        };
+
+        reference.step(&mut expected_memory);
+        processor.registers.set_pc(pc.wrapping_add(1));
+        processor
+            .execute(instruction, &mut actual_memory)
+            .expect("the modelled instructions never fail");
+
+        let expected = State {
+            registers: reference.r,
+            pc: reference.pc,
+            cond: reference.cond(),
+            writes: expected_memory.take_writes(),
+        };
+        let actual = State {
+            registers: std::array::from_fn(|i| processor.registers[Register::general(i as u16)]),
+            pc: processor.registers.pc(),
+            cond: processor.registers.condition().bits(),
+            writes: actual_memory.take_writes(),
+        };
+        if expected != actual {
+            return Some(Divergence {
+                case: case.clone(),
+                step,
+                reproducer,
+                expected,
+                actual,
+            });
+        }
+    }
+    None
+}
+
+/// shrinks the reproducer of `divergence` one simplification at a time, keeping each one
+/// that still diverges: zeroed memory, no extra words, zero registers, pc x3000, z set
+pub fn minimize(mut divergence: Divergence) -> Divergence {
+    loop {
+        let current = &divergence.reproducer;
+        let mut candidates = Vec::new();
+        if current.memory_seed != 0 {
+            candidates.push(FuzzCase {
+                memory_seed: 0,
+                ..current.clone()
+            });
+        }
+        if !current.memory.is_empty() {
+            candidates.push(FuzzCase {
+                memory: Vec::new(),
+                ..current.clone()
+            });
+        }
+        for i in 0..current.memory.len() {
+            let mut candidate = current.clone();
+            candidate.memory.remove(i);
+            candidates.push(candidate);
+        }
+        for i in 0..8 {
+            if current.registers[i] != 0 {
+                let mut candidate = current.clone();
+                candidate.registers[i] = 0;
+                candidates.push(candidate);
+            }
+        }
+        if current.pc != 0x3000 {
+            candidates.push(FuzzCase {
+                pc: 0x3000,
+                ..current.clone()
+            });
+        }
+        if current.cond != 0b010 {
+            candidates.push(FuzzCase {
+                cond: 0b010,
+                ..current.clone()
+            });
+        }
+
+        let Some(smaller) = candidates.iter().find_map(check) else {
+            return divergence;
+        };
+        divergence.reproducer = smaller.reproducer;
+        divergence.expected = smaller.expected;
+        divergence.actual = smaller.actual;
+    }
+}
+
+/// generates cases from a seed, the same seed always finds the same divergence
+pub struct Fuzzer {
+    rng: Rng,
+    pub program_len: usize,
+    pub steps: usize,
+}
+
+/// register values that tend to reach edge cases
+const INTERESTING: [u16; 6] = [0x0000, 0x0001, 0x7FFF, 0x8000, 0xFFFF, 0x3000];
+
+impl Fuzzer {
+    pub fn new(seed: u64) -> Self {
+        Fuzzer {
+            rng: Rng::new(seed),
+            program_len: 8,
+            steps: 16,
+        }
+    }
+
+    fn value(&mut self) -> u16 {
+        if self.rng.below(4) == 0 {
+            INTERESTING[self.rng.below(INTERESTING.len() as u64) as usize]
+        } else {
+            self.rng.word()
+        }
+    }
+
+    /// any instruction the reference model covers
+    fn instruction(&mut self) -> u16 {
+        loop {
+            let instruction = self.rng.word();
+            if Reference::models(instruction) {
+                return instruction;
+            }
+        }
+    }
+
+    pub fn case(&mut self) -> FuzzCase {
+        FuzzCase {
+            registers: std::array::from_fn(|_| self.value()),
+            pc: self.rng.word(),
+            cond: 1 << self.rng.below(3) as u16,
+            program: (0..self.program_len).map(|_| self.instruction()).collect(),
+            memory_seed: self.rng.next_u64() | 1,
+            memory: Vec::new(),
+            steps: self.steps,
+        }
+    }
+
+    /// checks up to `cases` cases and minimizes the first divergence
+    pub fn run(&mut self, cases: u64) -> Option<Divergence> {
+        (0..cases).find_map(|_| check(&self.case())).map(minimize)
+    }
+}
+
+impl fmt::Display for FuzzCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, value) in self.registers.iter().enumerate() {
+            write!(f, "R{} x{:04X}  ", i, value)?;
+        }
+        writeln!(f, "COND {}", Condition::from_bits(self.cond))?;
+        for (offset, &word) in self.program.iter().enumerate() {
+            let addr = self.pc.wrapping_add(offset as u16);
+            writeln!(f, "x{:04X}  x{:04X}  {}", addr, word, disassemble(addr, word))?;
+        }
+        for (addr, word) in &self.memory {
+            writeln!(f, "x{:04X}  x{:04X}", addr, word)?;
+        }
+        match self.memory_seed {
+            0 => write!(f, "other memory zeroed"),
+            seed => write!(f, "other memory from seed {}", seed),
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    /// the reproducer followed by every value the two models disagree on
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "divergence at instruction {} of the run, reproducer:", self.step + 1)?;
+        writeln!(f, "{}", self.reproducer)?;
+        let (expected, actual) = (&self.expected, &self.actual);
+        for i in 0..8 {
+            if expected.registers[i] != actual.registers[i] {
+                writeln!(
+                    f,
+                    "R{}: reference x{:04X}, processor x{:04X}",
+                    i, expected.registers[i], actual.registers[i]
+                )?;
+            }
+        }
+        if expected.pc != actual.pc {
+            writeln!(f, "PC: reference x{:04X}, processor x{:04X}", expected.pc, actual.pc)?;
+        }
+        if expected.cond != actual.cond {
+            writeln!(
+                f,
+                "COND: reference {}, processor {}",
+                Condition::from_bits(expected.cond),
+                Condition::from_bits(actual.cond)
+            )?;
+        }
+        if expected.writes != actual.writes {
+            writeln!(f, "writes: reference {:04X?}, processor {:04X?}", expected.writes, actual.writes)?;
+        }
+        Ok(())
+    }
+}
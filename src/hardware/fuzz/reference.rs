@@ -0,0 +1,138 @@
+//! the LC-3 instruction set written down from the ISA reference (Patt & Patel, appendix A),
+//! on purpose sharing no code with the processor it is checked against
+
+use crate::hardware::memory::Bus;
+
+/// bits `high` down to `low` of `word`
+fn bits(word: u16, high: u32, low: u32) -> u16 {
+    (word >> low) & ((1 << (high - low + 1)) - 1)
+}
+
+/// sign extends the low `width` bits
+fn sext(value: u16, width: u32) -> u16 {
+    let shift = 16 - width;
+    (((value << shift) as i16) >> shift) as u16
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub r: [u16; 8],
+    pub pc: u16,
+    pub n: bool,
+    pub z: bool,
+    pub p: bool,
+}
+
+impl Reference {
+    /// condition codes as the nzp bits of BR
+    pub fn cond(&self) -> u16 {
+        (self.n as u16) << 2 | (self.z as u16) << 1 | self.p as u16
+    }
+
+    pub fn set_cond(&mut self, cond: u16) {
+        self.n = cond & 0b100 != 0;
+        self.z = cond & 0b010 != 0;
+        self.p = cond & 0b001 != 0;
+    }
+
+    fn setcc(&mut self, value: u16) {
+        self.n = value & 0x8000 != 0;
+        self.z = value == 0;
+        self.p = !self.n && !self.z;
+    }
+
+    /// whether `instruction` is modelled, TRAP, RTI and the reserved opcode go through
+    /// the operating system or an exception instead
+    pub fn models(instruction: u16) -> bool {
+        !matches!(bits(instruction, 15, 12), 0b1111 | 0b1000 | 0b1101)
+    }
+
+    /// fetches and executes one instruction
+    pub fn step<M: Bus + ?Sized>(&mut self, memory: &mut M) {
+        let ir = memory.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+
+        let dr = bits(ir, 11, 9) as usize;
+        let sr1 = bits(ir, 8, 6) as usize;
+        let pc_offset9 = sext(bits(ir, 8, 0), 9);
+        let offset6 = sext(bits(ir, 5, 0), 6);
+
+        match bits(ir, 15, 12) {
+            // ADD, AND
+            op @ (0b0001 | 0b0101) => {
+                let operand = if bits(ir, 5, 5) == 1 {
+                    sext(bits(ir, 4, 0), 5)
+                } else {
+                    self.r[bits(ir, 2, 0) as usize]
+                };
+                let result = if op == 0b0001 {
+                    self.r[sr1].wrapping_add(operand)
+                } else {
+                    self.r[sr1] & operand
+                };
+                self.r[dr] = result;
+                self.setcc(result);
+            }
+            // NOT
+            0b1001 => {
+                let result = !self.r[sr1];
+                self.r[dr] = result;
+                self.setcc(result);
+            }
+            // BR
+            0b0000 => {
+                let (n, z, p) = (bits(ir, 11, 11) == 1, bits(ir, 10, 10) == 1, bits(ir, 9, 9) == 1);
+                if (n && self.n) || (z && self.z) || (p && self.p) {
+                    self.pc = self.pc.wrapping_add(pc_offset9);
+                }
+            }
+            // JMP, RET
+            0b1100 => self.pc = self.r[sr1],
+            // JSR, JSRR: the target is computed before R7 is written
+            0b0100 => {
+                let temp = self.pc;
+                self.pc = if bits(ir, 11, 11) == 1 {
+                    self.pc.wrapping_add(sext(bits(ir, 10, 0), 11))
+                } else {
+                    self.r[sr1]
+                };
+                self.r[7] = temp;
+            }
+            // LD
+            0b0010 => {
+                let value = memory.read(self.pc.wrapping_add(pc_offset9));
+                self.r[dr] = value;
+                self.setcc(value);
+            }
+            // LDI
+            0b1010 => {
+                let address = memory.read(self.pc.wrapping_add(pc_offset9));
+                let value = memory.read(address);
+                self.r[dr] = value;
+                self.setcc(value);
+            }
+            // LDR
+            0b0110 => {
+                let value = memory.read(self.r[sr1].wrapping_add(offset6));
+                self.r[dr] = value;
+                self.setcc(value);
+            }
+            // LEA, setting the condition codes like the second edition of the book
+            0b1110 => {
+                let value = self.pc.wrapping_add(pc_offset9);
+                self.r[dr] = value;
+                self.setcc(value);
+            }
+            // ST
+            0b0011 => memory.write(self.pc.wrapping_add(pc_offset9), self.r[dr]),
+            // STI
+            0b1011 => {
+                let address = memory.read(self.pc.wrapping_add(pc_offset9));
+                memory.write(address, self.r[dr]);
+            }
+            // STR
+            0b0111 => memory.write(self.r[sr1].wrapping_add(offset6), self.r[dr]),
+            _ => {}
+        }
+    }
+}
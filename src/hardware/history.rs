@@ -0,0 +1,71 @@
+use super::registers::Register;
+use std::collections::VecDeque;
+
+/// everything needed to undo a single executed instruction
+#[derive(Debug, Clone)]
+pub struct UndoRecord {
+    /// address the instruction was fetched from
+    pub pc: u16,
+    pub instruction: u16,
+    /// number of instructions executed before this one
+    pub count: u64,
+    /// (register, value before the instruction) for every register it changed
+    pub registers: Vec<(Register, u16)>,
+    /// (word index, value before the instruction) for every word it overwrote, oldest first.
+    /// the index is the address on LC-3 and half of it on LC-3b
+    pub memory: Vec<(u16, u16)>,
+}
+
+/// bounded ring buffer of undo records, the oldest ones are dropped once it is full
+pub struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity.min(1 << 16)),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: UndoRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+
+    /// the most recently recorded instruction
+    pub fn last(&self) -> Option<&UndoRecord> {
+        self.records.back()
+    }
+
+    /// most recent recorded instruction that wrote to `addr`
+    pub fn last_write(&self, addr: u16) -> Option<&UndoRecord> {
+        self.records
+            .iter()
+            .rev()
+            .find(|record| record.memory.iter().any(|&(a, _)| a == addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -0,0 +1,71 @@
+use super::{disasm, lc3b};
+use std::fmt;
+
+/// instruction set the processor runs. LC-3b is byte addressed, its PC and every address
+/// the vm hands out count bytes and words sit at even addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Isa {
+    #[default]
+    Lc3,
+    Lc3b,
+}
+
+impl Isa {
+    /// `lc3` or `lc3b`, in any case
+    pub fn parse(name: &str) -> Option<Isa> {
+        match name.to_ascii_lowercase().as_str() {
+            "lc3" | "lc-3" => Some(Isa::Lc3),
+            "lc3b" | "lc-3b" => Some(Isa::Lc3b),
+            _ => None,
+        }
+    }
+
+    /// addresses from one word to the next, what the PC moves by
+    pub fn word_size(self) -> u16 {
+        match self {
+            Isa::Lc3 => 1,
+            Isa::Lc3b => 2,
+        }
+    }
+
+    /// index of the word holding `addr` in a word array, the low bit of an LC-3b address
+    /// is ignored
+    pub fn word_index(self, addr: u16) -> u16 {
+        match self {
+            Isa::Lc3 => addr,
+            Isa::Lc3b => addr >> 1,
+        }
+    }
+
+    /// address of the word at `index` in a word array
+    pub fn word_address(self, index: u16) -> u16 {
+        match self {
+            Isa::Lc3 => index,
+            Isa::Lc3b => index << 1,
+        }
+    }
+
+    pub fn disassemble(self, addr: u16, instr: u16) -> String {
+        match self {
+            Isa::Lc3 => disasm::disassemble(addr, instr),
+            Isa::Lc3b => lc3b::disassemble(addr, instr),
+        }
+    }
+
+    /// why `instr` breaks the ISA, if it does, see `decode::spec_violation`
+    pub fn spec_violation(self, instr: u16) -> Option<&'static str> {
+        match self {
+            Isa::Lc3 => super::decode::spec_violation(instr),
+            Isa::Lc3b => lc3b::spec_violation(instr),
+        }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Isa::Lc3 => write!(f, "LC-3"),
+            Isa::Lc3b => write!(f, "LC-3b"),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::{Error, Result};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// keyboard status register, bit 15 is set when a key is available
+pub const KBSR: u16 = 0xFE00;
+/// keyboard data register, holds the last key made available through KBSR
+pub const KBDR: u16 = 0xFE02;
+
+/// a byte handed to the guest and the instruction count at which it was consumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub count: u64,
+    pub byte: u8,
+}
+
+/// parses a recording written by `--record-input`: one `<count> <byte>` pair per line, `#` starts a comment
+pub fn read_recording<R: BufRead>(reader: R) -> Result<Vec<InputEvent>> {
+    let mut events = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let event = match (fields.next(), fields.next(), fields.next()) {
+            (Some(count), Some(byte), None) => count.parse().ok().zip(byte.parse().ok()),
+            _ => None,
+        };
+        let (count, byte) = event.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("invalid input recording line {}: '{}'", number + 1, line))
+        })?;
+        events.push(InputEvent { count, byte });
+    }
+    Ok(events)
+}
+
+enum Source {
+    /// stdin, read on a background thread so KBSR can be polled without blocking. it is the
+    /// only reader, a front-end that takes commands from stdin scripts the input instead
+    Stdin(Option<Receiver<u8>>),
+    Replay(VecDeque<InputEvent>),
+    /// fixed input that is always ready, for scripted and benchmark runs
+    Script(VecDeque<u8>),
+}
+
+/// where guest input comes from, shared by the keyboard registers and the input traps
+pub(super) struct Keyboard {
+    source: Source,
+    recording: Option<Box<dyn Write>>,
+    /// instructions executed before the one currently running
+    clock: u64,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self {
+            source: Source::Stdin(None),
+            recording: None,
+            clock: 0,
+        }
+    }
+
+    pub fn set_clock(&mut self, count: u64) {
+        self.clock = count;
+    }
+
+    pub fn record(&mut self, mut writer: Box<dyn Write>) -> io::Result<()> {
+        writeln!(writer, "# rustvm input recording: <instruction count> <byte>")?;
+        self.recording = Some(writer);
+        Ok(())
+    }
+
+    pub fn replay(&mut self, events: Vec<InputEvent>) {
+        self.source = Source::Replay(events.into());
+    }
+
+    pub fn script(&mut self, input: Vec<u8>) {
+        self.source = Source::Script(input.into());
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self.source, Source::Replay(_))
+    }
+
+    /// appends to the scripted input, replacing stdin or a replay
+    pub fn push(&mut self, input: &[u8]) {
+        match &mut self.source {
+            Source::Script(bytes) => bytes.extend(input),
+            _ => self.script(input.to_vec()),
+        }
+    }
+
+    /// scripted or replayed bytes not consumed yet, stdin never has any
+    pub fn pending(&self) -> usize {
+        match &self.source {
+            Source::Stdin(_) => 0,
+            Source::Replay(events) => events.len(),
+            Source::Script(bytes) => bytes.len(),
+        }
+    }
+
+    /// next byte if one is available right now, used for KBSR
+    pub fn poll(&mut self) -> Result<Option<u8>> {
+        let clock = self.clock;
+        let byte = match &mut self.source {
+            // a closed stdin just never becomes ready again
+            Source::Stdin(receiver) => stdin_receiver(receiver).try_recv().ok(),
+            Source::Replay(events) => match events.front() {
+                Some(event) if event.count > clock => None,
+                Some(_) => Some(next_replayed(events, clock)?),
+                None => None,
+            },
+            Source::Script(bytes) => bytes.pop_front(),
+        };
+        if let Some(byte) = byte {
+            self.record_event(byte)?;
+        }
+        Ok(byte)
+    }
+
+    /// next byte, waiting for one if necessary, used by the input traps
+    pub fn read(&mut self) -> Result<u8> {
+        let clock = self.clock;
+        let byte = match &mut self.source {
+            Source::Stdin(receiver) => stdin_receiver(receiver).recv().map_err(|_| Error::EndOfInput)?,
+            Source::Replay(events) if events.is_empty() => return Err(Error::EndOfInput),
+            Source::Replay(events) => next_replayed(events, clock)?,
+            Source::Script(bytes) => bytes.pop_front().ok_or(Error::EndOfInput)?,
+        };
+        self.record_event(byte)?;
+        Ok(byte)
+    }
+
+    fn record_event(&mut self, byte: u8) -> Result<()> {
+        if let Some(writer) = self.recording.as_mut() {
+            // flushed per event so a recording survives the guest being killed
+            writeln!(writer, "{} {}", self.clock, byte)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn stdin_receiver(receiver: &mut Option<Receiver<u8>>) -> &Receiver<u8> {
+    receiver.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buf = [0u8];
+            while let Ok(1) = stdin.read(&mut buf) {
+                if sender.send(buf[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    })
+}
+
+fn next_replayed(events: &mut VecDeque<InputEvent>, clock: u64) -> Result<u8> {
+    let event = events.pop_front().ok_or(Error::EndOfInput)?;
+    if event.count != clock {
+        return Err(Error::ReplayDiverged {
+            byte: event.byte,
+            recorded: event.count,
+            consumed: clock,
+        });
+    }
+    Ok(event.byte)
+}
@@ -0,0 +1,236 @@
+//! LC-3b: byte addressed, with LDB/STB, LDW/STW, shifts and XOR where LC-3 has LD, ST,
+//! LDI, STI and NOT. PC relative offsets count words and are scaled to bytes when decoded
+
+use super::decode::Operand;
+use super::disasm::trap_name;
+use super::memory::Bus;
+use super::processor::ExecutionResult;
+use super::registers::{Register, Registers};
+use crate::error::Result;
+use crate::utils::sign_extend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    Left,
+    RightLogical,
+    RightArithmetic,
+}
+
+/// an LC-3b instruction with its fields extracted, offsets sign extended and word offsets
+/// already scaled to bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Add { dr: Register, sr1: Register, operand: Operand },
+    And { dr: Register, sr1: Register, operand: Operand },
+    /// NOT is XOR with #-1
+    Xor { dr: Register, sr1: Register, operand: Operand },
+    Br { nzp: u16, offset: u16 },
+    Jmp { base: Register },
+    Jsr { offset: u16 },
+    Jsrr { base: Register },
+    Ldb { dr: Register, base: Register, offset: u16 },
+    Ldw { dr: Register, base: Register, offset: u16 },
+    Stb { sr: Register, base: Register, offset: u16 },
+    Stw { sr: Register, base: Register, offset: u16 },
+    Shf { dr: Register, sr: Register, shift: Shift, amount: u16 },
+    Lea { dr: Register, offset: u16 },
+    Trap { vector: u8 },
+    Rti,
+    /// 1010 and 1011
+    Unused,
+}
+
+pub fn decode(instr: u16) -> Instruction {
+    let dr = Register::general(instr >> 9);
+    let sr1 = Register::general(instr >> 6);
+    let pcoffset9 = || sign_extend(instr & 0x1FF, 9) << 1;
+    let offset6 = || sign_extend(instr & 0x3F, 6);
+    let operand = || match (instr >> 5) & 0x1 {
+        0 => Operand::Register(Register::general(instr)),
+        _ => Operand::Immediate(sign_extend(instr & 0x1F, 5)),
+    };
+
+    match instr >> 12 {
+        0b0000 => Instruction::Br { nzp: (instr >> 9) & 0x7, offset: pcoffset9() },
+        0b0001 => Instruction::Add { dr, sr1, operand: operand() },
+        0b0010 => Instruction::Ldb { dr, base: sr1, offset: offset6() },
+        0b0011 => Instruction::Stb { sr: dr, base: sr1, offset: offset6() },
+        0b0100 => match (instr >> 11) & 0x1 {
+            0 => Instruction::Jsrr { base: sr1 },
+            _ => Instruction::Jsr { offset: sign_extend(instr & 0x7FF, 11) << 1 },
+        },
+        0b0101 => Instruction::And { dr, sr1, operand: operand() },
+        0b0110 => Instruction::Ldw { dr, base: sr1, offset: offset6() << 1 },
+        0b0111 => Instruction::Stw { sr: dr, base: sr1, offset: offset6() << 1 },
+        0b1000 => Instruction::Rti,
+        0b1001 => Instruction::Xor { dr, sr1, operand: operand() },
+        0b1100 => Instruction::Jmp { base: sr1 },
+        0b1101 => {
+            let shift = match (instr >> 4) & 0x3 {
+                0b00 | 0b10 => Shift::Left,
+                0b01 => Shift::RightLogical,
+                _ => Shift::RightArithmetic,
+            };
+            Instruction::Shf { dr, sr: sr1, shift, amount: instr & 0xF }
+        }
+        0b1110 => Instruction::Lea { dr, offset: pcoffset9() },
+        0b1111 => Instruction::Trap { vector: (instr & 0xFF) as u8 },
+        _ => Instruction::Unused,
+    }
+}
+
+/// why `instr` breaks the LC-3b ISA, if it does
+pub fn spec_violation(instr: u16) -> Option<&'static str> {
+    let must_be = |mask: u16, value: u16| instr & mask == value;
+    let well_formed = match instr >> 12 {
+        0b1010 | 0b1011 => return Some("unused opcode"),
+        0b1000 => return Some("RTI in user mode"),
+        0b0001 | 0b0101 | 0b1001 => instr & 0x20 != 0 || must_be(0x18, 0),
+        0b0100 => instr & 0x800 != 0 || must_be(0x63F, 0),
+        0b1100 => must_be(0xE3F, 0),
+        0b1101 => must_be(0x30, 0) || !must_be(0x30, 0x20),
+        0b1111 => must_be(0xF00, 0),
+        _ => true,
+    };
+    if well_formed { None } else { Some("reserved bits set") }
+}
+
+/// renders `instr` fetched from byte address `addr`, pc relative operands become absolute
+/// addresses and LDW/STW show the offset field as written, in words
+pub fn disassemble(addr: u16, instr: u16) -> String {
+    let register = |r: Register| format!("R{}", r.index());
+    let operand = |operand: Operand| match operand {
+        Operand::Register(r) => register(r),
+        Operand::Immediate(value) => format!("#{}", value as i16),
+    };
+    let three = |name: &str, dr: Register, sr1: Register, third: String| {
+        format!("{} {}, {}, {}", name, register(dr), register(sr1), third)
+    };
+    let pc = addr.wrapping_add(2);
+
+    match decode(instr) {
+        Instruction::Br { nzp: 0, .. } | Instruction::Unused => format!(".FILL x{:04X}", instr),
+        Instruction::Br { nzp, offset } => {
+            let flags: String = [(4, 'n'), (2, 'z'), (1, 'p')]
+                .iter()
+                .filter(|(bit, _)| nzp & bit != 0)
+                .map(|(_, flag)| flag)
+                .collect();
+            format!("BR{} x{:04X}", flags, pc.wrapping_add(offset))
+        }
+        Instruction::Add { dr, sr1, operand: second } => three("ADD", dr, sr1, operand(second)),
+        Instruction::And { dr, sr1, operand: second } => three("AND", dr, sr1, operand(second)),
+        Instruction::Xor {
+            dr,
+            sr1,
+            operand: Operand::Immediate(0xFFFF),
+        } => format!("NOT {}, {}", register(dr), register(sr1)),
+        Instruction::Xor { dr, sr1, operand: second } => three("XOR", dr, sr1, operand(second)),
+        Instruction::Ldb { dr, base, offset } => three("LDB", dr, base, format!("#{}", offset as i16)),
+        Instruction::Stb { sr, base, offset } => three("STB", sr, base, format!("#{}", offset as i16)),
+        Instruction::Ldw { dr, base, offset } => three("LDW", dr, base, format!("#{}", offset as i16 >> 1)),
+        Instruction::Stw { sr, base, offset } => three("STW", sr, base, format!("#{}", offset as i16 >> 1)),
+        Instruction::Shf { dr, sr, shift, amount } => {
+            let name = match shift {
+                Shift::Left => "LSHF",
+                Shift::RightLogical => "RSHFL",
+                Shift::RightArithmetic => "RSHFA",
+            };
+            three(name, dr, sr, format!("#{}", amount))
+        }
+        Instruction::Lea { dr, offset } => format!("LEA {}, x{:04X}", register(dr), pc.wrapping_add(offset)),
+        Instruction::Jmp { base: Register::R7 } => "RET".to_string(),
+        Instruction::Jmp { base } => format!("JMP {}", register(base)),
+        Instruction::Jsr { offset } => format!("JSR x{:04X}", pc.wrapping_add(offset)),
+        Instruction::Jsrr { base } => format!("JSRR {}", register(base)),
+        Instruction::Trap { vector } => match trap_name(vector) {
+            Some(name) => name.to_string(),
+            None => format!("TRAP x{:02X}", vector),
+        },
+        Instruction::Rti => "RTI".to_string(),
+    }
+}
+
+/// the byte at `addr`, memory words are little endian
+pub fn read_byte<M: Bus + ?Sized>(memory: &mut M, addr: u16) -> u8 {
+    let word = memory.read(addr >> 1);
+    if addr & 1 == 0 { word as u8 } else { (word >> 8) as u8 }
+}
+
+/// replaces the byte at `addr`, leaving the other half of its word
+fn write_byte<M: Bus + ?Sized>(memory: &mut M, addr: u16, byte: u8) {
+    let word = memory.peek(addr >> 1);
+    let word = if addr & 1 == 0 {
+        (word & 0xFF00) | byte as u16
+    } else {
+        (word & 0x00FF) | (byte as u16) << 8
+    };
+    memory.write(addr >> 1, word);
+}
+
+/// runs `instr` on `registers`, the pc already points past it. `memory` is indexed by word
+/// so word accesses ignore the low address bit
+pub(super) fn execute<M: Bus + ?Sized>(
+    registers: &mut Registers,
+    instr: Instruction,
+    memory: &mut M,
+) -> Result<ExecutionResult> {
+    let operand_value = |registers: &Registers, operand| match operand {
+        Operand::Register(sr2) => registers[sr2],
+        Operand::Immediate(value) => value,
+    };
+    let pc = registers.pc();
+    let set = |registers: &mut Registers, dr: Register, value: u16| {
+        registers[dr] = value;
+        registers.update_r_cond_register(dr);
+    };
+    match instr {
+        Instruction::Add { dr, sr1, operand } => {
+            set(registers, dr, registers[sr1].wrapping_add(operand_value(registers, operand)))
+        }
+        Instruction::And { dr, sr1, operand } => set(registers, dr, registers[sr1] & operand_value(registers, operand)),
+        Instruction::Xor { dr, sr1, operand } => set(registers, dr, registers[sr1] ^ operand_value(registers, operand)),
+        Instruction::Br { nzp, offset } => {
+            if registers.condition().matches(nzp) {
+                registers.set_pc(pc.wrapping_add(offset));
+            }
+        }
+        Instruction::Jmp { base } => registers.set_pc(registers[base]),
+        Instruction::Jsr { offset } => {
+            registers[Register::R7] = pc;
+            registers.set_pc(pc.wrapping_add(offset));
+        }
+        Instruction::Jsrr { base } => {
+            // read the base first, JSRR R7 jumps to the old R7
+            let target = registers[base];
+            registers[Register::R7] = pc;
+            registers.set_pc(target);
+        }
+        Instruction::Ldb { dr, base, offset } => {
+            let byte = read_byte(memory, registers[base].wrapping_add(offset));
+            set(registers, dr, sign_extend(byte as u16, 8));
+        }
+        Instruction::Ldw { dr, base, offset } => {
+            let word = memory.read(registers[base].wrapping_add(offset) >> 1);
+            set(registers, dr, word);
+        }
+        Instruction::Stb { sr, base, offset } => {
+            write_byte(memory, registers[base].wrapping_add(offset), registers[sr] as u8)
+        }
+        Instruction::Stw { sr, base, offset } => memory.write(registers[base].wrapping_add(offset) >> 1, registers[sr]),
+        Instruction::Shf { dr, sr, shift, amount } => {
+            let value = registers[sr];
+            let shifted = match shift {
+                Shift::Left => value << amount,
+                Shift::RightLogical => value >> amount,
+                Shift::RightArithmetic => ((value as i16) >> amount) as u16,
+            };
+            set(registers, dr, shifted);
+        }
+        // unlike LC-3, LEA leaves the condition codes alone
+        Instruction::Lea { dr, offset } => registers[dr] = pc.wrapping_add(offset),
+        Instruction::Trap { vector } => return Ok(ExecutionResult::Trap(vector)),
+        Instruction::Rti | Instruction::Unused => {}
+    }
+    Ok(ExecutionResult::Continue)
+}
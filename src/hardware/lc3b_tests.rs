@@ -0,0 +1,136 @@
+#![allow(clippy::unusual_byte_groupings)] // literals are grouped by instruction field
+
+use super::isa::Isa;
+use super::lc3b::{disassemble, spec_violation};
+use super::processor::Processor;
+use super::registers::{Condition, ConditionFlag, Register};
+use super::vm::VM;
+use super::watch::{Access, WatchHit, WatchKind, Watchpoint};
+
+fn processor() -> Processor {
+    let mut processor = Processor::new();
+    processor.isa = Isa::Lc3b;
+    processor
+}
+
+fn memory() -> [u16; 65536] {
+    [0; 65536]
+}
+
+#[test]
+fn shifts_and_xor() {
+    let mut processor = processor();
+    let mut mem = memory();
+    processor.registers[Register::R0] = 0x8421;
+
+    for instr in [
+        0b1101_001_000_00_0100, // LSHF R1, R0, #4
+        0b1101_010_000_01_0100, // RSHFL R2, R0, #4
+        0b1101_011_000_11_0100, // RSHFA R3, R0, #4
+        0b1001_100_000_1_11111, // NOT R4, R0
+    ] {
+        processor.execute(instr, &mut mem).unwrap();
+    }
+
+    assert_eq!(processor.registers[Register::R1], 0x4210);
+    assert_eq!(processor.registers[Register::R2], 0x0842);
+    assert_eq!(processor.registers[Register::R3], 0xF842);
+    assert_eq!(processor.registers[Register::R4], 0x7BDE);
+    assert_eq!(processor.registers.condition(), Condition::from(ConditionFlag::POS));
+}
+
+#[test]
+fn word_offsets_are_scaled_to_bytes() {
+    let mut processor = processor();
+    let mut mem = memory();
+    processor.registers[Register::R1] = 0x4000;
+    mem[0x2003] = 0xBEEF;
+
+    processor.execute(0b0110_010_001_000011, &mut mem).unwrap(); // LDW R2, R1, #3
+    assert_eq!(processor.registers[Register::R2], 0xBEEF);
+    processor.execute(0b0111_010_001_111111, &mut mem).unwrap(); // STW R2, R1, #-1
+    assert_eq!(mem[0x1FFF], 0xBEEF);
+
+    processor.registers.set_pc(0x3002);
+    processor.registers.set_condition(ConditionFlag::ZRO.into());
+    processor.execute(0b1110_101_111111111, &mut mem).unwrap(); // LEA R5, #-1
+    assert_eq!(processor.registers[Register::R5], 0x3000);
+    assert!(processor.registers.condition().z(), "LEA leaves the condition codes alone");
+    processor.execute(0b0000_010_111111110, &mut mem).unwrap(); // BRz #-2
+    assert_eq!(processor.registers.pc(), 0x2FFE);
+    processor.execute(0b0100_1_00000000101, &mut mem).unwrap(); // JSR #5
+    assert_eq!(processor.registers[Register::R7], 0x2FFE);
+    assert_eq!(processor.registers.pc(), 0x3008);
+}
+
+#[test]
+fn vm_runs_byte_loads_and_stores() {
+    // x3000: LEA R1, #3      -- x3008
+    // x3002: LDB R0, R1, #1  -- high byte of x3008
+    // x3004: STB R0, R1, #2  -- low byte of x300A
+    // x3006: HALT
+    let mut vm = VM::new();
+    vm.set_isa(Isa::Lc3b);
+    vm.set_output(Box::new(std::io::sink()));
+    vm.enable_history(16);
+    let program = [0xE203, 0x2041, 0x3042, 0xF025, 0x8041, 0x1234];
+    for (offset, &word) in program.iter().enumerate() {
+        vm.write_memory(0x3000 + 2 * offset as u16, word);
+    }
+    vm.execute().unwrap();
+
+    assert_eq!(vm.instruction_count(), 4);
+    assert_eq!(vm.pc(), 0x3008);
+    assert_eq!(vm.register(Register::R0), 0xFF80);
+    assert_eq!(vm.read_memory(0x300A), 0x1280);
+    assert_eq!(vm.read_memory(0x300B), 0x1280);
+    assert_eq!(vm.last_writer(0x300B).map(|record| record.pc), Some(0x3004));
+}
+
+#[test]
+fn disassembles_and_checks_lc3b_encodings() {
+    assert_eq!(disassemble(0x3000, 0xE203), "LEA R1, x3008");
+    assert_eq!(disassemble(0x3000, 0x2041), "LDB R0, R1, #1");
+    assert_eq!(disassemble(0x3000, 0x747F), "STW R2, R1, #-1");
+    assert_eq!(disassemble(0x3000, 0xD634), "RSHFA R3, R0, #4");
+    assert_eq!(disassemble(0x3000, 0x983F), "NOT R4, R0");
+    assert_eq!(disassemble(0x3000, 0x9801), "XOR R4, R0, R1");
+    assert_eq!(disassemble(0x3000, 0x05FE), "BRz x2FFE");
+    assert_eq!(disassemble(0x3000, 0xC1C0), "RET");
+    assert_eq!(disassemble(0x3000, 0xF025), "HALT");
+    assert_eq!(disassemble(0x3000, 0xA000), ".FILL xA000");
+
+    assert_eq!(spec_violation(0x2041), None);
+    assert_eq!(spec_violation(0xA000), Some("unused opcode"));
+    assert_eq!(spec_violation(0b1101_011_000_10_0100), Some("reserved bits set"));
+    assert_eq!(Isa::parse("LC-3b"), Some(Isa::Lc3b));
+    assert_eq!(Isa::parse("mips"), None);
+}
+
+#[test]
+fn watchpoints_on_odd_bytes_fire() {
+    // x3000: LEA R1, #3      -- x3008
+    // x3002: LDB R0, R1, #1  -- high byte of x3008
+    // x3004: STB R0, R1, #3  -- high byte of x300A
+    // x3006: HALT
+    let mut vm = VM::new();
+    vm.set_isa(Isa::Lc3b);
+    vm.set_output(Box::new(std::io::sink()));
+    let program = [0xE203, 0x2041, 0x3043, 0xF025, 0x8041, 0x1234];
+    for (offset, &word) in program.iter().enumerate() {
+        vm.write_memory(0x3000 + 2 * offset as u16, word);
+    }
+    vm.add_watchpoint(Watchpoint::new(0x3009..=0x3009, WatchKind::Read));
+    vm.add_watchpoint(Watchpoint::new(0x300B..=0x300B, WatchKind::Write));
+
+    let hits = vm.run_until_watch().unwrap().to_vec();
+    assert_eq!(
+        hits,
+        vec![WatchHit { id: 0, pc: 0x3002, addr: 0x3009, access: Access::Read, old: 0x8041, new: 0x8041 }]
+    );
+    let hits = vm.run_until_watch().unwrap().to_vec();
+    assert_eq!(
+        hits,
+        vec![WatchHit { id: 1, pc: 0x3004, addr: 0x300B, access: Access::Write, old: 0x1234, new: 0x8034 }]
+    );
+}
@@ -0,0 +1,179 @@
+use super::decode::{DecodeCache, Instruction};
+use super::isa::Isa;
+use super::keyboard::{KBDR, KBSR, Keyboard};
+use super::watch::{Access, WatchHit, Watchpoint};
+use crate::error::Error;
+
+pub const MEMORY_SIZE: usize = u16::MAX as usize + 1;
+
+/// word addressed access used by the processor and the trap routines
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u16;
+    fn write(&mut self, addr: u16, value: u16);
+    /// read without device side effects or watchpoints, for read-modify-write of a byte
+    fn peek(&self, addr: u16) -> u16;
+}
+
+impl Bus for [u16] {
+    fn read(&mut self, addr: u16) -> u16 {
+        self[addr as usize]
+    }
+
+    fn peek(&self, addr: u16) -> u16 {
+        self[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        self[addr as usize] = value;
+    }
+}
+
+impl<const N: usize> Bus for [u16; N] {
+    fn read(&mut self, addr: u16) -> u16 {
+        self[addr as usize]
+    }
+
+    fn peek(&self, addr: u16) -> u16 {
+        self[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        self[addr as usize] = value;
+    }
+}
+
+/// main memory of the vm with the memory mapped keyboard registers,
+/// optionally journaling overwritten words so a step can be undone
+pub(super) struct Memory {
+    words: Box<[u16]>,
+    journal: Option<Vec<(u16, u16)>>,
+    pub keyboard: Keyboard,
+    /// indexed by watchpoint id, removed ones are left as `None`
+    pub watchpoints: Vec<Option<Watchpoint>>,
+    /// watchpoints triggered since the last `take_watch_hits`, the pc is filled in by the vm
+    watch_hits: Vec<WatchHit>,
+    decode_cache: Option<DecodeCache>,
+    /// error raised by a device during a read, reported by the vm once the instruction is done
+    fault: Option<Error>,
+    /// how the guest's addresses map onto `words`, for the device registers and watchpoints
+    isa: Isa,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            words: vec![0; MEMORY_SIZE].into_boxed_slice(),
+            journal: None,
+            keyboard: Keyboard::new(),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            decode_cache: None,
+            fault: None,
+            isa: Isa::Lc3,
+        }
+    }
+
+    pub fn set_isa(&mut self, isa: Isa) {
+        self.isa = isa;
+    }
+
+    /// read without any side effects, for inspection from outside the guest
+    pub fn peek(&self, addr: u16) -> u16 {
+        self.words[addr as usize]
+    }
+
+    /// write that bypasses the journal, used for loading and for undoing writes
+    pub fn poke(&mut self, addr: u16, value: u16) {
+        self.invalidate(addr);
+        self.words[addr as usize] = value;
+    }
+
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled.then(DecodeCache::new);
+    }
+
+    /// the instruction at `addr`, decoded once and reused until the word is written.
+    /// `None` when the cache is disabled
+    pub fn fetch_decoded(&mut self, addr: u16) -> Option<Instruction> {
+        let word = self.words[addr as usize];
+        Some(self.decode_cache.as_mut()?.get_or_decode(addr, word))
+    }
+
+    fn invalidate(&mut self, addr: u16) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.invalidate(addr);
+        }
+    }
+
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// (address, previous value) of every write since `start_journal`, oldest first
+    pub fn take_journal(&mut self) -> Vec<(u16, u16)> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    pub fn take_fault(&mut self) -> Option<Error> {
+        self.fault.take()
+    }
+
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    /// the bus only sees whole words, so on LC-3b a watchpoint on either byte of the word fires
+    fn check_watchpoints(&mut self, index: u16, access: Access, old: u16, new: u16) {
+        let first = self.isa.word_address(index);
+        let addrs = (0..self.isa.word_size()).map(|byte| first + byte);
+        for (id, watchpoint) in self.watchpoints.iter().enumerate() {
+            if let Some(watchpoint) = watchpoint
+                && let Some(addr) = addrs.clone().find(|&addr| watchpoint.matches(addr, access, old, new))
+            {
+                self.watch_hits.push(WatchHit { id, pc: 0, addr, access, old, new });
+            }
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> u16 {
+        const READY: u16 = 1 << 15;
+        let (kbsr, kbdr) = (self.isa.word_index(KBSR) as usize, self.isa.word_index(KBDR) as usize);
+        match addr as usize {
+            // a key stays ready until it is read from KBDR
+            index if index == kbsr && self.words[kbsr] & READY == 0 => {
+                match self.keyboard.poll() {
+                    Ok(Some(byte)) => {
+                        self.words[kbsr] = READY;
+                        self.words[kbdr] = byte as u16;
+                    }
+                    Ok(None) => {}
+                    Err(e) => self.fault = Some(e),
+                }
+            }
+            index if index == kbdr => self.words[kbsr] &= !READY,
+            _ => {}
+        }
+        let value = self.words[addr as usize];
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, Access::Read, value, value);
+        }
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((addr, self.words[addr as usize]));
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, Access::Write, self.words[addr as usize], value);
+        }
+        self.invalidate(addr);
+        self.words[addr as usize] = value;
+    }
+
+    fn peek(&self, addr: u16) -> u16 {
+        Memory::peek(self, addr)
+    }
+}
@@ -0,0 +1,187 @@
+use super::*;
+use crate::assembler::assemble;
+use crate::hardware::watch::{WatchKind, Watchpoint};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// every instruction the instruction-level processor implements, with memory traffic
+/// through each addressing mode
+const PROGRAM: &str = "
+        .ORIG x3000
+        LD R1, COUNT
+        LEA R2, TABLE
+LOOP    LDR R3, R2, #0
+        NOT R3, R3
+        ADD R3, R3, #1
+        STR R3, R2, #0
+        ADD R2, R2, #1
+        ADD R1, R1, #-1
+        BRp LOOP
+        LDI R4, POINTER
+        STI R4, TARGET
+        JSR DOUBLE
+        LEA R5, DOUBLE
+        JSRR R5
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        ST R4, COUNT
+        HALT
+DOUBLE  ADD R4, R4, R4
+        RET
+COUNT   .FILL 3
+POINTER .FILL TABLE
+TARGET  .FILL SLOT
+TABLE   .FILL 5
+        .FILL -7
+        .FILL 0
+SLOT    .BLKW 1
+        .END
+";
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn micro_vm(words: &[u16]) -> VM {
+    let mut vm = VM::new();
+    vm.load(&Image { origin: 0x3000, words: words.to_vec(), isa: Default::default() });
+    vm.set_output(Box::new(io::sink()));
+    vm.set_microarchitecture(Microarchitecture::new(Microcode::lc3()));
+    vm
+}
+
+fn with_state(state: &str) -> Microcode {
+    let text = include_str!("lc3.ucode");
+    let number = state.split(':').next().unwrap();
+    let lines: Vec<&str> = text.lines().filter(|line| !line.starts_with(&format!("{}:", number))).collect();
+    Microcode::parse(&format!("{}\n{}\n", lines.join("\n"), state)).unwrap()
+}
+
+#[test]
+fn cycle_model_matches_the_functional_model() {
+    let program = assemble(PROGRAM).unwrap();
+    for memory_cycles in [1, 5] {
+        let mut micro = Microarchitecture::new(Microcode::lc3());
+        micro.set_memory_cycles(memory_cycles);
+        let equivalence = check(&program.image, micro, b"", 1000);
+        assert_eq!(equivalence.mismatch, None);
+        assert!(equivalence.halted);
+        assert_eq!(equivalence.instructions, 37);
+    }
+}
+
+#[test]
+fn instructions_take_the_textbook_number_of_cycles() {
+    // ADD R1, R1, #1 ; LD R2, #1 ; HALT ; x0042
+    let mut vm = micro_vm(&[0x1261, 0x2401, 0xF025, 0x0042]);
+    vm.step().unwrap();
+    assert_eq!(vm.microarchitecture().unwrap().cycles(), 5);
+    vm.step().unwrap();
+    assert_eq!(vm.microarchitecture().unwrap().cycles(), 12);
+    assert_eq!(vm.register(Register::R2), 0x42);
+
+    let mut slow = micro_vm(&[0x1261]);
+    let mut micro = Microarchitecture::new(Microcode::lc3());
+    micro.set_memory_cycles(4);
+    slow.set_microarchitecture(micro);
+    slow.step().unwrap();
+    let micro = slow.microarchitecture().unwrap();
+    assert_eq!(micro.cycles(), 8);
+    assert_eq!(micro.state_cycles()[33], 4);
+    assert_eq!((micro.state(), micro.ir(), micro.mar()), (FETCH, 0x1261, 0x3000));
+}
+
+#[test]
+fn trace_shows_every_cycle_with_its_signals() {
+    let mut vm = micro_vm(&[0x1261, 0xF025]);
+    let trace = Shared::default();
+    let mut micro = Microarchitecture::new(Microcode::lc3());
+    micro.set_trace(Box::new(trace.clone()));
+    vm.set_microarchitecture(micro);
+    vm.execute().unwrap();
+
+    let text = String::from_utf8(trace.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 10);
+    assert_eq!(
+        lines[0],
+        "       0  18  PC=x3000 IR=x0000 MAR=x0000 MDR=x0000 BEN=0 BUS=x3000  LD.MAR LD.PC GatePC PCMUX=PC+1 J=33"
+    );
+    assert_eq!(
+        lines[4],
+        "       4   1  PC=x3001 IR=x1261 MAR=x3000 MDR=x1261 BEN=0 BUS=x0001  \
+         LD.REG LD.CC GateALU DRMUX=11.9 SR1MUX=8.6 ALUK=ADD J=18"
+    );
+    assert_eq!(lines[9], "       9  15  PC=x3002 IR=xF025 MAR=x3001 MDR=xF025 BEN=0 BUS=-----  TRAP J=18");
+}
+
+#[test]
+fn broken_microcode_is_caught_by_the_check() {
+    let program = assemble(PROGRAM).unwrap();
+    let wrong_alu = with_state("1: LD.REG LD.CC GateALU DRMUX=11.9 SR1MUX=8.6 ALUK=AND J=18");
+    let equivalence = check(&program.image, Microarchitecture::new(wrong_alu), b"", 1000);
+    let mismatch = equivalence.mismatch.unwrap();
+    assert_eq!((mismatch.count, mismatch.pc), (4, 0x3004));
+    assert_eq!(mismatch.differences, vec!["R3: xFFFB, x0000", "COND: x0004, x0002"]);
+    assert!(mismatch.to_string().starts_with("instruction 4 at x3004 (ADD R3, R3, #1) differs"));
+
+    let no_str = with_state("7: J=40");
+    let mismatch = check(&program.image, Microarchitecture::new(no_str), b"", 1000).mismatch.unwrap();
+    assert_eq!(
+        mismatch.differences[0],
+        "outcome: ok, microcode stuck in state 40 at x3005: the microcode does not define it"
+    );
+}
+
+#[test]
+fn microcode_files_are_checked_when_parsed() {
+    let error = |text: &str| Microcode::parse(text).unwrap_err().to_string();
+    assert_eq!(error("18: LD.MAR GatePC\n1: GatePC GateMDR"), "line 2: GatePC and GateMDR both drive the bus");
+    assert_eq!(error("# fetch\n18: LD.IR"), "line 2: LD.IR loads from the bus but no gate drives it");
+    assert_eq!(error("18: LD.PC PCMUX=SIDEWAYS"), "line 1: PCMUX expects one of PC+1, BUS, ADDER, not SIDEWAYS");
+    assert_eq!(error("18: J=64"), "line 1: J expects a state from 0 to 63");
+    assert_eq!(error("18: FLUX"), "line 1: unknown control signal FLUX");
+    assert_eq!(error("18: J=33\n18: J=33"), "line 2: state 18 is defined twice");
+    assert_eq!(error("1: J=18"), "line 1: there is no state 18, where every instruction is fetched");
+
+    // names are case insensitive and selects can be given by their encoding
+    let micro = Microcode::parse("18: ld.mar gatepc ld.pc pcmux=0 j=33").unwrap();
+    assert_eq!(micro.state(18), Microcode::lc3().state(18));
+    assert_eq!(micro.state(FETCH).unwrap().to_string(), "LD.MAR LD.PC GatePC PCMUX=PC+1 J=33");
+}
+
+#[test]
+fn a_stuck_instruction_leaves_the_next_one_to_start_at_fetch() {
+    // ADD R1, R1, #1 ; AND R0, R0, #0 ; ADD goes to a state the microcode leaves out
+    let mut vm = micro_vm(&[0x1261, 0x5020]);
+    vm.set_register(Register::R0, 7);
+    vm.set_microarchitecture(Microarchitecture::new(with_state("1: J=40")));
+    let error = vm.step().unwrap_err();
+    assert!(matches!(error, Error::MicrocodeStuck { state: 40, pc: 0x3000, .. }), "{}", error);
+    assert_eq!(vm.microarchitecture().unwrap().state(), FETCH);
+
+    // the AND is fetched and runs instead of carrying on from state 40
+    vm.step().unwrap();
+    assert_eq!(vm.microarchitecture().unwrap().ir(), 0x5020);
+    assert_eq!(vm.register(Register::R0), 0);
+}
+
+#[test]
+fn instruction_fetches_do_not_trigger_read_watchpoints() {
+    // LD R2, #1 ; HALT ; x0042
+    let mut vm = micro_vm(&[0x2401, 0xF025, 0x0042]);
+    vm.add_watchpoint(Watchpoint::new(0x3000..=0x3002, WatchKind::Read));
+    vm.step().unwrap();
+    let hits: Vec<u16> = vm.watch_hits().iter().map(|hit| hit.addr).collect();
+    assert_eq!(hits, vec![0x3002]);
+}
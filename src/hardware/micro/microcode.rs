@@ -0,0 +1,394 @@
+//! the control store: one microinstruction per state, read from a text file with a line per
+//! state like `18: LD.MAR LD.PC GatePC PCMUX=PC+1 J=33`
+
+use crate::error::{Error, Result};
+use std::fmt;
+
+/// states the microsequencer can name with its 6 bit J field
+pub const STATES: usize = 64;
+/// where every instruction starts, MAR <- PC
+pub const FETCH: u8 = 18;
+
+/// the microcode for the textbook datapath, see `lc3.ucode`
+const LC3: &str = include_str!("lc3.ucode");
+
+/// which source drives the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    Pc,
+    Mdr,
+    Alu,
+    MarMux,
+}
+
+/// how the next state is picked from J, one bit of it is or-ed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cond {
+    #[default]
+    Unconditional,
+    /// J[1] when memory is ready
+    Ready,
+    /// J[2] when BEN is set
+    Branch,
+    /// J[0] when IR[11] is set
+    AddressingMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PcMux {
+    #[default]
+    Increment,
+    Bus,
+    Adder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrMux {
+    #[default]
+    Ir11,
+    R7,
+    R6,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sr1Mux {
+    #[default]
+    Ir11,
+    Ir8,
+    R6,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Addr1Mux {
+    #[default]
+    Pc,
+    BaseR,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Addr2Mux {
+    #[default]
+    Zero,
+    Offset6,
+    PcOffset9,
+    PcOffset11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarMux {
+    #[default]
+    Trapvect8,
+    Adder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aluk {
+    #[default]
+    Add,
+    And,
+    Not,
+    PassA,
+}
+
+/// a multiplexer select field: its name in the file and the names of its inputs, in
+/// encoding order
+trait Select: Copy + PartialEq + 'static {
+    const FIELD: &'static str;
+    const INPUTS: &'static [(&'static str, Self)];
+
+    fn name(self) -> &'static str {
+        Self::INPUTS.iter().find(|&&(_, input)| input == self).map_or("?", |&(name, _)| name)
+    }
+
+    /// an input by name, in any case, or by its encoding
+    fn parse(value: &str) -> std::result::Result<Self, String> {
+        let by_index = value.parse::<usize>().ok().and_then(|index| Self::INPUTS.get(index));
+        Self::INPUTS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .or(by_index)
+            .map(|&(_, input)| input)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::INPUTS.iter().map(|&(name, _)| name).collect();
+                format!("{} expects one of {}, not {}", Self::FIELD, names.join(", "), value)
+            })
+    }
+}
+
+impl Select for Cond {
+    const FIELD: &'static str = "COND";
+    const INPUTS: &'static [(&'static str, Cond)] = &[
+        ("UNCONDITIONAL", Cond::Unconditional),
+        ("READY", Cond::Ready),
+        ("BRANCH", Cond::Branch),
+        ("ADDR", Cond::AddressingMode),
+    ];
+}
+
+impl Select for PcMux {
+    const FIELD: &'static str = "PCMUX";
+    const INPUTS: &'static [(&'static str, PcMux)] =
+        &[("PC+1", PcMux::Increment), ("BUS", PcMux::Bus), ("ADDER", PcMux::Adder)];
+}
+
+impl Select for DrMux {
+    const FIELD: &'static str = "DRMUX";
+    const INPUTS: &'static [(&'static str, DrMux)] = &[("11.9", DrMux::Ir11), ("R7", DrMux::R7), ("R6", DrMux::R6)];
+}
+
+impl Select for Sr1Mux {
+    const FIELD: &'static str = "SR1MUX";
+    const INPUTS: &'static [(&'static str, Sr1Mux)] =
+        &[("11.9", Sr1Mux::Ir11), ("8.6", Sr1Mux::Ir8), ("R6", Sr1Mux::R6)];
+}
+
+impl Select for Addr1Mux {
+    const FIELD: &'static str = "ADDR1MUX";
+    const INPUTS: &'static [(&'static str, Addr1Mux)] = &[("PC", Addr1Mux::Pc), ("BaseR", Addr1Mux::BaseR)];
+}
+
+impl Select for Addr2Mux {
+    const FIELD: &'static str = "ADDR2MUX";
+    const INPUTS: &'static [(&'static str, Addr2Mux)] = &[
+        ("ZERO", Addr2Mux::Zero),
+        ("offset6", Addr2Mux::Offset6),
+        ("PCoffset9", Addr2Mux::PcOffset9),
+        ("PCoffset11", Addr2Mux::PcOffset11),
+    ];
+}
+
+impl Select for MarMux {
+    const FIELD: &'static str = "MARMUX";
+    const INPUTS: &'static [(&'static str, MarMux)] = &[("7.0", MarMux::Trapvect8), ("ADDER", MarMux::Adder)];
+}
+
+impl Select for Aluk {
+    const FIELD: &'static str = "ALUK";
+    const INPUTS: &'static [(&'static str, Aluk)] =
+        &[("ADD", Aluk::Add), ("AND", Aluk::And), ("NOT", Aluk::Not), ("PASSA", Aluk::PassA)];
+}
+
+/// the control signals of one state. select fields are `None` when the file leaves them
+/// out, the datapath then uses the first input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MicroInstruction {
+    pub ird: bool,
+    pub cond: Option<Cond>,
+    pub j: u8,
+    pub ld_mar: bool,
+    pub ld_mdr: bool,
+    pub ld_ir: bool,
+    pub ld_ben: bool,
+    pub ld_reg: bool,
+    pub ld_cc: bool,
+    pub ld_pc: bool,
+    pub gate: Option<Gate>,
+    pub pcmux: Option<PcMux>,
+    pub drmux: Option<DrMux>,
+    pub sr1mux: Option<Sr1Mux>,
+    pub addr1mux: Option<Addr1Mux>,
+    pub addr2mux: Option<Addr2Mux>,
+    pub marmux: Option<MarMux>,
+    pub aluk: Option<Aluk>,
+    pub mio_en: bool,
+    /// R.W, a write when set
+    pub write: bool,
+    /// hands the TRAP in IR to the built-in trap routines instead of going through the
+    /// trap vector table, like the instruction-level processor does
+    pub trap: bool,
+}
+
+impl MicroInstruction {
+    /// sets the signal `name`, `value` is what followed an `=`
+    fn set(&mut self, name: &str, value: Option<&str>) -> std::result::Result<(), String> {
+        let flag = |signal: &mut bool| match value {
+            None => {
+                *signal = true;
+                Ok(())
+            }
+            Some(_) => Err(format!("{} takes no value", name)),
+        };
+        let gate = |this: &mut MicroInstruction, gate: Gate| match this.gate {
+            Some(other) if other != gate => Err(format!("{} and {} both drive the bus", gate_name(other), name)),
+            _ => {
+                this.gate = Some(gate);
+                Ok(())
+            }
+        };
+        fn select<T: Select>(value: Option<&str>) -> std::result::Result<Option<T>, String> {
+            let value = value.ok_or_else(|| format!("{} expects a value", T::FIELD))?;
+            T::parse(value).map(Some)
+        }
+
+        match name.to_ascii_uppercase().as_str() {
+            "IRD" => flag(&mut self.ird)?,
+            "LD.MAR" => flag(&mut self.ld_mar)?,
+            "LD.MDR" => flag(&mut self.ld_mdr)?,
+            "LD.IR" => flag(&mut self.ld_ir)?,
+            "LD.BEN" => flag(&mut self.ld_ben)?,
+            "LD.REG" => flag(&mut self.ld_reg)?,
+            "LD.CC" => flag(&mut self.ld_cc)?,
+            "LD.PC" => flag(&mut self.ld_pc)?,
+            "MIO.EN" => flag(&mut self.mio_en)?,
+            "TRAP" => flag(&mut self.trap)?,
+            "GATEPC" => gate(self, Gate::Pc)?,
+            "GATEMDR" => gate(self, Gate::Mdr)?,
+            "GATEALU" => gate(self, Gate::Alu)?,
+            "GATEMARMUX" => gate(self, Gate::MarMux)?,
+            "R.W" => {
+                self.write = match value.map(str::to_ascii_uppercase).as_deref() {
+                    Some("RD" | "0") => false,
+                    Some("WR" | "1") => true,
+                    _ => return Err("R.W expects RD or WR".to_string()),
+                }
+            }
+            "J" => {
+                self.j = value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&j: &u8| (j as usize) < STATES)
+                    .ok_or_else(|| format!("J expects a state from 0 to {}", STATES - 1))?
+            }
+            "COND" => self.cond = select(value)?,
+            "PCMUX" => self.pcmux = select(value)?,
+            "DRMUX" => self.drmux = select(value)?,
+            "SR1MUX" => self.sr1mux = select(value)?,
+            "ADDR1MUX" => self.addr1mux = select(value)?,
+            "ADDR2MUX" => self.addr2mux = select(value)?,
+            "MARMUX" => self.marmux = select(value)?,
+            "ALUK" => self.aluk = select(value)?,
+            _ => return Err(format!("unknown control signal {}", name)),
+        }
+        Ok(())
+    }
+
+    /// a load from the bus that nothing drives, the value would be undefined
+    fn floating_load(&self) -> Option<&'static str> {
+        if self.gate.is_some() {
+            return None;
+        }
+        let loads = [
+            (self.ld_mar, "LD.MAR"),
+            (self.ld_mdr && !self.mio_en, "LD.MDR"),
+            (self.ld_ir, "LD.IR"),
+            (self.ld_reg, "LD.REG"),
+            (self.ld_cc, "LD.CC"),
+            (self.ld_pc && self.pcmux == Some(PcMux::Bus), "LD.PC"),
+        ];
+        loads.iter().find(|(load, _)| *load).map(|&(_, name)| name)
+    }
+}
+
+fn gate_name(gate: Gate) -> &'static str {
+    match gate {
+        Gate::Pc => "GatePC",
+        Gate::Mdr => "GateMDR",
+        Gate::Alu => "GateALU",
+        Gate::MarMux => "GateMARMUX",
+    }
+}
+
+/// the signals as they would be written in a microcode file
+impl fmt::Display for MicroInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut signals: Vec<String> = [
+            (self.ld_mar, "LD.MAR"),
+            (self.ld_mdr, "LD.MDR"),
+            (self.ld_ir, "LD.IR"),
+            (self.ld_ben, "LD.BEN"),
+            (self.ld_reg, "LD.REG"),
+            (self.ld_cc, "LD.CC"),
+            (self.ld_pc, "LD.PC"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| name.to_string())
+        .collect();
+        signals.extend(self.gate.map(|gate| gate_name(gate).to_string()));
+        fn field<T: Select>(value: Option<T>) -> Option<String> {
+            value.map(|value| format!("{}={}", T::FIELD, value.name()))
+        }
+        signals.extend(field(self.pcmux));
+        signals.extend(field(self.drmux));
+        signals.extend(field(self.sr1mux));
+        signals.extend(field(self.addr1mux));
+        signals.extend(field(self.addr2mux));
+        signals.extend(field(self.marmux));
+        signals.extend(field(self.aluk));
+        if self.mio_en {
+            signals.push("MIO.EN".to_string());
+            signals.push(format!("R.W={}", if self.write { "WR" } else { "RD" }));
+        }
+        if self.trap {
+            signals.push("TRAP".to_string());
+        }
+        signals.extend(field(self.cond));
+        if self.ird {
+            signals.push("IRD".to_string());
+        } else {
+            signals.push(format!("J={}", self.j));
+        }
+        write!(f, "{}", signals.join(" "))
+    }
+}
+
+/// the control store, states the file leaves out are `None`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Microcode {
+    states: Vec<Option<MicroInstruction>>,
+}
+
+impl Microcode {
+    /// the textbook LC-3 state machine, without interrupts and privilege
+    pub fn lc3() -> Microcode {
+        Microcode::parse(LC3).expect("the built-in microcode parses")
+    }
+
+    /// a line per state: its number, a colon and its signals, `#` starts a comment.
+    /// a state that neither decodes with IRD nor names its next state with J goes to state 0
+    pub fn parse(text: &str) -> Result<Microcode> {
+        let mut states = vec![None; STATES];
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| Error::Microcode { line: index + 1, message };
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (state, signals) = line
+                .split_once(':')
+                .ok_or_else(|| error("expected a state number and a colon".to_string()))?;
+            let state: usize = state
+                .trim()
+                .parse()
+                .ok()
+                .filter(|&state| state < STATES)
+                .ok_or_else(|| error(format!("invalid state {}, states go from 0 to {}", state.trim(), STATES - 1)))?;
+            if states[state].is_some() {
+                return Err(error(format!("state {} is defined twice", state)));
+            }
+
+            let mut micro = MicroInstruction::default();
+            for signal in signals.split_whitespace() {
+                let (name, value) = match signal.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (signal, None),
+                };
+                micro.set(name, value).map_err(error)?;
+            }
+            if let Some(load) = micro.floating_load() {
+                return Err(error(format!("{} loads from the bus but no gate drives it", load)));
+            }
+            states[state] = Some(micro);
+        }
+        if states[FETCH as usize].is_none() {
+            return Err(Error::Microcode {
+                line: text.lines().count(),
+                message: format!("there is no state {}, where every instruction is fetched", FETCH),
+            });
+        }
+        Ok(Microcode { states })
+    }
+
+    pub fn state(&self, state: u8) -> Option<&MicroInstruction> {
+        self.states.get(state as usize)?.as_ref()
+    }
+}
@@ -0,0 +1,398 @@
+//! cycle-level model of the textbook LC-3 microarchitecture: a control store driving the
+//! microsequencer and a datapath with MAR, MDR, IR and BEN around the same registers and
+//! memory the instruction-level processor uses
+
+use super::memory::Bus;
+use super::processor::ExecutionResult;
+use super::registers::{Condition, Register, Registers};
+use super::vm::VM;
+use crate::error::{Error, Result};
+use crate::loader::Image;
+use crate::utils::sign_extend;
+use std::fmt;
+use std::io::{self, Write};
+
+mod microcode;
+
+pub use microcode::{
+    Addr1Mux, Addr2Mux, Aluk, Cond, DrMux, FETCH, Gate, MarMux, MicroInstruction, Microcode, PcMux, STATES,
+    Sr1Mux,
+};
+
+#[cfg(test)]
+mod micro_tests;
+
+/// cycles one instruction may take before the microcode is considered stuck
+const MAX_CYCLES: u32 = 10_000;
+
+/// the microsequencer and the datapath registers the instruction-level processor does not have
+pub struct Microarchitecture {
+    microcode: Microcode,
+    state: u8,
+    mar: u16,
+    mdr: u16,
+    ir: u16,
+    ben: bool,
+    /// cycles a memory access takes until R is asserted
+    memory_cycles: u32,
+    /// cycles the current access has been going on
+    waited: u32,
+    /// from the fetch state until IR is loaded. memory reads in between are instruction
+    /// fetches, which like the functional model's do not trigger read watchpoints
+    fetching: bool,
+    cycles: u64,
+    state_cycles: [u64; STATES],
+    trace: Option<Box<dyn Write>>,
+}
+
+impl Microarchitecture {
+    pub fn new(microcode: Microcode) -> Self {
+        Self {
+            microcode,
+            state: FETCH,
+            mar: 0,
+            mdr: 0,
+            ir: 0,
+            ben: false,
+            memory_cycles: 1,
+            waited: 0,
+            fetching: false,
+            cycles: 0,
+            state_cycles: [0; STATES],
+            trace: None,
+        }
+    }
+
+    /// memory is ready after `cycles` cycles of an access, at least 1
+    pub fn set_memory_cycles(&mut self, cycles: u32) {
+        self.memory_cycles = cycles.max(1);
+    }
+
+    /// write a line for every cycle: count, state, the datapath registers before the clock
+    /// edge, what is on the bus and the asserted control signals
+    pub fn set_trace(&mut self, writer: Box<dyn Write>) {
+        self.trace = Some(writer);
+    }
+
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match self.trace.as_mut() {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// clock cycles run so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// cycles spent in each state, indexed by state number
+    pub fn state_cycles(&self) -> &[u64; STATES] {
+        &self.state_cycles
+    }
+
+    /// the state the next cycle runs in
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
+    pub fn ir(&self) -> u16 {
+        self.ir
+    }
+
+    pub fn mar(&self) -> u16 {
+        self.mar
+    }
+
+    pub fn mdr(&self) -> u16 {
+        self.mdr
+    }
+
+    pub fn ben(&self) -> bool {
+        self.ben
+    }
+
+    /// runs cycles until the microsequencer is back at the fetch state or a state hands a
+    /// TRAP over to the trap routines. after an error the next instruction starts over at
+    /// the fetch state
+    pub(super) fn execute<M: Bus + ?Sized>(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut M,
+    ) -> Result<ExecutionResult> {
+        let result = self.run_instruction(registers, memory);
+        if result.is_err() {
+            self.state = FETCH;
+            self.waited = 0;
+        }
+        result
+    }
+
+    fn run_instruction<M: Bus + ?Sized>(
+        &mut self,
+        registers: &mut Registers,
+        memory: &mut M,
+    ) -> Result<ExecutionResult> {
+        let pc = registers.pc();
+        self.fetching = true;
+        for _ in 0..MAX_CYCLES {
+            let state = self.state;
+            let Some(&micro) = self.microcode.state(state) else {
+                return Err(Error::MicrocodeStuck { state, pc, reason: "the microcode does not define it" });
+            };
+            self.cycle(state, &micro, registers, memory)?;
+            if micro.trap {
+                return Ok(ExecutionResult::Trap((self.ir & 0xFF) as u8));
+            }
+            if self.state == FETCH {
+                return Ok(ExecutionResult::Continue);
+            }
+        }
+        Err(Error::MicrocodeStuck {
+            state: self.state,
+            pc,
+            reason: "the instruction does not get back to the fetch state",
+        })
+    }
+
+    /// one clock cycle: everything is computed from the values before the clock edge, then
+    /// the registers with their LD signal set latch together
+    fn cycle<M: Bus + ?Sized>(
+        &mut self,
+        state: u8,
+        micro: &MicroInstruction,
+        registers: &mut Registers,
+        memory: &mut M,
+    ) -> Result<()> {
+        let ir = self.ir;
+        let pc = registers.pc();
+        let sr1 = registers[match micro.sr1mux.unwrap_or_default() {
+            Sr1Mux::Ir11 => Register::general(ir >> 9),
+            Sr1Mux::Ir8 => Register::general(ir >> 6),
+            Sr1Mux::R6 => Register::R6,
+        }];
+        let sr2 = match ir & 0x20 {
+            0 => registers[Register::general(ir)],
+            _ => sign_extend(ir & 0x1F, 5),
+        };
+        let alu = match micro.aluk.unwrap_or_default() {
+            Aluk::Add => sr1.wrapping_add(sr2),
+            Aluk::And => sr1 & sr2,
+            Aluk::Not => !sr1,
+            Aluk::PassA => sr1,
+        };
+        let addr1 = match micro.addr1mux.unwrap_or_default() {
+            Addr1Mux::Pc => pc,
+            Addr1Mux::BaseR => sr1,
+        };
+        let addr2 = match micro.addr2mux.unwrap_or_default() {
+            Addr2Mux::Zero => 0,
+            Addr2Mux::Offset6 => sign_extend(ir & 0x3F, 6),
+            Addr2Mux::PcOffset9 => sign_extend(ir & 0x1FF, 9),
+            Addr2Mux::PcOffset11 => sign_extend(ir & 0x7FF, 11),
+        };
+        let adder = addr1.wrapping_add(addr2);
+        let bus = micro.gate.map(|gate| match gate {
+            Gate::Pc => pc,
+            Gate::Mdr => self.mdr,
+            Gate::Alu => alu,
+            Gate::MarMux => match micro.marmux.unwrap_or_default() {
+                MarMux::Trapvect8 => ir & 0xFF,
+                MarMux::Adder => adder,
+            },
+        });
+
+        // an access goes through once, in the cycle memory becomes ready
+        let ready = micro.mio_en && self.waited + 1 >= self.memory_cycles;
+        self.waited = if micro.mio_en && !ready { self.waited + 1 } else { 0 };
+        let loaded = match (ready, micro.write) {
+            (true, false) if self.fetching => Some(memory.peek(self.mar)),
+            (true, false) => Some(memory.read(self.mar)),
+            (true, true) => {
+                memory.write(self.mar, self.mdr);
+                None
+            }
+            (false, _) => None,
+        };
+
+        if self.trace.is_some() {
+            self.write_trace(state, micro, pc, bus)?;
+        }
+
+        let next = if micro.ird {
+            (ir >> 12) as u8
+        } else {
+            micro.j
+                | match micro.cond.unwrap_or_default() {
+                    Cond::Unconditional => 0,
+                    Cond::Ready => (ready as u8) << 1,
+                    Cond::Branch => (self.ben as u8) << 2,
+                    Cond::AddressingMode => ((ir >> 11) & 1) as u8,
+                }
+        };
+
+        // the parser rejects loads from an undriven bus
+        let bus = bus.unwrap_or(0);
+        if micro.ld_ben {
+            self.ben = registers.condition().matches((ir >> 9) & 0x7);
+        }
+        if micro.ld_mar {
+            self.mar = bus;
+        }
+        if micro.ld_mdr {
+            match (micro.mio_en, loaded) {
+                (false, _) => self.mdr = bus,
+                (true, Some(word)) => self.mdr = word,
+                (true, None) => {}
+            }
+        }
+        if micro.ld_ir {
+            self.ir = bus;
+            self.fetching = false;
+        }
+        if micro.ld_reg {
+            let dr = match micro.drmux.unwrap_or_default() {
+                DrMux::Ir11 => Register::general(ir >> 9),
+                DrMux::R7 => Register::R7,
+                DrMux::R6 => Register::R6,
+            };
+            registers[dr] = bus;
+        }
+        if micro.ld_cc {
+            registers.set_condition(Condition::from_value(bus));
+        }
+        if micro.ld_pc {
+            registers.set_pc(match micro.pcmux.unwrap_or_default() {
+                PcMux::Increment => pc.wrapping_add(1),
+                PcMux::Bus => bus,
+                PcMux::Adder => adder,
+            });
+        }
+
+        self.state = next;
+        self.state_cycles[state as usize] += 1;
+        self.cycles += 1;
+        Ok(())
+    }
+
+    fn write_trace(&mut self, state: u8, micro: &MicroInstruction, pc: u16, bus: Option<u16>) -> Result<()> {
+        let bus = bus.map_or("-----".to_string(), |bus| format!("x{:04X}", bus));
+        let trace = self.trace.as_mut().expect("tracing is enabled");
+        writeln!(
+            trace,
+            "{:>8}  {:>2}  PC=x{:04X} IR=x{:04X} MAR=x{:04X} MDR=x{:04X} BEN={} BUS={}  {}",
+            self.cycles, state, pc, self.ir, self.mar, self.mdr, self.ben as u8, bus, micro
+        )?;
+        Ok(())
+    }
+}
+
+/// the first instruction after which the cycle-level model and the instruction-level
+/// processor disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// number of instructions both ran before this one
+    pub count: u64,
+    pub pc: u16,
+    pub instruction: u16,
+    /// what differs, as `what: functional, cycle-level`
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "instruction {} at x{:04X} ({}) differs from the functional model:",
+            self.count,
+            self.pc,
+            super::disasm::disassemble(self.pc, self.instruction)
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// how far a lockstep run got
+#[derive(Debug)]
+pub struct Equivalence {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub halted: bool,
+    pub mismatch: Option<Mismatch>,
+}
+
+/// runs `image` on the instruction-level processor and on `micro` side by side with the
+/// same `input`, comparing registers, condition codes and the words each instruction wrote
+/// after every instruction. stops at the first mismatch, when both halt or fail the same way,
+/// or after `budget` instructions
+pub fn check(image: &Image, micro: Microarchitecture, input: &[u8], budget: u64) -> Equivalence {
+    let machine = |micro: Option<Microarchitecture>| {
+        let mut vm = VM::new();
+        vm.load(image);
+        vm.set_input(input);
+        vm.set_output(Box::new(io::sink()));
+        vm.enable_history(1);
+        if let Some(micro) = micro {
+            vm.set_microarchitecture(micro);
+        }
+        vm
+    };
+    let (mut functional, mut cycle_level) = (machine(None), machine(Some(micro)));
+
+    let mut equivalence = Equivalence { instructions: 0, cycles: 0, halted: false, mismatch: None };
+    while equivalence.instructions < budget && !functional.is_halted() {
+        let pc = functional.pc();
+        let instruction = functional.read_memory(pc);
+        let expected = functional.step();
+        let actual = cycle_level.step();
+        equivalence.instructions += 1;
+        equivalence.cycles = cycle_level.microarchitecture().map_or(0, Microarchitecture::cycles);
+
+        let mut differences = Vec::new();
+        match (&expected, &actual) {
+            (Ok(()), Ok(())) => {}
+            (Err(expected), Err(actual)) if expected.to_string() == actual.to_string() => {}
+            (expected, actual) => differences.push(format!(
+                "outcome: {}, {}",
+                expected.as_ref().map_or_else(ToString::to_string, |()| "ok".to_string()),
+                actual.as_ref().map_or_else(ToString::to_string, |()| "ok".to_string())
+            )),
+        }
+        for register in Register::ALL {
+            let (expected, actual) = (functional.register(register), cycle_level.register(register));
+            if expected != actual {
+                differences.push(format!("{}: x{:04X}, x{:04X}", register, expected, actual));
+            }
+        }
+        if functional.is_halted() != cycle_level.is_halted() {
+            differences.push(format!("halted: {}, {}", functional.is_halted(), cycle_level.is_halted()));
+        }
+        let mut written: Vec<u16> = [&functional, &cycle_level]
+            .iter()
+            .filter_map(|vm| vm.history()?.last())
+            .flat_map(|record| record.memory.iter().map(|&(addr, _)| addr))
+            .collect();
+        written.sort_unstable();
+        written.dedup();
+        for addr in written {
+            let (expected, actual) = (functional.read_memory(addr), cycle_level.read_memory(addr));
+            if expected != actual {
+                differences.push(format!("x{:04X}: x{:04X}, x{:04X}", addr, expected, actual));
+            }
+        }
+
+        if !differences.is_empty() {
+            let count = equivalence.instructions - 1;
+            equivalence.mismatch = Some(Mismatch { count, pc, instruction, differences });
+            break;
+        }
+        if expected.is_err() {
+            break;
+        }
+    }
+    equivalence.halted = functional.is_halted();
+    equivalence
+}
@@ -1,7 +1,24 @@
 pub mod vm;
-mod registers;
-mod processor;
+pub mod call;
+pub mod decode;
+pub mod disasm;
+pub mod fuzz;
+pub mod history;
+pub mod isa;
+pub mod keyboard;
+pub mod lc3b;
+pub mod memory;
+pub mod micro;
+pub mod registers;
+pub mod processor;
 mod syscalls;
+pub mod watch;
 
+#[cfg(test)]
+mod lc3b_tests;
+#[cfg(test)]
+mod opcode_tests;
 #[cfg(test)]
 mod processor_tests;
+#[cfg(test)]
+mod vm_tests;
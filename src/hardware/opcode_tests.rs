@@ -0,0 +1,277 @@
+//! every instruction over its whole encoding space, checked against the ISA definition
+#![allow(clippy::unusual_byte_groupings)] // literals are grouped by instruction field
+
+use super::decode::spec_violation;
+use super::memory::Bus;
+use super::processor::{ExecutionResult, Processor};
+use super::registers::{Condition, Register};
+
+/// register files every encoding runs with, distinct values so a wrong register shows up
+const FILES: [[u16; 8]; 3] = [
+    [0x0000, 0x0001, 0x7FFF, 0x8000, 0xFFFF, 0x1234, 0xFFF0, 0x000F],
+    [0x3000, 0xFFFE, 0x0010, 0x8001, 0x7FFE, 0x0000, 0xFE00, 0x4321],
+    [0x00FF, 0xFF00, 0x5555, 0xAAAA, 0x0002, 0x7FF0, 0x8000, 0xFFFF],
+];
+
+/// values of the incremented pc, including both ends of memory for wrap-around
+const PCS: [u16; 5] = [0x3001, 0x0000, 0x0001, 0xFFFF, 0xFF00];
+
+/// memory reads return a fixed pattern of the address and writes are only recorded
+struct PatternMemory {
+    writes: Vec<(u16, u16)>,
+}
+
+fn pattern(addr: u16) -> u16 {
+    addr.rotate_left(5) ^ 0xA5A5
+}
+
+impl Bus for PatternMemory {
+    fn read(&mut self, addr: u16) -> u16 {
+        pattern(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u16 {
+        pattern(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        self.writes.push((addr, value));
+    }
+}
+
+fn sext(value: u16, bits: u32) -> u16 {
+    if value & (1 << (bits - 1)) != 0 { value | (0xFFFF << bits) } else { value }
+}
+
+/// nzp bits for a value written to a register
+fn cc(value: u16) -> u16 {
+    match value {
+        0 => 0b010,
+        v if v & 0x8000 != 0 => 0b100,
+        _ => 0b001,
+    }
+}
+
+struct After {
+    registers: [u16; 8],
+    pc: u16,
+    cond: u16,
+    writes: Vec<(u16, u16)>,
+}
+
+/// executes `instr` with the pc already incremented to `pc`, like the vm does
+fn run(instr: u16, registers: [u16; 8], pc: u16, cond: u16) -> After {
+    let mut processor = Processor::new();
+    for (i, &value) in registers.iter().enumerate() {
+        processor.registers[Register::general(i as u16)] = value;
+    }
+    processor.registers.set_pc(pc);
+    processor.registers.set_condition(Condition::from_bits(cond));
+    let mut memory = PatternMemory { writes: Vec::new() };
+    let result = processor.execute(instr, &mut memory).unwrap();
+    assert!(matches!(result, ExecutionResult::Continue), "{:04X}", instr);
+    After {
+        registers: std::array::from_fn(|i| processor.registers[Register::general(i as u16)]),
+        pc: processor.registers.pc(),
+        cond: processor.registers.condition().bits(),
+        writes: memory.writes,
+    }
+}
+
+/// runs every `opcode` encoding with every register file at every pc
+fn for_every_encoding(opcode: u16, pcs: &[u16], check: impl Fn(u16, [u16; 8], u16, After)) {
+    for low in 0..0x1000 {
+        let instr = opcode << 12 | low;
+        for registers in FILES {
+            for &pc in pcs {
+                check(instr, registers, pc, run(instr, registers, pc, 0b010));
+            }
+        }
+    }
+}
+
+fn dr(instr: u16) -> usize {
+    (instr >> 9 & 0x7) as usize
+}
+
+fn sr1(instr: u16) -> usize {
+    (instr >> 6 & 0x7) as usize
+}
+
+/// the result of ADD or AND is written to DR with the condition codes, nothing else changes
+fn check_operate(op: fn(u16, u16) -> u16) -> impl Fn(u16, [u16; 8], u16, After) {
+    move |instr, registers, pc, after| {
+        let operand = if instr & 0x20 != 0 { sext(instr & 0x1F, 5) } else { registers[(instr & 0x7) as usize] };
+        let mut expected = registers;
+        expected[dr(instr)] = op(registers[sr1(instr)], operand);
+        assert_eq!(after.registers, expected, "{:04X}", instr);
+        assert_eq!(after.cond, cc(expected[dr(instr)]), "{:04X}", instr);
+        assert_eq!(after.pc, pc);
+        assert!(after.writes.is_empty());
+    }
+}
+
+#[test]
+fn add_every_encoding() {
+    for_every_encoding(0b0001, &PCS[..1], check_operate(u16::wrapping_add));
+}
+
+#[test]
+fn and_every_encoding() {
+    for_every_encoding(0b0101, &PCS[..1], check_operate(|a, b| a & b));
+}
+
+#[test]
+fn not_every_encoding() {
+    for_every_encoding(0b1001, &PCS[..1], |instr, registers, pc, after| {
+        let mut expected = registers;
+        expected[dr(instr)] = !registers[sr1(instr)];
+        assert_eq!(after.registers, expected, "{:04X}", instr);
+        assert_eq!(after.cond, cc(expected[dr(instr)]));
+        assert_eq!(after.pc, pc);
+    });
+}
+
+#[test]
+fn br_every_encoding_and_condition() {
+    for low in 0..0x1000 {
+        let instr = low;
+        for cond in [0b001, 0b010, 0b100] {
+            for pc in PCS {
+                let after = run(instr, FILES[0], pc, cond);
+                let taken = (instr >> 9) & cond != 0;
+                let target = if taken { pc.wrapping_add(sext(instr & 0x1FF, 9)) } else { pc };
+                assert_eq!(after.pc, target, "{:04X} with nzp {:03b}", instr, cond);
+                assert_eq!(after.cond, cond);
+                assert_eq!(after.registers, FILES[0]);
+            }
+        }
+    }
+}
+
+#[test]
+fn jmp_every_encoding() {
+    for_every_encoding(0b1100, &PCS[..1], |instr, registers, _, after| {
+        assert_eq!(after.pc, registers[sr1(instr)], "{:04X}", instr);
+        assert_eq!(after.registers, registers);
+        assert_eq!(after.cond, 0b010);
+    });
+}
+
+#[test]
+fn jsr_every_offset() {
+    for_every_encoding(0b0100, &PCS, |instr, registers, pc, after| {
+        let base = sr1(instr);
+        let target = if instr & 0x800 != 0 { pc.wrapping_add(sext(instr & 0x7FF, 11)) } else { registers[base] };
+        let mut expected = registers;
+        expected[7] = pc;
+        assert_eq!(after.pc, target, "{:04X} at {:04X}", instr, pc);
+        assert_eq!(after.registers, expected);
+        assert_eq!(after.cond, 0b010);
+    });
+}
+
+#[test]
+fn loads_every_encoding() {
+    for (opcode, indirect) in [(0b0010, false), (0b1010, true)] {
+        for_every_encoding(opcode, &PCS, |instr, registers, pc, after| {
+            let addr = pc.wrapping_add(sext(instr & 0x1FF, 9));
+            let value = if indirect { pattern(pattern(addr)) } else { pattern(addr) };
+            let mut expected = registers;
+            expected[dr(instr)] = value;
+            assert_eq!(after.registers, expected, "{:04X} at {:04X}", instr, pc);
+            assert_eq!(after.cond, cc(value));
+            assert_eq!(after.pc, pc);
+        });
+    }
+}
+
+#[test]
+fn ldr_every_encoding() {
+    for_every_encoding(0b0110, &PCS[..1], |instr, registers, pc, after| {
+        let value = pattern(registers[sr1(instr)].wrapping_add(sext(instr & 0x3F, 6)));
+        let mut expected = registers;
+        expected[dr(instr)] = value;
+        assert_eq!(after.registers, expected, "{:04X}", instr);
+        assert_eq!(after.cond, cc(value));
+        assert_eq!(after.pc, pc);
+    });
+}
+
+#[test]
+fn lea_every_encoding() {
+    for_every_encoding(0b1110, &PCS, |instr, registers, pc, after| {
+        let value = pc.wrapping_add(sext(instr & 0x1FF, 9));
+        let mut expected = registers;
+        expected[dr(instr)] = value;
+        assert_eq!(after.registers, expected, "{:04X} at {:04X}", instr, pc);
+        // second edition semantics, LEA sets the condition codes
+        assert_eq!(after.cond, cc(value));
+        assert!(after.writes.is_empty());
+    });
+}
+
+#[test]
+fn stores_every_encoding() {
+    for (opcode, indirect) in [(0b0011, false), (0b1011, true)] {
+        for_every_encoding(opcode, &PCS, |instr, registers, pc, after| {
+            let addr = pc.wrapping_add(sext(instr & 0x1FF, 9));
+            let addr = if indirect { pattern(addr) } else { addr };
+            assert_eq!(after.writes, vec![(addr, registers[dr(instr)])], "{:04X} at {:04X}", instr, pc);
+            assert_eq!(after.registers, registers);
+            assert_eq!(after.cond, 0b010);
+            assert_eq!(after.pc, pc);
+        });
+    }
+}
+
+#[test]
+fn str_every_encoding() {
+    for_every_encoding(0b0111, &PCS[..1], |instr, registers, _, after| {
+        let addr = registers[sr1(instr)].wrapping_add(sext(instr & 0x3F, 6));
+        assert_eq!(after.writes, vec![(addr, registers[dr(instr)])], "{:04X}", instr);
+        assert_eq!(after.registers, registers);
+    });
+}
+
+#[test]
+fn condition_codes_for_every_value() {
+    for value in 0..=0xFFFF {
+        let mut registers = [0; 8];
+        registers[1] = value;
+        let after = run(0b0001_000_001_1_00000, registers, 0x3001, 0b010); // ADD R0, R1, #0
+        assert_eq!(after.cond, cc(value), "{:04X}", value);
+        let expected = if value == 0 {
+            Condition::from_bits(0b010)
+        } else if (value as i16) < 0 {
+            Condition::from_bits(0b100)
+        } else {
+            Condition::from_bits(0b001)
+        };
+        assert_eq!(Condition::from_value(value), expected);
+    }
+}
+
+#[test]
+fn trap_hands_every_vector_to_the_os() {
+    for low in 0..0x1000u16 {
+        let mut processor = Processor::new();
+        let mut memory = PatternMemory { writes: Vec::new() };
+        let result = processor.execute(0xF000 | low, &mut memory).unwrap();
+        assert!(matches!(result, ExecutionResult::Trap(vector) if vector == low as u8));
+        assert_eq!(processor.registers, Processor::new().registers);
+    }
+}
+
+#[test]
+fn strict_mode_allows_only_the_documented_encodings() {
+    let mut rejected = [0; 16];
+    for instr in 0..=0xFFFF {
+        if spec_violation(instr).is_some() {
+            rejected[(instr >> 12) as usize] += 1;
+        }
+    }
+    // ADD and AND: bits 4-3 of the register form, NOT: bits 5-0 all ones,
+    // JMP and JSRR: only the base register, TRAP: bits 11-8, RTI and the reserved opcode always
+    assert_eq!(rejected, [0, 1536, 0, 0, 2040, 1536, 0, 0, 4096, 4032, 0, 0, 4088, 4096, 0, 3840]);
+}
@@ -1,6 +1,12 @@
-use super::registers::Registers;
-use crate::utils::sign_extend;
-
+use super::decode::{Instruction, Operand, decode};
+use super::isa::Isa;
+use super::lc3b;
+use super::memory::Bus;
+use super::registers::{Register, Registers};
+use crate::error::Result;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     BR = 0, // branch
     ADD,    // add
@@ -21,6 +27,26 @@ pub enum OpCode {
 }
 
 impl OpCode {
+    /// every opcode, indexed by its encoding
+    pub const ALL: [OpCode; 16] = [
+        OpCode::BR,
+        OpCode::ADD,
+        OpCode::LD,
+        OpCode::ST,
+        OpCode::JSR,
+        OpCode::AND,
+        OpCode::LDR,
+        OpCode::STR,
+        OpCode::RTI,
+        OpCode::NOT,
+        OpCode::LDI,
+        OpCode::STI,
+        OpCode::JMP,
+        OpCode::RES,
+        OpCode::LEA,
+        OpCode::TRAP,
+    ];
+
     pub fn get_op_code(val: &u16) -> Option<OpCode> {
         match val >> 12 {
             0 => Some(OpCode::BR),
@@ -52,190 +78,137 @@ pub enum ExecutionResult {
 
 pub(super) struct Processor {
     pub registers: Registers,
+    pub isa: Isa,
 }
 
 impl Processor {
     pub fn new() -> Self {
         Self {
             registers: Registers::new(),
+            isa: Isa::Lc3,
         }
     }
 
-    pub fn execute(&mut self, instr: u16, memory: &mut [u16]) -> ExecutionResult {
-        let op = match OpCode::get_op_code(&instr) {
-            Some(op) => op,
-            None => return ExecutionResult::Continue, //handle invalid opcode?
-        };
-
-        match op {
-            OpCode::ADD => self.add(instr),
-            OpCode::AND => self.and(instr),
-            OpCode::NOT => self.not(instr),
-            OpCode::BR => self.br(instr),
-            OpCode::JMP => self.jmp(instr),
-            OpCode::JSR => self.jsr(instr),
-            OpCode::LD => self.ld(instr, memory),
-            OpCode::LDI => self.ldi(instr, memory),
-            OpCode::LDR => self.ldr(instr, memory),
-            OpCode::LEA => self.lea(instr),
-            OpCode::ST => self.st(instr, memory),
-            OpCode::STI => self.sti(instr, memory),
-            OpCode::STR => self.str(instr, memory),
-            OpCode::TRAP => return self.trap(instr), // pass to OS
-            _ => {}
+    pub fn execute<M: Bus + ?Sized>(&mut self, instr: u16, memory: &mut M) -> Result<ExecutionResult> {
+        match self.isa {
+            Isa::Lc3 => self.execute_decoded(decode(instr), memory),
+            Isa::Lc3b => lc3b::execute(&mut self.registers, lc3b::decode(instr), memory),
         }
-        ExecutionResult::Continue
-    }
-
-    fn add(&mut self, instr: u16) {
-        let dr = (instr >> 9) & 0x7;
-        let sr1 = (instr >> 6) & 0x7;
-        let mode = (instr >> 5) & 0x1;
-        let val1 = self.registers.get(sr1);
-        let val2: u16;
-        match mode {
-            0 => {
-                let sr2 = instr & 0x7;
-                val2 = self.registers.get(sr2);
-            }
-            1 => val2 = sign_extend(instr & 0x1F, 5),
-            _ => unreachable!(),
+    }
+
+    pub fn execute_decoded<M: Bus + ?Sized>(&mut self, instr: Instruction, memory: &mut M) -> Result<ExecutionResult> {
+        match instr {
+            Instruction::Add { dr, sr1, operand } => self.add(dr, sr1, operand),
+            Instruction::And { dr, sr1, operand } => self.and(dr, sr1, operand),
+            Instruction::Not { dr, sr } => self.not(dr, sr),
+            Instruction::Br { nzp, offset } => self.br(nzp, offset),
+            Instruction::Jmp { base } => self.jmp(base),
+            Instruction::Jsr { offset } => self.jsr(offset),
+            Instruction::Jsrr { base } => self.jsrr(base),
+            Instruction::Ld { dr, offset } => self.ld(dr, offset, memory),
+            Instruction::Ldi { dr, offset } => self.ldi(dr, offset, memory),
+            Instruction::Ldr { dr, base, offset } => self.ldr(dr, base, offset, memory),
+            Instruction::Lea { dr, offset } => self.lea(dr, offset),
+            Instruction::St { sr, offset } => self.st(sr, offset, memory),
+            Instruction::Sti { sr, offset } => self.sti(sr, offset, memory),
+            Instruction::Str { sr, base, offset } => self.str(sr, base, offset, memory),
+            Instruction::Trap { vector } => return Ok(ExecutionResult::Trap(vector)), // pass to OS
+            Instruction::Rti | Instruction::Res => {} //handle invalid opcode?
         }
+        Ok(ExecutionResult::Continue)
+    }
 
-        self.registers.update(dr, val1.wrapping_add(val2));
+    fn operand_value(&self, operand: Operand) -> u16 {
+        match operand {
+            Operand::Register(sr2) => self.registers[sr2],
+            Operand::Immediate(value) => value,
+        }
+    }
+
+    fn add(&mut self, dr: Register, sr1: Register, operand: Operand) {
+        let val1 = self.registers[sr1];
+        let val2 = self.operand_value(operand);
+        self.registers[dr] = val1.wrapping_add(val2);
         self.registers.update_r_cond_register(dr);
     }
 
-    fn and(&mut self, instr: u16) {
-        let dr = (instr >> 9) & 0x7;
-        let sr1 = (instr >> 6) & 0x7;
-        let mode = (instr >> 5) & 0x1;
-        let val1 = self.registers.get(sr1);
-        let val2 = match mode {
-            0 => {
-                let sr2 = instr & 0x7;
-                self.registers.get(sr2)
-            }
-            1 => sign_extend(instr & 0x1F, 5),
-            _ => unreachable!(),
-        };
-        self.registers.update(dr, val1 & val2);
+    fn and(&mut self, dr: Register, sr1: Register, operand: Operand) {
+        let val1 = self.registers[sr1];
+        let val2 = self.operand_value(operand);
+        self.registers[dr] = val1 & val2;
         self.registers.update_r_cond_register(dr);
     }
 
-    fn not(&mut self, instr: u16) {
-        let dr = (instr >> 9) & 0x7;
-        let sr = (instr >> 6) & 0x7;
-        let val = !self.registers.get(sr);
-        self.registers.update(dr, val);
+    fn not(&mut self, dr: Register, sr: Register) {
+        self.registers[dr] = !self.registers[sr];
         self.registers.update_r_cond_register(dr);
     }
 
-    fn br(&mut self, instr: u16) {
-        let offset = sign_extend(instr & 0x1FF, 9);
-        let cond = self.registers.cond;
-        let nzp = instr >> 9;
-        let res = cond & nzp;
-        match res {
-            0x1 => {
-                self.registers.pc = self.registers.pc.wrapping_add(offset);
-            }
-            0x2 => {
-                self.registers.pc = self.registers.pc.wrapping_add(offset);
-            }
-            0x4 => {
-                self.registers.pc = self.registers.pc.wrapping_add(offset);
-            }
-            _ => {}
+    fn br(&mut self, nzp: u16, offset: u16) {
+        if self.registers.condition().matches(nzp) {
+            let pc = self.registers.pc();
+            self.registers.set_pc(pc.wrapping_add(offset));
         }
     }
 
-    fn jmp(&mut self, instr: u16) {
-        let target_reg = (instr >> 6) & 0x7;
-        let target_addr = self.registers.get(target_reg);
-        self.registers.pc = target_addr;
+    fn jmp(&mut self, base: Register) {
+        let target_addr = self.registers[base];
+        self.registers.set_pc(target_addr);
     }
 
-    fn jsr(&mut self, instr: u16) {
-        let mode = (instr >> 11) & 0x1;
-        self.registers.update(7, self.registers.get(8));
-        let base_register = (instr >> 6) & 0x7;
-        let offset: u16;
+    fn jsr(&mut self, offset: u16) {
+        let pc = self.registers.pc();
+        self.registers[Register::R7] = pc;
+        self.registers.set_pc(pc.wrapping_add(offset));
+    }
 
-        match mode {
-            0x0 => { //JSRR
-                self.registers.pc = self.registers.get(base_register);
-            }
-            0x1 => { //JSR
-                offset = sign_extend(instr & 0x7FF, 11);
-                self.registers.update(8, self.registers.pc.wrapping_add(offset));
-            }
-            _ => unreachable!(),
-        }
+    fn jsrr(&mut self, base: Register) {
+        // read the base first, JSRR R7 jumps to the old R7
+        let target_addr = self.registers[base];
+        self.registers[Register::R7] = self.registers.pc();
+        self.registers.set_pc(target_addr);
     }
 
-    fn ld(&mut self, instr: u16, memory: &[u16]) {
-        let dr = (instr >> 9) & 0x7;
-        let pcoffset9 = sign_extend(instr & 0x1FF, 9);
-        let val = memory[self.registers.pc.wrapping_add(pcoffset9) as usize];
-        self.registers.update(dr, val);
+    fn ld<M: Bus + ?Sized>(&mut self, dr: Register, offset: u16, memory: &mut M) {
+        let val = memory.read(self.registers.pc().wrapping_add(offset));
+        self.registers[dr] = val;
         self.registers.update_r_cond_register(dr);
     }
 
-    fn ldi(&mut self, instr: u16, memory: &[u16]) {
-        let dr = (instr >> 9) & 0x7;
-        let pcoffset9 = sign_extend(instr & 0x1FF, 9);
-        let val1 = memory[self.registers.pc.wrapping_add(pcoffset9) as usize];
-        let val2 = memory[val1 as usize];
-        self.registers.update(dr, val2);
+    fn ldi<M: Bus + ?Sized>(&mut self, dr: Register, offset: u16, memory: &mut M) {
+        let val1 = memory.read(self.registers.pc().wrapping_add(offset));
+        let val2 = memory.read(val1);
+        self.registers[dr] = val2;
         self.registers.update_r_cond_register(dr);
     }
 
-    fn ldr(&mut self, instr: u16, memory: &mut [u16]) {
-        let dr = (instr >> 9) & 0x7;
-        let base_reg = (instr >> 6) & 0x7;
-        let offset6 = sign_extend(instr & 0x3F, 6);
-        let val = self.registers.get(base_reg);
-        let res = memory[(val.wrapping_add(offset6)) as usize];
-        self.registers.update(dr, res);
+    fn ldr<M: Bus + ?Sized>(&mut self, dr: Register, base: Register, offset: u16, memory: &mut M) {
+        let val = self.registers[base];
+        let res = memory.read(val.wrapping_add(offset));
+        self.registers[dr] = res;
         self.registers.update_r_cond_register(dr);
     }
 
-    fn lea(&mut self, instr: u16) {
-        let dr = (instr >> 9) & 0x7;
-        let pc_offset = sign_extend(instr & 0x1FF, 9);
-        let val = self.registers.pc.wrapping_add(pc_offset);
-        self.registers.update(dr, val);
+    fn lea(&mut self, dr: Register, offset: u16) {
+        let val = self.registers.pc().wrapping_add(offset);
+        self.registers[dr] = val;
         self.registers.update_r_cond_register(dr);
     }
 
-    fn st(&mut self, instr: u16, memory: &mut [u16]) {
-        let sr = (instr >> 9) & 0x7;
-        let pcoffset9 = sign_extend(instr & 0x1FF, 9);
-        let addr = self.registers.pc.wrapping_add(pcoffset9) as usize;
-        memory[addr] = self.registers.get(sr);
+    fn st<M: Bus + ?Sized>(&mut self, sr: Register, offset: u16, memory: &mut M) {
+        let addr = self.registers.pc().wrapping_add(offset);
+        memory.write(addr, self.registers[sr]);
     }
 
-    fn sti(&mut self, instr: u16, memory: &mut [u16]) {
-        let sr = (instr >> 9) & 0x7;
-        let pcoffset9 = sign_extend(instr & 0x1FF, 9);
-
-        let addr1 = self.registers.pc.wrapping_add(pcoffset9) as usize;
-        let addr2 = memory[addr1] as usize;
-
-        memory[addr2] = self.registers.get(sr);
-    }
+    fn sti<M: Bus + ?Sized>(&mut self, sr: Register, offset: u16, memory: &mut M) {
+        let addr1 = self.registers.pc().wrapping_add(offset);
+        let addr2 = memory.read(addr1);
 
-    fn str(&mut self, instr: u16, memory: &mut [u16]) {
-        let sr = (instr >> 9) & 0x7;
-        let base_reg = (instr >> 6) & 0x7;
-        let offset6 = sign_extend(instr & 0x3F, 6);
-        let val = self.registers.get(base_reg);
-        memory[val.wrapping_add(offset6) as usize] = self.registers.get(sr);
+        memory.write(addr2, self.registers[sr]);
     }
 
-    fn trap(&mut self, instr: u16) -> ExecutionResult {
-        let trap_vector = (instr & 0xFF) as u8;
-        ExecutionResult::Trap(trap_vector)
+    fn str<M: Bus + ?Sized>(&mut self, sr: Register, base: Register, offset: u16, memory: &mut M) {
+        let val = self.registers[base];
+        memory.write(val.wrapping_add(offset), self.registers[sr]);
     }
 }
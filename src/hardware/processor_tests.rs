@@ -1,4 +1,7 @@
+#![allow(clippy::unusual_byte_groupings)] // literals are grouped by instruction field
+
 use super::processor::{ExecutionResult, Processor};
+use super::registers::{Condition, Register};
 
 fn memory() -> [u16; 65536] {
     [0; 65536]
@@ -8,207 +11,251 @@ fn memory() -> [u16; 65536] {
 fn add_register_mode() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 10);
-    processor.registers.update(2, 5);
+    processor.registers[Register::R1] = 10;
+    processor.registers[Register::R2] = 5;
 
     let instr = 0b0001_000_001_0_00_010; // ADD R0, R1, R2
-    let result = processor.execute(instr, &mut mem);
+    let result = processor.execute(instr, &mut mem).unwrap();
 
     assert!(matches!(result, ExecutionResult::Continue));
-    assert_eq!(processor.registers.get(0), 15);
-    assert_eq!(processor.registers.cond, 1); // POS
+    assert_eq!(processor.registers[Register::R0], 15);
+    assert_eq!(processor.registers.condition().bits(), 1); // POS
 }
 
 #[test]
 fn add_immediate_mode() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 10);
+    processor.registers[Register::R1] = 10;
 
     let instr = 0b0001_000_001_1_11110; // ADD R0, R1, -2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 8);
+    assert_eq!(processor.registers[Register::R0], 8);
 }
 
 #[test]
 fn and_register_mode() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 0b1010);
-    processor.registers.update(2, 0b1100);
+    processor.registers[Register::R1] = 0b1010;
+    processor.registers[Register::R2] = 0b1100;
 
     let instr = 0b0101_000_001_0_00_010; // AND R0, R1, R2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0b1000);
+    assert_eq!(processor.registers[Register::R0], 0b1000);
 }
 
 #[test]
 fn and_immediate_mode() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 0b1111);
+    processor.registers[Register::R1] = 0b1111;
 
     let instr = 0b0101_000_001_1_00101; // AND R0, R1, #5
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0b0101);
+    assert_eq!(processor.registers[Register::R0], 0b0101);
 }
 
 #[test]
 fn not_register_mode_and_negative_flag() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 0x0001);
+    processor.registers[Register::R1] = 0x0001;
 
     let instr = 0b1001_000_001_111111; // NOT R0, R1
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0xFFFE);
-    assert_eq!(processor.registers.cond, 4); // NEG
+    assert_eq!(processor.registers[Register::R0], 0xFFFE);
+    assert_eq!(processor.registers.condition().bits(), 4); // NEG
 }
 
 #[test]
 fn br_takes_branch_when_condition_matches() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.cond = 2; // ZRO
-    let initial_pc = processor.registers.pc;
+    processor.registers.set_condition(Condition::from_bits(2)); // ZRO
+    let initial_pc = processor.registers.pc();
 
     let instr = 0b0000_010_000001010; // BRz +10
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.pc, initial_pc.wrapping_add(10));
+    assert_eq!(processor.registers.pc(), initial_pc.wrapping_add(10));
 }
 
 #[test]
 fn br_does_not_branch_when_condition_does_not_match() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.cond = 1; // POS
-    let initial_pc = processor.registers.pc;
+    processor.registers.set_condition(Condition::from_bits(1)); // POS
+    let initial_pc = processor.registers.pc();
 
     let instr = 0b0000_010_000001010; // BRz +10
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.pc, initial_pc);
+    assert_eq!(processor.registers.pc(), initial_pc);
 }
 
 #[test]
 fn br_with_clear_condition_register_is_noop() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.cond = 0;
-    let initial_pc = processor.registers.pc;
+    processor.registers.set_condition(Condition::from_bits(0));
+    let initial_pc = processor.registers.pc();
 
     let instr = 0b0000_111_000000001; // BRnzp +1
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.pc, initial_pc);
+    assert_eq!(processor.registers.pc(), initial_pc);
 }
 
 #[test]
 fn jmp_sets_pc_to_base_register() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(3, 0x3456);
+    processor.registers[Register::R3] = 0x3456;
 
     let instr = 0b1100_000_011_000000; // JMP R3
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.pc, 0x3456);
+    assert_eq!(processor.registers.pc(), 0x3456);
 }
 
 #[test]
 fn jsrr_saves_return_address_and_jumps_to_base_register() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3005;
-    processor.registers.update(2, 0x4000);
+    processor.registers.set_pc(0x3005);
+    processor.registers[Register::R2] = 0x4000;
 
     let instr = 0b0100_0_00_010_000000; // JSRR R2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
+
+    assert_eq!(processor.registers[Register::R7], 0x3005);
+    assert_eq!(processor.registers.pc(), 0x4000);
+}
+
+#[test]
+fn jsrr_r7_jumps_to_the_old_r7() {
+    let mut processor = Processor::new();
+    let mut mem = memory();
+    processor.registers.set_pc(0x3005);
+    processor.registers[Register::R7] = 0x4000;
+
+    let instr = 0b0100_0_00_111_000000; // JSRR R7
+    processor.execute(instr, &mut mem).unwrap();
+
+    assert_eq!(processor.registers.pc(), 0x4000);
+    assert_eq!(processor.registers[Register::R7], 0x3005);
+}
+
+#[test]
+fn jsr_overwrites_r7_even_when_jumping_to_the_return_address() {
+    let mut processor = Processor::new();
+    let mut mem = memory();
+    processor.registers.set_pc(0x3005);
+    processor.registers[Register::R7] = 0x1234;
+
+    let instr = 0b0100_1_00000000000; // JSR +0
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(7), 0x3005);
-    assert_eq!(processor.registers.pc, 0x4000);
+    assert_eq!(processor.registers.pc(), 0x3005);
+    assert_eq!(processor.registers[Register::R7], 0x3005);
 }
 
 #[test]
 fn jsr_saves_return_address_and_jumps_by_offset() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3005;
+    processor.registers.set_pc(0x3005);
 
     let instr = 0b0100_1_00000000011; // JSR +3
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(7), 0x3005);
-    assert_eq!(processor.registers.pc, 0x3008);
+    assert_eq!(processor.registers[Register::R7], 0x3005);
+    assert_eq!(processor.registers.pc(), 0x3008);
 }
 
 #[test]
 fn ld_loads_from_pc_relative_address_and_sets_flags() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3000;
+    processor.registers.set_pc(0x3000);
     mem[0x3002] = 0xFFFF;
 
     let instr = 0b0010_000_000000010; // LD R0, +2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0xFFFF);
-    assert_eq!(processor.registers.cond, 4); // NEG
+    assert_eq!(processor.registers[Register::R0], 0xFFFF);
+    assert_eq!(processor.registers.condition().bits(), 4); // NEG
 }
 
 #[test]
 fn ldi_loads_indirect_value() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3000;
+    processor.registers.set_pc(0x3000);
     mem[0x3001] = 0x4000;
     mem[0x4000] = 0x1234;
 
     let instr = 0b1010_000_000000001; // LDI R0, +1
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0x1234);
+    assert_eq!(processor.registers[Register::R0], 0x1234);
 }
 
 #[test]
 fn ldr_loads_base_plus_offset() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(1, 0x4100);
+    processor.registers[Register::R1] = 0x4100;
     mem[0x4102] = 0xBEEF;
 
     let instr = 0b0110_000_001_000010; // LDR R0, R1, +2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
+
+    assert_eq!(processor.registers[Register::R0], 0xBEEF);
+}
+
+#[test]
+fn ldr_with_dr_equal_to_base_uses_the_old_base() {
+    let mut processor = Processor::new();
+    let mut mem = memory();
+    processor.registers[Register::R1] = 0x4000;
+    mem[0x4002] = 0x4321;
+    mem[0x4323] = 0xBAD0;
 
-    assert_eq!(processor.registers.get(0), 0xBEEF);
+    let instr = 0b0110_001_001_000010; // LDR R1, R1, #2
+    processor.execute(instr, &mut mem).unwrap();
+    processor.execute(instr, &mut mem).unwrap();
+
+    assert_eq!(processor.registers[Register::R1], 0xBAD0);
+    assert_eq!(processor.registers.condition().bits(), 4); // NEG
 }
 
 #[test]
 fn lea_writes_effective_address() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3000;
+    processor.registers.set_pc(0x3000);
 
     let instr = 0b1110_000_000000111; // LEA R0, +7
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
-    assert_eq!(processor.registers.get(0), 0x3007);
+    assert_eq!(processor.registers[Register::R0], 0x3007);
 }
 
 #[test]
 fn st_writes_to_pc_relative_address() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3000;
-    processor.registers.update(3, 0xCAFE);
+    processor.registers.set_pc(0x3000);
+    processor.registers[Register::R3] = 0xCAFE;
 
     let instr = 0b0011_011_000000010; // ST R3, +2
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
     assert_eq!(mem[0x3002], 0xCAFE);
 }
@@ -217,12 +264,12 @@ fn st_writes_to_pc_relative_address() {
 fn sti_writes_indirectly() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.pc = 0x3000;
-    processor.registers.update(4, 0xA0A0);
+    processor.registers.set_pc(0x3000);
+    processor.registers[Register::R4] = 0xA0A0;
     mem[0x3001] = 0x4200;
 
     let instr = 0b1011_100_000000001; // STI R4, +1
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
     assert_eq!(mem[0x4200], 0xA0A0);
 }
@@ -231,15 +278,53 @@ fn sti_writes_indirectly() {
 fn str_writes_base_plus_offset() {
     let mut processor = Processor::new();
     let mut mem = memory();
-    processor.registers.update(5, 0xABCD);
-    processor.registers.update(2, 0x4400);
+    processor.registers[Register::R5] = 0xABCD;
+    processor.registers[Register::R2] = 0x4400;
 
     let instr = 0b0111_101_010_000001; // STR R5, R2, +1
-    processor.execute(instr, &mut mem);
+    processor.execute(instr, &mut mem).unwrap();
 
     assert_eq!(mem[0x4401], 0xABCD);
 }
 
+#[test]
+fn str_with_sr_equal_to_base_stores_the_address_itself() {
+    let mut processor = Processor::new();
+    let mut mem = memory();
+    processor.registers[Register::R3] = 0x4000;
+
+    let instr = 0b0111_011_011_111111; // STR R3, R3, #-1
+    processor.execute(instr, &mut mem).unwrap();
+
+    assert_eq!(mem[0x3FFF], 0x4000);
+    assert_eq!(processor.registers[Register::R3], 0x4000);
+}
+
+#[test]
+fn operate_instructions_with_dr_equal_to_sr() {
+    let mut processor = Processor::new();
+    let mut mem = memory();
+    processor.registers[Register::R2] = 0x00F0;
+
+    processor.execute(0b1001_010_010_111111, &mut mem).unwrap(); // NOT R2, R2
+    assert_eq!(processor.registers[Register::R2], 0xFF0F);
+    assert_eq!(processor.registers.condition().bits(), 4);
+
+    processor.execute(0b0001_010_010_0_00_010, &mut mem).unwrap(); // ADD R2, R2, R2
+    assert_eq!(processor.registers[Register::R2], 0xFE1E);
+
+    processor.execute(0b0101_010_010_0_00_010, &mut mem).unwrap(); // AND R2, R2, R2
+    assert_eq!(processor.registers[Register::R2], 0xFE1E);
+
+    processor.execute(0b0001_010_010_1_00010, &mut mem).unwrap(); // ADD R2, R2, #2
+    assert_eq!(processor.registers[Register::R2], 0xFE20);
+
+    processor.registers[Register::R2] = 0x8000;
+    processor.execute(0b0001_010_010_0_00_010, &mut mem).unwrap(); // ADD R2, R2, R2 overflows to 0
+    assert_eq!(processor.registers[Register::R2], 0);
+    assert_eq!(processor.registers.condition().bits(), 2); // ZRO
+}
+
 #[test]
 fn trap_vectors_are_returned_to_system_layer() {
     let mut processor = Processor::new();
@@ -248,7 +333,7 @@ fn trap_vectors_are_returned_to_system_layer() {
 
     for vector in vectors {
         let instr = 0xF000 | vector; // TRAP x??
-        let result = processor.execute(instr, &mut mem);
+        let result = processor.execute(instr, &mut mem).unwrap();
         assert!(matches!(result, ExecutionResult::Trap(v) if v == vector as u8));
     }
 }
@@ -262,11 +347,35 @@ fn rti_and_res_do_not_panic_and_continue() {
     let res = 0xD000;
 
     assert!(matches!(
-        processor.execute(rti, &mut mem),
+        processor.execute(rti, &mut mem).unwrap(),
         ExecutionResult::Continue
     ));
     assert!(matches!(
-        processor.execute(res, &mut mem),
+        processor.execute(res, &mut mem).unwrap(),
         ExecutionResult::Continue
     ));
 }
+
+#[test]
+fn condition_flags_follow_the_written_value() {
+    let cases = [(0x0000, "z"), (0x0001, "p"), (0x8000, "n")];
+    for (value, flags) in cases {
+        let condition = Condition::from_value(value);
+        assert_eq!(condition.to_string(), flags);
+        assert_eq!((condition.n(), condition.z(), condition.p()), (value >> 15 != 0, value == 0, flags == "p"));
+    }
+    assert!(Condition::from_value(5).matches(0b011));
+    assert!(!Condition::from_value(5).matches(0b110));
+}
+
+#[test]
+fn register_file_is_indexed_by_register() {
+    let mut processor = Processor::new();
+    for (i, register) in Register::ALL.into_iter().enumerate() {
+        processor.registers[register] = i as u16;
+        assert_eq!(Register::general(i as u16), Register::ALL[i % 8]);
+    }
+    assert_eq!(processor.registers.pc(), 8);
+    assert_eq!(processor.registers[Register::R7], 7);
+    assert_eq!(Register::COND.to_string(), "COND");
+}
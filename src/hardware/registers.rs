@@ -1,81 +1,197 @@
-const PC_START: u16 = 0x3000;
+use crate::error::{Error, Result};
+use std::fmt;
+use std::ops::{Index, IndexMut};
 
-enum ConditionFlag {
+/// where execution starts, the vm does not look at the origin of the image
+pub const PC_START: u16 = 0x3000;
+/// r0-r7, pc and cond, in `Register` order
+pub const REGISTER_COUNT: u16 = 10;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    R0 = 0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    PC,
+    COND,
+}
+
+impl Register {
+    /// every register, indexed by `index()`
+    pub const ALL: [Register; REGISTER_COUNT as usize] = [
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::PC,
+        Register::COND,
+    ];
+
+    /// general purpose register named by a 3 bit instruction field, higher bits are ignored
+    pub fn general(field: u16) -> Register {
+        Register::ALL[(field & 0x7) as usize]
+    }
+
+    pub fn from_index(index: u16) -> Result<Register> {
+        Register::ALL
+            .get(index as usize)
+            .copied()
+            .ok_or(Error::InvalidRegister(index))
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::PC => write!(f, "PC"),
+            Register::COND => write!(f, "COND"),
+            general => write!(f, "R{}", general.index()),
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionFlag {
     POS = 1 << 0,
     ZRO = 1 << 1,
     NEG = 1 << 2,
 }
 
-#[derive(Debug)]
+/// contents of the condition register, the same bit layout as the nzp field of BR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition(u16);
+
+impl Condition {
+    pub fn from_bits(bits: u16) -> Self {
+        Condition(bits & 0x7)
+    }
+
+    /// the flag an instruction writing `value` sets
+    pub fn from_value(value: u16) -> Self {
+        let flag = if value == 0 {
+            ConditionFlag::ZRO
+        } else if (value >> 15) != 0 {
+            ConditionFlag::NEG
+        } else {
+            ConditionFlag::POS
+        };
+        flag.into()
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn n(self) -> bool {
+        self.is_set(ConditionFlag::NEG)
+    }
+
+    pub fn z(self) -> bool {
+        self.is_set(ConditionFlag::ZRO)
+    }
+
+    pub fn p(self) -> bool {
+        self.is_set(ConditionFlag::POS)
+    }
+
+    pub fn is_set(self, flag: ConditionFlag) -> bool {
+        self.0 & flag as u16 != 0
+    }
+
+    /// whether a BR with this `nzp` field is taken
+    pub fn matches(self, nzp: u16) -> bool {
+        self.0 & nzp != 0
+    }
+}
+
+impl From<ConditionFlag> for Condition {
+    fn from(flag: ConditionFlag) -> Self {
+        Condition(flag as u16)
+    }
+}
+
+impl fmt::Display for Condition {
+    /// set flags as letters, e.g. `z` or `np`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (set, letter) in [(self.n(), 'n'), (self.z(), 'z'), (self.p(), 'p')] {
+            if set {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub(super) struct Registers {
-    pub r0: u16,
-    pub r1: u16,
-    pub r2: u16,
-    pub r3: u16,
-    pub r4: u16,
-    pub r5: u16,
-    pub r6: u16,
-    pub r7: u16,
-    pub pc: u16,
-    pub cond: u16,
+    values: [u16; REGISTER_COUNT as usize],
 }
 
 impl Registers {
     pub fn new() -> Registers {
-        Registers {
-            r0: 0,
-            r1: 0,
-            r2: 0,
-            r3: 0,
-            r4: 0,
-            r5: 0,
-            r6: 0,
-            r7: 0,
-            pc: PC_START,
-            cond: ConditionFlag::ZRO as u16,
-        }
+        let mut registers = Registers {
+            values: [0; REGISTER_COUNT as usize],
+        };
+        registers[Register::PC] = PC_START;
+        registers.set_condition(ConditionFlag::ZRO.into());
+        registers
     }
 
-    pub fn update(&mut self, index: u16, value: u16) {
-        match index {
-            0 => self.r0 = value,
-            1 => self.r1 = value,
-            2 => self.r2 = value,
-            3 => self.r3 = value,
-            4 => self.r4 = value,
-            5 => self.r5 = value,
-            6 => self.r6 = value,
-            7 => self.r7 = value,
-            8 => self.pc = value,
-            9 => self.cond = value,
-            _ => panic!("Invalid register index"),
-        }
+    pub fn pc(&self) -> u16 {
+        self[Register::PC]
     }
 
-    pub fn get(&self, index: u16) -> u16 {
-        match index {
-            0 => self.r0,
-            1 => self.r1,
-            2 => self.r2,
-            3 => self.r3,
-            4 => self.r4,
-            5 => self.r5,
-            6 => self.r6,
-            7 => self.r7,
-            8 => self.pc,
-            9 => self.cond,
-            _ => panic!("Invalid register index"),
-        }
+    pub fn set_pc(&mut self, pc: u16) {
+        self[Register::PC] = pc;
     }
 
-    pub fn update_r_cond_register(&mut self, r: u16) {
-        let val = self.get(r);
-        if val == 0 {
-            self.update(9, ConditionFlag::ZRO as u16);
-        } else if (val >> 15) != 0 {
-            self.update(9, ConditionFlag::NEG as u16);
-        } else {
-            self.update(9, ConditionFlag::POS as u16);
-        }
+    pub fn condition(&self) -> Condition {
+        Condition::from_bits(self[Register::COND])
+    }
+
+    pub fn set_condition(&mut self, condition: Condition) {
+        self[Register::COND] = condition.bits();
+    }
+
+    /// (register, value in self) of every register that differs in `other`
+    pub fn diff(&self, other: &Registers) -> Vec<(Register, u16)> {
+        Register::ALL
+            .into_iter()
+            .filter(|&r| self[r] != other[r])
+            .map(|r| (r, self[r]))
+            .collect()
+    }
+
+    pub fn update_r_cond_register(&mut self, r: Register) {
+        self.set_condition(Condition::from_value(self[r]));
+    }
+}
+
+impl Index<Register> for Registers {
+    type Output = u16;
+
+    fn index(&self, register: Register) -> &u16 {
+        &self.values[register.index()]
+    }
+}
+
+impl IndexMut<Register> for Registers {
+    fn index_mut(&mut self, register: Register) -> &mut u16 {
+        &mut self.values[register.index()]
     }
 }
@@ -1,87 +1,131 @@
-use super::registers::Registers;
-use std::io::{self, Read, Write};
+use super::isa::Isa;
+use super::lc3b::read_byte;
+use super::memory::{Bus, Memory};
+use super::registers::{Register, Registers};
+use crate::error::{Error, Result};
+use std::io::{self, Write};
+
+pub(super) enum TrapOutcome {
+    Continue,
+    Halt,
+}
 
 /// the kernel itself
-pub struct System;
+pub struct System {
+    output: Box<dyn Write>,
+}
 
 impl System {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            output: Box::new(io::stdout()),
+        }
     }
 
-    pub fn handle_trap(&mut self, trap_vector: u8, registers: &mut Registers, memory: &mut [u16]) {
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    pub(super) fn handle_trap(
+        &mut self,
+        trap_vector: u8,
+        isa: Isa,
+        registers: &mut Registers,
+        memory: &mut Memory,
+    ) -> Result<TrapOutcome> {
         match trap_vector {
-            0x20 => self.getc(registers),
-            0x21 => self.out(registers),
-            0x22 => self.puts(registers, memory),
-            0x23 => self.in_char(registers),
-            0x24 => self.putsp(registers, memory),
-            0x25 => self.halt(),
+            0x20 => self.getc(registers, memory)?,
+            0x21 => self.out(registers)?,
+            // strings are packed a character per byte on LC-3b, both traps print them the same
+            0x22 | 0x24 if isa == Isa::Lc3b => self.puts_bytes(registers, memory)?,
+            0x22 => self.puts(registers, memory)?,
+            0x23 => self.in_char(registers, memory)?,
+            0x24 => self.putsp(registers, memory)?,
+            0x25 => return Ok(TrapOutcome::Halt),
             _ => {
-                println!("Unknown TRAP vector: {:#04x}", trap_vector);
-                std::process::exit(1);
+                return Err(Error::UnknownTrap {
+                    vector: trap_vector,
+                    // the pc was already incremented past the TRAP
+                    pc: registers.pc().wrapping_sub(isa.word_size()),
+                });
             }
         }
+        Ok(TrapOutcome::Continue)
     }
 
     ///prints string starting from address stored in r0
-    fn puts(&self, registers: &Registers, memory: &[u16]) {
-        let mut address = registers.r0 as usize;
-
-        let mut stdout = io::stdout();
-        while let Some(&word) = memory.get(address) {
+    fn puts(&mut self, registers: &Registers, memory: &mut Memory) -> Result<()> {
+        let output = &mut self.output;
+        for address in registers[Register::R0]..=u16::MAX {
+            let word = memory.read(address);
             if word == 0 {
                 break;
             }
             let c = (word & 0xFF) as u8;
-            write!(stdout, "{}", c as char).unwrap();
-            address += 1;
+            write!(output, "{}", c as char)?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    /// prints the string at byte address r0
+    fn puts_bytes(&mut self, registers: &Registers, memory: &mut Memory) -> Result<()> {
+        let output = &mut self.output;
+        for address in registers[Register::R0]..=u16::MAX {
+            let c = read_byte(memory, address);
+            if c == 0 {
+                break;
+            }
+            write!(output, "{}", c as char)?;
         }
-        stdout.flush().unwrap();
+        output.flush()?;
+        Ok(())
     }
 
-    fn getc(&self, registers: &mut Registers) {
-        let mut char_buf = [0u8];
-        io::stdin().read_exact(&mut char_buf).unwrap();
-        registers.r0 = char_buf[0] as u16;
+    fn getc(&mut self, registers: &mut Registers, memory: &mut Memory) -> Result<()> {
+        let char = memory.keyboard.read()?;
+        registers[Register::R0] = char as u16;
+        Ok(())
     }
 
-    fn out(&self, registers: &Registers) {
-        let char = (registers.r0 & 0xFF) as u8;
-        print!("{}", char as char);
-        io::stdout().flush().unwrap();
+    fn out(&mut self, registers: &Registers) -> Result<()> {
+        let char = (registers[Register::R0] & 0xFF) as u8;
+        write!(self.output, "{}", char as char)?;
+        self.output.flush()?;
+        Ok(())
     }
 
-    fn in_char(&self, registers: &mut Registers) {
-        let mut stdout = io::stdout();
-        write!(stdout, "Enter character: ").unwrap();
-        stdout.flush().unwrap();
-        let mut char_buf = [0u8];
-        io::stdin().read_exact(&mut char_buf).unwrap();
-        write!(stdout, "{}", char_buf[0] as char).unwrap();
-        stdout.flush().unwrap();
-        registers.r0 = char_buf[0] as u16;
+    fn in_char(&mut self, registers: &mut Registers, memory: &mut Memory) -> Result<()> {
+        let output = &mut self.output;
+        write!(output, "Enter character: ")?;
+        output.flush()?;
+        let char = memory.keyboard.read()?;
+        write!(output, "{}", char as char)?;
+        output.flush()?;
+        registers[Register::R0] = char as u16;
+        Ok(())
     }
 
-    fn putsp(&self, registers: &Registers, memory: &[u16]) {
-        let start_addr = registers.r0 as usize;
-        let mut stdout = io::stdout();
-        for i in start_addr..memory.len() {
-            let low_byte: u8 = (memory[i] & 0xFF) as u8;
-            let high_byte: u8 = ((memory[i] >> 8) & 0xFF) as u8;
-            write!(stdout, "{}", low_byte as char).unwrap();
+    fn putsp(&mut self, registers: &Registers, memory: &mut Memory) -> Result<()> {
+        let output = &mut self.output;
+        for address in registers[Register::R0]..=u16::MAX {
+            let word = memory.read(address);
+            let low_byte: u8 = (word & 0xFF) as u8;
+            let high_byte: u8 = ((word >> 8) & 0xFF) as u8;
+            // an even length string ends with a whole x0000 word
+            if low_byte == 0 {
+                break;
+            }
+            write!(output, "{}", low_byte as char)?;
             if high_byte != 0 {
-                write!(stdout, "{}", high_byte as char).unwrap();
+                write!(output, "{}", high_byte as char)?;
                 continue;
             }
 
             break;
         }
 
-        stdout.flush().unwrap();
-    }
-
-    fn halt(&self) {
-        std::process::exit(0);
+        output.flush()?;
+        Ok(())
     }
 }
@@ -1,43 +1,447 @@
+use super::call::{CallOutcome, CallResult, Clobber, RETURN_SENTINEL};
+use super::history::{History, UndoRecord};
+use super::isa::Isa;
+use super::keyboard::InputEvent;
+use super::memory::Memory;
+use super::micro::Microarchitecture;
 use super::processor::{ExecutionResult, Processor};
-use super::syscalls::System;
-
-const MEMORY_SIZE: usize = u16::MAX as usize + 1;
+use super::registers::{Condition, Register};
+use super::syscalls::{System, TrapOutcome};
+use super::watch::{WatchHit, Watchpoint};
+use crate::coverage::Coverage;
+use crate::error::{Error, Result};
+use crate::loader::Image;
+use crate::profiler::Profiler;
+use crate::source_map::{SourceEntry, SourceMap};
+use std::io::{self, Write};
 
 pub struct VM {
-    memory: [u16; MEMORY_SIZE],
+    memory: Memory,
     processor: Processor,
+    /// runs instructions cycle by cycle through microcode instead of `processor`
+    micro: Option<Microarchitecture>,
     system: System,
+    halted: bool,
+    strict: bool,
+    instructions: u64,
+    history: Option<History>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    source_map: Option<SourceMap>,
+    trace: Option<Box<dyn Write>>,
+    watch_hits: Vec<WatchHit>,
 }
 
 impl VM {
     pub fn new() -> VM {
         VM {
-            memory: [0; MEMORY_SIZE],
+            memory: Memory::new(),
             processor: Processor::new(),
+            micro: None,
             system: System::new(),
+            halted: false,
+            strict: false,
+            instructions: 0,
+            history: None,
+            profiler: None,
+            coverage: None,
+            source_map: None,
+            trace: None,
+            watch_hits: Vec::new(),
+        }
+    }
+
+    /// copies `image` into memory, execution still starts at x3000
+    pub fn load(&mut self, image: &Image) {
+        for (addr, word) in image.iter() {
+            self.write_memory(addr, word);
         }
     }
 
+    /// the instruction set to run, set it before loading. on LC-3b every address taken or
+    /// returned by the vm is a byte address and reads and writes go to the word holding it
+    pub fn set_isa(&mut self, isa: Isa) {
+        self.processor.isa = isa;
+        self.memory.set_isa(isa);
+    }
+
+    pub fn isa(&self) -> Isa {
+        self.processor.isa
+    }
+
+    /// run LC-3 instructions on the cycle-level model from here on, registers and memory
+    /// stay shared with the instruction-level processor
+    pub fn set_microarchitecture(&mut self, micro: Microarchitecture) {
+        self.micro = Some(micro);
+    }
+
+    pub fn microarchitecture(&self) -> Option<&Microarchitecture> {
+        self.micro.as_ref()
+    }
+
     pub fn write_memory(&mut self, addr: u16, value: u16) {
-        self.memory[addr as usize] = value;
+        self.memory.poke(self.isa().word_index(addr), value);
     }
 
     pub fn read_memory(&self, addr: u16) -> u16 {
-        self.memory[addr as usize]
+        self.memory.peek(self.isa().word_index(addr))
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        self.processor.registers[register]
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        self.processor.registers[register] = value;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.processor.registers.pc()
+    }
+
+    pub fn condition(&self) -> Condition {
+        self.processor.registers.condition()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// number of instructions executed so far
+    pub fn instruction_count(&self) -> u64 {
+        self.instructions
+    }
+
+    /// keep undo information for the last `capacity` instructions so they can be stepped back
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// count executed instructions per address, opcode, subroutine and trap from here on
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::new(self.pc()));
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// reuse decoded instructions instead of decoding every fetch, stores to an address
+    /// drop its cached decoding so self modifying code still works
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.memory.set_decode_cache(enabled);
+    }
+
+    /// spec compliance mode: reserved opcodes, RTI and encodings with wrong fixed bits stop
+    /// the program with an error instead of being ignored
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// track executed addresses and branch directions from here on
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// where the loaded program came from, used to name source lines in traces and errors
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = Some(source_map);
+    }
+
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    /// the source line `addr` was assembled from, if there is a source map covering it
+    pub fn source_line(&self, addr: u16) -> Option<&SourceEntry> {
+        self.source_map.as_ref()?.lookup(addr)
+    }
+
+    /// `error` followed by the source line of the faulting instruction, when it is known
+    pub fn describe_error(&self, error: &Error) -> String {
+        match error.pc().and_then(|pc| self.source_line(pc)) {
+            Some(entry) => format!("{} ({})", error, entry),
+            None => error.to_string(),
+        }
+    }
+
+    /// write a line for every instruction before it executes: count, address, word,
+    /// disassembly and the source line it came from
+    pub fn set_trace(&mut self, writer: Box<dyn Write>) {
+        self.trace = Some(writer);
+    }
+
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        if let Some(micro) = self.micro.as_mut() {
+            micro.flush_trace()?;
+        }
+        match self.trace.as_mut() {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// returns the id used to remove the watchpoint again
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.memory.watchpoints.push(Some(watchpoint));
+        self.memory.watchpoints.len() - 1
+    }
+
+    /// ids are never reused, so a stale id cannot remove a later watchpoint
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        self.memory.watchpoints.get_mut(id).and_then(Option::take).is_some()
+    }
+
+    /// (id, watchpoint) of every active watchpoint
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.memory
+            .watchpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(id, watchpoint)| Some((id, watchpoint.as_ref()?)))
+    }
+
+    /// watchpoints triggered by the last executed instruction
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    /// log every input byte handed to the guest, with the instruction count it was consumed at
+    pub fn record_input(&mut self, writer: Box<dyn Write>) -> io::Result<()> {
+        self.memory.keyboard.record(writer)
+    }
+
+    /// feed input from a recording instead of stdin, each byte is handed out at exactly
+    /// the recorded instruction count so the run is reproduced bit for bit
+    pub fn replay_input(&mut self, events: Vec<InputEvent>) {
+        self.memory.keyboard.replay(events);
+    }
+
+    /// whether guest input comes from `replay_input`
+    pub fn is_replaying_input(&self) -> bool {
+        self.memory.keyboard.is_replay()
+    }
+
+    /// feed `input` to the guest instead of stdin, every byte is available immediately
+    pub fn set_input(&mut self, input: impl Into<Vec<u8>>) {
+        self.memory.keyboard.script(input.into());
+    }
+
+    /// adds to the input fed to the guest, as if `set_input` had been called with
+    /// everything pushed so far that was not consumed yet
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.memory.keyboard.push(input);
+    }
+
+    /// bytes from `set_input`, `push_input` or a replay that the guest has not read yet
+    pub fn pending_input(&self) -> usize {
+        self.memory.keyboard.pending()
+    }
+
+    /// where the output trap routines write to, stdout by default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.system.set_output(output);
+    }
+
+    /// runs until the machine halts or an instruction fails
+    pub fn execute(&mut self) -> Result<()> {
+        while !self.halted {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// runs until the machine halts or `budget` instructions were executed,
+    /// returns the number of instructions executed
+    pub fn run_for(&mut self, budget: u64) -> Result<u64> {
+        let start = self.instructions;
+        while !self.halted && self.instructions - start < budget {
+            self.step()?;
+        }
+        Ok(self.instructions - start)
+    }
+
+    /// runs until the machine halts or an instruction triggers a watchpoint
+    pub fn run_until_watch(&mut self) -> Result<&[WatchHit]> {
+        while !self.halted {
+            self.step()?;
+            if !self.watch_hits.is_empty() {
+                break;
+            }
+        }
+        Ok(&self.watch_hits)
     }
 
-    pub fn execute(&mut self) {
-        while (self.processor.registers.pc as usize) < MEMORY_SIZE {
-            let pc = self.processor.registers.pc;
-            let instruction = self.memory[pc as usize];
-            self.processor.registers.pc += 1;
+    /// calls the subroutine at `entry` as if by JSR from `RETURN_SENTINEL` and runs until it
+    /// returns there, halts or `budget` instructions were executed. `callee_saved` registers
+    /// are compared with their values before the call once it returned
+    pub fn call(&mut self, entry: u16, callee_saved: &[Register], budget: u64) -> Result<CallResult> {
+        let before: Vec<u16> = callee_saved.iter().map(|&register| self.register(register)).collect();
+        self.processor.registers[Register::R7] = RETURN_SENTINEL;
+        self.processor.registers.set_pc(entry);
+        self.halted = false;
 
-            match self.processor.execute(instruction, &mut self.memory) {
-                ExecutionResult::Continue => {}
-                ExecutionResult::Trap(trap_vector) => {
-                    self.system.handle_trap(trap_vector, &mut self.processor.registers, &mut self.memory);
+        let start = self.instructions;
+        let outcome = loop {
+            if self.pc() == RETURN_SENTINEL {
+                break CallOutcome::Returned;
+            }
+            if self.halted {
+                break CallOutcome::Halted;
+            }
+            if self.instructions - start >= budget {
+                break CallOutcome::Timeout;
+            }
+            self.step()?;
+        };
+
+        let mut clobbered = Vec::new();
+        if outcome == CallOutcome::Returned {
+            for (&register, &before) in callee_saved.iter().zip(&before) {
+                let after = self.register(register);
+                if after != before {
+                    clobbered.push(Clobber { register, before, after });
                 }
             }
         }
+        Ok(CallResult {
+            outcome,
+            instructions: self.instructions - start,
+            clobbered,
+        })
+    }
+
+    /// executes a single instruction, does nothing once the machine is halted.
+    /// an instruction that fails still counts as executed and can be stepped back
+    pub fn step(&mut self) -> Result<()> {
+        if self.halted {
+            return Ok(());
+        }
+
+        let isa = self.processor.isa;
+        let pc = self.processor.registers.pc();
+        let instruction = self.read_memory(pc);
+        let decoded = match isa {
+            Isa::Lc3 => self.memory.fetch_decoded(pc),
+            Isa::Lc3b => None,
+        };
+        let cond = self.processor.registers.condition();
+        if self.trace.is_some() {
+            self.write_trace(pc, instruction)?;
+        }
+        let snapshot = self.history.as_ref().map(|_| {
+            self.memory.start_journal();
+            self.processor.registers.clone()
+        });
+
+        self.memory.keyboard.set_clock(self.instructions);
+        let violation = if self.strict { isa.spec_violation(instruction) } else { None };
+        let result = match violation {
+            // not executed, the pc stays at the instruction
+            Some(reason) => Err(Error::IllegalInstruction { instruction, pc, reason }),
+            None => match self.micro.as_mut() {
+                // the microcode increments the pc itself
+                Some(micro) => micro.execute(&mut self.processor.registers, &mut self.memory),
+                None => {
+                    self.processor.registers.set_pc(pc.wrapping_add(isa.word_size()));
+                    match decoded {
+                        Some(decoded) => self.processor.execute_decoded(decoded, &mut self.memory),
+                        None => self.processor.execute(instruction, &mut self.memory),
+                    }
+                }
+            },
+        };
+        let result = match result {
+            Ok(ExecutionResult::Continue) => Ok(()),
+            Ok(ExecutionResult::Trap(trap_vector)) => self
+                .system
+                .handle_trap(trap_vector, isa, &mut self.processor.registers, &mut self.memory)
+                .map(|outcome| {
+                    if let TrapOutcome::Halt = outcome {
+                        self.halted = true;
+                    }
+                }),
+            Err(e) => Err(e),
+        };
+        // a device error during a load, e.g. a diverged replay behind KBSR
+        let result = match self.memory.take_fault() {
+            Some(fault) => result.and(Err(fault)),
+            None => result,
+        };
+
+        self.watch_hits = self.memory.take_watch_hits();
+        for hit in &mut self.watch_hits {
+            hit.pc = pc;
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc, instruction, cond.bits());
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, instruction, self.processor.registers.pc());
+        }
+        if let (Some(history), Some(before)) = (self.history.as_mut(), snapshot) {
+            history.push(UndoRecord {
+                pc,
+                instruction,
+                count: self.instructions,
+                registers: before.diff(&self.processor.registers),
+                memory: self.memory.take_journal(),
+            });
+        }
+        self.instructions += 1;
+        result
+    }
+
+    fn write_trace(&mut self, pc: u16, instruction: u16) -> Result<()> {
+        let mut line = format!(
+            "{:>8}  x{:04X}  x{:04X}  {:24}",
+            self.instructions,
+            pc,
+            instruction,
+            self.isa().disassemble(pc, instruction)
+        );
+        if let Some(entry) = self.source_line(pc) {
+            line.push_str(&format!("{}  {}", entry, entry.text));
+        }
+        let trace = self.trace.as_mut().expect("tracing is enabled");
+        writeln!(trace, "{}", line.trim_end())?;
+        Ok(())
+    }
+
+    /// undoes the most recent recorded instruction, returns false when the history is exhausted.
+    /// console i/o is not undone, input consumed by an undone instruction stays consumed
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.as_mut().and_then(History::pop) else {
+            return false;
+        };
+
+        for &(register, value) in &record.registers {
+            self.processor.registers[register] = value;
+        }
+        for &(addr, value) in record.memory.iter().rev() {
+            self.memory.poke(addr, value);
+        }
+        self.instructions = record.count;
+        self.halted = false;
+        true
+    }
+
+    /// most recent instruction still in the history that wrote to `addr`
+    pub fn last_writer(&self, addr: u16) -> Option<&UndoRecord> {
+        self.history.as_ref()?.last_write(self.isa().word_index(addr))
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
     }
 }
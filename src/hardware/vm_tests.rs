@@ -0,0 +1,409 @@
+use super::call::{CALLEE_SAVED, CallOutcome, Clobber, RETURN_SENTINEL};
+use super::keyboard::{InputEvent, KBDR, KBSR, read_recording};
+use super::watch::{Access, WatchCondition, WatchHit, WatchKind, Watchpoint};
+use super::registers::Register;
+use super::vm::VM;
+use crate::error::Error;
+
+fn vm_with_program(words: &[u16]) -> VM {
+    let mut vm = VM::new();
+    for (offset, word) in words.iter().enumerate() {
+        vm.write_memory(0x3000 + offset as u16, *word);
+    }
+    vm
+}
+
+#[test]
+fn step_back_restores_registers_memory_and_halt_state() {
+    // x3000: ADD R1, R1, #5
+    // x3001: ST R1, #1      -- x3003
+    // x3002: HALT
+    // x3003: x1111
+    let mut vm = vm_with_program(&[0x1265, 0x3201, 0xF025, 0x1111]);
+    vm.enable_history(16);
+    vm.execute().unwrap();
+
+    assert!(vm.is_halted());
+    assert_eq!(vm.read_memory(0x3003), 5);
+
+    assert!(vm.step_back());
+    assert!(!vm.is_halted());
+    assert_eq!(vm.pc(), 0x3002);
+
+    assert!(vm.step_back());
+    assert_eq!(vm.read_memory(0x3003), 0x1111);
+
+    assert!(vm.step_back());
+    assert_eq!(vm.register(Register::R1), 0);
+    assert!(vm.condition().z());
+    assert_eq!(vm.pc(), 0x3000);
+    assert_eq!(vm.instruction_count(), 0);
+
+    assert!(!vm.step_back());
+}
+
+#[test]
+fn history_drops_oldest_records_when_full() {
+    // x3000-x3002: ADD R1, R1, #1
+    let mut vm = vm_with_program(&[0x1261, 0x1261, 0x1261]);
+    vm.enable_history(2);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+
+    assert!(vm.step_back());
+    assert!(vm.step_back());
+    assert!(!vm.step_back());
+    assert_eq!(vm.register(Register::R1), 1);
+    assert_eq!(vm.pc(), 0x3001);
+}
+
+#[test]
+fn last_writer_reports_most_recent_store() {
+    // x3000: ST R0, #3      -- x3004
+    // x3001: ADD R0, R0, #1
+    // x3002: ST R0, #1      -- x3004
+    // x3003: HALT
+    let mut vm = vm_with_program(&[0x3003, 0x1021, 0x3001, 0xF025, 0x0000]);
+    vm.enable_history(16);
+    vm.execute().unwrap();
+
+    let record = vm.last_writer(0x3004).expect("store should be recorded");
+    assert_eq!(record.pc, 0x3002);
+    assert_eq!(record.count, 2);
+    assert!(vm.last_writer(0x3005).is_none());
+}
+
+#[test]
+fn step_back_without_history_does_nothing() {
+    let mut vm = vm_with_program(&[0x1261]);
+    vm.step().unwrap();
+
+    assert!(!vm.step_back());
+    assert_eq!(vm.register(Register::R1), 1);
+}
+
+#[test]
+fn replayed_getc_delivers_recorded_byte() {
+    // x3000: TRAP x20 (GETC)
+    // x3001: HALT
+    let mut vm = vm_with_program(&[0xF020, 0xF025]);
+    vm.replay_input(vec![InputEvent { count: 0, byte: b'x' }]);
+    vm.execute().unwrap();
+
+    assert_eq!(vm.register(Register::R0), b'x' as u16);
+}
+
+#[test]
+fn replayed_kbsr_is_not_ready_until_recorded_instruction() {
+    // x3000: LDI R1, #3     -- KBSR
+    // x3001: BRzp #-2
+    // x3002: LDI R0, #2     -- KBDR
+    // x3003: HALT
+    let mut vm = vm_with_program(&[0xA203, 0x07FE, 0xA002, 0xF025, KBSR, KBDR]);
+    vm.replay_input(vec![InputEvent { count: 6, byte: b'q' }]);
+    vm.execute().unwrap();
+
+    assert_eq!(vm.register(Register::R0), b'q' as u16);
+    assert_eq!(vm.instruction_count(), 10);
+    assert_eq!(vm.read_memory(KBSR) & 0x8000, 0);
+}
+
+#[test]
+fn replay_consumed_at_a_different_instruction_is_an_error() {
+    let mut vm = vm_with_program(&[0xF020, 0xF025]);
+    vm.replay_input(vec![InputEvent { count: 1, byte: b'x' }]);
+    assert!(matches!(
+        vm.execute(),
+        Err(Error::ReplayDiverged { byte: b'x', recorded: 1, consumed: 0 })
+    ));
+}
+
+#[test]
+fn diverged_replay_behind_kbsr_is_reported_by_step() {
+    // x3000: NOP ; x3001: LDI R1, KBSR_PTR ; x3002: HALT ; x3003: .FILL xFE00
+    // the byte was recorded as ready at instruction 0 but is first polled at instruction 1
+    let mut vm = vm_with_program(&[0x0000, 0xA201, 0xF025, KBSR]);
+    vm.replay_input(vec![InputEvent { count: 0, byte: b'x' }]);
+    assert!(matches!(vm.step(), Ok(())));
+    assert!(matches!(vm.step(), Err(Error::ReplayDiverged { consumed: 1, .. })));
+}
+
+#[test]
+fn unknown_trap_is_an_error_with_its_address() {
+    let mut vm = vm_with_program(&[0x1021, 0xF0AA]);
+    assert!(matches!(vm.execute(), Err(Error::UnknownTrap { vector: 0xAA, pc: 0x3001 })));
+    assert_eq!(vm.instruction_count(), 2);
+    assert!(!vm.is_halted());
+}
+
+#[test]
+fn strict_mode_stops_at_illegal_instructions() {
+    // x3000: ADD R1, R1, #1
+    // x3001: RES
+    // x3002: HALT
+    let mut vm = vm_with_program(&[0x1261, 0xD000, 0xF025]);
+    vm.set_strict(true);
+    let error = vm.execute().unwrap_err();
+    assert_eq!(error.to_string(), "illegal instruction xD000 at x3001: reserved opcode");
+    assert_eq!(vm.pc(), 0x3001);
+    assert_eq!(vm.register(Register::R1), 1);
+
+    // ignored outside of strict mode
+    let mut vm = vm_with_program(&[0x1261, 0xD000, 0xF025]);
+    vm.execute().unwrap();
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn strict_mode_rejects_reserved_bits() {
+    for instruction in [0x8000, 0x9AB0, 0x1298, 0xC5C0, 0x42C1, 0xF125] {
+        let mut vm = vm_with_program(&[instruction, 0xF025]);
+        vm.set_strict(true);
+        assert!(
+            matches!(vm.execute(), Err(Error::IllegalInstruction { pc: 0x3000, .. })),
+            "{:04X}",
+            instruction
+        );
+    }
+    // NOT R5, R2; ADD R1, R2, R3; JSRR R3
+    let mut vm = vm_with_program(&[0x9ABF, 0x1283, 0x40C0, 0xF025]);
+    vm.set_strict(true);
+    vm.set_register(Register::R3, 0x3003);
+    vm.execute().unwrap();
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn input_trap_without_input_is_end_of_input() {
+    let mut vm = vm_with_program(&[0xF020, 0xF020, 0xF025]);
+    vm.set_input("a");
+    assert!(matches!(vm.execute(), Err(Error::EndOfInput)));
+    assert_eq!(vm.register(Register::R0), b'a' as u16);
+}
+
+#[test]
+fn pushed_input_is_queued_behind_what_is_left() {
+    let mut vm = vm_with_program(&[0xF020, 0xF020, 0xF020, 0xF025]);
+    assert_eq!(vm.pending_input(), 0);
+    vm.push_input(b"a");
+    vm.step().unwrap();
+    assert_eq!(vm.pending_input(), 0);
+    vm.push_input(b"bc");
+    assert_eq!(vm.pending_input(), 2);
+    vm.execute().unwrap();
+    assert_eq!(vm.register(Register::R0), b'c' as u16);
+}
+
+#[test]
+fn invalid_register_index_is_an_error() {
+    assert_eq!(Register::from_index(8).unwrap(), Register::PC);
+    assert!(matches!(Register::from_index(10), Err(Error::InvalidRegister(10))));
+}
+
+#[test]
+fn recordings_are_parsed_ignoring_comments() {
+    let recording = "# rustvm input recording\n12 97\n\n40 10\n";
+    let events = read_recording(recording.as_bytes()).unwrap();
+
+    assert_eq!(
+        events,
+        vec![InputEvent { count: 12, byte: 97 }, InputEvent { count: 40, byte: 10 }]
+    );
+    assert!(read_recording("12 300\n".as_bytes()).is_err());
+}
+
+// x3000: LEA R0, #3     -- x3004
+// x3001: PUTS
+// x3002: ST R0, #2      -- x3005
+// x3003: HALT
+// x3004: 'h'
+// x3005: 0
+const PUTS_THEN_STORE: [u16; 6] = [0xE003, 0xF022, 0x3002, 0xF025, 0x0068, 0x0000];
+
+#[test]
+fn read_watchpoint_triggers_on_trap_routine_reads() {
+    let mut vm = vm_with_program(&PUTS_THEN_STORE);
+    vm.add_watchpoint(Watchpoint::new(0x3005..=0x3005, WatchKind::Read));
+
+    let hits = vm.run_until_watch().unwrap().to_vec();
+
+    assert_eq!(
+        hits,
+        vec![WatchHit { id: 0, pc: 0x3001, addr: 0x3005, access: Access::Read, old: 0, new: 0 }]
+    );
+    assert_eq!(vm.pc(), 0x3002);
+}
+
+#[test]
+fn write_watchpoint_condition_on_new_value() {
+    let mut vm = vm_with_program(&PUTS_THEN_STORE);
+    let watchpoint = Watchpoint::new(0x3004..=0x3005, WatchKind::Write).with_condition(WatchCondition::NewEquals(0x3004));
+    vm.add_watchpoint(watchpoint);
+
+    let hits = vm.run_until_watch().unwrap().to_vec();
+
+    assert_eq!(
+        hits,
+        vec![WatchHit { id: 0, pc: 0x3002, addr: 0x3005, access: Access::Write, old: 0, new: 0x3004 }]
+    );
+}
+
+#[test]
+fn unmatched_or_removed_watchpoints_do_not_stop_execution() {
+    let mut vm = vm_with_program(&PUTS_THEN_STORE);
+    let unmatched = Watchpoint::new(0x3005..=0x3005, WatchKind::Write).with_condition(WatchCondition::OldEquals(1));
+    vm.add_watchpoint(unmatched);
+    let removed = vm.add_watchpoint(Watchpoint::new(0x3004..=0x3004, WatchKind::Access));
+    assert!(vm.remove_watchpoint(removed));
+
+    assert!(vm.run_until_watch().unwrap().is_empty());
+    assert!(vm.is_halted());
+    assert_eq!(vm.watchpoints().count(), 1);
+}
+
+#[test]
+fn watchpoint_ids_are_not_reused_after_removing_all() {
+    let mut vm = vm_with_program(&PUTS_THEN_STORE);
+    let first = vm.add_watchpoint(Watchpoint::new(0x3004..=0x3004, WatchKind::Write));
+    assert!(vm.remove_watchpoint(first));
+    let second = vm.add_watchpoint(Watchpoint::new(0x3005..=0x3005, WatchKind::Write));
+
+    assert_ne!(first, second);
+    assert!(!vm.remove_watchpoint(first));
+    assert_eq!(vm.watchpoints().map(|(id, _)| id).collect::<Vec<_>>(), vec![second]);
+}
+
+#[test]
+fn decode_cache_sees_self_modifying_code() {
+    // x3000 PATCH: ADD R0, R0, #1
+    // x3001:       ADD R1, R1, #1
+    // x3002:       ADD R2, R1, #-2
+    // x3003:       BRzp DONE
+    // x3004:       LD R3, NEW
+    // x3005:       ST R3, PATCH
+    // x3006:       BRnzp PATCH
+    // x3007 DONE:  HALT
+    // x3008 NEW:   ADD R0, R0, #10
+    let program = [0x1021, 0x1261, 0x147E, 0x0603, 0x2603, 0x37FA, 0x0FF9, 0xF025, 0x102A];
+    for decode_cache in [false, true] {
+        let mut vm = vm_with_program(&program);
+        vm.set_decode_cache(decode_cache);
+        vm.execute().unwrap();
+
+        assert_eq!(vm.register(Register::R0), 11, "decode cache {decode_cache}");
+    }
+}
+
+#[test]
+fn run_for_stops_at_budget_and_scripted_input_is_consumed_in_order() {
+    // x3000: GETC
+    // x3001: ADD R1, R0, #0
+    // x3002: GETC
+    // x3003: HALT
+    let mut vm = vm_with_program(&[0xF020, 0x1220, 0xF020, 0xF025]);
+    vm.set_input("ab");
+
+    assert_eq!(vm.run_for(2).unwrap(), 2);
+    assert!(!vm.is_halted());
+    assert_eq!(vm.run_for(100).unwrap(), 2);
+    assert!(vm.is_halted());
+    assert_eq!((vm.register(Register::R1), vm.register(Register::R0)), (b'a' as u16, b'b' as u16));
+}
+
+// x3000 DOUBLE: ADD R0, R0, R0
+// x3001:        RET
+// x3002 BAD:    AND R4, R4, #0
+// x3003:        ADD R0, R0, #1
+// x3004:        RET
+// x3005 OUTER:  ADD R5, R7, #0
+// x3006:        JSR DOUBLE
+// x3007:        ADD R7, R5, #0
+// x3008:        RET
+// x3009 STOP:   HALT
+// x300A SPIN:   BRnzp SPIN
+const SUBROUTINES: [u16; 11] = [
+    0x1000, 0xC1C0, 0x5920, 0x1021, 0xC1C0, 0x1BE0, 0x4FF9, 0x1F60, 0xC1C0, 0xF025, 0x0FFF,
+];
+
+#[test]
+fn call_runs_until_the_subroutine_returns() {
+    let mut vm = vm_with_program(&SUBROUTINES);
+    vm.set_register(Register::R0, 21);
+    let result = vm.call(0x3000, &CALLEE_SAVED, 100).unwrap();
+    assert_eq!(result.outcome, CallOutcome::Returned);
+    assert_eq!(result.instructions, 2);
+    assert!(result.clobbered.is_empty());
+    assert_eq!(vm.register(Register::R0), 42);
+    assert_eq!(vm.pc(), RETURN_SENTINEL);
+}
+
+#[test]
+fn nested_calls_return_to_the_outer_caller_only() {
+    let mut vm = vm_with_program(&SUBROUTINES);
+    vm.set_register(Register::R0, 3);
+    let result = vm.call(0x3005, &[Register::R1], 100).unwrap();
+    assert_eq!((result.outcome, result.instructions), (CallOutcome::Returned, 6));
+    assert_eq!(vm.register(Register::R0), 6);
+}
+
+#[test]
+fn call_reports_clobbered_callee_saved_registers() {
+    let mut vm = vm_with_program(&SUBROUTINES);
+    vm.set_register(Register::R4, 9);
+    let result = vm.call(0x3002, &CALLEE_SAVED, 100).unwrap();
+    assert_eq!(
+        result.clobbered,
+        vec![Clobber { register: Register::R4, before: 9, after: 0 }]
+    );
+    assert_eq!(result.clobbered[0].to_string(), "R4 not preserved: x0009 before the call, x0000 after");
+}
+
+#[test]
+fn call_stops_on_halt_and_budget() {
+    let mut vm = vm_with_program(&SUBROUTINES);
+    assert_eq!(vm.call(0x3009, &CALLEE_SAVED, 100).unwrap().outcome, CallOutcome::Halted);
+    // a halted machine can still be called into
+    let result = vm.call(0x300A, &CALLEE_SAVED, 25).unwrap();
+    assert_eq!((result.outcome, result.instructions), (CallOutcome::Timeout, 25));
+}
+
+/// a writer the test can still read after handing it to the vm
+#[derive(Clone, Default)]
+struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_and_errors_name_the_source_line() {
+    let program = crate::assembler::assemble(".ORIG x3000\nADD R0, R0, #2\nTRAP x30\n.END\n").unwrap();
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.set_source_map(program.source_map());
+    let trace = Shared::default();
+    vm.set_trace(Box::new(trace.clone()));
+
+    let error = vm.execute().unwrap_err();
+    assert_eq!(vm.describe_error(&error), "unknown TRAP vector x30 at x3001 (line 3)");
+    assert_eq!(
+        String::from_utf8(trace.0.borrow().clone()).unwrap(),
+        "       0  x3000  x1022  ADD R0, R0, #2          line 2  ADD R0, R0, #2\n       \
+                1  x3001  xF030  TRAP x30                line 3  TRAP x30\n"
+    );
+
+    // without a map the trace has no source column
+    let mut vm = vm_with_program(&[0xF025]);
+    let trace = Shared::default();
+    vm.set_trace(Box::new(trace.clone()));
+    vm.execute().unwrap();
+    assert_eq!(String::from_utf8(trace.0.borrow().clone()).unwrap(), "       0  x3000  xF025  HALT\n");
+}
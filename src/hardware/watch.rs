@@ -0,0 +1,110 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// either a read or a write
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// extra condition on the value of the watched word, for reads old and new are the value read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchCondition {
+    Always,
+    OldEquals(u16),
+    NewEquals(u16),
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub kind: WatchKind,
+    pub condition: WatchCondition,
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<u16>, kind: WatchKind) -> Self {
+        Self {
+            range,
+            kind,
+            condition: WatchCondition::Always,
+        }
+    }
+
+    pub fn with_condition(mut self, condition: WatchCondition) -> Self {
+        self.condition = condition;
+        self
+    }
+
+    pub fn matches(&self, addr: u16, access: Access, old: u16, new: u16) -> bool {
+        let kind_matches = matches!(
+            (self.kind, access),
+            (WatchKind::Access, _) | (WatchKind::Read, Access::Read) | (WatchKind::Write, Access::Write)
+        );
+        let condition_matches = match self.condition {
+            WatchCondition::Always => true,
+            WatchCondition::OldEquals(value) => old == value,
+            WatchCondition::NewEquals(value) => new == value,
+            WatchCondition::Changed => old != new,
+        };
+        kind_matches && condition_matches && self.range.contains(&addr)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        };
+        write!(f, "{} x{:04X}", kind, self.range.start())?;
+        if self.range.end() != self.range.start() {
+            write!(f, "-x{:04X}", self.range.end())?;
+        }
+        match self.condition {
+            WatchCondition::Always => Ok(()),
+            WatchCondition::OldEquals(value) => write!(f, " if old == x{:04X}", value),
+            WatchCondition::NewEquals(value) => write!(f, " if new == x{:04X}", value),
+            WatchCondition::Changed => write!(f, " if changed"),
+        }
+    }
+}
+
+/// a triggered watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub id: usize,
+    /// address of the instruction that made the access
+    pub pc: u16,
+    pub addr: u16,
+    pub access: Access,
+    pub old: u16,
+    pub new: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(
+                f,
+                "watchpoint {}: x{:04X} read x{:04X} at x{:04X}",
+                self.id, self.addr, self.new, self.pc
+            ),
+            Access::Write => write!(
+                f,
+                "watchpoint {}: x{:04X} written x{:04X} -> x{:04X} at x{:04X}",
+                self.id, self.addr, self.old, self.new, self.pc
+            ),
+        }
+    }
+}
@@ -0,0 +1,16 @@
+pub mod analysis;
+pub mod assembler;
+pub mod coverage;
+pub mod debugger;
+pub mod error;
+pub mod grader;
+pub mod hardware;
+pub mod linker;
+pub mod loader;
+pub mod lsp;
+pub mod profiler;
+pub mod runner;
+pub mod source_map;
+pub mod symbols;
+pub mod tui;
+pub mod utils;
@@ -0,0 +1,133 @@
+use super::*;
+use crate::assembler::assemble_object_in;
+use crate::hardware::registers::Register;
+use crate::hardware::vm::VM;
+use std::path::Path;
+
+fn object(source: &str) -> Object {
+    assemble_object_in(source, Path::new("")).unwrap()
+}
+
+const MAIN: &str = "
+        .EXTERN PRINT, COUNT
+        .SECTION text
+MAIN    LEA R0, MSG
+        JSR PRINT
+        LD R1, PCOUNT
+        LDR R2, R1, #0
+        HALT
+PCOUNT  .FILL COUNT + 1
+        .SECTION data
+MSG     .STRINGZ \"hi\"
+";
+
+const LIB: &str = "
+        .GLOBAL PRINT, COUNT
+        .SECTION text
+PRINT   PUTS
+        RET
+COUNT   .FILL 41
+        .FILL 42
+";
+
+#[test]
+fn links_modules_and_resolves_references_between_them() {
+    let linked = link(&[object(MAIN), object(LIB)], DEFAULT_BASE).unwrap();
+    assert_eq!(linked.image.origin, 0x3000);
+    assert_eq!(
+        linked.image.words,
+        vec![
+            // main text at x3000
+            0xE005, 0x4807, 0x2202, 0x6440, 0xF025, 0x300C,
+            // main data at x3006
+            0x0068, 0x0069, 0x0000,
+            // lib text at x3009
+            0xF022, 0xC1C0, 41, 42,
+        ]
+    );
+    assert_eq!(linked.symbols.address("PRINT"), Some(0x3009));
+    assert_eq!(linked.symbols.address("module1.MSG"), Some(0x3006));
+    assert_eq!(linked.symbols.address("MSG"), None);
+    assert_eq!(linked.source_map.lookup(0x300A).map(|entry| entry.line), Some(5));
+    assert_eq!(linked.source_map.lookup(0x3007).map(|entry| entry.text.as_str()), Some("MSG     .STRINGZ \"hi\""));
+
+    let mut vm = VM::new();
+    vm.load(&linked.image);
+    vm.set_output(Box::new(std::io::sink()));
+    vm.execute().unwrap();
+    assert_eq!(vm.register(Register::R2), 42);
+}
+
+#[test]
+fn placed_sections_go_around_orig_blocks() {
+    let fixed = object(".ORIG x3002\n.GLOBAL TABLE\nTABLE .FILL 7\n.FILL 8\n");
+    let code = object(".EXTERN TABLE\n.SECTION a\nLD R0, TABLE\n.SECTION b\n.FILL TABLE\n.FILL 0\n");
+    let linked = link(&[code, fixed], DEFAULT_BASE).unwrap();
+    // a fits before the block, b does not
+    assert_eq!(linked.image.words, vec![0x2001, 0, 7, 8, 0x3002, 0]);
+}
+
+#[test]
+fn pc_offsets_are_checked_once_the_target_is_known() {
+    let far = object(".GLOBAL FAR\n.SECTION text\n.BLKW 300\nFAR RET\n");
+    let near = object(".EXTERN FAR\n.SECTION text\nBR FAR\nJSR FAR\n");
+    assert_eq!(
+        link(&[near, far], DEFAULT_BASE).unwrap_err().to_string(),
+        "line 3: x312E is out of reach of x3000, offset 301 does not fit in 9 bits (-256 to 255)"
+    );
+}
+
+#[test]
+fn symbols_have_to_be_defined_exactly_once() {
+    let error = link(&[object(MAIN)], DEFAULT_BASE).unwrap_err();
+    assert_eq!(error.to_string(), "line 5: undefined symbol PRINT");
+
+    let again = object(".GLOBAL PRINT\n.SECTION text\nPRINT RET\n");
+    let error = link(&[object(MAIN), object(LIB), again], DEFAULT_BASE).unwrap_err();
+    assert_eq!(error.to_string(), "PRINT is defined by both <source> and <source>");
+}
+
+#[test]
+fn overlapping_orig_blocks_are_an_error() {
+    let first = object(".ORIG x3000\n.BLKW 4\n");
+    let second = object(".ORIG x3003\nHALT\n");
+    assert_eq!(
+        link(&[first, second], DEFAULT_BASE).unwrap_err().to_string(),
+        "section .ORIG of <source> at x3003 overlaps .ORIG of <source> at x3000"
+    );
+    assert_eq!(
+        link(&[], DEFAULT_BASE).unwrap_err().to_string(),
+        "nothing to link, the objects have no code"
+    );
+}
+
+#[test]
+fn objects_round_trip_through_json() {
+    let module = object(MAIN);
+    assert_eq!(module.imports, vec!["COUNT".to_string(), "PRINT".to_string()]);
+    assert_eq!(module.sections[0].relocations.len(), 3);
+
+    let mut json = Vec::new();
+    module.write_json(&mut json).unwrap();
+    assert_eq!(Object::read(json.as_slice()).unwrap(), module);
+    assert!(Object::read(&b"{\"sections\": 3}"[..]).unwrap_err().to_string().starts_with("invalid object file"));
+}
+
+#[test]
+fn local_labels_are_qualified_with_their_module() {
+    let module = |path: &str, source: &str| Object {
+        source: Some(path.to_string()),
+        ..object(source)
+    };
+    let objects = [
+        module("src/main.asm", ".EXTERN WAIT\n.SECTION text\nLOOP JSR WAIT\nBR LOOP\n"),
+        module("src/util.asm", ".GLOBAL WAIT\n.SECTION text\nWAIT ADD R0, R0, #-1\nLOOP BRp LOOP\nRET\n"),
+        module("old/util.asm", ".SECTION text\nLOOP HALT\n"),
+    ];
+    let linked = link(&objects, DEFAULT_BASE).unwrap();
+    assert_eq!(linked.symbols.address("main.LOOP"), Some(0x3000));
+    assert_eq!(linked.symbols.address("WAIT"), Some(0x3002));
+    assert_eq!(linked.symbols.address("src/util.asm.LOOP"), Some(0x3003));
+    assert_eq!(linked.symbols.address("old/util.asm.LOOP"), Some(0x3005));
+    assert_eq!(linked.symbols.name(0x3003), Some("src/util.asm.LOOP"));
+}
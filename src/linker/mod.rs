@@ -0,0 +1,329 @@
+//! relocatable objects written by `rustvm asm -c` and the linker that turns them into the
+//! absolute image the loader reads
+
+use crate::error::{Error, Result};
+use crate::hardware::isa::Isa;
+use crate::loader::Image;
+use crate::source_map::{SourceEntry, SourceMap};
+use crate::symbols::SymbolTable;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+#[cfg(test)]
+mod linker_tests;
+
+/// where relocatable sections go unless told otherwise
+pub const DEFAULT_BASE: u16 = 0x3000;
+
+/// an assembled module that still has to be placed in memory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Object {
+    /// the file the module was assembled from, for messages
+    pub source: Option<String>,
+    pub sections: Vec<Section>,
+    /// every label of the module, the `.GLOBAL` ones can be referenced by other modules
+    pub symbols: Vec<ObjectSymbol>,
+    /// names declared `.EXTERN`, defined by another module
+    pub imports: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Section {
+    pub name: String,
+    /// fixed address of a `.ORIG` block, none for a section the linker places
+    pub origin: Option<u16>,
+    pub words: Vec<u16>,
+    pub relocations: Vec<Relocation>,
+    /// source lines with addresses relative to the start of the section
+    #[serde(default)]
+    pub lines: Vec<SourceEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub section: usize,
+    /// from the start of the section
+    pub offset: u16,
+    pub global: bool,
+}
+
+/// a word whose value depends on where something ends up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Relocation {
+    /// index of the word in its section
+    pub offset: u16,
+    pub kind: RelocationKind,
+    pub target: Target,
+    pub addend: i32,
+    /// where the reference was written, for messages
+    pub file: Option<String>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocationKind {
+    /// the whole word is the address, `.FILL LABEL`
+    Word,
+    /// the low 9 bits are an offset from the incremented pc, BR, LD, LDI, LEA, ST and STI
+    Pc9,
+    /// the low 11 bits, JSR
+    Pc11,
+}
+
+impl RelocationKind {
+    fn bits(self) -> u32 {
+        match self {
+            RelocationKind::Word => 16,
+            RelocationKind::Pc9 => 9,
+            RelocationKind::Pc11 => 11,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// a `.GLOBAL` label of another module
+    Symbol(String),
+    /// the start of a section of the same module
+    Section(usize),
+}
+
+impl fmt::Display for Relocation {
+    /// `a.asm:12`, or `line 12` without a file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file, self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+impl Object {
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        serde_json::from_reader(reader).map_err(|e| Error::Link(format!("invalid object file: {}", e)))
+    }
+
+    pub fn write_json<W: Write>(&self, mut output: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut output, self)?;
+        writeln!(output)
+    }
+
+    fn name(&self) -> &str {
+        self.source.as_deref().unwrap_or("<source>")
+    }
+}
+
+/// output of a successful link
+#[derive(Debug, Clone)]
+pub struct Linked {
+    pub image: Image,
+    /// every label of every module at its final address, labels that are not `.GLOBAL`
+    /// qualified with their module, `util.LOOP`
+    pub symbols: SymbolTable,
+    pub source_map: SourceMap,
+}
+
+/// a section with its final address
+struct Placed<'a> {
+    object: usize,
+    section: &'a Section,
+    start: u16,
+}
+
+impl Placed<'_> {
+    /// one past the last word, as u32 so a section ending at xFFFF does not wrap
+    fn end(&self) -> u32 {
+        self.start as u32 + self.section.words.len() as u32
+    }
+
+    fn describe(&self, objects: &[Object]) -> String {
+        format!("{} of {}", self.section.name, objects[self.object].name())
+    }
+}
+
+/// start address of every section, indexed by object and section: `.ORIG` blocks where
+/// they say, the others one after another from `base` in the order given, skipping over
+/// the fixed ones
+fn place(objects: &[Object], base: u16) -> Result<Vec<Vec<u16>>> {
+    let fixed: Vec<(u32, u32)> = objects
+        .iter()
+        .flat_map(|object| &object.sections)
+        .filter_map(|section| Some((section.origin? as u32, section.origin? as u32 + section.words.len() as u32)))
+        .collect();
+    let mut next = base as u32;
+    let mut starts = Vec::new();
+    for object in objects {
+        let mut object_starts = Vec::new();
+        for section in &object.sections {
+            if let Some(origin) = section.origin {
+                object_starts.push(origin);
+                continue;
+            }
+            let len = section.words.len() as u32;
+            while let Some(&(_, end)) = fixed.iter().find(|&&(start, end)| next < end && start < next + len) {
+                next = end;
+            }
+            if next + len > u16::MAX as u32 + 1 {
+                return Err(Error::Link(format!(
+                    "section {} of {} does not fit in memory",
+                    section.name,
+                    object.name()
+                )));
+            }
+            object_starts.push(next as u16);
+            next += len;
+        }
+        starts.push(object_starts);
+    }
+    Ok(starts)
+}
+
+/// places the sections of `objects` and resolves every relocation, the result is a
+/// single image from the lowest to the highest address used with gaps filled with zeros
+pub fn link(objects: &[Object], base: u16) -> Result<Linked> {
+    let starts = place(objects, base)?;
+    let mut placed: Vec<Placed> = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        for (section, &start) in object.sections.iter().zip(&starts[index]) {
+            if !section.words.is_empty() {
+                placed.push(Placed {
+                    object: index,
+                    section,
+                    start,
+                });
+            }
+        }
+    }
+    placed.sort_by_key(|placed| placed.start);
+    for pair in placed.windows(2) {
+        if (pair[1].start as u32) < pair[0].end() {
+            return Err(Error::Link(format!(
+                "section {} at x{:04X} overlaps {} at x{:04X}",
+                pair[1].describe(objects),
+                pair[1].start,
+                pair[0].describe(objects),
+                pair[0].start
+            )));
+        }
+    }
+
+    let mut globals: HashMap<&str, (u16, usize)> = HashMap::new();
+    let mut symbols = SymbolTable::new();
+    let modules = module_names(objects);
+    for (index, object) in objects.iter().enumerate() {
+        for symbol in &object.symbols {
+            let start = starts[index].get(symbol.section).ok_or_else(|| {
+                Error::Link(format!("{} of {} is in a missing section", symbol.name, object.name()))
+            })?;
+            let addr = start.wrapping_add(symbol.offset);
+            if !symbol.global {
+                // locals of different modules may share a name
+                symbols.insert(&format!("{}.{}", modules[index], symbol.name), addr);
+                continue;
+            }
+            symbols.insert(&symbol.name, addr);
+            if let Some((_, first)) = globals.insert(&symbol.name, (addr, index)) {
+                return Err(Error::Link(format!(
+                    "{} is defined by both {} and {}",
+                    symbol.name,
+                    objects[first].name(),
+                    object.name()
+                )));
+            }
+        }
+    }
+
+    let (Some(first), Some(last)) = (placed.first(), placed.iter().map(Placed::end).max()) else {
+        return Err(Error::Link("nothing to link, the objects have no code".to_string()));
+    };
+    let origin = first.start;
+    let mut words = vec![0; (last - origin as u32) as usize];
+    let mut source_map = SourceMap::new();
+    for placed in &placed {
+        let at = (placed.start - origin) as usize;
+        let section_words = &mut words[at..at + placed.section.words.len()];
+        section_words.copy_from_slice(&placed.section.words);
+
+        for relocation in &placed.section.relocations {
+            let target = match &relocation.target {
+                Target::Symbol(name) => match globals.get(name.as_str()) {
+                    Some(&(addr, _)) => addr,
+                    None => return Err(Error::Link(format!("{}: undefined symbol {}", relocation, name))),
+                },
+                Target::Section(section) => *starts[placed.object]
+                    .get(*section)
+                    .ok_or_else(|| Error::Link(format!("{}: relocation against a missing section", relocation)))?,
+            };
+            let word = section_words
+                .get_mut(relocation.offset as usize)
+                .ok_or_else(|| Error::Link(format!("{}: relocation outside of its section", relocation)))?;
+            let addr = placed.start.wrapping_add(relocation.offset);
+            *word = resolve(relocation, *word, addr, target as i32 + relocation.addend)?;
+        }
+        for line in &placed.section.lines {
+            source_map.insert(SourceEntry {
+                address: placed.start.wrapping_add(line.address),
+                ..line.clone()
+            });
+        }
+    }
+    Ok(Linked {
+        image: Image { origin, words, isa: Isa::Lc3 },
+        symbols,
+        source_map,
+    })
+}
+
+/// what qualifies the local labels of each module: the file stem of its source, the whole
+/// path when two modules share a stem, `module2` for the second object when it has no source
+fn module_names(objects: &[Object]) -> Vec<String> {
+    let stem = |object: &Object| {
+        let path = Path::new(object.source.as_deref()?);
+        Some(path.file_stem()?.to_string_lossy().into_owned())
+    };
+    let stems: Vec<Option<String>> = objects.iter().map(stem).collect();
+    objects
+        .iter()
+        .zip(&stems)
+        .enumerate()
+        .map(|(index, (object, own))| match own {
+            Some(own) if stems.iter().filter(|other| other.as_ref() == Some(own)).count() == 1 => own.clone(),
+            Some(_) => object.name().to_string(),
+            None => format!("module{}", index + 1),
+        })
+        .collect()
+}
+
+/// `word` with the field of `relocation` set for `target`, checking that it fits
+fn resolve(relocation: &Relocation, word: u16, addr: u16, target: i32) -> Result<u16> {
+    let value = match relocation.kind {
+        RelocationKind::Word => target,
+        _ => target - (addr as i32 + 1),
+    };
+    let bits = relocation.kind.bits();
+    let (low, high) = match relocation.kind {
+        RelocationKind::Word => (-(1 << 15), (1 << 16) - 1),
+        _ => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+    };
+    if !(low..=high).contains(&value) {
+        return Err(Error::Link(match relocation.kind {
+            RelocationKind::Word => format!("{}: {} does not fit in a word", relocation, value),
+            _ => format!(
+                "{}: x{:04X} is out of reach of x{:04X}, offset {} does not fit in {} bits ({} to {})",
+                relocation, target as u16, addr, value, bits, low, high
+            ),
+        }));
+    }
+    let mask = ((1u32 << bits) - 1) as u16;
+    Ok(word & !mask | value as u16 & mask)
+}
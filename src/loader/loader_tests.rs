@@ -0,0 +1,39 @@
+use super::*;
+use crate::error::Error;
+
+#[test]
+fn reads_origin_and_words() {
+    let image = Image::read(&[0x30, 0x00, 0x12, 0x34, 0xF0, 0x25][..]).unwrap();
+    assert_eq!(image.origin, 0x3000);
+    assert_eq!(image.words, vec![0x1234, 0xF025]);
+    assert_eq!(image.iter().collect::<Vec<_>>(), vec![(0x3000, 0x1234), (0x3001, 0xF025)]);
+    assert_eq!(image.range(), Some(0x3000..=0x3001));
+}
+
+#[test]
+fn empty_file_is_an_error() {
+    assert!(matches!(Image::read(&[][..]), Err(Error::EmptyImage)));
+    assert!(matches!(Image::read(&[0x30][..]), Err(Error::EmptyImage)));
+}
+
+#[test]
+fn image_past_end_of_memory_is_an_error() {
+    let bytes = [0xFF, 0xFF, 0x00, 0x01, 0x00, 0x02];
+    assert!(matches!(
+        Image::read(&bytes[..]),
+        Err(Error::ImageTooLarge { origin: 0xFFFF, len: 2 })
+    ));
+}
+
+#[test]
+fn lc3b_images_carry_a_header_and_count_bytes() {
+    let image = Image { origin: 0x3000, words: vec![0x1234, 0xF025], isa: Isa::Lc3b };
+    let mut bytes = Vec::new();
+    image.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..LC3B_MAGIC.len()], LC3B_MAGIC);
+
+    let read = Image::read(&bytes[..]).unwrap();
+    assert_eq!(read, image);
+    assert_eq!(read.iter().collect::<Vec<_>>(), vec![(0x3000, 0x1234), (0x3002, 0xF025)]);
+    assert_eq!(read.range(), Some(0x3000..=0x3002));
+}
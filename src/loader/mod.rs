@@ -0,0 +1,78 @@
+use crate::error::{Error, Result};
+use crate::hardware::isa::Isa;
+use crate::hardware::memory::MEMORY_SIZE;
+use crate::utils::U16FileReader;
+use std::io::{self, Read, Write};
+
+#[cfg(test)]
+mod loader_tests;
+
+/// starts an LC-3b object file, LC-3 object files have no header
+pub const LC3B_MAGIC: &[u8] = b"LC-3b\n";
+
+/// contents of an object file: an origin word followed by the words placed from there on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    /// what the words are for, on LC-3b the origin is a byte address
+    pub isa: Isa,
+}
+
+impl Image {
+    /// a trailing odd byte is ignored, like the reference simulator does
+    pub fn read<R: Read>(mut reader: R) -> Result<Image> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (isa, bytes) = match bytes.strip_prefix(LC3B_MAGIC) {
+            Some(rest) => (Isa::Lc3b, rest),
+            None => (Isa::Lc3, &bytes[..]),
+        };
+
+        let mut reader = U16FileReader::new(bytes);
+        let origin = match reader.read_u16() {
+            Ok(origin) => origin,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(Error::EmptyImage),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut words = Vec::new();
+        loop {
+            match reader.read_u16() {
+                Ok(word) => words.push(word),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if isa.word_index(origin) as usize + words.len() > MEMORY_SIZE / isa.word_size() as usize {
+                return Err(Error::ImageTooLarge { origin, len: words.len() });
+            }
+        }
+        Ok(Image { origin, words, isa })
+    }
+
+    /// big endian words, origin first, the format `read` expects
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        if self.isa == Isa::Lc3b {
+            writer.write_all(LC3B_MAGIC)?;
+        }
+        for word in std::iter::once(&self.origin).chain(&self.words) {
+            writer.write_all(&word.to_be_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// (address, word) of every word in the image
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .map(|(offset, &word)| (self.origin.wrapping_add(offset as u16 * self.isa.word_size()), word))
+    }
+
+    /// addresses covered by the image, none for an image without words. on LC-3b this
+    /// ends at the address of the last word, not its second byte
+    pub fn range(&self) -> Option<std::ops::RangeInclusive<u16>> {
+        let len = u16::try_from(self.words.len()).ok()?;
+        (len > 0).then(|| self.origin..=self.origin + (len - 1) * self.isa.word_size())
+    }
+}
@@ -0,0 +1,186 @@
+use super::transport::MAX_MESSAGE_LENGTH;
+use super::*;
+
+const URI: &str = "file:///work/prog.asm";
+
+const PROGRAM: &str = "\
+COUNT   .EQU 3
+        .ORIG x3000
+MAIN    AND R1, R1, #0
+        ADD R1, R1, COUNT
+LOOP    ADD R1, R1, #-1
+        BRp LOOP
+        JSR DONE
+DONE    HALT
+        .END
+";
+
+fn request(server: &mut Server, method: &str, params: Value) -> Value {
+    let mut replies = server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}));
+    assert_eq!(replies.len(), 1);
+    replies.remove(0)
+}
+
+fn open(text: &str) -> (Server, Vec<Value>) {
+    let mut server = Server::new();
+    request(&mut server, "initialize", json!({}));
+    let params = json!({"textDocument": {"uri": URI, "languageId": "lc3", "version": 1, "text": text}});
+    let published = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": params}));
+    (server, published)
+}
+
+fn at(line: usize, character: usize) -> Value {
+    json!({"textDocument": {"uri": URI}, "position": {"line": line, "character": character}})
+}
+
+fn hover(server: &mut Server, line: usize, character: usize) -> String {
+    let reply = request(server, "textDocument/hover", at(line, character));
+    reply["result"]["contents"]["value"].as_str().unwrap_or_default().to_string()
+}
+
+#[test]
+fn diagnostics_follow_the_text() {
+    let (mut server, published) = open(PROGRAM);
+    assert_eq!(published[0]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+
+    let broken = PROGRAM.replace("BRp LOOP", "BRp LOOOP");
+    let change = json!({"textDocument": {"uri": URI, "version": 2}, "contentChanges": [{"text": broken}]});
+    let published = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": change}));
+    let diagnostics = &published[0]["params"]["diagnostics"];
+    assert_eq!(diagnostics[0]["message"], "undefined label LOOOP");
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 5);
+    assert_eq!(diagnostics[0]["range"]["end"]["character"], 17);
+
+    let unfinished = PROGRAM.replace("        .END\n", "");
+    let change = json!({"textDocument": {"uri": URI, "version": 3}, "contentChanges": [{"text": unfinished}]});
+    let published = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": change}));
+    let diagnostics = &published[0]["params"]["diagnostics"];
+    assert_eq!(diagnostics[0]["message"], "missing .END");
+    assert_eq!(diagnostics[0]["severity"], SEVERITY_WARNING);
+
+    let close = json!({"textDocument": {"uri": URI}});
+    let published = server.handle(&json!({"jsonrpc": "2.0", "method": "textDocument/didClose", "params": close}));
+    assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+    let reply = request(&mut server, "textDocument/hover", at(5, 0));
+    assert_eq!(reply["error"]["code"], INVALID_PARAMS);
+}
+
+#[test]
+fn definitions_of_labels_and_constants() {
+    let (mut server, _) = open(PROGRAM);
+    let reply = request(&mut server, "textDocument/definition", at(5, 14));
+    assert_eq!(reply["result"][0]["uri"], URI);
+    assert_eq!(reply["result"][0]["range"]["start"]["line"], 4);
+
+    let reply = request(&mut server, "textDocument/definition", at(3, 24));
+    assert_eq!(reply["result"][0]["range"]["start"]["line"], 0);
+    let reply = request(&mut server, "textDocument/definition", at(2, 0));
+    assert_eq!(reply["result"][0]["range"]["start"]["line"], 2);
+    let reply = request(&mut server, "textDocument/definition", at(2, 9));
+    assert_eq!(reply["result"], json!([]));
+}
+
+#[test]
+fn hover_shows_words_and_pc_offset_reach() {
+    let (mut server, _) = open(PROGRAM);
+    assert_eq!(
+        hover(&mut server, 5, 9),
+        "```\nx3003  x03FE  0000001111111110\n```\n\n\
+         PCoffset9 #-2 to x3002\n\nreaches -256 to 255 words from x3004, x2F04 to x3103"
+    );
+    assert!(hover(&mut server, 6, 9).contains("PCoffset11 #0 to x3005\n\nreaches -1024 to 1023 words"));
+    assert_eq!(hover(&mut server, 2, 9), "```\nx3000  x5260  0101001001100000\n```");
+    assert_eq!(hover(&mut server, 5, 14), "```lc3\nLOOP    ADD R1, R1, #-1\n```\n\nLOOP is x3002");
+    assert_eq!(hover(&mut server, 3, 25), "```lc3\nCOUNT   .EQU 3\n```");
+    assert_eq!(request(&mut server, "textDocument/hover", at(8, 0))["result"], Value::Null);
+}
+
+#[test]
+fn hover_in_sections_leaves_addresses_to_the_linker() {
+    let (mut server, _) = open(".EXTERN PUTS2\n.SECTION text\nJSR PUTS2\nHALT\n");
+    assert_eq!(
+        hover(&mut server, 2, 1),
+        "```\ntext+x0000  x4800  0100100000000000\n```\n\n\
+         PCoffset11 filled in by the linker for PUTS2\n\nreaches -1024 to 1023 words from text+x0001"
+    );
+}
+
+#[test]
+fn completion_and_document_symbols() {
+    let (mut server, _) = open(PROGRAM);
+    let reply = request(&mut server, "textDocument/completion", at(5, 0));
+    let labels: Vec<&str> = reply["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    for expected in ["ADD", "BRNZP", ".STRINGZ", "COUNT", "MAIN", "LOOP", "DONE"] {
+        assert!(labels.contains(&expected), "{} missing", expected);
+    }
+
+    let reply = request(&mut server, "textDocument/documentSymbol", json!({"textDocument": {"uri": URI}}));
+    let symbols: Vec<(&str, &str)> = reply["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| (symbol["name"].as_str().unwrap(), symbol["detail"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        symbols,
+        vec![("COUNT", "constant"), ("MAIN", "label"), ("LOOP", "label"), ("DONE", "label")]
+    );
+}
+
+#[test]
+fn uris_and_words_under_the_cursor() {
+    assert_eq!(uri_path("file:///home/a%20b/x.asm"), Some(PathBuf::from("/home/a b/x.asm")));
+    assert_eq!(uri_path("untitled:1"), None);
+    assert_eq!(path_uri(Path::new("/home/a b/x.asm")), "file:///home/a%20b/x.asm");
+    assert_eq!(word_at("  BRp LOOP ; go", 8), Some(("LOOP", 6, 10)));
+    assert_eq!(word_at("  BRp LOOP", 10), Some(("LOOP", 6, 10)));
+    assert_eq!(word_at("  BRp LOOP", 1), None);
+}
+
+#[test]
+fn serves_framed_messages_until_exit() {
+    let mut input = Vec::new();
+    for message in [
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "workspace/symbol", "params": {}}),
+        json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ] {
+        write_message(&mut input, &message).unwrap();
+    }
+    input.extend_from_slice(b"Content-Length: 2\r\n\r\n{}");
+    let mut output = Vec::new();
+    assert!(serve(input.as_slice(), &mut output).unwrap());
+
+    let mut output = output.as_slice();
+    let mut replies = Vec::new();
+    while let Some(body) = read_message(&mut output).unwrap() {
+        replies.push(serde_json::from_slice::<Value>(&body).unwrap());
+    }
+    assert_eq!(replies.len(), 3);
+    assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+    assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(replies[2], json!({"jsonrpc": "2.0", "id": 3, "result": null}));
+
+    // no shutdown before the input ends
+    let mut output = Vec::new();
+    assert!(!serve(&b"Content-Length: 3\r\n\r\nnot"[..], &mut output).unwrap());
+    assert!(String::from_utf8(output).unwrap().contains(&PARSE_ERROR.to_string()));
+}
+
+#[test]
+fn oversized_messages_are_refused_before_reading_them() {
+    let header = format!("Content-Length: {}\r\n\r\n{{}}", MAX_MESSAGE_LENGTH + 1);
+    let error = read_message(&mut header.as_bytes()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), format!("Content-Length {} is over the limit of 16777216 bytes", MAX_MESSAGE_LENGTH + 1));
+
+    let header = format!("Content-Length: {}\r\n\r\n", usize::MAX);
+    assert!(serve(header.as_bytes(), Vec::new()).is_err());
+}
@@ -0,0 +1,544 @@
+//! language server for lc3 assembly: assembly errors and lint warnings, go to definition of
+//! labels, constants and macros, hover with the encoded words and PC-offset reach,
+//! completion and document symbols. the `rustvm-lsp` binary runs it over stdio
+
+use crate::assembler::{self, Definition, DefinitionKind};
+use crate::error::{Error, Result};
+use crate::linker::{Object, Relocation, Section, Target};
+use crate::utils::sign_extend;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use transport::{read_message, write_message};
+
+mod transport;
+
+#[cfg(test)]
+mod lsp_tests;
+
+// json-rpc error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// DiagnosticSeverity, CompletionItemKind and SymbolKind values of the protocol
+const SEVERITY_ERROR: u32 = 1;
+const SEVERITY_WARNING: u32 = 2;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_CONSTANT: u32 = 21;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+const SYMBOL_CONSTANT: u32 = 14;
+
+/// statements longer than this only show their first words on hover
+const HOVER_WORDS: usize = 8;
+
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+fn invalid_params(message: impl Into<String>) -> ResponseError {
+    ResponseError {
+        code: INVALID_PARAMS,
+        message: message.into(),
+    }
+}
+
+type Reply = std::result::Result<Value, ResponseError>;
+
+/// a document the editor has open
+struct Document {
+    text: String,
+    /// none for a buffer that is not a file on disk
+    path: Option<PathBuf>,
+}
+
+impl Document {
+    /// `.INCLUDE` paths are relative to this
+    fn dir(&self) -> &Path {
+        self.path.as_deref().and_then(Path::parent).unwrap_or(Path::new(""))
+    }
+
+    /// by zero based number as the protocol counts them
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line).unwrap_or("")
+    }
+
+    fn definitions(&self) -> Vec<Definition> {
+        assembler::definitions(&self.text, None, self.dir())
+    }
+
+    /// assembled as an object, so `.SECTION` and `.EXTERN` are not reported as errors
+    fn assemble(&self) -> Result<Object> {
+        assembler::assemble_object_in(&self.text, self.dir())
+    }
+
+    /// the whole of a zero based line
+    fn line_range(&self, line: usize) -> Value {
+        let end = self.line(line).encode_utf16().count();
+        json!({"start": {"line": line, "character": 0}, "end": {"line": line, "character": end}})
+    }
+}
+
+/// one session with an editor
+#[derive(Default)]
+pub struct Server {
+    /// by uri
+    documents: HashMap<String, Document>,
+    shut_down: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// whether the editor sent `exit`
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// whether the editor asked for a `shutdown` first, as it should
+    pub fn shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    /// handles one message, returns the responses and notifications to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // a response, the server never sends requests
+            return Vec::new();
+        };
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let reply = if self.shut_down {
+            Err(ResponseError {
+                code: INVALID_REQUEST,
+                message: "the server is shutting down".to_string(),
+            })
+        } else {
+            self.request(method, params)
+        };
+        vec![match reply {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(error) => error_response(id.clone(), error),
+        }]
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Reply {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {"name": "rustvm-lsp", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {}", method),
+            }),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        match method {
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default().to_string();
+                let path = uri_path(&uri);
+                self.documents.insert(uri.clone(), Document { text, path });
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                // full sync, the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes.and_then(|changes| changes.last()?["text"].as_str()) else {
+                    return Vec::new();
+                };
+                let Some(document) = self.documents.get_mut(&uri) else {
+                    return Vec::new();
+                };
+                document.text = text.to_string();
+                vec![self.diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![publish(&uri, Vec::new())]
+            }
+            // initialized, didSave, $/cancelRequest and the like
+            _ => Vec::new(),
+        }
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> std::result::Result<(&'a str, &'a Document), ResponseError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| invalid_params("missing textDocument.uri"))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| invalid_params(format!("{} is not open", uri)))?;
+        Ok((uri, document))
+    }
+
+    /// the first assembly error of a document, the assembler stops there, or the lint
+    /// warnings about its own lines once it assembles
+    fn diagnostics(&self, uri: &str) -> Value {
+        let document = &self.documents[uri];
+        let diagnostics = match document.assemble() {
+            Ok(_) => assembler::lint_in(&document.text, document.dir())
+                .unwrap_or_default()
+                .iter()
+                .filter(|warning| warning.location.file.is_none())
+                .map(|warning| {
+                    let range = document.line_range(warning.location.line.saturating_sub(1));
+                    json!({"range": range, "severity": SEVERITY_WARNING, "source": "rustvm", "message": warning.message})
+                })
+                .collect(),
+            Err(error) => vec![diagnostic(document, &error)],
+        };
+        publish(uri, diagnostics)
+    }
+
+    fn definition(&self, params: &Value) -> Reply {
+        let (uri, document) = self.document(params)?;
+        let (line, character) = position(params)?;
+        let Some((name, ..)) = word_at(document.line(line), character) else {
+            return Ok(Value::Null);
+        };
+        let locations: Vec<Value> = document
+            .definitions()
+            .iter()
+            .filter(|definition| defines(definition, name))
+            .map(|definition| match &definition.file {
+                Some(file) => json!({"uri": path_uri(file), "range": line_start(definition.line - 1)}),
+                None => json!({"uri": uri, "range": document.line_range(definition.line - 1)}),
+            })
+            .collect();
+        Ok(Value::Array(locations))
+    }
+
+    /// a name describes its definition, anything else on a line the words it assembled to
+    fn hover(&self, params: &Value) -> Reply {
+        let (_, document) = self.document(params)?;
+        let (line, character) = position(params)?;
+        let object = document.assemble().ok();
+        let mut contents: Vec<String> = match word_at(document.line(line), character) {
+            Some((name, ..)) => document
+                .definitions()
+                .iter()
+                .filter(|definition| defines(definition, name))
+                .map(|definition| describe_definition(definition, object.as_ref()))
+                .collect(),
+            None => Vec::new(),
+        };
+        if contents.is_empty()
+            && let Some(object) = &object
+        {
+            contents = describe_line(object, line + 1);
+        }
+        if contents.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(json!({"contents": {"kind": "markdown", "value": contents.join("\n\n---\n\n")}}))
+    }
+
+    fn completion(&self, params: &Value) -> Reply {
+        let (_, document) = self.document(params)?;
+        let mut items: Vec<Value> = assembler::mnemonics()
+            .into_iter()
+            .map(|mnemonic| json!({"label": mnemonic, "kind": COMPLETION_KEYWORD}))
+            .collect();
+        let mut seen = HashSet::new();
+        for definition in document.definitions() {
+            if seen.insert(definition.name.clone()) {
+                let kind = match definition.kind {
+                    DefinitionKind::Label => COMPLETION_VARIABLE,
+                    DefinitionKind::Constant => COMPLETION_CONSTANT,
+                    DefinitionKind::Macro => COMPLETION_FUNCTION,
+                };
+                items.push(json!({"label": definition.name, "kind": kind, "detail": definition.text}));
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    /// definitions of the document itself, not of the files it includes
+    fn document_symbols(&self, params: &Value) -> Reply {
+        let (_, document) = self.document(params)?;
+        let symbols: Vec<Value> = document
+            .definitions()
+            .into_iter()
+            .filter(|definition| definition.file.is_none())
+            .map(|definition| {
+                let (detail, kind) = match definition.kind {
+                    DefinitionKind::Label => ("label", SYMBOL_VARIABLE),
+                    DefinitionKind::Constant => ("constant", SYMBOL_CONSTANT),
+                    DefinitionKind::Macro => ("macro", SYMBOL_FUNCTION),
+                };
+                let range = document.line_range(definition.line - 1);
+                json!({"name": definition.name, "detail": detail, "kind": kind, "range": range, "selectionRange": range})
+            })
+            .collect();
+        Ok(Value::Array(symbols))
+    }
+}
+
+fn error_response(id: Value, error: ResponseError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": error.code, "message": error.message}})
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+/// an error on the line it names, one in an included file goes on the `.INCLUDE` of that
+/// file when the document has one, otherwise on the first line
+fn diagnostic(document: &Document, error: &Error) -> Value {
+    let (line, message) = match error {
+        Error::Assemble {
+            file: None,
+            line,
+            message,
+        } => (line.saturating_sub(1), message.clone()),
+        Error::Assemble { file: Some(file), .. } => {
+            let name = Path::new(file).file_name().unwrap_or_default().to_string_lossy();
+            let include = document.text.lines().position(|text| {
+                text.trim_start().to_ascii_uppercase().starts_with(".INCLUDE") && text.contains(&*name)
+            });
+            (include.unwrap_or(0), error.to_string())
+        }
+        error => (0, error.to_string()),
+    };
+    json!({"range": document.line_range(line), "severity": SEVERITY_ERROR, "source": "rustvm", "message": message})
+}
+
+fn position(params: &Value) -> std::result::Result<(usize, usize), ResponseError> {
+    let position = &params["position"];
+    match (position["line"].as_u64(), position["character"].as_u64()) {
+        (Some(line), Some(character)) => Ok((line as usize, character as usize)),
+        _ => Err(invalid_params("missing position")),
+    }
+}
+
+fn line_start(line: usize) -> Value {
+    json!({"start": {"line": line, "character": 0}, "end": {"line": line, "character": 0}})
+}
+
+/// the name `character` is in or right after, columns count utf-16 units like the protocol
+fn word_at(text: &str, character: usize) -> Option<(&str, usize, usize)> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut column = 0;
+    for (i, c) in text.char_indices() {
+        if is_name(c) {
+            start.get_or_insert((i, column));
+        } else if let Some((first, first_column)) = start.take() {
+            words.push((first, i, first_column, column));
+        }
+        column += c.len_utf16();
+    }
+    if let Some((first, first_column)) = start {
+        words.push((first, text.len(), first_column, column));
+    }
+    words
+        .into_iter()
+        .find(|&(.., first_column, end_column)| first_column <= character && character <= end_column)
+        .map(|(first, end, first_column, end_column)| (&text[first..end], first_column, end_column))
+}
+
+/// labels and constants are case sensitive, macros are not
+fn defines(definition: &Definition, name: &str) -> bool {
+    match definition.kind {
+        DefinitionKind::Macro => definition.name.eq_ignore_ascii_case(name),
+        _ => definition.name == name,
+    }
+}
+
+/// `x3004` in a `.ORIG` block, `text+x0004` in a section the linker places
+fn address(section: &Section, offset: u16) -> String {
+    match section.origin {
+        Some(origin) => format!("x{:04X}", origin.wrapping_add(offset)),
+        None => format!("{}+x{:04X}", section.name, offset),
+    }
+}
+
+fn describe_definition(definition: &Definition, object: Option<&Object>) -> String {
+    let mut text = format!("```lc3\n{}\n```", definition.text);
+    if let Some(file) = &definition.file {
+        text.push_str(&format!("\n\n{} line {}", file.display(), definition.line));
+    }
+    if definition.kind == DefinitionKind::Label
+        && let Some(object) = object
+        && let Some(symbol) = object.symbols.iter().find(|symbol| symbol.name == definition.name)
+        && let Some(section) = object.sections.get(symbol.section)
+    {
+        text.push_str(&format!("\n\n{} is {}", definition.name, address(section, symbol.offset)));
+    }
+    text
+}
+
+/// the words a line of the document assembled to, once for every expansion of a line in
+/// a macro body
+fn describe_line(object: &Object, line: usize) -> Vec<String> {
+    let mut found = Vec::new();
+    for section in &object.sections {
+        for entry in section.lines.iter().filter(|entry| entry.file.is_none() && entry.line == line) {
+            let mut text = String::from("```\n");
+            for offset in 0..entry.words.min(HOVER_WORDS) {
+                let at = entry.address.wrapping_add(offset as u16);
+                let word = section.words.get(at as usize).copied().unwrap_or(0);
+                text.push_str(&format!("{}  x{:04X}  {:016b}\n", address(section, at), word, word));
+            }
+            if entry.words > HOVER_WORDS {
+                text.push_str(&format!("... {} more words\n", entry.words - HOVER_WORDS));
+            }
+            text.push_str("```");
+            if !entry.data
+                && let Some(&word) = section.words.get(entry.address as usize)
+                && let Some(reach) = pc_offset(object, section, entry.address, word)
+            {
+                text.push_str("\n\n");
+                text.push_str(&reach);
+            }
+            found.push(text);
+        }
+    }
+    found
+}
+
+/// where the PCoffset field of the instruction at `at` points and what it can reach
+fn pc_offset(object: &Object, section: &Section, at: u16, word: u16) -> Option<String> {
+    let bits: u8 = match word >> 12 {
+        // BR, LD, ST, LDI, STI, LEA
+        0x0 | 0x2 | 0x3 | 0xA | 0xB | 0xE => 9,
+        // JSR, JSRR has a base register
+        0x4 if word & 0x0800 != 0 => 11,
+        _ => return None,
+    };
+    let low = -(1i32 << (bits - 1));
+    let high = (1i32 << (bits - 1)) - 1;
+    let next = at.wrapping_add(1);
+    let target = match section.relocations.iter().find(|relocation| relocation.offset == at) {
+        Some(relocation) => format!("filled in by the linker for {}", relocation_target(object, relocation)),
+        None => {
+            let offset = sign_extend(word & ((1 << bits) - 1), bits);
+            format!("#{} to {}", offset as i16, address(section, next.wrapping_add(offset)))
+        }
+    };
+    let mut text = format!(
+        "PCoffset{} {}\n\nreaches {} to {} words from {}",
+        bits,
+        target,
+        low,
+        high,
+        address(section, next)
+    );
+    if section.origin.is_some() {
+        text.push_str(&format!(
+            ", {} to {}",
+            address(section, next.wrapping_add(low as u16)),
+            address(section, next.wrapping_add(high as u16))
+        ));
+    }
+    Some(text)
+}
+
+fn relocation_target(object: &Object, relocation: &Relocation) -> String {
+    let name = match &relocation.target {
+        Target::Symbol(name) => name.clone(),
+        Target::Section(index) => object.sections.get(*index).map_or_else(String::new, |s| s.name.clone()),
+    };
+    match relocation.addend {
+        0 => name,
+        addend if addend > 0 => format!("{}+{}", name, addend),
+        addend => format!("{}{}", name, addend),
+    }
+}
+
+/// the path of a `file://` uri, percent escapes decoded
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < encoded.len() {
+        let escape = encoded.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (encoded[i], escape) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn path_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.display().to_string().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// answers the messages on `input` until the editor sends `exit` or closes it. true when
+/// it asked for a `shutdown` before that, which is how it should end
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match serde_json::from_slice::<Value>(&body) {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![error_response(
+                Value::Null,
+                ResponseError {
+                    code: PARSE_ERROR,
+                    message: e.to_string(),
+                },
+            )],
+        };
+        for reply in &replies {
+            write_message(&mut output, reply)?;
+        }
+        if server.exited() {
+            break;
+        }
+    }
+    Ok(server.shut_down())
+}
@@ -0,0 +1,50 @@
+//! json-rpc messages framed by a `Content-Length` header, as the language server protocol
+//! sends them over stdio
+
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+/// largest body accepted, far more than any assembly source needs. a bad header cannot
+/// make the server allocate more than this
+pub const MAX_MESSAGE_LENGTH: usize = 16 << 20;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// the body of the next message, none at end of input
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            let value = value.trim();
+            length = Some(value.parse().map_err(|_| invalid_data(format!("invalid Content-Length '{}'", value)))?);
+        }
+    }
+    let length = length.ok_or_else(|| invalid_data("message without a Content-Length header".to_string()))?;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(invalid_data(format!(
+            "Content-Length {} is over the limit of {} bytes",
+            length, MAX_MESSAGE_LENGTH
+        )));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
@@ -1,41 +1,764 @@
-use crate::hardware::vm::VM;
-use crate::utils::U16FileReader;
+use rustvm::analysis::cfg::Cfg;
+use rustvm::analysis::dataflow;
+use rustvm::assembler::{self, Program};
+use rustvm::debugger::Debugger;
+use rustvm::error::Error;
+use rustvm::grader;
+use rustvm::hardware::fuzz::Fuzzer;
+use rustvm::hardware::isa::Isa;
+use rustvm::hardware::keyboard::read_recording;
+use rustvm::hardware::micro::{self, Microarchitecture, Microcode};
+use rustvm::hardware::registers::PC_START;
+use rustvm::hardware::vm::VM;
+use rustvm::linker::{self, DEFAULT_BASE, Object};
+use rustvm::loader::Image;
+use rustvm::runner::Spec;
+use rustvm::source_map::SourceMap;
+use rustvm::symbols::SymbolTable;
+use rustvm::tui;
 use std::env::args;
+use std::fmt::Display;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{self, BufReader, BufWriter, Read};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
 
-mod hardware;
-mod utils;
+const DEFAULT_HISTORY: usize = 100_000;
+const DEFAULT_FUZZ_CASES: u64 = 100_000;
+/// instructions `micro --check` compares before it gives up on a program that does not halt
+const DEFAULT_CHECK_BUDGET: u64 = 10_000_000;
 
-fn main() {
-    let path = args().nth(1).unwrap_or("./rogue.obj".to_string());
-    let app = File::open(path).expect("Failed to open application file");
+struct Options {
+    path: String,
+    debug: bool,
+    history: Option<usize>,
+    record_input: Option<String>,
+    replay_input: Option<String>,
+    /// guest input read from a file instead of stdin
+    input: Option<String>,
+    symbols: Option<String>,
+    profile: bool,
+    profile_json: Option<String>,
+    profile_folded: Option<String>,
+    coverage: Option<String>,
+    source_map: Option<String>,
+    trace: Option<String>,
+    strict: bool,
+    /// overrides what the object file says
+    isa: Option<Isa>,
+}
 
-    let mut buf = U16FileReader::new(BufReader::new(app));
+/// exit codes, so scripts can tell a misbehaving program from a broken setup
+const EXIT_GUEST_FAULT: i32 = 1;
+const EXIT_TEST_FAILED: i32 = 1;
+/// `lint` or `analyze` found something, `fmt --check` found a file that is not formatted
+const EXIT_WARNINGS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_LOAD: i32 = 3;
+const EXIT_IO: i32 = 4;
 
-    let mut vm = VM::new();
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        path: "./rogue.obj".to_string(),
+        debug: false,
+        history: None,
+        record_input: None,
+        replay_input: None,
+        input: None,
+        symbols: None,
+        profile: false,
+        profile_json: None,
+        profile_folded: None,
+        coverage: None,
+        source_map: None,
+        trace: None,
+        strict: false,
+        isa: None,
+    };
+
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut file = |flag: &str| args.next().ok_or_else(|| format!("{} expects a file", flag));
+        match arg.as_str() {
+            "--debug" => options.debug = true,
+            "--history" => {
+                let size = args.next().and_then(|n| n.parse().ok());
+                options.history = Some(size.ok_or("--history expects a number of instructions")?);
+            }
+            "--record-input" => options.record_input = Some(file("--record-input")?),
+            "--replay-input" => options.replay_input = Some(file("--replay-input")?),
+            "--input" => options.input = Some(file("--input")?),
+            "--symbols" => options.symbols = Some(file("--symbols")?),
+            "--profile" => options.profile = true,
+            "--profile-json" => options.profile_json = Some(file("--profile-json")?),
+            "--profile-folded" => options.profile_folded = Some(file("--profile-folded")?),
+            "--coverage" => options.coverage = Some(file("--coverage")?),
+            "--source-map" => options.source_map = Some(file("--source-map")?),
+            "--trace" => options.trace = Some(file("--trace")?),
+            "--strict" => options.strict = true,
+            "--isa" => options.isa = Some(parse_isa(args.next().as_deref())?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => options.path = arg,
+        }
+    }
+    Ok(options)
+}
+
+/// prints `message` and exits with `code`
+fn fail(code: i32, message: impl Display) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(code);
+}
+
+/// guest faults and i/o problems get different exit codes
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::Io(_) | Error::EndOfInput => EXIT_IO,
+        Error::EmptyImage | Error::ImageTooLarge { .. } => EXIT_LOAD,
+        _ => EXIT_GUEST_FAULT,
+    }
+}
+
+fn load_image(path: &str) -> Result<Image, Error> {
+    Image::read(BufReader::new(File::open(path)?))
+}
+
+/// `.asm` files are assembled, anything else is loaded as an object file
+fn load_program(path: &str) -> Result<Program, Error> {
+    if path.ends_with(".asm") {
+        return assembler::assemble_file(Path::new(path));
+    }
+    Ok(Program {
+        image: load_image(path)?,
+        symbols: SymbolTable::new(),
+        lines: Vec::new(),
+    })
+}
+
+fn parse_isa(name: Option<&str>) -> Result<Isa, String> {
+    name.and_then(Isa::parse).ok_or_else(|| "--isa expects lc3 or lc3b".to_string())
+}
+
+/// `load_program` for the `requested` instruction set or else the one the object file header
+/// names, the image is marked with the choice. the assembler only produces LC-3 code
+fn load_program_for(path: &str, requested: Option<Isa>) -> (Program, Isa) {
+    if requested == Some(Isa::Lc3b) && path.ends_with(".asm") {
+        fail(EXIT_USAGE, "LC-3b programs have to be loaded as object files, the assembler only knows LC-3");
+    }
+    let mut program =
+        load_program(path).unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load {}: {}", path, e)));
+    let isa = requested.unwrap_or(program.image.isa);
+    program.image.isa = isa;
+    (program, isa)
+}
+
+/// an address written like a number in assembly, `x3000`, `0x3000`, `#12288` or `12288`.
+/// `what` the address is goes in the message when it is none of these
+fn parse_address(what: &str, addr: &str) -> u16 {
+    assembler::parse_word(addr).unwrap_or_else(|| fail(EXIT_USAGE, format_args!("invalid {} address {}", what, addr)))
+}
+
+fn read_symbols(path: &str) -> SymbolTable {
+    File::open(path)
+        .and_then(|file| SymbolTable::parse(BufReader::new(file)))
+        .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read symbol file {}: {}", path, e)))
+}
+
+/// writes a file with `write`, failing with the path in the message
+fn write_file(path: &Path, write: &dyn Fn(BufWriter<File>) -> io::Result<()>) {
+    File::create(path)
+        .and_then(|file| write(BufWriter::new(file)))
+        .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write {}: {}", path.display(), e)));
+}
+
+/// `rustvm asm <file.asm> [-c] [-o FILE] [--listing FILE] [--source-map FILE]`, writes the
+/// object file next to the source unless `-o` says otherwise. with `-c` the output is a
+/// relocatable `.rel` object for `rustvm link` instead
+fn run_assembler(args: &[String]) {
+    let (mut source, mut object, mut listing, mut source_map) = (None, None, None, None);
+    let mut relocatable = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut file = |flag: &str| {
+            args.next()
+                .cloned()
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a file", flag)))
+        };
+        match arg.as_str() {
+            "-c" => relocatable = true,
+            "-o" => object = Some(file("-o")),
+            "--listing" => listing = Some(file("--listing")),
+            "--source-map" => source_map = Some(file("--source-map")),
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => source = Some(arg),
+        }
+    }
+    let Some(source) = source else {
+        fail(EXIT_USAGE, "asm expects a source file");
+    };
+
+    if relocatable {
+        if listing.is_some() || source_map.is_some() {
+            fail(EXIT_USAGE, "listings and source maps are written when linking");
+        }
+        let module = assembler::assemble_object_file(Path::new(source)).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+        let object = object.map_or_else(|| Path::new(source).with_extension("rel"), PathBuf::from);
+        write_file(&object, &|file| module.write_json(file));
+        return;
+    }
+
+    let program = assembler::assemble_file(Path::new(source)).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+    let object = object.map_or_else(|| Path::new(source).with_extension("obj"), PathBuf::from);
+    write_file(&object, &|file| program.image.write(file));
+    if let Some(path) = listing {
+        write_file(Path::new(&path), &|file| program.write_listing(file));
+    }
+    if let Some(path) = source_map {
+        write_file(Path::new(&path), &|file| program.source_map().write_json(file));
+    }
+}
+
+/// `rustvm link <module>... -o FILE [--base ADDR] [--source-map FILE]`, places relocatable
+/// objects, `.asm` modules are assembled on the way, and writes an absolute object file
+fn run_linker(args: &[String]) {
+    let (mut output, mut source_map, mut base) = (None, None, DEFAULT_BASE);
+    let mut modules = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a value", flag)))
+        };
+        match arg.as_str() {
+            "-o" => output = Some(value("-o")),
+            "--source-map" => source_map = Some(value("--source-map")),
+            "--base" => base = parse_address("base", value("--base")),
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => modules.push(arg),
+        }
+    }
+    let Some(output) = output else {
+        fail(EXIT_USAGE, "link expects an output file, -o FILE");
+    };
+    if modules.is_empty() {
+        fail(EXIT_USAGE, "link expects at least one object");
+    }
+
+    let objects: Vec<Object> = modules
+        .iter()
+        .map(|path| {
+            let loaded = if path.ends_with(".asm") {
+                assembler::assemble_object_file(Path::new(path))
+            } else {
+                File::open(path).map_err(Error::from).and_then(|file| Object::read(BufReader::new(file)))
+            };
+            loaded.unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load {}: {}", path, e)))
+        })
+        .collect();
+    let linked = linker::link(&objects, base).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+    write_file(Path::new(output), &|file| linked.image.write(file));
+    if let Some(path) = source_map {
+        write_file(Path::new(path), &|file| linked.source_map.write_json(file));
+    }
+}
+
+/// `rustvm cfg <program> [--entry ADDR] [--symbols FILE] [--json | --call-graph] [-o FILE]`,
+/// the control-flow graph from the entry, x3000 unless given, as graphviz dot. `--json`
+/// writes it with the subroutines and their calls as json, `--call-graph` only the calls
+/// as dot
+fn run_cfg(args: &[String]) {
+    let (mut program, mut output, mut symbols) = (None, None, None);
+    let (mut entry, mut json, mut call_graph) = (PC_START, false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a value", flag)))
+        };
+        match arg.as_str() {
+            "--entry" => entry = parse_address("entry", value("--entry")),
+            "--symbols" => symbols = Some(read_symbols(value("--symbols"))),
+            "--json" => json = true,
+            "--call-graph" => call_graph = true,
+            "-o" => output = Some(value("-o")),
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => program = Some(arg),
+        }
+    }
+    let Some(path) = program else {
+        fail(EXIT_USAGE, "cfg expects a program");
+    };
+    if json && call_graph {
+        fail(EXIT_USAGE, "--json already has the call graph");
+    }
+    let program = load_program(path).unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load {}: {}", path, e)));
+    let symbols = symbols.unwrap_or(program.symbols);
+    let cfg = Cfg::build(&program.image, entry, &symbols);
+
+    let write = |output: &mut dyn io::Write| {
+        if json {
+            cfg.write_json(output)
+        } else if call_graph {
+            cfg.write_call_graph_dot(output)
+        } else {
+            cfg.write_dot(output, &symbols)
+        }
+    };
+    match output {
+        Some(path) => write_file(Path::new(path), &|mut file| write(&mut file)),
+        None => write(&mut io::stdout().lock()).unwrap_or_else(|e| fail(EXIT_IO, e)),
+    }
+}
+
+/// `rustvm analyze <program> [--entry ADDR] [--symbols FILE]`, warns about registers read
+/// before they are written, subroutines that lose R7 and stores into code, at the source
+/// line for an assembled program. exits with 1 if there were any
+fn run_analysis(args: &[String]) {
+    let (mut program, mut symbols, mut entry) = (None, None, PC_START);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a value", flag)))
+        };
+        match arg.as_str() {
+            "--entry" => entry = parse_address("entry", value("--entry")),
+            "--symbols" => symbols = Some(read_symbols(value("--symbols"))),
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => program = Some(arg),
+        }
+    }
+    let Some(path) = program else {
+        fail(EXIT_USAGE, "analyze expects a program");
+    };
+    let program = load_program(path).unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load {}: {}", path, e)));
+    let symbols = symbols.as_ref().unwrap_or(&program.symbols);
+    let cfg = Cfg::build(&program.image, entry, symbols);
 
-    let base_addr = buf.read_u16().unwrap();
-    let mut addr = base_addr as usize;
-    loop {
-        match buf.read_u16() {
-            Ok(instruction) => {
-                println!("{} - {}", addr, instruction);
-                vm.write_memory(addr as u16, instruction);
+    let warnings = dataflow::analyze(&cfg, &program.image, symbols);
+    for warning in &warnings {
+        let line = program
+            .lines
+            .iter()
+            .find(|line| (line.addr..line.addr.wrapping_add(line.size as u16)).contains(&warning.address));
+        match line {
+            Some(line) => println!("warning: {}", line.location.error(warning.message.as_str())),
+            None => println!("warning: {}", warning),
+        }
+    }
+    if !warnings.is_empty() {
+        let count = warnings.len();
+        println!("{} warning{}", count, if count == 1 { "" } else { "s" });
+        process::exit(EXIT_WARNINGS);
+    }
+}
 
-                addr += 1;
+/// `rustvm tui <program> [--symbols FILE] [--isa lc3|lc3b]`, the debugger full screen with the guest's
+/// console in a pane of its own
+fn run_tui(args: &[String]) {
+    let (mut program, mut symbols, mut isa) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--symbols" => {
+                let path = args.next().unwrap_or_else(|| fail(EXIT_USAGE, "--symbols expects a file"));
+                symbols = Some(read_symbols(path));
             }
-            Err(e) => {
-                if let Some(io_err) = e.downcast_ref::<std::io::Error>() && io_err.kind() ==
-                    std::io::ErrorKind::UnexpectedEof {
-                    println!("OK");
-                } else {
-                    println!("Error: {}", e);
-                }
-                break;
+            "--isa" => isa = Some(parse_isa(args.next().map(String::as_str)).unwrap_or_else(|e| fail(EXIT_USAGE, e))),
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => program = Some(arg),
+        }
+    }
+    let Some(path) = program else {
+        fail(EXIT_USAGE, "tui expects a program");
+    };
+    let (program, isa) = load_program_for(path, isa);
+
+    let mut vm = VM::new();
+    vm.set_decode_cache(true);
+    vm.set_isa(isa);
+    vm.load(&program.image);
+    if !program.lines.is_empty() {
+        vm.set_source_map(program.source_map());
+    }
+    vm.enable_history(DEFAULT_HISTORY);
+    let mut app = tui::App::new(Debugger::new(vm), symbols.unwrap_or(program.symbols));
+    tui::run(&mut app).unwrap_or_else(|e| fail(EXIT_IO, e));
+}
+
+/// `rustvm micro <program> [--microcode FILE] [--memory-cycles N] [--trace FILE] [--check]
+/// [--budget N]`, runs the program cycle by cycle on the textbook microarchitecture, the
+/// built-in LC-3 microcode unless a file is given. `--trace` writes the state and control
+/// signals of every cycle, `--check` runs the functional model alongside on stdin as input
+/// and exits with 1 at the first instruction they disagree on
+fn run_micro(args: &[String]) {
+    let (mut program, mut microcode, mut trace) = (None, None, None);
+    let (mut memory_cycles, mut check, mut budget) = (1, false, DEFAULT_CHECK_BUDGET);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a value", flag)))
+        };
+        match arg.as_str() {
+            "--microcode" => microcode = Some(value("--microcode")),
+            "--trace" => trace = Some(value("--trace")),
+            "--memory-cycles" => {
+                memory_cycles = value("--memory-cycles")
+                    .parse()
+                    .ok()
+                    .filter(|&cycles| cycles > 0)
+                    .unwrap_or_else(|| fail(EXIT_USAGE, "--memory-cycles expects a number of cycles"))
+            }
+            "--budget" => {
+                budget = value("--budget")
+                    .parse()
+                    .unwrap_or_else(|_| fail(EXIT_USAGE, "--budget expects a number of instructions"))
             }
+            "--check" => check = true,
+            _ if arg.starts_with('-') => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => program = Some(arg),
         }
     }
+    let Some(path) = program else {
+        fail(EXIT_USAGE, "micro expects a program");
+    };
+    let microcode = match microcode {
+        Some(file) => std::fs::read_to_string(file)
+            .map_err(Error::from)
+            .and_then(|text| Microcode::parse(&text))
+            .unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load microcode {}: {}", file, e))),
+        None => Microcode::lc3(),
+    };
+    let program = load_program(path).unwrap_or_else(|e| fail(EXIT_LOAD, format_args!("cannot load {}: {}", path, e)));
+    if program.image.isa != Isa::Lc3 {
+        fail(EXIT_USAGE, "the microarchitecture only runs LC-3 programs");
+    }
+
+    let mut machine = Microarchitecture::new(microcode);
+    machine.set_memory_cycles(memory_cycles);
+    if let Some(path) = trace {
+        File::create(path)
+            .map(|file| machine.set_trace(Box::new(BufWriter::new(file))))
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write trace {}: {}", path, e)));
+    }
+
+    if check {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read input: {}", e)));
+        let equivalence = micro::check(&program.image, machine, &input, budget);
+        if let Some(mismatch) = &equivalence.mismatch {
+            print!("{}", mismatch);
+            process::exit(EXIT_TEST_FAILED);
+        }
+        let end = if equivalence.halted { "until it halted" } else { "before the budget ran out" };
+        println!(
+            "the models agree on {} instructions ({} cycles) {}",
+            equivalence.instructions, equivalence.cycles, end
+        );
+        return;
+    }
+
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    if !program.lines.is_empty() {
+        vm.set_source_map(program.source_map());
+    }
+    vm.set_microarchitecture(machine);
+    let result = vm.execute();
+    vm.flush_trace().unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write trace: {}", e)));
+    let cycles = vm.microarchitecture().map_or(0, Microarchitecture::cycles);
+    let instructions = vm.instruction_count();
+    eprintln!(
+        "{} instructions in {} cycles, {:.2} cycles per instruction",
+        instructions,
+        cycles,
+        cycles as f64 / instructions.max(1) as f64
+    );
+    if let Err(e) = result {
+        fail(exit_code(&e), vm.describe_error(&e));
+    }
+}
+
+/// `rustvm fmt [--check] <file.asm>...`, rewrites every file laid out canonically, with
+/// `--check` only names the ones that are not and exits with 1 if there are any
+fn run_formatter(args: &[String]) {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if let Some(option) = files.iter().find(|arg| arg.starts_with('-')) {
+        fail(EXIT_USAGE, format_args!("unknown option {}", option));
+    }
+    if files.is_empty() {
+        fail(EXIT_USAGE, "fmt expects at least one source file");
+    }
+    let mut unformatted = 0;
+    for path in files {
+        let path = Path::new(path);
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read {}: {}", path.display(), e)));
+        let formatted = assembler::format(&source, path.parent().unwrap_or(Path::new("")));
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(path, formatted)
+                .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write {}: {}", path.display(), e)));
+        }
+    }
+    if unformatted > 0 {
+        process::exit(EXIT_WARNINGS);
+    }
+}
+
+/// `rustvm lint <file.asm>...`, prints a warning for every likely mistake and exits with 1
+/// if there were any
+fn run_linter(files: &[String]) {
+    if files.is_empty() {
+        fail(EXIT_USAGE, "lint expects at least one source file");
+    }
+    let mut count = 0;
+    for path in files {
+        let warnings = assembler::lint_file(Path::new(path)).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+        for warning in &warnings {
+            println!("warning: {}", warning);
+        }
+        count += warnings.len();
+    }
+    if count > 0 {
+        println!("{} warning{}", count, if count == 1 { "" } else { "s" });
+        process::exit(EXIT_WARNINGS);
+    }
+}
+
+/// `rustvm test <spec.toml>...`, runs every case in process and exits with 1 if any failed
+fn run_tests(specs: &[String]) {
+    if specs.is_empty() {
+        fail(EXIT_USAGE, "test expects at least one spec file");
+    }
+    let mut failed = 0;
+    for path in specs {
+        let spec = Spec::load(Path::new(path)).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+        let report = spec.run();
+        println!("{}", path);
+        report.write_text(io::stdout()).unwrap_or_else(|e| fail(EXIT_IO, e));
+        failed += report.failed();
+    }
+    if failed > 0 {
+        process::exit(EXIT_TEST_FAILED);
+    }
+}
+
+/// `rustvm grade <rubric.toml> <submission>... [--report-dir DIR]`, prints every score and
+/// writes `<student>.json` and `<student>.xml` reports, the student being the file stem
+fn run_grading(args: &[String]) {
+    let mut report_dir = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report-dir" => {
+                let dir = args.next().unwrap_or_else(|| fail(EXIT_USAGE, "--report-dir expects a directory"));
+                report_dir = Some(Path::new(dir));
+            }
+            _ if arg.starts_with("--") => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+            _ => paths.push(arg),
+        }
+    }
+    let Some((rubric, submissions)) = paths.split_first() else {
+        fail(EXIT_USAGE, "grade expects a rubric and at least one submission");
+    };
+    if submissions.is_empty() {
+        fail(EXIT_USAGE, "grade expects at least one submission");
+    }
+
+    let rubric = Spec::load(Path::new(rubric)).unwrap_or_else(|e| fail(EXIT_LOAD, e));
+    if let Some(dir) = report_dir {
+        std::fs::create_dir_all(dir).unwrap_or_else(|e| fail(EXIT_IO, e));
+    }
+    for submission in submissions {
+        let submission = Path::new(submission);
+        let student = submission.file_stem().unwrap_or_default().to_string_lossy();
+        let grade = grader::grade(&rubric, submission, &student);
+        grade.write_text(io::stdout()).unwrap_or_else(|e| fail(EXIT_IO, e));
+
+        if let Some(dir) = report_dir {
+            let path = |extension: &str| dir.join(format!("{}.{}", student, extension));
+            write_file(&path("json"), &|file| grade.write_json(file));
+            write_file(&path("xml"), &|file| grade.write_junit(file));
+        }
+    }
+}
+
+/// `rustvm fuzz [--seed N] [--cases N]`, compares the processor with the reference model
+/// and prints a minimized reproducer of the first divergence
+fn run_fuzzer(args: &[String]) {
+    let (mut seed, mut cases) = (0, DEFAULT_FUZZ_CASES);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = |flag: &str| {
+            args.next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| fail(EXIT_USAGE, format_args!("{} expects a number", flag)))
+        };
+        match arg.as_str() {
+            "--seed" => seed = number("--seed"),
+            "--cases" => cases = number("--cases"),
+            _ => fail(EXIT_USAGE, format_args!("unknown option {}", arg)),
+        }
+    }
+
+    match Fuzzer::new(seed).run(cases) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            process::exit(EXIT_TEST_FAILED);
+        }
+        None => println!("no divergence in {} cases", cases),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("test") => return run_tests(&args[1..]),
+        Some("grade") => return run_grading(&args[1..]),
+        Some("fuzz") => return run_fuzzer(&args[1..]),
+        Some("asm") => return run_assembler(&args[1..]),
+        Some("link") => return run_linker(&args[1..]),
+        Some("cfg") => return run_cfg(&args[1..]),
+        Some("analyze") => return run_analysis(&args[1..]),
+        Some("tui") => return run_tui(&args[1..]),
+        Some("micro") => return run_micro(&args[1..]),
+        Some("fmt") => return run_formatter(&args[1..]),
+        Some("lint") => return run_linter(&args[1..]),
+        _ => {}
+    }
+
+    let options = parse_options().unwrap_or_else(|e| fail(EXIT_USAGE, e));
+    let (program, isa) = load_program_for(&options.path, options.isa);
+    let profiling = options.profile || options.profile_json.is_some() || options.profile_folded.is_some();
+    if isa == Isa::Lc3b && (profiling || options.coverage.is_some()) {
+        fail(EXIT_USAGE, "profiling and coverage only support LC-3 programs");
+    }
+    let image = &program.image;
+
+    let mut vm = VM::new();
+    vm.set_decode_cache(true);
+    vm.set_strict(options.strict);
+    vm.set_isa(isa);
+    for (addr, word) in image.iter() {
+        println!("{} - {}", addr, word);
+    }
+    vm.load(image);
+    println!("OK");
+
+    if let Some(path) = &options.source_map {
+        let map = File::open(path)
+            .and_then(|file| SourceMap::read(BufReader::new(file)))
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read source map {}: {}", path, e)));
+        vm.set_source_map(map);
+    } else if !program.lines.is_empty() {
+        vm.set_source_map(program.source_map());
+    }
+    if let Some(path) = &options.trace {
+        File::create(path)
+            .map(|file| vm.set_trace(Box::new(BufWriter::new(file))))
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write trace {}: {}", path, e)));
+    }
+
+    if let Some(path) = &options.record_input {
+        File::create(path)
+            .and_then(|file| vm.record_input(Box::new(file)))
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot record input to {}: {}", path, e)));
+    }
+    if let Some(path) = &options.replay_input {
+        let events = File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| read_recording(BufReader::new(file)))
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read input recording {}: {}", path, e)));
+        vm.replay_input(events);
+    } else if let Some(path) = &options.input {
+        let input = std::fs::read(path)
+            .unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot read input {}: {}", path, e)));
+        vm.set_input(input);
+    } else if options.debug {
+        // stdin carries the debugger's commands, the guest gets what the `input` command queues
+        vm.set_input(Vec::new());
+    }
+
+    if options.debug {
+        vm.enable_history(options.history.unwrap_or(DEFAULT_HISTORY));
+        let mut debugger = Debugger::new(vm);
+        if let Err(e) = debugger.run(BufReader::new(io::stdin()), io::stderr()) {
+            fail(EXIT_IO, e);
+        }
+        return;
+    }
+
+    if let Some(capacity) = options.history {
+        vm.enable_history(capacity);
+    }
+    if profiling {
+        vm.enable_profiling();
+    }
+    if options.coverage.is_some() {
+        vm.enable_coverage();
+    }
     println!("Executing now");
-    vm.execute();
+    let result = vm.execute();
+    vm.flush_trace().unwrap_or_else(|e| fail(EXIT_IO, format_args!("cannot write trace: {}", e)));
+
+    // reports are still written for a run that failed, they show how it got there
+    let symbols = match &options.symbols {
+        Some(path) => Some(read_symbols(path)),
+        None => (!program.symbols.is_empty()).then_some(program.symbols),
+    };
+    if profiling {
+        write_profile(&vm, &options, symbols.as_ref()).unwrap_or_else(|e| fail(EXIT_IO, e));
+    }
+    if let (Some(path), Some(range)) = (&options.coverage, image.range()) {
+        let cfg = symbols.as_ref().map(|symbols| Cfg::build(image, PC_START, symbols));
+        write_coverage(&vm, path, &options.path, range, cfg.as_ref()).unwrap_or_else(|e| fail(EXIT_IO, e));
+    }
+    if let Err(e) = result {
+        fail(exit_code(&e), vm.describe_error(&e));
+    }
+}
+
+fn write_coverage(
+    vm: &VM,
+    path: &str,
+    source: &str,
+    image: RangeInclusive<u16>,
+    cfg: Option<&Cfg>,
+) -> io::Result<()> {
+    let coverage = vm.coverage().expect("coverage was enabled");
+    let read = |addr| vm.read_memory(addr);
+    let file = BufWriter::new(File::create(path)?);
+    match vm.source_map() {
+        Some(map) => coverage.write_lcov_mapped(file, source, map, cfg, read)?,
+        None => coverage.write_lcov(file, source, image.clone(), cfg, read)?,
+    }
+    coverage.summary(image, read).write_text(io::stderr())
+}
+
+fn write_profile(vm: &VM, options: &Options, symbols: Option<&SymbolTable>) -> io::Result<()> {
+    let profiler = vm.profiler().expect("profiling was enabled");
+    let report = profiler.report(symbols, |addr| vm.read_memory(addr));
+
+    if options.profile {
+        report.write_text(io::stderr())?;
+    }
+    if let Some(path) = &options.profile_json {
+        report.write_json(BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(path) = &options.profile_folded {
+        profiler.write_folded(BufWriter::new(File::create(path)?), symbols)?;
+    }
+    Ok(())
 }
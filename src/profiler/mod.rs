@@ -0,0 +1,337 @@
+use crate::hardware::disasm::{disassemble, trap_name};
+use crate::hardware::memory::MEMORY_SIZE;
+use crate::hardware::processor::OpCode;
+use crate::symbols::SymbolTable;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+#[cfg(test)]
+mod profiler_tests;
+
+const TOP_ADDRESSES: usize = 20;
+
+/// one node of the calling context tree, a subroutine reached through a particular chain of calls
+struct Frame {
+    function: u16,
+    parent: Option<usize>,
+    /// where the matching RET goes back to
+    return_addr: u16,
+    children: HashMap<u16, usize>,
+    /// instructions executed while this frame was on top
+    count: u64,
+}
+
+/// counts executed instructions per address, opcode, subroutine and trap.
+/// subroutines are tracked through JSR/JSRR and the RET that returns to the call site
+pub struct Profiler {
+    address_counts: Vec<u64>,
+    opcode_counts: [u64; 16],
+    calls: BTreeMap<u16, u64>,
+    traps: BTreeMap<u8, u64>,
+    frames: Vec<Frame>,
+    current: usize,
+    total: u64,
+}
+
+impl Profiler {
+    /// `entry` is the address execution starts at, it stands for the root of every call stack
+    pub fn new(entry: u16) -> Self {
+        Self {
+            address_counts: vec![0; MEMORY_SIZE],
+            opcode_counts: [0; 16],
+            calls: BTreeMap::new(),
+            traps: BTreeMap::new(),
+            frames: vec![Frame {
+                function: entry,
+                parent: None,
+                return_addr: entry,
+                children: HashMap::new(),
+                count: 0,
+            }],
+            current: 0,
+            total: 0,
+        }
+    }
+
+    /// accounts for `instr` executed at `pc`, `next_pc` is where execution continues
+    pub fn record(&mut self, pc: u16, instr: u16, next_pc: u16) {
+        self.total += 1;
+        self.address_counts[pc as usize] += 1;
+        self.opcode_counts[(instr >> 12) as usize] += 1;
+        self.frames[self.current].count += 1;
+
+        match OpCode::get_op_code(&instr) {
+            Some(OpCode::JSR) => {
+                *self.calls.entry(next_pc).or_insert(0) += 1;
+                self.enter(next_pc, pc.wrapping_add(1));
+            }
+            Some(OpCode::JMP) if (instr >> 6) & 0x7 == 7 => self.leave(next_pc),
+            Some(OpCode::TRAP) => *self.traps.entry((instr & 0xFF) as u8).or_insert(0) += 1,
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, function: u16, return_addr: u16) {
+        let next = self.frames.len();
+        let child = *self.frames[self.current].children.entry(function).or_insert(next);
+        if child == next {
+            self.frames.push(Frame {
+                function,
+                parent: Some(self.current),
+                return_addr,
+                children: HashMap::new(),
+                count: 0,
+            });
+        }
+        self.frames[child].return_addr = return_addr;
+        self.current = child;
+    }
+
+    /// pops back to the innermost frame returning to `target`, a RET that matches no
+    /// frame (e.g. used as a computed jump) leaves the stack alone
+    fn leave(&mut self, target: u16) {
+        let mut frame = self.current;
+        while let Some(parent) = self.frames[frame].parent {
+            if self.frames[frame].return_addr == target {
+                self.current = parent;
+                return;
+            }
+            frame = parent;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn address_count(&self, addr: u16) -> u64 {
+        self.address_counts[addr as usize]
+    }
+
+    pub fn opcode_count(&self, op: OpCode) -> u64 {
+        self.opcode_counts[op as usize]
+    }
+
+    /// builds the report, `read` supplies memory contents for disassembly
+    pub fn report(&self, symbols: Option<&SymbolTable>, read: impl Fn(u16) -> u16) -> ProfileReport {
+        let name = |addr: u16| match symbols.and_then(|s| s.describe(addr)) {
+            Some(name) => name,
+            None => format!("x{:04X}", addr),
+        };
+        let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+
+        let mut hot: Vec<(u16, u64)> = self
+            .address_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(addr, &count)| (addr as u16, count))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let addresses = hot
+            .into_iter()
+            .take(TOP_ADDRESSES)
+            .map(|(addr, count)| AddressProfile {
+                address: addr,
+                symbol: symbols.and_then(|s| s.describe(addr)),
+                disassembly: disassemble(addr, read(addr)),
+                count,
+                percent: percent(count),
+            })
+            .collect();
+
+        let opcodes = OpCode::ALL
+            .iter()
+            .map(|&op| (op, self.opcode_counts[op as usize]))
+            .filter(|&(_, count)| count > 0)
+            .map(|(op, count)| OpcodeProfile {
+                opcode: format!("{:?}", op),
+                count,
+                percent: percent(count),
+            })
+            .collect();
+
+        let mut exclusive: BTreeMap<u16, u64> = BTreeMap::new();
+        let mut inclusive: BTreeMap<u16, u64> = BTreeMap::new();
+        let subtree_totals = self.subtree_totals();
+        for (index, frame) in self.frames.iter().enumerate() {
+            *exclusive.entry(frame.function).or_insert(0) += frame.count;
+            // recursive frames are already part of an outer frame of the same function
+            if !self.has_ancestor(index, frame.function) {
+                *inclusive.entry(frame.function).or_insert(0) += subtree_totals[index];
+            }
+        }
+        let mut subroutines: Vec<SubroutineProfile> = inclusive
+            .iter()
+            .map(|(&addr, &inclusive)| SubroutineProfile {
+                address: addr,
+                name: name(addr),
+                calls: self.calls.get(&addr).copied().unwrap_or(0),
+                inclusive,
+                exclusive: exclusive[&addr],
+            })
+            .collect();
+        subroutines.sort_by(|a, b| b.inclusive.cmp(&a.inclusive).then(a.address.cmp(&b.address)));
+
+        let traps = self
+            .traps
+            .iter()
+            .map(|(&vector, &count)| TrapProfile {
+                vector,
+                name: trap_name(vector).map(str::to_string),
+                count,
+            })
+            .collect();
+
+        ProfileReport {
+            instructions: self.total,
+            addresses,
+            opcodes,
+            subroutines,
+            traps,
+        }
+    }
+
+    /// one `caller;callee count` line per call stack, the input format of flamegraph.pl and inferno
+    pub fn write_folded<W: Write>(&self, mut output: W, symbols: Option<&SymbolTable>) -> io::Result<()> {
+        let name = |addr: u16| match symbols.and_then(|s| s.describe(addr)) {
+            Some(name) => name,
+            None => format!("x{:04X}", addr),
+        };
+        let mut lines = Vec::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            if frame.count == 0 {
+                continue;
+            }
+            let mut stack = Vec::new();
+            let mut cursor = Some(index);
+            while let Some(i) = cursor {
+                stack.push(name(self.frames[i].function));
+                cursor = self.frames[i].parent;
+            }
+            stack.reverse();
+            lines.push(format!("{} {}", stack.join(";"), frame.count));
+        }
+        lines.sort();
+        for line in lines {
+            writeln!(output, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn subtree_totals(&self) -> Vec<u64> {
+        // children are always pushed after their parent
+        let mut totals: Vec<u64> = self.frames.iter().map(|frame| frame.count).collect();
+        for index in (1..self.frames.len()).rev() {
+            if let Some(parent) = self.frames[index].parent {
+                totals[parent] += totals[index];
+            }
+        }
+        totals
+    }
+
+    fn has_ancestor(&self, index: usize, function: u16) -> bool {
+        let mut cursor = self.frames[index].parent;
+        while let Some(i) = cursor {
+            if self.frames[i].function == function {
+                return true;
+            }
+            cursor = self.frames[i].parent;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileReport {
+    pub instructions: u64,
+    /// hottest addresses first
+    pub addresses: Vec<AddressProfile>,
+    pub opcodes: Vec<OpcodeProfile>,
+    /// by inclusive count, the program entry point is listed as a subroutine with no calls
+    pub subroutines: Vec<SubroutineProfile>,
+    pub traps: Vec<TrapProfile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressProfile {
+    pub address: u16,
+    pub symbol: Option<String>,
+    pub disassembly: String,
+    pub count: u64,
+    pub percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpcodeProfile {
+    pub opcode: String,
+    pub count: u64,
+    pub percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubroutineProfile {
+    pub address: u16,
+    pub name: String,
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrapProfile {
+    pub vector: u8,
+    pub name: Option<String>,
+    pub count: u64,
+}
+
+impl ProfileReport {
+    pub fn write_text<W: Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "profile: {} instructions executed", self.instructions)?;
+
+        writeln!(output, "\nhot addresses:")?;
+        for entry in &self.addresses {
+            writeln!(
+                output,
+                "  {:>10} {:>6.2}%  x{:04X}  {:<16} {}",
+                entry.count,
+                entry.percent,
+                entry.address,
+                entry.symbol.as_deref().unwrap_or(""),
+                entry.disassembly
+            )?;
+        }
+
+        writeln!(output, "\nopcodes:")?;
+        for entry in &self.opcodes {
+            writeln!(output, "  {:<5} {:>10} {:>6.2}%", entry.opcode, entry.count, entry.percent)?;
+        }
+
+        writeln!(output, "\nsubroutines:")?;
+        writeln!(output, "  {:>8} {:>10} {:>10}  name", "calls", "inclusive", "exclusive")?;
+        for entry in &self.subroutines {
+            writeln!(
+                output,
+                "  {:>8} {:>10} {:>10}  {}",
+                entry.calls, entry.inclusive, entry.exclusive, entry.name
+            )?;
+        }
+
+        writeln!(output, "\ntraps:")?;
+        for entry in &self.traps {
+            writeln!(
+                output,
+                "  x{:02X} {:<6} {:>10}",
+                entry.vector,
+                entry.name.as_deref().unwrap_or(""),
+                entry.count
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, output: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(output, self).map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::hardware::processor::OpCode;
+use crate::hardware::vm::VM;
+use crate::symbols::SymbolTable;
+
+// x3000 MAIN: JSR A
+// x3001:      JSR A
+// x3002:      HALT
+// x3003:      .FILL 0
+// x3004 A:    ADD R1, R1, #1
+// x3005:      ST R7, SAVE
+// x3006:      JSR B
+// x3007:      LD R7, SAVE
+// x3008:      RET
+// x3009 B:    RET
+// x300A SAVE: .FILL 0
+const NESTED_CALLS: [u16; 11] = [
+    0x4803, 0x4802, 0xF025, 0x0000, 0x1261, 0x3E04, 0x4802, 0x2E02, 0xC1C0, 0xC1C0, 0x0000,
+];
+
+const SYMBOLS: &str = "\
+// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tMAIN              3000
+//\tA                 3004
+//\tB                 3009
+";
+
+fn profiled_vm() -> VM {
+    let mut vm = VM::new();
+    for (offset, word) in NESTED_CALLS.iter().enumerate() {
+        vm.write_memory(0x3000 + offset as u16, *word);
+    }
+    vm.enable_profiling();
+    vm.execute().unwrap();
+    vm
+}
+
+#[test]
+fn counts_addresses_and_opcodes() {
+    let vm = profiled_vm();
+    let profiler = vm.profiler().unwrap();
+
+    assert_eq!(profiler.total(), 15);
+    assert_eq!(profiler.address_count(0x3004), 2);
+    assert_eq!(profiler.address_count(0x3003), 0);
+    assert_eq!(profiler.opcode_count(OpCode::JSR), 4);
+    assert_eq!(profiler.opcode_count(OpCode::JMP), 4);
+    assert_eq!(profiler.opcode_count(OpCode::TRAP), 1);
+}
+
+#[test]
+fn subroutines_get_inclusive_and_exclusive_counts() {
+    let vm = profiled_vm();
+    let symbols = SymbolTable::parse(SYMBOLS.as_bytes()).unwrap();
+    let report = vm.profiler().unwrap().report(Some(&symbols), |addr| vm.read_memory(addr));
+
+    let summary: Vec<(&str, u64, u64, u64)> = report
+        .subroutines
+        .iter()
+        .map(|s| (s.name.as_str(), s.calls, s.inclusive, s.exclusive))
+        .collect();
+    assert_eq!(summary, vec![("MAIN", 0, 15, 3), ("A", 2, 12, 10), ("B", 2, 2, 2)]);
+    assert_eq!(report.traps.len(), 1);
+    assert_eq!(report.traps[0].name.as_deref(), Some("HALT"));
+    assert_eq!(report.addresses[0].symbol.as_deref(), Some("A"));
+    assert_eq!(report.addresses[0].disassembly, "ADD R1, R1, #1");
+}
+
+#[test]
+fn folded_stacks_follow_jsr_and_ret() {
+    let vm = profiled_vm();
+    let symbols = SymbolTable::parse(SYMBOLS.as_bytes()).unwrap();
+    let mut folded = Vec::new();
+    vm.profiler().unwrap().write_folded(&mut folded, Some(&symbols)).unwrap();
+
+    assert_eq!(String::from_utf8(folded).unwrap(), "MAIN 3\nMAIN;A 10\nMAIN;A;B 2\n");
+}
+
+#[test]
+fn symbols_describe_addresses_relative_to_nearest_label() {
+    let symbols = SymbolTable::parse("LOOP x3010\n".as_bytes()).unwrap();
+
+    assert_eq!(symbols.describe(0x3010).as_deref(), Some("LOOP"));
+    assert_eq!(symbols.describe(0x3013).as_deref(), Some("LOOP+3"));
+    assert_eq!(symbols.describe(0x300F), None);
+    assert_eq!(symbols.address("LOOP"), Some(0x3010));
+}
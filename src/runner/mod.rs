@@ -0,0 +1,442 @@
+use crate::assembler::{self, Program};
+use crate::error::{Error, Result};
+use crate::hardware::call::{CALLEE_SAVED, CallOutcome};
+use crate::hardware::registers::Register;
+use crate::hardware::vm::VM;
+use crate::loader::Image;
+use crate::symbols::SymbolTable;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod runner_tests;
+
+pub const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000;
+/// the stack of a `call` case grows down from here, just below the device registers
+pub const STACK_BASE: u16 = 0xFE00;
+
+/// a test spec file: shared settings and a list of `[[case]]` tables
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    /// program for cases that do not name their own
+    pub program: Option<String>,
+    /// instruction budget for cases that do not set their own
+    pub max_instructions: Option<u64>,
+    /// run every case in spec compliance mode, see `VM::set_strict`
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default, rename = "case")]
+    pub cases: Vec<Case>,
+    /// directory `program` paths are relative to
+    #[serde(skip)]
+    pub base: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    pub name: String,
+    /// `.asm` files are assembled, anything else is loaded as an object file
+    pub program: Option<String>,
+    /// inline assembly instead of a program file
+    pub source: Option<String>,
+    #[serde(default)]
+    pub input: String,
+    pub max_instructions: Option<u64>,
+    /// wall clock limit, for programs that are slow without looping forever.
+    /// not checked for `call` cases
+    pub timeout_ms: Option<u64>,
+    /// label or address of a subroutine to call instead of running the program from its
+    /// origin, the case ends when it returns
+    pub call: Option<String>,
+    /// registers the called subroutine has to preserve, R1-R6 by default
+    pub callee_saved: Option<Vec<String>>,
+    /// words pushed before a `call`, the first one ends up on top and R6 points at it
+    #[serde(default)]
+    pub stack: Vec<i64>,
+    /// weight of the case when grading
+    pub points: Option<u32>,
+    /// initial values, the pc starts at the program origin unless given here
+    #[serde(default)]
+    pub registers: BTreeMap<String, i64>,
+    #[serde(default)]
+    pub memory: BTreeMap<String, Words>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    /// halt unless `error` is given
+    pub outcome: Option<Outcome>,
+    /// text the error message has to contain
+    pub error: Option<String>,
+    pub output: Option<String>,
+    #[serde(default)]
+    pub registers: BTreeMap<String, i64>,
+    #[serde(default)]
+    pub memory: BTreeMap<String, Words>,
+}
+
+/// memory contents starting at an address, a string is stored one character per word
+/// followed by a zero like `.STRINGZ`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Words {
+    One(i64),
+    Many(Vec<i64>),
+    Text(String),
+}
+
+/// how a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Halt,
+    /// a called subroutine returned
+    Return,
+    /// the instruction budget or the time limit ran out first
+    Timeout,
+    /// the vm reported an error
+    Error,
+    /// the emulator itself panicked, only reported by the grader
+    Crash,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub instructions: u64,
+    pub output: String,
+    pub error: Option<String>,
+    /// every expectation that did not hold, empty when the case passed
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases.len() - self.passed()
+    }
+
+    pub fn write_text<W: Write>(&self, mut output: W) -> io::Result<()> {
+        for case in &self.cases {
+            if case.passed() {
+                writeln!(output, "PASS {} ({} instructions)", case.name, case.instructions)?;
+                continue;
+            }
+            writeln!(output, "FAIL {}", case.name)?;
+            for failure in &case.failures {
+                writeln!(output, "  {}", failure)?;
+            }
+        }
+        writeln!(output, "{} passed, {} failed", self.passed(), self.failed())
+    }
+}
+
+impl Spec {
+    pub fn load(path: &Path) -> Result<Spec> {
+        let text = std::fs::read_to_string(path)?;
+        let mut spec = Spec::parse(&text).map_err(|e| match e {
+            Error::InvalidSpec(message) => Error::InvalidSpec(format!("{}: {}", path.display(), message)),
+            e => e,
+        })?;
+        spec.base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(spec)
+    }
+
+    /// program paths are relative to the current directory until `base` is set
+    pub fn parse(text: &str) -> Result<Spec> {
+        toml::from_str(text).map_err(|e| Error::InvalidSpec(e.message().to_string()))
+    }
+
+    pub fn run(&self) -> Report {
+        Report {
+            cases: self.cases.iter().map(|case| self.run_case(case)).collect(),
+        }
+    }
+
+    pub fn run_case(&self, case: &Case) -> CaseResult {
+        let mut result = CaseResult {
+            name: case.name.clone(),
+            outcome: Outcome::Error,
+            instructions: 0,
+            output: String::new(),
+            error: None,
+            failures: Vec::new(),
+        };
+        let program = match self.program(case) {
+            Ok(program) => program,
+            Err(e) => {
+                result.failures.push(format!("cannot load program: {}", e));
+                return result;
+            }
+        };
+        let output = Capture::default();
+        let mut vm = match prepare(case, &program, &output) {
+            Ok(vm) => vm,
+            Err(message) => {
+                result.failures.push(message);
+                return result;
+            }
+        };
+        vm.set_strict(self.strict);
+
+        let budget = case
+            .max_instructions
+            .or(self.max_instructions)
+            .unwrap_or(DEFAULT_MAX_INSTRUCTIONS);
+        let mut clobbered = Vec::new();
+        let run = match &case.call {
+            Some(target) => call(&mut vm, case, target, &program.symbols, budget).map(|(outcome, clobbers)| {
+                clobbered = clobbers;
+                outcome
+            }),
+            None => {
+                let deadline = case.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+                run_bounded(&mut vm, budget, deadline).map_err(|e| vm.describe_error(&e))
+            }
+        };
+        match run {
+            Ok(outcome) => result.outcome = outcome,
+            Err(e) => {
+                result.outcome = Outcome::Error;
+                result.error = Some(e);
+            }
+        }
+        result.instructions = vm.instruction_count();
+        result.output = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+        let default_outcome = if case.call.is_some() { Outcome::Return } else { Outcome::Halt };
+        result.failures = check(&case.expect, default_outcome, &result, &vm, &program.symbols);
+        result.failures.extend(clobbered);
+        result
+    }
+
+    fn program(&self, case: &Case) -> Result<Program> {
+        if let Some(source) = &case.source {
+            return assembler::assemble_in(source, &self.base);
+        }
+        let path = case
+            .program
+            .as_ref()
+            .or(self.program.as_ref())
+            .ok_or_else(|| Error::InvalidSpec(format!("case '{}' has no program or source", case.name)))?;
+        let path = self.base.join(path);
+        let in_file = |e: Error| match e {
+            Error::Io(e) => Error::Io(io::Error::new(e.kind(), format!("{}: {}", path.display(), e))),
+            e => e,
+        };
+        if path.extension().is_some_and(|extension| extension == "asm") {
+            return assembler::assemble_file(&path).map_err(in_file);
+        }
+        let file = File::open(&path).map_err(|e| in_file(e.into()))?;
+        Ok(Program {
+            image: Image::read(BufReader::new(file))?,
+            symbols: SymbolTable::new(),
+            lines: Vec::new(),
+        })
+    }
+}
+
+/// runs until the vm halts, `budget` instructions were executed or `deadline` passed
+fn run_bounded(vm: &mut VM, budget: u64, deadline: Option<Instant>) -> Result<Outcome> {
+    // the clock is only checked between slices
+    const SLICE: u64 = 10_000;
+    let mut remaining = budget;
+    while !vm.is_halted() && remaining > 0 {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        remaining -= vm.run_for(remaining.min(SLICE))?;
+    }
+    Ok(if vm.is_halted() { Outcome::Halt } else { Outcome::Timeout })
+}
+
+/// calls `target` with the case's stack, returns the outcome and a failure for every
+/// callee saved register that was not restored
+fn call(
+    vm: &mut VM,
+    case: &Case,
+    target: &str,
+    symbols: &SymbolTable,
+    budget: u64,
+) -> std::result::Result<(Outcome, Vec<String>), String> {
+    let entry = parse_location(target, symbols)?;
+    let callee_saved = match &case.callee_saved {
+        Some(names) => names.iter().map(|name| parse_register(name)).collect::<std::result::Result<_, _>>()?,
+        None => CALLEE_SAVED.to_vec(),
+    };
+    if !case.stack.is_empty() {
+        let top = STACK_BASE.wrapping_sub(case.stack.len() as u16);
+        for (offset, &value) in case.stack.iter().enumerate() {
+            vm.write_memory(top.wrapping_add(offset as u16), word(value)?);
+        }
+        vm.set_register(Register::R6, top);
+    }
+
+    let result = vm.call(entry, &callee_saved, budget).map_err(|e| vm.describe_error(&e))?;
+    let outcome = match result.outcome {
+        CallOutcome::Returned => Outcome::Return,
+        CallOutcome::Halted => Outcome::Halt,
+        CallOutcome::Timeout => Outcome::Timeout,
+    };
+    Ok((outcome, result.clobbered.iter().map(ToString::to_string).collect()))
+}
+
+/// vm with the program loaded and the case's initial state applied
+fn prepare(case: &Case, program: &Program, output: &Capture) -> std::result::Result<VM, String> {
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.set_source_map(program.source_map());
+    vm.set_register(Register::PC, program.image.origin);
+    vm.set_input(case.input.as_bytes());
+    vm.set_output(Box::new(output.clone()));
+
+    for (name, &value) in &case.registers {
+        vm.set_register(parse_register(name)?, word(value)?);
+    }
+    for (location, words) in &case.memory {
+        let start = parse_location(location, &program.symbols)?;
+        for (offset, value) in expand(words)?.into_iter().enumerate() {
+            vm.write_memory(start.wrapping_add(offset as u16), value);
+        }
+    }
+    Ok(vm)
+}
+
+fn check(
+    expect: &Expect,
+    default_outcome: Outcome,
+    result: &CaseResult,
+    vm: &VM,
+    symbols: &SymbolTable,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    let outcome = expect.outcome.unwrap_or(match expect.error {
+        Some(_) => Outcome::Error,
+        None => default_outcome,
+    });
+    if result.outcome != outcome {
+        let detail = result.error.as_ref().map(|e| format!(": {}", e)).unwrap_or_default();
+        failures.push(format!(
+            "expected outcome {:?}, got {:?}{}",
+            outcome, result.outcome, detail
+        ));
+    }
+    if let Some(expected) = &expect.error {
+        let error = result.error.as_deref().unwrap_or("");
+        if !error.contains(expected.as_str()) {
+            failures.push(format!("expected an error containing {:?}, got {:?}", expected, error));
+        }
+    }
+    if let Some(expected) = &expect.output
+        && *expected != result.output
+    {
+        failures.push(format!("output: expected {:?}, got {:?}", expected, result.output));
+    }
+
+    for (name, &value) in &expect.registers {
+        match parse_register(name).and_then(|register| Ok((register, word(value)?))) {
+            Ok((register, expected)) if vm.register(register) != expected => failures.push(format!(
+                "{}: expected x{:04X}, got x{:04X}",
+                register,
+                expected,
+                vm.register(register)
+            )),
+            Ok(_) => {}
+            Err(message) => failures.push(message),
+        }
+    }
+    for (location, words) in &expect.memory {
+        let expected = parse_location(location, symbols).and_then(|start| Ok((start, expand(words)?)));
+        let (start, expected) = match expected {
+            Ok(expected) => expected,
+            Err(message) => {
+                failures.push(message);
+                continue;
+            }
+        };
+        for (offset, expected) in expected.into_iter().enumerate() {
+            let addr = start.wrapping_add(offset as u16);
+            let actual = vm.read_memory(addr);
+            if actual != expected {
+                failures.push(format!("memory x{:04X}: expected x{:04X}, got x{:04X}", addr, expected, actual));
+            }
+        }
+    }
+    failures
+}
+
+fn parse_register(name: &str) -> std::result::Result<Register, String> {
+    Register::ALL
+        .into_iter()
+        .find(|register| register.to_string().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown register {}", name))
+}
+
+/// an address as `x4000`, `0x4000` or decimal, or a label of an assembled program
+fn parse_location(location: &str, symbols: &SymbolTable) -> std::result::Result<u16, String> {
+    let hex = location.strip_prefix("0x").or_else(|| location.strip_prefix('x'));
+    let parsed = match hex {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => location.parse().ok(),
+    };
+    parsed
+        .or_else(|| symbols.address(location))
+        .ok_or_else(|| format!("unknown address or label {}", location))
+}
+
+/// negative values are stored as two's complement
+fn word(value: i64) -> std::result::Result<u16, String> {
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(format!("{} does not fit in a word", value))
+    }
+}
+
+fn expand(words: &Words) -> std::result::Result<Vec<u16>, String> {
+    match words {
+        Words::One(value) => Ok(vec![word(*value)?]),
+        Words::Many(values) => values.iter().map(|&value| word(value)).collect(),
+        Words::Text(text) => Ok(text.chars().map(|c| c as u16).chain([0]).collect()),
+    }
+}
+
+/// guest output collected in memory
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use super::*;
+
+const ECHO: &str = r#"
+[[case]]
+name = "echo two characters"
+input = "ab"
+source = """
+        .ORIG x3000
+        GETC
+        OUT
+        ADD R1, R0, #0
+        GETC
+        OUT
+        HALT
+        .END
+"""
+expect = { output = "ab", registers = { R0 = 98, R1 = 97 } }
+"#;
+
+#[test]
+fn passing_case_reports_outcome_and_output() {
+    let report = Spec::parse(ECHO).unwrap().run();
+    let case = &report.cases[0];
+    assert!(case.passed(), "{:?}", case.failures);
+    assert_eq!(case.outcome, Outcome::Halt);
+    assert_eq!(case.output, "ab");
+    assert_eq!(case.instructions, 6);
+    assert_eq!((report.passed(), report.failed()), (1, 0));
+}
+
+#[test]
+fn initial_state_and_memory_expectations_use_labels() {
+    let spec = r#"
+[[case]]
+name = "sum"
+registers = { R1 = -3 }
+memory = { A = 10 }
+source = """
+        .ORIG x3000
+        LD R0, A
+        ADD R0, R0, R1
+        ST R0, B
+        HALT
+A       .FILL 0
+B       .FILL 0
+        .END
+"""
+
+[case.expect]
+registers = { R0 = 7, COND = 1 }
+memory = { B = 7, x3004 = [10, 7] }
+"#;
+    let report = Spec::parse(spec).unwrap().run();
+    assert!(report.cases[0].passed(), "{:?}", report.cases[0].failures);
+}
+
+#[test]
+fn every_failed_expectation_is_listed() {
+    let spec = r#"
+[[case]]
+name = "wrong"
+source = ".ORIG x3000\nAND R0, R0, #0\nHALT\n"
+expect = { output = "x", registers = { R0 = 1 }, memory = { x4000 = "a" } }
+"#;
+    let report = Spec::parse(spec).unwrap().run();
+    let failures = &report.cases[0].failures;
+    assert_eq!(failures.len(), 3, "{:?}", failures);
+    assert_eq!(failures[1], "R0: expected x0001, got x0000");
+    assert_eq!(failures[2], "memory x4000: expected x0061, got x0000");
+
+    let mut text = Vec::new();
+    report.write_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("FAIL wrong\n"));
+    assert!(text.ends_with("0 passed, 1 failed\n"));
+}
+
+#[test]
+fn timeouts_and_errors_are_outcomes() {
+    let spec = r#"
+[[case]]
+name = "loops forever"
+source = ".ORIG x3000\nBR #-1\n"
+max_instructions = 50
+expect = { outcome = "timeout" }
+
+[[case]]
+name = "bad trap"
+source = ".ORIG x3000\nTRAP x99\n"
+expect = { error = "unknown TRAP vector x99" }
+
+[[case]]
+name = "runs out of input"
+source = ".ORIG x3000\nGETC\nHALT\n"
+"#;
+    let report = Spec::parse(spec).unwrap().run();
+    assert!(report.cases[0].passed(), "{:?}", report.cases[0].failures);
+    assert_eq!(report.cases[0].instructions, 50);
+    assert!(report.cases[1].passed(), "{:?}", report.cases[1].failures);
+    assert_eq!(report.cases[2].outcome, Outcome::Error);
+    assert!(report.cases[2].failures[0].starts_with("expected outcome Halt, got Error"));
+}
+
+#[test]
+fn broken_programs_fail_their_case_only() {
+    let spec = r#"
+[[case]]
+name = "syntax error"
+source = ".ORIG x3000\nADD R1\n"
+
+[[case]]
+name = "missing file"
+program = "does-not-exist.obj"
+
+[[case]]
+name = "fine"
+source = ".ORIG x3000\nHALT\n"
+"#;
+    let report = Spec::parse(spec).unwrap().run();
+    assert!(report.cases[0].failures[0].contains("line 2"));
+    assert!(report.cases[1].failures[0].starts_with("cannot load program"));
+    assert!(report.cases[2].passed());
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    assert!(matches!(Spec::parse("[[case]]\nname = \"x\"\nregister = {}\n"), Err(Error::InvalidSpec(_))));
+}
+
+const STRLEN: &str = r#"
+        .ORIG x3000
+        HALT
+; R0 = length of the string whose address is on top of the stack
+STRLEN  LDR R1, R6, #0
+        AND R0, R0, #0
+LOOP    LDR R2, R1, #0
+        BRz DONE
+        ADD R0, R0, #1
+        ADD R1, R1, #1
+        BR LOOP
+DONE    RET
+TEXT    .STRINGZ "four"
+        .END
+"#;
+
+fn call_spec(extra: &str) -> Report {
+    let spec = format!("[[case]]\nname = \"strlen\"\nsource = '''{}'''\ncall = \"STRLEN\"\n{}", STRLEN, extra);
+    Spec::parse(&spec).unwrap().run()
+}
+
+#[test]
+fn call_cases_run_a_single_subroutine() {
+    let report = call_spec("stack = [0x3009]\ncallee_saved = [\"R3\", \"R6\"]\nexpect = { registers = { R0 = 4, R6 = 0xFDFF } }\n");
+    assert!(report.cases[0].passed(), "{:?}", report.cases[0].failures);
+    assert_eq!(report.cases[0].outcome, Outcome::Return);
+    assert_eq!(report.cases[0].instructions, 25);
+}
+
+#[test]
+fn call_cases_flag_clobbered_registers() {
+    let report = call_spec("stack = [0x3009]\nregisters = { R2 = 7 }\n");
+    assert_eq!(
+        report.cases[0].failures,
+        vec![
+            "R1 not preserved: x0000 before the call, x300D after".to_string(),
+            "R2 not preserved: x0007 before the call, x0000 after".to_string(),
+        ]
+    );
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// the statement a run of words was assembled from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceEntry {
+    pub address: u16,
+    /// number of words the statement assembled to
+    pub words: usize,
+    /// none for source that was not read from a file
+    pub file: Option<String>,
+    pub line: usize,
+    /// the statement after macro arguments were substituted
+    pub text: String,
+    /// `.FILL`, `.BLKW` and `.STRINGZ` words, which are not meant to be executed
+    #[serde(default)]
+    pub data: bool,
+}
+
+impl fmt::Display for SourceEntry {
+    /// `prog.asm:12`, or `line 12` without a file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file, self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SourceMapFile {
+    entries: Vec<SourceEntry>,
+}
+
+/// which source line every address was assembled from, written by `rustvm asm` as json
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    by_address: BTreeMap<u16, SourceEntry>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// an entry at an address that is already mapped replaces the earlier one
+    pub fn insert(&mut self, entry: SourceEntry) {
+        self.by_address.insert(entry.address, entry);
+    }
+
+    /// the entry whose words include `addr`
+    pub fn lookup(&self, addr: u16) -> Option<&SourceEntry> {
+        let (_, entry) = self.by_address.range(..=addr).next_back()?;
+        (((addr - entry.address) as usize) < entry.words).then_some(entry)
+    }
+
+    /// first address assembled from `line` of a file whose path ends in the components of
+    /// `file`, so `a.asm` matches `src/a.asm` but not `data.asm`
+    pub fn address(&self, file: &str, line: usize) -> Option<u16> {
+        self.iter()
+            .find(|entry| entry.line == line && entry.file.as_deref().is_some_and(|path| Path::new(path).ends_with(file)))
+            .map(|entry| entry.address)
+    }
+
+    /// entries in address order
+    pub fn iter(&self) -> impl Iterator<Item = &SourceEntry> {
+        self.by_address.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_address.is_empty()
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Self> {
+        let file: SourceMapFile = serde_json::from_reader(reader)?;
+        let mut map = Self::new();
+        for entry in file.entries {
+            map.insert(entry);
+        }
+        Ok(map)
+    }
+
+    pub fn write_json<W: Write>(&self, mut output: W) -> io::Result<()> {
+        let file = SourceMapFile {
+            entries: self.iter().cloned().collect(),
+        };
+        serde_json::to_writer_pretty(&mut output, &file)?;
+        writeln!(output)
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead};
+
+/// label addresses, as found in the `.sym` files written by lc3as
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    by_address: BTreeMap<u16, String>,
+    by_name: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// accepts lc3as output (`//\tLABEL   3000` lines under a comment header)
+    /// as well as plain `LABEL x3000` lines
+    pub fn parse<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut table = Self::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim().trim_start_matches("//").trim();
+            let mut fields = line.split_whitespace();
+            if let (Some(name), Some(addr), None) = (fields.next(), fields.next(), fields.next()) {
+                let addr = addr.trim_start_matches("0x").trim_start_matches('x');
+                if let Ok(addr) = u16::from_str_radix(addr, 16) {
+                    table.insert(name, addr);
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.by_address.entry(addr).or_insert_with(|| name.to_string());
+        self.by_name.insert(name.to_string(), addr);
+    }
+
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.by_address.get(&addr).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// nearest label at or before `addr`, as `LABEL` or `LABEL+3`
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let (&base, name) = self.by_address.range(..=addr).next_back()?;
+        if base == addr {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+{}", name, addr - base))
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.by_address.iter().map(|(&addr, name)| (addr, name.as_str()))
+    }
+}
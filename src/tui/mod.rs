@@ -0,0 +1,498 @@
+//! `rustvm tui`: the debugger full screen, with panes for the code around the PC, the
+//! registers, memory, the guest's console and debugger commands
+
+pub mod screen;
+
+#[cfg(test)]
+mod tui_tests;
+
+use crate::assembler::parse_word;
+use crate::debugger::Debugger;
+use crate::hardware::registers::Register;
+use crate::symbols::SymbolTable;
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use screen::Screen;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// instructions run between two looks at the keyboard
+pub const SLICE: usize = 20_000;
+/// how often the screen is redrawn while the guest runs
+const FRAME: Duration = Duration::from_millis(33);
+/// how long to wait for a key while nothing runs
+const IDLE: Duration = Duration::from_millis(250);
+/// words PageUp and PageDown move the memory pane by
+const MEMORY_PAGE: u16 = 0x40;
+pub const MIN_WIDTH: usize = 60;
+pub const MIN_HEIGHT: usize = 20;
+const REGISTERS_HEIGHT: usize = 6;
+/// lines the debugger pane keeps
+const LOG_LINES: usize = 500;
+
+const KEYS: &str = "s step  c continue  b break  : command  i input  q quit";
+
+const HELP: &str = "\
+keys:
+  s, F10                   step one instruction
+  c, F5                    continue until a breakpoint, a watchpoint or HALT
+  Esc, Ctrl-C              pause
+  b, F9                    toggle a breakpoint at the highlighted line
+  Up, Down, Home           move the highlighted line, Home goes back to the pc
+  PageUp, PageDown         scroll the memory pane
+  i                        type into the guest's console, Esc to stop
+  :                        enter a command
+  q                        quit
+  mem <addr>               show memory from addr in the memory pane";
+
+/// guest output, kept for the console pane
+#[derive(Clone, Default)]
+struct Console(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// where keys go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Focus {
+    /// the shortcuts
+    Keys,
+    /// the command line, with what was typed so far
+    Command(String),
+    /// the guest's keyboard
+    Console,
+}
+
+/// what the screen shows and what keys do to it, apart from the terminal
+pub struct App {
+    debugger: Debugger,
+    symbols: SymbolTable,
+    console: Console,
+    log: Vec<String>,
+    focus: Focus,
+    running: bool,
+    error: Option<String>,
+    /// the highlighted line of the code pane, which is centred on it
+    cursor: u16,
+    /// first word of the memory pane
+    memory: u16,
+    quit: bool,
+}
+
+impl App {
+    /// takes over the guest's console, its output goes to the console pane and its input
+    /// is what gets typed there
+    pub fn new(mut debugger: Debugger, symbols: SymbolTable) -> App {
+        let console = Console::default();
+        let vm = debugger.vm_mut();
+        vm.set_output(Box::new(console.clone()));
+        vm.set_input(Vec::new());
+        let pc = vm.pc();
+        App {
+            debugger,
+            symbols,
+            console,
+            log: Vec::new(),
+            focus: Focus::Keys,
+            running: false,
+            error: None,
+            cursor: pc,
+            memory: pc,
+            quit: false,
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn focus(&self) -> &Focus {
+        &self.focus
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// what the guest wrote so far
+    pub fn console_output(&self) -> String {
+        String::from_utf8_lossy(&self.console.0.borrow()).into_owned()
+    }
+
+    /// the next instruction is GETC or IN and nothing was typed for it
+    pub fn waiting_for_input(&self) -> bool {
+        self.debugger.waiting_for_input()
+    }
+
+    pub fn status(&self) -> String {
+        let vm = self.debugger.vm();
+        if let Some(error) = &self.error {
+            format!("error: {}", error)
+        } else if vm.is_halted() {
+            format!("halted after {} instructions", vm.instruction_count())
+        } else if self.waiting_for_input() {
+            "waiting for input, press i and type".to_string()
+        } else if self.running {
+            "running".to_string()
+        } else {
+            format!("paused at x{:04X}", vm.pc())
+        }
+    }
+
+    fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > LOG_LINES {
+            self.log.drain(..self.log.len() - LOG_LINES);
+        }
+    }
+
+    /// one instruction, false if there was nothing to run, it failed or it hit a
+    /// watchpoint
+    fn step(&mut self) -> bool {
+        if self.debugger.vm().is_halted() || self.waiting_for_input() {
+            return false;
+        }
+        if let Err(e) = self.debugger.vm_mut().step() {
+            self.error = Some(self.debugger.vm().describe_error(&e));
+            return false;
+        }
+        let hits: Vec<String> = self.debugger.vm().watch_hits().iter().map(ToString::to_string).collect();
+        let hit = !hits.is_empty();
+        for hit in hits {
+            self.print(hit);
+        }
+        !hit
+    }
+
+    fn resume(&mut self) {
+        self.error = None;
+        self.running = true;
+    }
+
+    /// runs up to `budget` instructions of a continue, stopping like the debugger's. a
+    /// program waiting for input keeps running and the console gets the keys
+    pub fn tick(&mut self, budget: usize) {
+        if !self.running {
+            return;
+        }
+        for _ in 0..budget {
+            if self.waiting_for_input() {
+                self.focus = Focus::Console;
+                break;
+            }
+            if !self.step() || self.debugger.breakpoints().contains(&self.debugger.vm().pc()) {
+                self.running = false;
+                break;
+            }
+        }
+        self.cursor = self.debugger.vm().pc();
+    }
+
+    fn toggle_breakpoint(&mut self, addr: u16) {
+        if self.debugger.remove_breakpoint(addr) {
+            self.print(format!("deleted breakpoint at x{:04X}", addr));
+        } else {
+            self.debugger.add_breakpoint(addr);
+            self.print(format!("breakpoint at x{:04X}", addr));
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            return;
+        }
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.running = false;
+            self.focus = Focus::Keys;
+            return;
+        }
+        match &mut self.focus {
+            Focus::Keys => self.shortcut(key.code),
+            Focus::Command(text) => match key.code {
+                KeyCode::Esc => self.focus = Focus::Keys,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Enter => {
+                    let line = std::mem::take(text);
+                    self.focus = Focus::Keys;
+                    self.command(&line);
+                }
+                _ => {}
+            },
+            Focus::Console => {
+                let byte = match key.code {
+                    KeyCode::Esc => {
+                        // a run that waits for input would only take the keys back
+                        if self.waiting_for_input() {
+                            self.running = false;
+                        }
+                        self.focus = Focus::Keys;
+                        None
+                    }
+                    KeyCode::Enter => Some(b'\n'),
+                    KeyCode::Backspace => Some(0x08),
+                    KeyCode::Tab => Some(b'\t'),
+                    KeyCode::Char(c) if c.is_ascii() => Some(c as u8),
+                    _ => None,
+                };
+                if let Some(byte) = byte {
+                    self.debugger.vm_mut().push_input(&[byte]);
+                }
+            }
+        }
+    }
+
+    fn shortcut(&mut self, code: KeyCode) {
+        let (pc, word) = (self.debugger.vm().pc(), self.debugger.vm().isa().word_size());
+        match code {
+            KeyCode::Char('s') | KeyCode::F(10) => {
+                self.running = false;
+                self.error = None;
+                self.step();
+                self.cursor = self.debugger.vm().pc();
+            }
+            KeyCode::Char('c') | KeyCode::F(5) => self.resume(),
+            KeyCode::Esc => self.running = false,
+            KeyCode::Char('b') | KeyCode::F(9) => self.toggle_breakpoint(self.cursor),
+            KeyCode::Up => self.cursor = self.cursor.wrapping_sub(word),
+            KeyCode::Down => self.cursor = self.cursor.wrapping_add(word),
+            KeyCode::Home => self.cursor = pc,
+            KeyCode::PageUp => self.memory = self.memory.wrapping_sub(MEMORY_PAGE),
+            KeyCode::PageDown => self.memory = self.memory.wrapping_add(MEMORY_PAGE),
+            KeyCode::Char(':') => self.focus = Focus::Command(String::new()),
+            KeyCode::Char('i') => self.focus = Focus::Console,
+            KeyCode::Char('q') => self.quit = true,
+            _ => {}
+        }
+    }
+
+    /// a command line. `c` and `s` work like the keys so the screen keeps up with them,
+    /// `mem ADDR` moves the memory pane and the rest goes to the debugger
+    pub fn command(&mut self, line: &str) {
+        self.print(format!("(lc3) {}", line));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["c" | "continue"] => self.resume(),
+            ["s" | "step", count @ ..] if count.len() <= 1 => {
+                let Some(count) = count.first().map_or(Some(1), |count| count.parse::<usize>().ok()) else {
+                    return self.print(format!("invalid count '{}'", count[0]));
+                };
+                self.running = false;
+                self.error = None;
+                for _ in 0..count {
+                    if !self.step() {
+                        break;
+                    }
+                }
+            }
+            ["mem", addr] => match parse_word(addr) {
+                Some(addr) => self.memory = addr,
+                None => self.print(format!("invalid address '{}'", addr)),
+            },
+            _ => {
+                let mut output = Vec::new();
+                match self.debugger.command(line, &mut output) {
+                    Ok(true) => {}
+                    Ok(false) => self.quit = true,
+                    // only bad arguments, the output is in memory
+                    Err(e) => output.extend_from_slice(format!("{}\n", e).as_bytes()),
+                }
+                if matches!(words.as_slice(), ["h" | "help"]) {
+                    output.extend_from_slice(HELP.as_bytes());
+                }
+                for line in String::from_utf8_lossy(&output).lines() {
+                    self.print(line);
+                }
+            }
+        }
+        self.cursor = self.debugger.vm().pc();
+    }
+
+    /// the whole screen at `width` by `height` characters
+    pub fn render(&self, width: usize, height: usize) -> Screen {
+        let mut screen = Screen::new(width, height);
+        if width < MIN_WIDTH || height < MIN_HEIGHT {
+            let message = format!("the terminal needs to be at least {}x{}", MIN_WIDTH, MIN_HEIGHT);
+            screen.put(0, 0, width, &message);
+            return screen;
+        }
+        let left = width / 2;
+        let bottom = ((height - 2) / 3).max(6);
+        let top = height - 2 - bottom;
+        self.draw_code(&mut screen, 0, 0, left, top);
+        self.draw_registers(&mut screen, left, width - left);
+        self.draw_memory(&mut screen, left, REGISTERS_HEIGHT, width - left, top - REGISTERS_HEIGHT);
+
+        let typing = self.focus == Focus::Console;
+        let title = if typing { "console, typing (Esc to stop)" } else { "console" };
+        screen.frame(0, top, left, bottom, title);
+        let output = self.console_output();
+        let lines: Vec<&str> = output.split('\n').collect();
+        pane_tail(&mut screen, 0, top, left, bottom, &lines);
+        screen.frame(left, top, width - left, bottom, "debugger");
+        let log: Vec<&str> = self.log.iter().map(String::as_str).collect();
+        pane_tail(&mut screen, left, top, width - left, bottom, &log);
+
+        if let Focus::Command(text) = &self.focus {
+            screen.put(0, height - 2, width, &format!(":{}", text));
+        }
+        screen.put(0, height - 1, width, &format!(" {}  |  {}", self.status(), KEYS));
+        screen.highlight(0, height - 1, width);
+        screen
+    }
+
+    fn draw_code(&self, screen: &mut Screen, x: usize, y: usize, width: usize, height: usize) {
+        screen.frame(x, y, width, height, "code");
+        let vm = self.debugger.vm();
+        let (rows, word) = (height - 2, vm.isa().word_size());
+        let first = self.cursor.wrapping_sub((rows / 2) as u16 * word);
+        for row in 0..rows {
+            let addr = first.wrapping_add(row as u16 * word);
+            let breakpoint = if self.debugger.breakpoints().contains(&addr) { '*' } else { ' ' };
+            let pc = if addr == vm.pc() { '>' } else { ' ' };
+            let label = self.symbols.name(addr).unwrap_or("");
+            let line = format!(
+                "{}{} x{:04X}  {:<8} {}",
+                breakpoint,
+                pc,
+                addr,
+                label,
+                vm.isa().disassemble(addr, vm.read_memory(addr))
+            );
+            screen.put(x + 1, y + 1 + row, x + width - 1, &line);
+            if addr == self.cursor {
+                screen.highlight(x + 1, y + 1 + row, width - 2);
+            }
+        }
+    }
+
+    fn draw_registers(&self, screen: &mut Screen, x: usize, width: usize) {
+        screen.frame(x, 0, width, REGISTERS_HEIGHT, "registers");
+        let vm = self.debugger.vm();
+        let extra = [
+            format!("PC   x{:04X}", vm.pc()),
+            format!("COND {}", vm.condition()),
+            format!("ran  {}", vm.instruction_count()),
+            String::new(),
+        ];
+        for (row, extra) in extra.iter().enumerate() {
+            let (low, high) = (Register::ALL[row], Register::ALL[row + 4]);
+            let line = format!("{} x{:04X}  {} x{:04X}  {}", low, vm.register(low), high, vm.register(high), extra);
+            screen.put(x + 1, 1 + row, x + width - 1, &line);
+        }
+    }
+
+    fn draw_memory(&self, screen: &mut Screen, x: usize, y: usize, width: usize, height: usize) {
+        screen.frame(x, y, width, height, "memory");
+        let vm = self.debugger.vm();
+        // `xADDR` and then ` WORD` as often as it fits, a power of two up to 8 a row
+        let per_row = 1 << ((width - 2).saturating_sub(5) / 5).clamp(1, 8).ilog2();
+        let word = vm.isa().word_size();
+        for row in 0..height.saturating_sub(2) {
+            let start = self.memory.wrapping_add((row * per_row) as u16 * word);
+            let mut line = format!("x{:04X}", start);
+            for offset in 0..per_row {
+                line.push_str(&format!(" {:04X}", vm.read_memory(start.wrapping_add(offset as u16 * word))));
+            }
+            screen.put(x + 1, y + 1 + row, x + width - 1, &line);
+        }
+    }
+}
+
+/// the last lines that fit inside a pane, long ones wrapped
+fn pane_tail(screen: &mut Screen, x: usize, y: usize, width: usize, height: usize, lines: &[&str]) {
+    let inner = width - 2;
+    let mut wrapped: Vec<String> = Vec::new();
+    for line in lines {
+        let chars: Vec<char> = line.chars().filter(|&c| c != '\r').collect();
+        if chars.is_empty() {
+            wrapped.push(String::new());
+        }
+        wrapped.extend(chars.chunks(inner).map(|chunk| chunk.iter().collect()));
+    }
+    let rows = height - 2;
+    for (row, line) in wrapped[wrapped.len().saturating_sub(rows)..].iter().enumerate() {
+        screen.put(x + 1, y + 1 + row, x + width - 1, line);
+    }
+}
+
+/// raw mode on the alternate screen for as long as it lives
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        let terminal = Terminal;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // nothing better to do with a terminal that cannot be restored
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw<W: Write>(output: &mut W, screen: &Screen) -> io::Result<()> {
+    for y in 0..screen.height {
+        queue!(output, MoveTo(0, y as u16))?;
+        for (text, highlighted) in screen.runs(y) {
+            if highlighted {
+                queue!(output, SetAttribute(Attribute::Reverse), Print(text), SetAttribute(Attribute::NoReverse))?;
+            } else {
+                queue!(output, Print(text))?;
+            }
+        }
+    }
+    output.flush()
+}
+
+/// `app` full screen until it quits. the guest runs in slices between looks at the
+/// keyboard and the screen is redrawn at most every `FRAME` while it does
+pub fn run(app: &mut App) -> io::Result<()> {
+    let _terminal = Terminal::enter()?;
+    let mut stdout = io::stdout();
+    let mut drawn: Option<Instant> = None;
+    while !app.has_quit() {
+        if drawn.is_none_or(|at| at.elapsed() >= FRAME) {
+            let (width, height) = terminal::size()?;
+            draw(&mut stdout, &app.render(width as usize, height as usize))?;
+            drawn = Some(Instant::now());
+        }
+        let timeout = if app.is_running() { Duration::ZERO } else { IDLE };
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                app.key(key);
+            }
+            drawn = None;
+        }
+        let running = app.is_running();
+        app.tick(SLICE);
+        if running && !app.is_running() {
+            drawn = None;
+        }
+    }
+    Ok(())
+}
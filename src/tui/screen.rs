@@ -0,0 +1,73 @@
+//! a grid of characters the panes are drawn into before it goes to the terminal
+
+/// `width` by `height` characters, with spans shown in reverse video
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Vec<char>>,
+    /// row, first column and column past the end
+    highlights: Vec<(usize, usize, usize)>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen {
+            width,
+            height,
+            cells: vec![vec![' '; width]; height],
+            highlights: Vec::new(),
+        }
+    }
+
+    /// writes `text` from column `x` of row `y`, cut off at column `right`. control
+    /// characters show as spaces
+    pub fn put(&mut self, x: usize, y: usize, right: usize, text: &str) {
+        let Some(row) = self.cells.get_mut(y) else {
+            return;
+        };
+        let right = right.min(row.len());
+        for (cell, c) in row.iter_mut().take(right).skip(x).zip(text.chars()) {
+            *cell = if c.is_control() { ' ' } else { c };
+        }
+    }
+
+    /// shows `width` columns of row `y` from `x` in reverse video
+    pub fn highlight(&mut self, x: usize, y: usize, width: usize) {
+        self.highlights.push((y, x, (x + width).min(self.width)));
+    }
+
+    /// a box with `title` in its top edge
+    pub fn frame(&mut self, x: usize, y: usize, width: usize, height: usize, title: &str) {
+        if width < 2 || height < 2 {
+            return;
+        }
+        let right = x + width;
+        let inner = "─".repeat(width - 2);
+        self.put(x, y, right, &format!("┌{}┐", inner));
+        self.put(x + 2, y, right - 1, &format!(" {} ", title));
+        for row in y + 1..y + height - 1 {
+            self.put(x, row, right, "│");
+            self.put(right - 1, row, right, "│");
+        }
+        self.put(x, y + height - 1, right, &format!("└{}┘", inner));
+    }
+
+    /// every row as text, without the highlights
+    pub fn lines(&self) -> Vec<String> {
+        self.cells.iter().map(|row| row.iter().collect()).collect()
+    }
+
+    /// row `y` split into runs of text and whether they are highlighted
+    pub fn runs(&self, y: usize) -> Vec<(String, bool)> {
+        let mut runs: Vec<(String, bool)> = Vec::new();
+        for (x, &c) in self.cells[y].iter().enumerate() {
+            let highlighted = self.highlights.iter().any(|&(row, start, end)| row == y && (start..end).contains(&x));
+            match runs.last_mut() {
+                Some((text, last)) if *last == highlighted => text.push(c),
+                _ => runs.push((c.to_string(), highlighted)),
+            }
+        }
+        runs
+    }
+}
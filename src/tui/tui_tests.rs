@@ -0,0 +1,142 @@
+use super::screen::Screen;
+use super::*;
+use crate::assembler::assemble;
+use crate::hardware::vm::VM;
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R0, R0, #0
+LOOP    ADD R0, R0, #1
+        ADD R1, R0, #-3
+        BRn LOOP
+        HALT
+        .END
+";
+
+fn app(source: &str) -> App {
+    let program = assemble(source).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program.image);
+    vm.enable_history(64);
+    App::new(Debugger::new(vm), program.symbols)
+}
+
+fn press(app: &mut App, code: KeyCode) {
+    app.key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+fn type_text(app: &mut App, text: &str) {
+    for c in text.chars() {
+        press(app, KeyCode::Char(c));
+    }
+}
+
+#[test]
+fn panes_show_code_registers_and_memory() {
+    let app = app(PROGRAM);
+    let lines = app.render(80, 24).lines();
+    assert_eq!(lines.len(), 24);
+    assert!(lines.iter().all(|line| line.chars().count() == 80));
+    assert!(lines[0].starts_with("┌─ code ─"));
+    assert!(lines[0].contains("┐┌─ registers ─"));
+    assert!(lines.iter().any(|line| line.starts_with("│ > x3000           AND R0, R0, #0")));
+    assert!(lines.iter().any(|line| line.starts_with("│   x3001  LOOP     ADD R0, R0, #1")));
+    assert!(lines[1].contains("│R0 x0000  R4 x0000  PC   x3000"));
+    assert!(lines.iter().any(|line| line.contains("│x3004 F025 0000 0000 0000  ")));
+    assert!(lines[22].trim().is_empty());
+    assert_eq!(lines[23].trim_end(), " paused at x3000  |  s step  c continue  b break  : command  i input  q quit");
+
+    let wide = app.render(120, 24).lines();
+    assert!(wide.iter().any(|line| line.contains("│x3000 5020 1021 123D 09FD F025 0000 0000 0000  ")));
+    let small = app.render(40, 10).lines();
+    assert!(small[0].starts_with("the terminal needs to be at least 60x20"));
+}
+
+#[test]
+fn keys_step_set_breakpoints_and_continue() {
+    let mut app = app(PROGRAM);
+    press(&mut app, KeyCode::Char('s'));
+    assert_eq!(app.debugger().vm().pc(), 0x3001);
+
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Down);
+    app.tick(SLICE);
+    press(&mut app, KeyCode::Char('b'));
+    assert!(app.debugger().breakpoints().contains(&0x3003));
+    press(&mut app, KeyCode::Char('c'));
+    assert!(app.is_running());
+    app.tick(SLICE);
+    assert!(!app.is_running());
+    assert_eq!(app.debugger().vm().pc(), 0x3003);
+    assert_eq!(app.status(), "paused at x3003");
+    assert!(app.render(80, 24).lines().iter().any(|line| line.starts_with("│*> x3003")));
+
+    press(&mut app, KeyCode::F(9));
+    press(&mut app, KeyCode::F(5));
+    app.tick(SLICE);
+    assert_eq!(app.status(), "halted after 11 instructions");
+}
+
+#[test]
+fn guest_console_is_kept_apart_from_the_ui() {
+    let mut app = app(".ORIG x3000\nLEA R0, HELLO\nPUTS\nGETC\nOUT\nHALT\nHELLO .STRINGZ \"name? \"\n.END\n");
+    press(&mut app, KeyCode::Char('c'));
+    app.tick(SLICE);
+    assert!(app.is_running());
+    assert!(app.waiting_for_input());
+    assert_eq!(app.focus(), &Focus::Console);
+    assert_eq!(app.status(), "waiting for input, press i and type");
+    assert_eq!(app.console_output(), "name? ");
+
+    // typed keys go to the guest, not to the shortcuts
+    type_text(&mut app, "q");
+    assert!(!app.has_quit());
+    app.tick(SLICE);
+    assert!(app.debugger().vm().is_halted());
+    assert_eq!(app.console_output(), "name? q");
+    let lines = app.render(80, 24).lines();
+    assert!(lines.iter().any(|line| line.starts_with("│name? q ")));
+
+    press(&mut app, KeyCode::Esc);
+    assert_eq!(app.focus(), &Focus::Keys);
+}
+
+#[test]
+fn command_line_goes_to_the_debugger() {
+    let mut app = app(PROGRAM);
+    press(&mut app, KeyCode::Char(':'));
+    type_text(&mut app, "x x3001 2");
+    assert_eq!(app.focus(), &Focus::Command("x x3001 2".to_string()));
+    assert!(app.render(80, 24).lines()[22].starts_with(":x x3001 2 "));
+    press(&mut app, KeyCode::Enter);
+    let lines = app.render(80, 24).lines();
+    assert!(lines.iter().any(|line| line.contains("│(lc3) x x3001 2")));
+    assert!(lines.iter().any(|line| line.contains("│x3001: x1021")));
+
+    app.command("s 3");
+    assert_eq!(app.debugger().vm().pc(), 0x3003);
+    app.command("mem x4000");
+    assert!(app.render(80, 24).lines().iter().any(|line| line.contains("│x4000 0000")));
+    app.command("mem #16400");
+    assert!(app.render(80, 24).lines().iter().any(|line| line.contains("│x4010 0000")));
+    app.command("break nowhere");
+    app.command("mem nowhere");
+    let lines = app.render(80, 24).lines();
+    assert!(lines.iter().any(|line| line.contains("│invalid address 'nowhere'")));
+
+    app.command("quit");
+    assert!(app.has_quit());
+}
+
+#[test]
+fn screen_clips_text_and_groups_highlights() {
+    let mut screen = Screen::new(10, 2);
+    screen.put(2, 0, 6, "abcdefgh");
+    screen.put(0, 1, 10, "x\ty");
+    screen.highlight(3, 0, 2);
+    assert_eq!(screen.lines(), vec!["  abcd    ", "x y       "]);
+    assert_eq!(
+        screen.runs(0),
+        vec![("  a".to_string(), false), ("bc".to_string(), true), ("d    ".to_string(), false)]
+    );
+}
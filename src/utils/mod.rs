@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod utils;
 mod u16_reader;
 
@@ -1,20 +1,20 @@
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, Read};
 
-pub struct U16FileReader {
-    reader: BufReader<File>,
+/// big endian words, the byte order of lc3 object files
+pub struct U16FileReader<R> {
+    reader: R,
 }
 
-impl U16FileReader {
-    pub fn new(reader: BufReader<File>) -> U16FileReader {
+impl<R: Read> U16FileReader<R> {
+    pub fn new(reader: R) -> U16FileReader<R> {
         U16FileReader {
             reader
         }
     }
 
-    pub fn read_u16(&mut self) -> Result<u16, anyhow::Error> {
+    pub fn read_u16(&mut self) -> io::Result<u16> {
         let mut u16_buf = [0; 2];
         self.reader.read_exact(&mut u16_buf)?;
         Ok(u16::from_be_bytes(u16_buf))
     }
-}
\ No newline at end of file
+}
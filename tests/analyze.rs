@@ -0,0 +1,17 @@
+mod common;
+
+use common::{run_vm, stdout, write_file};
+
+#[test]
+fn analyze_warns_at_the_source_line() {
+    let source = write_file("asm", ".ORIG x3000\nADD R0, R1, #1\nHALT\n.END\n");
+    let output = run_vm(&["analyze", source.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    let expected = format!("warning: {}:2: reads R1, which is not written on every path to here\n1 warning", source.arg());
+    assert_eq!(stdout(&output).trim(), expected);
+
+    std::fs::write(source.path(), ".ORIG x3000\nAND R1, R1, #0\nADD R0, R1, #1\nHALT\n.END\n").unwrap();
+    let output = run_vm(&["analyze", source.arg()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(run_vm(&["analyze"]).status.code(), Some(2));
+}
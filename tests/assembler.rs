@@ -0,0 +1,30 @@
+mod common;
+
+use common::{TempFile, run_vm, stderr, write_file};
+
+#[test]
+fn assembled_programs_fail_with_their_source_line() {
+    let source = write_file("asm", ".ORIG x3000\nADD R0, R0, #1\nTRAP x30\n.END\n");
+    let (object, listing, map) = (TempFile::new("obj"), TempFile::new("lst"), TempFile::new("map"));
+    let output = run_vm(&["asm", source.arg(), "-o", object.arg(), "--listing", listing.arg(), "--source-map", map.arg()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(std::fs::read(object.path()).unwrap(), [0x30, 0x00, 0x10, 0x21, 0xF0, 0x30]);
+    assert!(std::fs::read_to_string(listing.path()).unwrap().contains("x3001  xF030  1111000000110000"));
+
+    let expected = format!("error: unknown TRAP vector x30 at x3001 ({}:3)", source.arg());
+    let output = run_vm(&[object.arg(), "--source-map", map.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output).trim(), expected);
+
+    let output = run_vm(&[source.arg()]);
+    assert_eq!(stderr(&output).trim(), expected);
+}
+
+#[test]
+fn assembly_errors_exit_with_three() {
+    let source = write_file("asm", ".ORIG x3000\nADD R0, R9, #1\n.END\n");
+    let output = run_vm(&["asm", source.arg()]);
+    assert_eq!(output.status.code(), Some(3));
+    let expected = format!("error: {}:2: expected a register, found R9", source.arg());
+    assert_eq!(stderr(&output).trim(), expected);
+}
@@ -0,0 +1,17 @@
+mod common;
+
+use common::{run_vm, stderr, stdout, write_file};
+
+#[test]
+fn cfg_writes_dot_for_a_program() {
+    let source = write_file("asm", ".ORIG x3000\nLOOP BRz LOOP\nHALT\n.END\n");
+    let output = run_vm(&["cfg", source.arg(), "--call-graph"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("\"x3000\" [label=\"LOOP\"];"));
+
+    let output = run_vm(&["cfg", source.arg(), "--json", "--call-graph"]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = run_vm(&["cfg", source.arg(), "--entry", "x30000"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output).trim(), "error: invalid entry address x30000");
+}
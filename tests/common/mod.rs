@@ -0,0 +1,92 @@
+//! fixtures for the tests that run the rustvm binary
+
+#![allow(dead_code)] // every test binary uses a different part
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// a path in the temp directory, the file is removed when this is dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    pub fn new(extension: &str) -> TempFile {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before unix epoch")
+            .as_nanos();
+        let count = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("rustvm-test-{}-{unique}-{count}.{extension}", std::process::id());
+        TempFile(std::env::temp_dir().join(name))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// the path as a command line argument
+    pub fn arg(&self) -> &str {
+        self.0.to_str().expect("temp path is not utf-8")
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// an object file: the origin followed by the words, big endian
+pub fn write_obj_file(words: &[u16]) -> TempFile {
+    let file = TempFile::new("obj");
+    let mut out = File::create(file.path()).expect("failed to create temp obj file");
+    for word in words {
+        out.write_all(&word.to_be_bytes()).expect("failed to write obj word");
+    }
+    file
+}
+
+/// a file holding `text`
+pub fn write_file(extension: &str, text: &str) -> TempFile {
+    let file = TempFile::new(extension);
+    std::fs::write(file.path(), text).expect("failed to write temp file");
+    file
+}
+
+/// runs rustvm with stdin closed right away
+pub fn run_vm(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rustvm"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .expect("failed to run rustvm")
+}
+
+/// runs rustvm with `input` on stdin
+pub fn run_vm_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rustvm"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn rustvm");
+    let mut stdin = child.stdin.take().expect("failed to open child stdin");
+    stdin.write_all(input.as_bytes()).expect("failed to write child stdin");
+    drop(stdin);
+    child.wait_with_output().expect("failed to wait for rustvm")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,14 @@
+use rustvm::runner::Spec;
+use std::path::Path;
+
+#[test]
+fn golden_programs_pass() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance/conformance.toml");
+    let report = Spec::load(&path).expect("failed to load the conformance suite").run();
+
+    let mut text = Vec::new();
+    report.write_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(report.failed(), 0, "{text}");
+    assert_eq!(report.passed(), 14, "{text}");
+}
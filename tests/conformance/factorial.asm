@@ -0,0 +1,36 @@
+; R0 = R1! computed recursively, saved registers and return addresses on the stack
+        .ORIG x3000
+        LD R6, STACK
+        JSR FACT
+        HALT
+STACK   .FILL xFE00
+
+; R0 = R1!, preserves R1, R6 and R7, clobbers R2 and R3
+FACT    ADD R6, R6, #-1
+        STR R7, R6, #0
+        ADD R6, R6, #-1
+        STR R1, R6, #0
+        ADD R2, R1, #-1
+        BRp RECURSE
+        AND R0, R0, #0
+        ADD R0, R0, #1
+        BR RETURN
+RECURSE ADD R1, R1, #-1
+        JSR FACT
+        LDR R1, R6, #0
+        JSR MUL
+RETURN  LDR R1, R6, #0
+        ADD R6, R6, #1
+        LDR R7, R6, #0
+        ADD R6, R6, #1
+        RET
+
+; R0 = R0 * R1 for a positive R1, clobbers R2 and R3
+MUL     AND R2, R2, #0
+        ADD R3, R1, #0
+MLOOP   ADD R2, R2, R0
+        ADD R3, R3, #-1
+        BRp MLOOP
+        ADD R0, R2, #0
+        RET
+        .END
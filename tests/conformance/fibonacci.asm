@@ -0,0 +1,18 @@
+; the first COUNT Fibonacci numbers into FIB
+        .ORIG x3000
+        LEA R1, FIB
+        AND R2, R2, #0      ; F(n)
+        AND R3, R3, #0
+        ADD R3, R3, #1      ; F(n + 1)
+        LD R4, COUNT
+LOOP    STR R2, R1, #0
+        ADD R1, R1, #1
+        ADD R5, R2, R3
+        ADD R2, R3, #0
+        ADD R3, R5, #0
+        ADD R4, R4, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #10
+FIB     .BLKW #10
+        .END
@@ -0,0 +1,12 @@
+; R0 = R1 * R2 by repeated addition, R2 must not be negative
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R2, R2, #0
+        BRz DONE
+LOOP    ADD R0, R0, R1
+        ADD R2, R2, #-1
+        BRp LOOP
+DONE    ST R0, RESULT
+        HALT
+RESULT  .FILL 0
+        .END
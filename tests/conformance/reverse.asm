@@ -0,0 +1,21 @@
+; echoes a line read with GETC backwards, through a stack growing down from xFE00
+        .ORIG x3000
+        LD R6, STACK
+        AND R1, R1, #0      ; characters on the stack
+READ    GETC
+        ADD R2, R0, #-10    ; the newline ends the line
+        BRz PRINT
+        ADD R6, R6, #-1
+        STR R0, R6, #0
+        ADD R1, R1, #1
+        BR READ
+PRINT   ADD R1, R1, #0
+        BRz DONE
+POP     LDR R0, R6, #0
+        ADD R6, R6, #1
+        OUT
+        ADD R1, R1, #-1
+        BRp POP
+DONE    HALT
+STACK   .FILL xFE00
+        .END
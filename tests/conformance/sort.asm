@@ -0,0 +1,29 @@
+; sorts the COUNT words at DATA in ascending order with bubble sort
+        .ORIG x3000
+OUTER   AND R5, R5, #0      ; swaps in this pass
+        LEA R1, DATA
+        LD R4, COUNT
+        ADD R4, R4, #-1     ; pairs to compare
+INNER   LDR R2, R1, #0
+        LDR R3, R1, #1
+        NOT R0, R3
+        ADD R0, R0, #1
+        ADD R0, R2, R0      ; a - b
+        BRnz NEXT
+        STR R3, R1, #0
+        STR R2, R1, #1
+        ADD R5, R5, #1
+NEXT    ADD R1, R1, #1
+        ADD R4, R4, #-1
+        BRp INNER
+        ADD R5, R5, #0
+        BRp OUTER
+        HALT
+COUNT   .FILL #6
+DATA    .FILL #5
+        .FILL #-2
+        .FILL #9
+        .FILL #0
+        .FILL #-7
+        .FILL #3
+        .END
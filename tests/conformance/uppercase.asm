@@ -0,0 +1,19 @@
+; echoes a line with its lowercase letters made uppercase
+        .ORIG x3000
+        LD R2, MINUS_A
+        LD R3, MINUS_Z
+        LD R4, TO_UPPER
+LOOP    GETC
+        ADD R1, R0, R2
+        BRn ECHO
+        ADD R1, R0, R3
+        BRp ECHO
+        ADD R0, R0, R4
+ECHO    OUT
+        ADD R1, R0, #-10
+        BRnp LOOP
+        HALT
+MINUS_A .FILL #-97
+MINUS_Z .FILL #-122
+TO_UPPER .FILL #-32
+        .END
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{run_vm_with_input, stderr, stdout, write_file, write_obj_file};
+
+// x3000: GETC
+// x3001: OUT
+// x3002: HALT
+const ECHO: [u16; 4] = [0x3000, 0xF020, 0xF021, 0xF025];
+
+#[test]
+fn getc_under_the_debugger_takes_queued_input_not_commands() {
+    let obj = write_obj_file(&ECHO);
+    let output = run_vm_with_input(&["--debug", obj.arg()], "c\ninput k\nc\nq\n");
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("pc x3000: waiting for input"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("halted after 3 instructions"), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("OK\nk"), "{}", stderr(&output));
+}
+
+#[test]
+fn input_file_feeds_the_guest_under_the_debugger() {
+    let obj = write_obj_file(&ECHO);
+    let input = write_file("input", "z");
+    let output = run_vm_with_input(&["--debug", "--input", input.arg(), obj.arg()], "c\n");
+
+    assert!(stderr(&output).contains("halted after 3 instructions"), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("OK\nz"), "{}", stderr(&output));
+}
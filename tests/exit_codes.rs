@@ -0,0 +1,51 @@
+mod common;
+
+use common::{TempFile, run_vm, stderr, stdout, write_obj_file};
+
+#[test]
+fn halting_program_exits_with_zero() {
+    let obj = write_obj_file(&[0x3000, 0xF025]);
+    let output = run_vm(&[obj.arg()]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn unknown_trap_exits_with_one_and_a_clean_message() {
+    let obj = write_obj_file(&[0x3000, 0xF0AA]);
+    let output = run_vm(&[obj.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output).trim(), "error: unknown TRAP vector xAA at x3000");
+}
+
+#[test]
+fn unknown_option_exits_with_two() {
+    let output = run_vm(&["--bogus"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("error: unknown option --bogus"));
+}
+
+#[test]
+fn missing_or_empty_image_exits_with_three() {
+    let missing = TempFile::new("obj");
+    assert_eq!(run_vm(&[missing.arg()]).status.code(), Some(3));
+
+    let empty = write_obj_file(&[]);
+    let output = run_vm(&[empty.arg()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("object file is empty"));
+}
+
+#[test]
+fn reading_closed_stdin_exits_with_four() {
+    let obj = write_obj_file(&[0x3000, 0xF020, 0xF025]);
+    let output = run_vm(&[obj.arg()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+}
+
+#[test]
+fn fuzzer_without_divergence_exits_with_zero() {
+    let output = run_vm(&["fuzz", "--seed", "3", "--cases", "1000"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "no divergence in 1000 cases\n");
+}
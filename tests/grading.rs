@@ -0,0 +1,31 @@
+use std::process::Command;
+
+#[test]
+fn grade_prints_scores_and_writes_reports() {
+    let reports = std::env::temp_dir().join(format!("rustvm-grading-{}", std::process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_rustvm"))
+        .args(["grade", "tests/specs/countdown-rubric.toml", "tests/specs/countdown.asm", "--report-dir"])
+        .arg(&reports)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run rustvm grade");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    assert!(stdout.starts_with("countdown: 5/5\n"), "{stdout}");
+
+    let json = std::fs::read_to_string(reports.join("countdown.json")).expect("missing json report");
+    assert!(json.contains("\"score\": 5"));
+    let junit = std::fs::read_to_string(reports.join("countdown.xml")).expect("missing junit report");
+    assert!(junit.contains(r#"<testsuite name="countdown" tests="3" failures="0" errors="0">"#));
+}
+
+#[test]
+fn grade_without_submissions_is_a_usage_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rustvm"))
+        .args(["grade", "tests/specs/countdown-rubric.toml"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run rustvm grade");
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{TempFile, run_vm_with_input, stderr, stdout, write_obj_file};
+
+// x3000: TRAP x20 (GETC)
+// x3001: TRAP x21 (OUT)
+// x3002: TRAP x20 (GETC)
+// x3003: TRAP x21 (OUT)
+// x3004: TRAP x25 (HALT)
+const ECHO_TWICE: [u16; 6] = [0x3000, 0xF020, 0xF021, 0xF020, 0xF021, 0xF025];
+
+#[test]
+fn recorded_input_replays_without_stdin() {
+    let obj = write_obj_file(&ECHO_TWICE);
+    let recording = TempFile::new("input");
+
+    let recorded = run_vm_with_input(&["--record-input", recording.arg(), obj.arg()], "ok");
+    assert!(recorded.status.success(), "recording run failed: {}", stderr(&recorded));
+    let log = std::fs::read_to_string(recording.path()).expect("recording was not written");
+    assert!(log.lines().any(|line| line == "0 111"), "unexpected recording:\n{log}");
+    assert!(log.lines().any(|line| line == "2 107"), "unexpected recording:\n{log}");
+
+    let replayed = run_vm_with_input(&["--replay-input", recording.arg(), obj.arg()], "");
+    assert!(replayed.status.success(), "replay run failed: {}", stderr(&replayed));
+    assert_eq!(recorded.stdout, replayed.stdout);
+}
+
+#[test]
+fn input_typed_into_the_debugger_is_recorded_and_replayed() {
+    let obj = write_obj_file(&ECHO_TWICE);
+    let recording = TempFile::new("input");
+
+    let recorded = run_vm_with_input(&["--debug", "--record-input", recording.arg(), obj.arg()], "c\ninput ok\nc\nq\n");
+    let log = std::fs::read_to_string(recording.path()).expect("recording was not written");
+    assert!(log.lines().any(|line| line == "0 111"), "unexpected recording:\n{log}");
+    assert!(log.lines().any(|line| line == "2 107"), "unexpected recording:\n{log}");
+
+    // debugger commands never reach the guest, and typing more cannot change a replay
+    let replayed = run_vm_with_input(&["--debug", "--replay-input", recording.arg(), obj.arg()], "input zz\nc\nq\n");
+    let stderr = stderr(&replayed);
+    assert!(stderr.contains("input comes from the replayed recording"), "{stderr}");
+    assert!(stderr.contains("halted after 5 instructions"), "{stderr}");
+    assert_eq!(recorded.stdout, replayed.stdout);
+    assert!(stdout(&replayed).ends_with("OK\nok"), "{stderr}");
+}
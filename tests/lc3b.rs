@@ -0,0 +1,26 @@
+mod common;
+
+use common::{TempFile, run_vm, stderr, stdout, write_obj_file};
+
+#[test]
+fn lc3b_programs_are_picked_by_header_or_flag() {
+    // x3000: LEA R0, #2 ; x3002: PUTS ; x3004: HALT ; x3006: "hi"
+    let words = [0x3000, 0xE002, 0xF022, 0xF025, 0x6968, 0x0000];
+    let headerless = write_obj_file(&words);
+    let with_header = TempFile::new("obj");
+    let mut bytes = b"LC-3b\n".to_vec();
+    bytes.extend(std::fs::read(headerless.path()).unwrap());
+    std::fs::write(with_header.path(), bytes).unwrap();
+
+    for args in [vec![with_header.arg()], vec!["--isa", "lc3b", headerless.arg()]] {
+        let output = run_vm(&args);
+        assert_eq!(output.status.code(), Some(0));
+        assert!(stdout(&output).ends_with("Executing now\nhi"));
+    }
+
+    let trap = write_obj_file(&[0x3000, 0x0000, 0xF0AA]);
+    let output = run_vm(&["--isa", "lc3b", trap.arg()]);
+    assert_eq!(stderr(&output).trim(), "error: unknown TRAP vector xAA at x3002");
+    assert_eq!(run_vm(&["--isa", "mips", trap.arg()]).status.code(), Some(2));
+    assert_eq!(run_vm(&["--isa", "lc3b", "program.asm"]).status.code(), Some(2));
+}
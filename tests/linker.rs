@@ -0,0 +1,38 @@
+mod common;
+
+use common::{TempFile, run_vm, stderr, stdout, write_file};
+
+#[test]
+fn linked_modules_run_like_an_assembled_program() {
+    let main = write_file("asm", ".EXTERN SHOW\n.SECTION text\nLEA R0, MSG\nJSR SHOW\nHALT\nMSG .STRINGZ \"ok\"\n");
+    let lib = write_file("asm", ".GLOBAL SHOW\n.SECTION text\nSHOW PUTS\nRET\n");
+    let (main_object, linked) = (TempFile::new("rel"), TempFile::new("obj"));
+
+    let output = run_vm(&["asm", "-c", main.arg(), "-o", main_object.arg()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let output = run_vm(&["link", main_object.arg(), lib.arg(), "-o", linked.arg()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let output = run_vm(&[linked.arg()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).ends_with("Executing now\nok"));
+
+    let output = run_vm(&["link", main_object.arg(), "-o", linked.arg()]);
+    assert_eq!(output.status.code(), Some(3));
+    let expected = format!("error: {}:4: undefined symbol SHOW", main.arg());
+    assert_eq!(stderr(&output).trim(), expected);
+}
+
+#[test]
+fn base_takes_any_number_the_assembler_accepts() {
+    let main = write_file("asm", ".SECTION text\nHALT\n");
+    let linked = TempFile::new("obj");
+    for base in ["x4000", "0x4000", "#16384", "16384"] {
+        let output = run_vm(&["link", main.arg(), "--base", base, "-o", linked.arg()]);
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        assert_eq!(std::fs::read(linked.path()).unwrap(), [0x40, 0x00, 0xF0, 0x25]);
+    }
+    let output = run_vm(&["link", main.arg(), "--base", "x10000", "-o", linked.arg()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output).trim(), "error: invalid base address x10000");
+}
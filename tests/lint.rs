@@ -0,0 +1,24 @@
+mod common;
+
+use common::{run_vm, stdout, write_file};
+
+#[test]
+fn lint_and_format_check_exit_with_one_when_something_is_off() {
+    let source = write_file("asm", ".orig x3000\nhalt\nadd r0,r0,#1\n.end\n");
+
+    let output = run_vm(&["lint", source.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    let expected = format!(
+        "warning: {}:3: unreachable, nothing branches here and line 2 never falls through\n1 warning",
+        source.arg()
+    );
+    assert_eq!(stdout(&output).trim(), expected);
+
+    let output = run_vm(&["fmt", "--check", source.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output).trim(), source.arg());
+    assert_eq!(run_vm(&["fmt", source.arg()]).status.code(), Some(0));
+    assert_eq!(run_vm(&["fmt", "--check", source.arg()]).status.code(), Some(0));
+    let formatted = std::fs::read_to_string(source.path()).unwrap();
+    assert_eq!(formatted, "        .ORIG   x3000\n        HALT\n        ADD     r0, r0, #1\n        .END\n");
+}
@@ -0,0 +1,31 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn language_server_exits_cleanly_only_after_a_shutdown() {
+    let run = |messages: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rustvm-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to run rustvm-lsp");
+        let mut stdin = child.stdin.take().unwrap();
+        for message in messages {
+            write!(stdin, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+        }
+        drop(stdin);
+        child.wait_with_output().expect("failed to wait for rustvm-lsp")
+    };
+
+    let output = run(&[
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Content-Length: "));
+    assert!(stdout.contains(r#""result":null"#));
+
+    assert_eq!(run(&[r#"{"jsonrpc":"2.0","method":"exit"}"#]).status.code(), Some(1));
+}
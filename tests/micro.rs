@@ -0,0 +1,34 @@
+mod common;
+
+use common::{TempFile, run_vm, stderr, stdout, write_file, write_obj_file};
+
+#[test]
+fn micro_runs_cycle_by_cycle_and_checks_against_the_functional_model() {
+    // x3000: ADD R1, R1, #1 ; x3001: HALT
+    let obj = write_obj_file(&[0x3000, 0x1261, 0xF025]);
+    let trace = TempFile::new("cycles");
+    let output = run_vm(&["micro", obj.arg(), "--trace", trace.arg()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output).trim(), "2 instructions in 10 cycles, 5.00 cycles per instruction");
+    assert_eq!(std::fs::read_to_string(trace.path()).unwrap().lines().count(), 10);
+
+    let output = run_vm(&["micro", "--check", obj.arg()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output).trim(), "the models agree on 2 instructions (10 cycles) until it halted");
+
+    // ADD does nothing
+    let fetch = "18: LD.MAR LD.PC GatePC J=33\n33: LD.MDR MIO.EN COND=READY J=33\n35: LD.IR GateMDR J=32\n32: IRD\n";
+    let microcode = write_file("ucode", &format!("{fetch}1: J=18\n15: TRAP J=18\n"));
+    let output = run_vm(&["micro", "--check", "--microcode", microcode.arg(), obj.arg()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "instruction 0 at x3000 (ADD R1, R1, #1) differs from the functional model:\n  \
+         R1: x0001, x0000\n  COND: x0001, x0002\n"
+    );
+
+    std::fs::write(microcode.path(), "18: LD.MAR\n").unwrap();
+    let output = run_vm(&["micro", "--microcode", microcode.arg(), obj.arg()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).ends_with("line 1: LD.MAR loads from the bus but no gate drives it\n"));
+}
@@ -0,0 +1,35 @@
+use std::process::Command;
+
+fn run_test_command(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_rustvm"))
+        .arg("test")
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run rustvm test")
+}
+
+#[test]
+fn example_specs_pass() {
+    let output = run_test_command(&["tests/specs/examples.toml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{stdout}");
+    assert!(stdout.ends_with("4 passed, 0 failed\n"), "{stdout}");
+}
+
+#[test]
+fn failing_spec_exits_non_zero() {
+    let spec = std::env::temp_dir().join(format!("rustvm-spec-{}.toml", std::process::id()));
+    std::fs::write(&spec, "[[case]]\nname = \"wrong\"\nsource = \".ORIG x3000\\nHALT\\n\"\nexpect = { output = \"x\" }\n")
+        .expect("failed to write spec");
+    let output = run_test_command(&[spec.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("FAIL wrong"));
+}
+
+#[test]
+fn unreadable_spec_is_a_load_error() {
+    let output = run_test_command(&["tests/specs/missing.toml"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+}
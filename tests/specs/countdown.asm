@@ -0,0 +1,12 @@
+; prints the digits from R1 down to 1, R1 holds the count on entry
+        .ORIG x3000
+        LD R2, ZERO
+LOOP    ADD R0, R1, R2
+        OUT
+        ADD R1, R1, #-1
+        BRp LOOP
+        ST R1, DONE
+        HALT
+ZERO    .FILL x30
+DONE    .FILL xFFFF
+        .END
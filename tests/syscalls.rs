@@ -142,3 +142,29 @@ fn syscall_putsp_prints_packed_string() {
         "expected packed string output \"Hi!\", got:\n{runtime}"
     );
 }
+
+#[test]
+fn syscall_putsp_stops_at_a_whole_zero_word_after_an_even_length_string() {
+    // x3000: LEA R0, #2      -- R0 -> x3003
+    // x3001: TRAP x24 (PUTSP)
+    // x3002: TRAP x25 (HALT)
+    // x3003: 0x6948 ('H','i')
+    // x3004: 0x2121 ('!','!')
+    // x3005: 0x0000 (terminator)
+    let obj = write_obj_file(&[0x3000, 0xE002, 0xF024, 0xF025, 0x6948, 0x2121, 0x0000]);
+    let output = run_vm(&obj, "");
+    std::fs::remove_file(&obj).ok();
+
+    assert!(
+        output.status.success(),
+        "process failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let runtime = runtime_stdout(&stdout);
+    assert!(
+        runtime.contains("Hi!!") && !runtime.contains('\0'),
+        "expected packed string output \"Hi!!\" without a NUL, got:\n{runtime:?}"
+    );
+}
@@ -0,0 +1,10 @@
+mod common;
+
+use common::{run_vm, stderr};
+
+#[test]
+fn tui_without_a_program_exits_with_two() {
+    let output = run_vm(&["tui"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output).trim(), "error: tui expects a program");
+}